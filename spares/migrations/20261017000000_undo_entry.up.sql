@@ -0,0 +1,6 @@
+-- Create the 'undo_entry' table
+CREATE TABLE IF NOT EXISTS undo_entry (
+    id INTEGER PRIMARY KEY NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')) NOT NULL, -- Store as Unix Time
+    data TEXT NOT NULL -- JSON string
+);
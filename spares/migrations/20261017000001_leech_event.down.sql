@@ -0,0 +1 @@
+DROP TABLE IF EXISTS leech_event;
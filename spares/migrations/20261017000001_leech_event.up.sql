@@ -0,0 +1,8 @@
+-- Create the 'leech_event' table
+CREATE TABLE IF NOT EXISTS leech_event (
+    id INTEGER PRIMARY KEY NOT NULL,
+    card_id INTEGER NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')) NOT NULL, -- Store as Unix Time
+    lapses INTEGER NOT NULL,
+    FOREIGN KEY (card_id) REFERENCES card(id) ON DELETE CASCADE
+);
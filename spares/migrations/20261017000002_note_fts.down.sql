@@ -0,0 +1,7 @@
+DROP TRIGGER IF EXISTS note_fts_tag_update;
+DROP TRIGGER IF EXISTS note_fts_note_tag_delete;
+DROP TRIGGER IF EXISTS note_fts_note_tag_insert;
+DROP TRIGGER IF EXISTS note_fts_note_delete;
+DROP TRIGGER IF EXISTS note_fts_note_update;
+DROP TRIGGER IF EXISTS note_fts_note_insert;
+DROP TABLE IF EXISTS note_fts;
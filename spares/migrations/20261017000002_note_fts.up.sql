@@ -0,0 +1,39 @@
+-- Create the 'note_fts' full text index. The rowid of each entry is the ID of the note it indexes.
+CREATE VIRTUAL TABLE IF NOT EXISTS note_fts USING fts5(data, keywords, tags);
+
+-- Index existing notes
+INSERT INTO note_fts (rowid, data, keywords, tags)
+SELECT n.id, n.data, n.keywords, COALESCE((SELECT group_concat(t.name, ' ') FROM note_tag nt JOIN tag t ON t.id = nt.tag_id WHERE nt.note_id = n.id), '')
+FROM note n;
+
+-- Keep the index in sync with notes
+CREATE TRIGGER IF NOT EXISTS note_fts_note_insert AFTER INSERT ON note BEGIN
+    INSERT INTO note_fts (rowid, data, keywords, tags) VALUES (new.id, new.data, new.keywords, '');
+END;
+
+CREATE TRIGGER IF NOT EXISTS note_fts_note_update AFTER UPDATE OF data, keywords ON note BEGIN
+    UPDATE note_fts SET data = new.data, keywords = new.keywords WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS note_fts_note_delete AFTER DELETE ON note BEGIN
+    DELETE FROM note_fts WHERE rowid = old.id;
+END;
+
+-- Keep the index in sync with tags
+CREATE TRIGGER IF NOT EXISTS note_fts_note_tag_insert AFTER INSERT ON note_tag BEGIN
+    UPDATE note_fts
+    SET tags = COALESCE((SELECT group_concat(t.name, ' ') FROM note_tag nt JOIN tag t ON t.id = nt.tag_id WHERE nt.note_id = new.note_id), '')
+    WHERE rowid = new.note_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS note_fts_note_tag_delete AFTER DELETE ON note_tag BEGIN
+    UPDATE note_fts
+    SET tags = COALESCE((SELECT group_concat(t.name, ' ') FROM note_tag nt JOIN tag t ON t.id = nt.tag_id WHERE nt.note_id = old.note_id), '')
+    WHERE rowid = old.note_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS note_fts_tag_update AFTER UPDATE OF name ON tag BEGIN
+    UPDATE note_fts
+    SET tags = COALESCE((SELECT group_concat(t.name, ' ') FROM note_tag nt JOIN tag t ON t.id = nt.tag_id WHERE nt.note_id = note_fts.rowid), '')
+    WHERE rowid IN (SELECT note_id FROM note_tag WHERE tag_id = new.id);
+END;
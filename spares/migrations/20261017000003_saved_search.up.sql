@@ -0,0 +1,6 @@
+-- Create the 'saved_search' table
+CREATE TABLE IF NOT EXISTS saved_search (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name VARCHAR NOT NULL UNIQUE,
+    query TEXT NOT NULL
+);
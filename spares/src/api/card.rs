@@ -1,10 +1,14 @@
 use crate::{
     Error, LibraryError, SchedulerErrorKind,
     config::read_external_config,
-    model::{Card, CardId, NEW_CARD_STATE, NoteId, ReviewLog, SpecialState, TagId},
+    model::{Card, CardId, LeechEvent, NEW_CARD_STATE, NoteId, ReviewLog, SpecialState, TagId},
     schedulers::get_scheduler_from_string,
-    schema::card::{
-        CardResponse, CardsSelector, GetLeechesRequest, SpecialStateUpdate, UpdateCardRequest,
+    schema::{
+        FilterOptions,
+        card::{
+            CardResponse, CardsSelector, GetLeechesRequest, LeechEventResponse, SpecialStateUpdate,
+            UpdateCardRequest,
+        },
     },
     search::evaluator::Evaluator,
 };
@@ -40,7 +44,7 @@ pub async fn update_card(
     let card_ids = match body.selector {
         CardsSelector::Ids(vec) => vec,
         CardsSelector::Query(query) => {
-            let evaluator = Evaluator::new(&query);
+            let evaluator = Evaluator::new(&query).with_time(at);
             evaluator.get_card_ids(db).await?
         }
     };
@@ -135,6 +139,48 @@ pub async fn get_leeches(
     Ok(card_responses)
 }
 
+/// Returns the cards that most recently became leeches.
+pub async fn get_leech_events(
+    db: &SqlitePool,
+    opts: FilterOptions,
+) -> Result<Vec<LeechEventResponse>, Error> {
+    #[derive(sqlx::FromRow)]
+    struct LeechEventRow {
+        #[sqlx(flatten)]
+        leech_event: LeechEvent,
+        note_id: NoteId,
+    }
+    let limit = opts.limit.unwrap_or(10);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+    let rows: Vec<LeechEventRow> = sqlx::query_as(
+        r"SELECT le.*, c.note_id
+        FROM leech_event le
+        JOIN card c ON le.card_id = c.id
+        ORDER BY le.created_at DESC, le.id DESC
+        LIMIT ? OFFSET ?",
+    )
+    .bind(limit as u32)
+    .bind(offset as u32)
+    .fetch_all(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |LeechEventRow {
+                 leech_event,
+                 note_id,
+             }| LeechEventResponse {
+                id: leech_event.id,
+                card_id: leech_event.card_id,
+                note_id,
+                created_at: leech_event.created_at,
+                lapses: leech_event.lapses,
+            },
+        )
+        .collect())
+}
+
 pub async fn create_card_tags(
     db: &SqlitePool,
     card_tag_entries: &[(CardId, TagId)],
@@ -1,7 +1,9 @@
 pub mod card;
 pub mod note;
 pub mod parser;
+pub mod preset;
 pub mod review;
+pub mod saved_search;
 pub mod scheduler;
 pub mod statistics;
 pub mod tag;
@@ -5,7 +5,7 @@ use crate::{
     helpers::parse_list,
     model::{Note, NoteId, NoteLink, TagId},
     parsers::{
-        Parseable, RenderOutputDirectoryType, find_parser,
+        Parseable, ParserConstructor, RenderOutputDirectoryType, find_parser,
         generate_files::{CardSide, RenderOutputType},
         get_output_raw_dir,
         image_occlusion::{
@@ -192,7 +192,7 @@ pub fn delete_note_files(
 pub async fn delete_note(
     db: &SqlitePool,
     id: NoteId,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<(), Error> {
     let card_orders_db: Vec<(u32,)> =
         sqlx::query_as(r#"SELECT "order" FROM card WHERE note_id = ?"#)
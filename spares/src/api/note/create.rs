@@ -9,7 +9,7 @@ use crate::{
     helpers::{intersect, parse_list},
     model::{Card, CardId, Note, NoteId, SpecialState, TagId},
     parsers::{
-        Parseable, add_order_to_note_data, find_parser,
+        ParserConstructor, add_order_to_note_data, find_parser,
         generate_files::{
             GenerateNoteFilesRequest, GenerateNoteFilesRequests, create_note_files_bulk,
         },
@@ -56,7 +56,7 @@ pub async fn create_notes(
     db: &SqlitePool,
     body: CreateNotesRequest,
     at: DateTime<Utc>,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<NotesResponse, Error> {
     // Get parser
     let (parser_name,): (String,) = sqlx::query_as(r"SELECT name FROM parser WHERE id = ?")
@@ -184,7 +184,7 @@ pub async fn create_notes(
         let mut card_filtered_tag_entries = Vec::new();
         for (tag_id, query) in existing_filtered_tags {
             // Reexecute query to see if this card matches
-            let evaluator = Evaluator::new(query.as_str());
+            let evaluator = Evaluator::new(query.as_str()).with_time(at);
             let card_ids = evaluator.get_card_ids(db).await?;
             let card_ids_to_tag = intersect(&card_ids, &created_card_ids);
             let card_tags = card_ids_to_tag
@@ -15,12 +15,13 @@ pub use update::*;
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use crate::api::note::{create_notes, update_notes};
+    use crate::api::note::{create_notes, explain_search, update_notes};
     use crate::api::parser::tests::create_parser_helper;
     use crate::api::tag::create_tag;
     use crate::parsers::get_all_parsers;
     use crate::schema::note::{
-        CreateNoteRequest, CreateNotesRequest, NotesSelector, UpdateNotesRequest,
+        CreateNoteRequest, CreateNotesRequest, NotesSelector, SearchNotesRequest,
+        UpdateNotesRequest,
     };
     use crate::schema::tag::CreateTagRequest;
     use chrono::Utc;
@@ -28,7 +29,320 @@ pub(crate) mod tests {
     use sqlx::SqlitePool;
 
     pub use super::basic::*;
+    use crate::api::tag::{get_tag_by_name, update_tag};
     use crate::model::Tag;
+    use crate::schema::tag::UpdateTagRequest;
+    use crate::search::{QueryReturnItemType, evaluator::Evaluator};
+
+    #[sqlx::test]
+    async fn test_regex_and_fuzzy_search(pool: SqlitePool) -> () {
+        let parser = create_parser_helper(&pool, "markdown").await;
+        let create_note_request =
+            |data: &str, keywords: Vec<String>, tags: Vec<String>| CreateNoteRequest {
+                data: data.to_string(),
+                keywords,
+                tags,
+                is_suspended: false,
+                custom_data: Map::new(),
+            };
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: vec![
+                create_note_request(
+                    "The {{ eigenvalues }} of a matrix",
+                    vec!["matrix".to_string(), "eigenvalue".to_string()],
+                    vec!["linear-algebra".to_string()],
+                ),
+                create_note_request("{{ Foobar }} and foo", vec![], vec!["algebra".to_string()]),
+            ],
+        };
+        let notes = create_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap()
+            .notes;
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                let mut note_ids = Evaluator::new(query).get_note_ids(&pool).await.unwrap();
+                note_ids.sort_unstable();
+                note_ids
+            }
+        };
+
+        assert_eq!(search(r"data~/\bfoo\b/").await, vec![notes[1].id]);
+        assert_eq!(search(r"data~/^THE\s/").await, Vec::<i64>::new());
+        assert_eq!(search(r"data~/(?i)^THE\s/").await, vec![notes[0].id]);
+        // Tags are matched through cards, so each note has a cloze
+        assert_eq!(search("tag~/^algebra$/").await, vec![notes[1].id]);
+        assert_eq!(search("keyword%eigenvlaue").await, vec![notes[0].id]);
+        assert_eq!(search("keyword%vector").await, Vec::<i64>::new());
+        assert_eq!(search("tag%algebar").await, vec![notes[1].id]);
+        assert_eq!(search("-tag%algebar").await, vec![notes[0].id]);
+    }
+
+    #[sqlx::test]
+    async fn test_tag_hierarchy_search(pool: SqlitePool) -> () {
+        // math > algebra > linear-algebra, and history
+        let mut parent_id = None;
+        for name in ["math", "algebra", "linear-algebra", "history"] {
+            let request = CreateTagRequest {
+                name: name.to_string(),
+                description: String::new(),
+                parent_id: if name == "history" { None } else { parent_id },
+                query: None,
+                auto_delete: false,
+            };
+            parent_id = Some(create_tag(&pool, request).await.unwrap().id);
+        }
+        let parser = create_parser_helper(&pool, "markdown").await;
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: ["math", "linear-algebra", "history"]
+                .into_iter()
+                .map(|tag| CreateNoteRequest {
+                    data: format!("{{{{ {tag} }}}}"),
+                    keywords: vec![],
+                    tags: vec![tag.to_string()],
+                    is_suspended: false,
+                    custom_data: Map::new(),
+                })
+                .collect(),
+        };
+        let notes = create_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap()
+            .notes;
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                let mut note_ids = Evaluator::new(query).get_note_ids(&pool).await.unwrap();
+                note_ids.sort_unstable();
+                note_ids
+            }
+        };
+
+        assert_eq!(search("tag=math").await, vec![notes[0].id]);
+        assert_eq!(
+            search("tag_under=math").await,
+            vec![notes[0].id, notes[1].id]
+        );
+        assert_eq!(search("tag_under=algebra").await, vec![notes[1].id]);
+        assert_eq!(
+            search("-tag_under=algebra").await,
+            vec![notes[0].id, notes[2].id]
+        );
+        assert_eq!(search("tag_parent=algebra").await, vec![notes[1].id]);
+        assert_eq!(search("tag_depth=0").await, vec![notes[0].id, notes[2].id]);
+        assert_eq!(search("tag_depth>=2").await, vec![notes[1].id]);
+    }
+
+    #[sqlx::test]
+    async fn test_sort_limit_offset_search(pool: SqlitePool) -> () {
+        let parser = create_parser_helper(&pool, "markdown").await;
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: ["b {{ note }}", "c {{ note }}", "a {{ note }}"]
+                .into_iter()
+                .map(|data| CreateNoteRequest {
+                    data: data.to_string(),
+                    keywords: vec![],
+                    tags: vec![],
+                    is_suspended: false,
+                    custom_data: Map::new(),
+                })
+                .collect(),
+        };
+        let notes = create_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap()
+            .notes;
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                Evaluator::new(query)
+                    .get_notes(&pool)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(note, _parser_name, _snippet)| note.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(
+            search("sort:data").await,
+            vec![notes[2].id, notes[0].id, notes[1].id]
+        );
+        assert_eq!(
+            search("sort:id desc limit:2").await,
+            vec![notes[2].id, notes[1].id]
+        );
+        assert_eq!(
+            search("data~note sort:data limit:1 offset:1").await,
+            vec![notes[0].id]
+        );
+        assert_eq!(search("sort:data offset:2").await, vec![notes[1].id]);
+        assert_eq!(
+            Evaluator::new("sort:c.created_at desc sort:id limit:1")
+                .get_card_ids(&pool)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_explain_search(pool: SqlitePool) -> () {
+        let parser = create_parser_helper(&pool, "markdown").await;
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: ["cat {{ dog }}", "dog {{ fish }}", "fish {{ bird }}"]
+                .into_iter()
+                .map(|data| CreateNoteRequest {
+                    data: data.to_string(),
+                    keywords: vec![],
+                    tags: vec![],
+                    is_suspended: false,
+                    custom_data: Map::new(),
+                })
+                .collect(),
+        };
+        create_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap();
+
+        let explanation = explain_search(
+            &pool,
+            SearchNotesRequest {
+                query: "dog and fish and id=7".to_string(),
+                output_type: QueryReturnItemType::Notes,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            explanation.tree,
+            r#"(and (~ data "dog") (~ data "fish") (= id 7))"#
+        );
+        assert_eq!(explanation.count, 0);
+        assert_eq!(
+            explanation
+                .clauses
+                .iter()
+                .map(|clause| clause.count)
+                .collect::<Vec<_>>(),
+            vec![2, 2, 0]
+        );
+        assert!(explanation.sql.starts_with("SELECT"));
+        assert_eq!(explanation.binds.len(), 3);
+        assert!(explanation.warnings.is_empty());
+
+        let explanation = explain_search(
+            &pool,
+            SearchNotesRequest {
+                query: "data>5".to_string(),
+                output_type: QueryReturnItemType::Cards,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(explanation.count, 3);
+        assert_eq!(explanation.clauses.len(), 1);
+        assert_eq!(explanation.warnings[0].span, 0..6);
+    }
+
+    #[sqlx::test]
+    async fn test_full_text_search_index(pool: SqlitePool) -> () {
+        let parser = create_parser_helper(&pool, "markdown").await;
+        let create_note_request = |data: &str, tags: Vec<String>| CreateNoteRequest {
+            data: data.to_string(),
+            keywords: vec![],
+            tags,
+            is_suspended: false,
+            custom_data: Map::new(),
+        };
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: vec![
+                create_note_request("The quick brown fox jumps over the lazy dog", vec![]),
+                create_note_request("A fox, a fox, and another fox", vec!["animals".to_string()]),
+                create_note_request("Nothing to see here", vec![]),
+            ],
+        };
+        let notes = create_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap()
+            .notes;
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                Evaluator::new(query)
+                    .get_notes(&pool)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(note, _parser_name, snippet)| (note.id, snippet))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // Notes that mention the term more often are ranked first
+        let results = search("fts=fox").await;
+        assert_eq!(
+            results.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![notes[1].id, notes[0].id]
+        );
+        assert_eq!(
+            results[1].1.as_deref(),
+            Some("The quick brown **fox** jumps over the lazy dog")
+        );
+        assert_eq!(search("fts=\"qui*\"").await.len(), 1);
+        assert_eq!(search("fts=animals").await[0].0, notes[1].id);
+
+        // Updated data is indexed
+        let request = UpdateNotesRequest {
+            selector: NotesSelector::Ids(vec![notes[2].id]),
+            data: Some("Something to see here".to_string()),
+            parser_id: None,
+            keywords: None,
+            tags_to_add: Some(vec!["plants".to_string()]),
+            tags_to_remove: None,
+            custom_data: None,
+        };
+        update_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap();
+        assert!(search("fts=nothing").await.is_empty());
+        assert_eq!(search("fts=something").await[0].0, notes[2].id);
+        assert_eq!(search("fts=plants").await[0].0, notes[2].id);
+
+        // Renamed tags are indexed
+        let tag = get_tag_by_name(&pool, "animals").await.unwrap();
+        let request = UpdateTagRequest {
+            parent_id: None,
+            name: Some("mammals".to_string()),
+            description: None,
+            query: None,
+            auto_delete: None,
+        };
+        update_tag(&pool, request, tag.id).await.unwrap();
+        assert!(search("fts=animals").await.is_empty());
+        assert_eq!(search("fts=mammals").await[0].0, notes[1].id);
+
+        // Deleted notes are removed from the index
+        delete_note(&pool, notes[1].id, &get_all_parsers())
+            .await
+            .unwrap();
+        assert_eq!(
+            search("fts=fox")
+                .await
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            vec![notes[0].id]
+        );
+    }
 
     #[sqlx::test]
     async fn test_create_note_filtered_tag_error(pool: SqlitePool) -> () {
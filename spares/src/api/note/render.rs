@@ -5,7 +5,7 @@ use crate::{
     helpers::parse_list,
     model::{NoteId, NoteLink},
     parsers::{
-        Parseable, find_parser,
+        ParserConstructor, find_parser,
         generate_files::{
             GenerateNoteFilesRequest, GenerateNoteFilesRequests, create_note_files_bulk,
         },
@@ -36,7 +36,7 @@ struct RenderNoteData {
 async fn match_keyword_to_linked_note(
     db: &SqlitePool,
     notes_data_grouped: HashMap<&str, Vec<&RenderNoteData>>,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<Vec<NoteLink>, Error> {
     // Get all keywords
     let keywords = get_keywords(db).await?;
@@ -100,7 +100,7 @@ async fn match_keyword_to_linked_note(
 pub async fn render_notes(
     db: &SqlitePool,
     body: RenderNotesRequest,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<(), Error> {
     let RenderNotesRequest {
         generate_files_note_ids,
@@ -6,7 +6,9 @@ use crate::{
     model::NoteId,
     schema::{
         card::CardResponse,
-        note::{SearchKeywordRequest, SearchNotesRequest, SearchNotesResponse},
+        note::{
+            ExplainSearchResponse, SearchKeywordRequest, SearchNotesRequest, SearchNotesResponse,
+        },
     },
     search::evaluator::Evaluator,
 };
@@ -48,10 +50,11 @@ pub async fn search_notes(
             let notes = evaluator.get_notes(db).await?;
             let mut note_responses = Vec::new();
             let config = read_internal_config()?;
-            for (note, parser_name) in notes {
+            for (note, parser_name, snippet) in notes {
                 note_responses.push((
                     enrich_note(db, &note, config.linked_notes_generated).await?,
                     parser_name,
+                    snippet,
                 ));
             }
             Ok(SearchNotesResponse::Notes(note_responses))
@@ -59,6 +62,15 @@ pub async fn search_notes(
     }
 }
 
+/// Explains how a search query is run, without returning its results.
+pub async fn explain_search(
+    db: &SqlitePool,
+    body: SearchNotesRequest,
+) -> Result<ExplainSearchResponse, Error> {
+    let SearchNotesRequest { query, output_type } = body;
+    Evaluator::new(&query).explain(db, output_type).await
+}
+
 pub async fn search_keyword(
     db: &SqlitePool,
     body: SearchKeywordRequest,
@@ -9,7 +9,8 @@ use crate::{
     helpers::parse_list,
     model::{Card, CardId, Note, NoteId, SpecialState, TagId},
     parsers::{
-        CardData, MatchCardsResult, Parseable, add_order_to_note_data, find_parser,
+        CardData, MatchCardsResult, Parseable, ParserConstructor, add_order_to_note_data,
+        find_parser,
         generate_files::{
             GenerateNoteFilesRequest, GenerateNoteFilesRequests, create_note_files_bulk,
         },
@@ -125,7 +126,7 @@ async fn update_cards(
 }
 
 #[allow(clippy::too_many_lines)]
-async fn update_tags(
+pub(crate) async fn update_tags(
     db: &SqlitePool,
     tags_to_remove: Option<&Vec<String>>,
     tags_to_add: Option<&Vec<String>>,
@@ -288,7 +289,7 @@ fn get_parser_and_cards(
     parser_rows: &[(i64, String)],
     parser_id: i64,
     note_data: &str,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<(Box<dyn Parseable>, Vec<CardData>), Error> {
     let (_, parser_name) =
         parser_rows
@@ -307,7 +308,7 @@ pub async fn update_notes(
     db: &SqlitePool,
     body: UpdateNotesRequest,
     at: DateTime<Utc>,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<Vec<NoteResponse>, Error> {
     let mut note_responses = Vec::new();
     // Destructuring is used so if the struct is ever updated, the compiler will warn us to make the appropriate changes here.
@@ -325,7 +326,7 @@ pub async fn update_notes(
     let note_ids = match selector {
         NotesSelector::Ids(vec) => vec,
         NotesSelector::Query(query) => {
-            let evaluator = Evaluator::new(&query);
+            let evaluator = Evaluator::new(&query).with_time(at);
             evaluator.get_note_ids(db).await?
         }
     };
@@ -468,7 +469,7 @@ pub async fn update_notes(
         let mut delete_card_tag_entries = Vec::new();
         for (tag_id, query) in existing_filtered_tags {
             // Reexecute query to see if this card matches
-            let evaluator = Evaluator::new(query.as_str());
+            let evaluator = Evaluator::new(query.as_str()).with_time(at);
             let search_card_ids = evaluator.get_card_ids(db).await?;
             let (card_ids_to_add_tag, card_ids_to_remove_tag): (Vec<_>, Vec<_>) = created_card_ids
                 .iter()
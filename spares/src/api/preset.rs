@@ -0,0 +1,193 @@
+use crate::{
+    Error,
+    config::SparesExternalConfig,
+    model::{Card, NoteId, NoteTag, Tag, TagId},
+};
+use sqlx::sqlite::SqlitePool;
+use std::collections::{HashMap, HashSet};
+
+/// Returns the preset of each tag, which is the preset attached to the tag or to its closest ancestor.
+fn get_tag_presets<'a>(tags: &[Tag], config: &'a SparesExternalConfig) -> HashMap<TagId, &'a str> {
+    let tag_name_to_preset = config
+        .presets
+        .iter()
+        .flat_map(|(preset_name, preset)| {
+            preset
+                .tags
+                .iter()
+                .map(move |tag_name| (tag_name.as_str(), preset_name.as_str()))
+        })
+        .collect::<HashMap<_, _>>();
+    let tags_by_id = tags
+        .iter()
+        .map(|tag| (tag.id, tag))
+        .collect::<HashMap<_, _>>();
+    let mut tag_presets = HashMap::new();
+    for tag in tags {
+        let mut current_tag = Some(tag);
+        // Guards against cycles in the tag tree.
+        let mut visited = HashSet::new();
+        while let Some(ancestor) = current_tag {
+            if !visited.insert(ancestor.id) {
+                break;
+            }
+            if let Some(preset_name) = tag_name_to_preset.get(ancestor.name.as_str()) {
+                tag_presets.insert(tag.id, *preset_name);
+                break;
+            }
+            current_tag = ancestor
+                .parent_id
+                .and_then(|parent_id| tags_by_id.get(&parent_id).copied());
+        }
+    }
+    tag_presets
+}
+
+/// Returns the name of the preset used by each note that has one. Notes that have tags with different presets use the preset whose name comes first alphabetically.
+pub async fn get_note_presets(
+    db: &SqlitePool,
+    config: &SparesExternalConfig,
+) -> Result<HashMap<NoteId, String>, Error> {
+    if config.presets.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let tags: Vec<Tag> = sqlx::query_as(r"SELECT * FROM tag")
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let tag_presets = get_tag_presets(&tags, config);
+    let note_tags: Vec<NoteTag> = sqlx::query_as(r"SELECT note_id, tag_id FROM note_tag")
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let mut note_presets: HashMap<NoteId, &str> = HashMap::new();
+    for NoteTag { note_id, tag_id } in note_tags {
+        if let Some(preset_name) = tag_presets.get(&tag_id) {
+            note_presets
+                .entry(note_id)
+                .and_modify(|current| *current = (*current).min(*preset_name))
+                .or_insert(preset_name);
+        }
+    }
+    Ok(note_presets
+        .into_iter()
+        .map(|(note_id, preset_name)| (note_id, preset_name.to_string()))
+        .collect())
+}
+
+/// Returns the name of the preset used by a note.
+pub async fn get_note_preset(
+    db: &SqlitePool,
+    config: &SparesExternalConfig,
+    note_id: NoteId,
+) -> Result<Option<String>, Error> {
+    if config.presets.is_empty() {
+        return Ok(None);
+    }
+    let tags: Vec<Tag> = sqlx::query_as(r"SELECT * FROM tag")
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let tag_presets = get_tag_presets(&tags, config);
+    let note_tag_ids: Vec<(TagId,)> =
+        sqlx::query_as(r"SELECT tag_id FROM note_tag WHERE note_id = ?")
+            .bind(note_id)
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(note_tag_ids
+        .into_iter()
+        .filter_map(|(tag_id,)| tag_presets.get(&tag_id))
+        .min()
+        .map(|preset_name| (*preset_name).to_string()))
+}
+
+/// Overrides the card's desired retention with the preset's, if it has one.
+pub fn apply_preset_to_card(
+    config: &SparesExternalConfig,
+    preset_name: Option<&str>,
+    card: Card,
+) -> Card {
+    let desired_retention = preset_name
+        .and_then(|name| config.presets.get(name))
+        .and_then(|preset| preset.desired_retention);
+    match desired_retention {
+        Some(desired_retention) => Card {
+            desired_retention,
+            ..card
+        },
+        None => card,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PresetConfig;
+
+    fn tag(id: TagId, name: &str, parent_id: Option<TagId>) -> Tag {
+        Tag {
+            id,
+            parent_id,
+            name: name.to_string(),
+            description: String::new(),
+            query: None,
+            auto_delete: false,
+        }
+    }
+
+    #[test]
+    fn test_get_tag_presets() {
+        let mut config = SparesExternalConfig::default();
+        config.presets.insert(
+            "vocab".to_string(),
+            PresetConfig {
+                tags: vec!["languages".to_string()],
+                ..Default::default()
+            },
+        );
+        config.presets.insert(
+            "proofs".to_string(),
+            PresetConfig {
+                tags: vec!["math".to_string()],
+                ..Default::default()
+            },
+        );
+        let tags = vec![
+            tag(1, "languages", None),
+            tag(2, "spanish", Some(1)),
+            tag(3, "verbs", Some(2)),
+            tag(4, "math", Some(1)),
+            tag(5, "algebra", Some(4)),
+            tag(6, "other", None),
+        ];
+        let tag_presets = get_tag_presets(&tags, &config);
+        assert_eq!(tag_presets.get(&1), Some(&"vocab"));
+        assert_eq!(tag_presets.get(&3), Some(&"vocab"));
+        // The closest ancestor's preset is used.
+        assert_eq!(tag_presets.get(&5), Some(&"proofs"));
+        assert_eq!(tag_presets.get(&6), None);
+    }
+
+    #[test]
+    fn test_apply_preset() {
+        let mut config = SparesExternalConfig::default();
+        config.presets.insert(
+            "vocab".to_string(),
+            PresetConfig {
+                new_cards_daily_limit: Some(50),
+                desired_retention: Some(0.8),
+                ..Default::default()
+            },
+        );
+        let preset_config = config.apply_preset(Some("vocab"));
+        assert_eq!(preset_config.new_cards_daily_limit, 50);
+        assert_eq!(preset_config.maximum_interval, config.maximum_interval);
+        let card = apply_preset_to_card(&config, Some("vocab"), Card::default());
+        assert!((card.desired_retention - 0.8).abs() < f64::EPSILON);
+        assert_eq!(
+            config.apply_preset(None).new_cards_daily_limit,
+            config.new_cards_daily_limit
+        );
+    }
+}
@@ -0,0 +1,1632 @@
+use super::note::delete_empty_tags;
+use crate::{
+    Error, LibraryError, SchedulerErrorKind, TagErrorKind,
+    api::{
+        card::{delete_card_tags, update_card},
+        note::update_tags,
+        preset::{apply_preset_to_card, get_note_preset, get_note_presets},
+        saved_search::get_saved_search_queries,
+    },
+    config::{
+        ReviewOrder, SparesExternalConfig, read_external_config, read_internal_config,
+        write_internal_config,
+    },
+    helpers::get_start_end_local_date,
+    model::{
+        Card, CardId, NEW_CARD_STATE, NoteId, REVIEW_CARD_STATE, RatingId, ReviewLog, SpecialState,
+        StateId, Tag, TagId,
+    },
+    parsers::{
+        BackType, ParserConstructor, RenderOutputDirectoryType, find_parser,
+        generate_files::{CardSide, RenderOutputType},
+        get_output_raw_dir,
+    },
+    schedulers::{SrsScheduler, get_scheduler_from_string},
+    schema::card::{CardsSelector, SpecialStateUpdate, UpdateCardRequest},
+    schema::review::{
+        CardBackRenderedPath, GetReviewCardFilterRequest, GetReviewCardRequest,
+        GetReviewCardResponse, RatingSubmission, StudyAction, SubmitStudyActionRequest,
+        SubmitStudyActionResponse,
+    },
+    search::{evaluator::Evaluator, functions::acquire_search_connection},
+};
+use chrono::{DateTime, Days, Duration, Utc};
+use indoc::indoc;
+use itertools::{Either, Itertools};
+use order::{ReviewCard, choose_review_card, get_note_tag, is_ordered_in_memory, push_order_by};
+use serde_json::Value;
+use sqlx::{QueryBuilder, sqlite::SqlitePool};
+use undo::push_undo_entry;
+pub use undo::{UndoEntry, undo_study_action};
+
+mod order;
+mod undo;
+use std::collections::HashMap;
+
+async fn unbury_cards(db: &SqlitePool) -> Result<(), Error> {
+    let mut config = read_internal_config()?;
+    let now = Utc::now();
+    let unburied_limit =
+        config
+            .last_unburied
+            .checked_add_days(Days::new(1))
+            .ok_or(Error::Library(LibraryError::InvalidConfig(
+                "Failed to add days since `config.last_unburied` is past limit.".to_string(),
+            )))?;
+    if unburied_limit < now {
+        // Unbury
+        let _unbury_result = sqlx::query(
+            r"UPDATE card SET special_state = NULL, updated_at = ? WHERE special_state IN (?, ?)",
+        )
+        .bind(now.timestamp())
+        .bind(SpecialState::UserBuried)
+        .bind(SpecialState::SchedulerBuried)
+        .execute(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+
+        // Update config
+        config.last_unburied = now;
+        write_internal_config(&config)?;
+    }
+    Ok(())
+}
+
+// Note that `requested_date` is not in `ReviewOptions` since we don't want the user to be able to edit it. However, for testing purposes, we still want to be able to mimic calling this function on different days, so it is included as an argument.
+pub async fn get_review_card(
+    db: &SqlitePool,
+    body: GetReviewCardRequest,
+    requested_date: DateTime<Utc>,
+    all_parsers: &[ParserConstructor],
+) -> Result<Option<GetReviewCardResponse>, Error> {
+    let config = read_external_config()?;
+    get_review_card_with_config(db, body, requested_date, all_parsers, &config).await
+}
+
+#[allow(clippy::too_many_lines)]
+async fn get_review_card_with_config(
+    db: &SqlitePool,
+    body: GetReviewCardRequest,
+    requested_date: DateTime<Utc>,
+    all_parsers: &[ParserConstructor],
+    config: &SparesExternalConfig,
+) -> Result<Option<GetReviewCardResponse>, Error> {
+    let GetReviewCardRequest { filter } = body;
+
+    // Unbury cards, if needed
+    unbury_cards(db).await?;
+
+    // Get cards reviewed on `requested_date`
+    let (lower_limit, upper_limit) = get_start_end_local_date(&requested_date);
+    let cards_studied_on_requested_date: Vec<(i64, NoteId, StateId, i64)> = sqlx::query_as(
+        r"SELECT rl.card_id, c.note_id, rl.previous_state, rl.reviewed_at FROM review_log rl JOIN card c ON rl.card_id = c.id WHERE rl.reviewed_at >= ? AND rl.reviewed_at <= ?",
+    )
+    .bind(lower_limit.timestamp())
+    .bind(upper_limit.timestamp())
+    .fetch_all(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+    let card_due_limit = upper_limit;
+
+    // New cards of presets with their own limit are counted separately from the global limit.
+    let note_presets = get_note_presets(db, config).await?;
+    let get_limit_preset = |note_id: &NoteId| {
+        note_presets
+            .get(note_id)
+            .filter(|preset_name| config.presets[*preset_name].new_cards_daily_limit.is_some())
+            .map(String::as_str)
+    };
+    let mut new_cards_studied_on_requested_date: HashMap<Option<&str>, u32> = HashMap::new();
+    for (_, note_id, _, _) in cards_studied_on_requested_date
+        .iter()
+        .unique_by(|(card_id, _, _, _)| card_id)
+        .filter(|(_, _, state, _)| *state == NEW_CARD_STATE)
+    {
+        *new_cards_studied_on_requested_date
+            .entry(get_limit_preset(note_id))
+            .or_default() += 1;
+    }
+    let is_limit_reached = |preset_name: Option<&str>| {
+        new_cards_studied_on_requested_date
+            .get(&preset_name)
+            .copied()
+            .unwrap_or_default()
+            >= config.apply_preset(preset_name).new_cards_daily_limit
+    };
+    let (limit_reached_note_ids, limit_not_reached_note_ids): (Vec<NoteId>, Vec<NoteId>) =
+        note_presets
+            .keys()
+            .filter_map(|note_id| get_limit_preset(note_id).map(|preset| (note_id, preset)))
+            .partition_map(|(note_id, preset_name)| {
+                if is_limit_reached(Some(preset_name)) {
+                    Either::Left(note_id)
+                } else {
+                    Either::Right(note_id)
+                }
+            });
+    // Only reviews of cards in review count towards the limit, so cards that are being learned or relearned can always be finished.
+    let reviews_studied_on_requested_date = cards_studied_on_requested_date
+        .iter()
+        .filter(|(_, _, state, _)| *state == REVIEW_CARD_STATE)
+        .unique_by(|(card_id, _, _, _)| card_id)
+        .count() as u32;
+    let study_order = &config.study_order;
+    let last_reviewed_at_column = if study_order.review_order == ReviewOrder::Retrievability {
+        "(SELECT MAX(rl.reviewed_at) FROM review_log rl WHERE rl.card_id = c.id)"
+    } else {
+        "NULL"
+    };
+    let mut query_builder = QueryBuilder::new(format!(
+        indoc! {
+        "WITH candidate AS (SELECT
+            n.id as note_id,
+            p.name as parser_name,
+            c.\"order\" as card_order,
+            c.back_type as card_back_type,
+            c.id as card_id,
+            c.state as state,
+            c.due as due,
+            c.stability as stability,
+            c.difficulty as difficulty,
+            n.created_at as note_created_at,
+            {} as last_reviewed_at
+        FROM card c
+        JOIN note n ON c.note_id = n.id
+        JOIN parser p ON n.parser_id = p.id
+        WHERE c.special_state IS NULL"
+        },
+        last_reviewed_at_column
+    ));
+    if let Some(GetReviewCardFilterRequest::FilteredTag { tag_id }) = filter {
+        // Verify tag has a query
+        let tag_query_opt: Option<(Option<String>,)> =
+            sqlx::query_as(r"SELECT query FROM tag WHERE id = ?")
+                .bind(tag_id)
+                .fetch_optional(db)
+                .await
+                .map_err(|e| Error::Sqlx { source: e })?;
+        if let Some((tag_query,)) = tag_query_opt {
+            if tag_query.is_none() {
+                return Err(Error::Library(LibraryError::Tag(
+                    TagErrorKind::InvalidInput(
+                        "Cannot study a tag that does not have a query.".to_string(),
+                    ),
+                )));
+            }
+        } else {
+            return Ok(None);
+            // return Err(Error::Library(LibraryError::Tag(
+            //     TagErrorKind::InvalidInput("Tag does not exist.".to_string()),
+            // )));
+        }
+        // Get all review cards that match the tag, regardless of whether they are due today
+        query_builder
+            .push(" AND c.id IN (SELECT ct.card_id FROM card_tag ct WHERE ct.tag_id = ")
+            .push_bind(tag_id)
+            .push(")");
+    } else {
+        query_builder
+            .push(" AND c.due <= ")
+            .push_bind(card_due_limit.timestamp());
+        let (note_id_condition, note_ids) = if is_limit_reached(None) {
+            (" OR c.note_id IN ", limit_not_reached_note_ids)
+        } else {
+            (" OR c.note_id NOT IN ", limit_reached_note_ids)
+        };
+        // The note ids are bound as a single json array, since there can be more notes than SQLite's limit on the number of bound parameters.
+        query_builder
+            .push(" AND (c.state != ")
+            .push_bind(NEW_CARD_STATE)
+            .push(note_id_condition)
+            .push("(SELECT value FROM json_each(")
+            .push_bind(Value::from(note_ids).to_string())
+            .push(")))");
+        if config
+            .reviews_daily_limit
+            .is_some_and(|reviews_daily_limit| {
+                reviews_studied_on_requested_date >= reviews_daily_limit
+            })
+        {
+            query_builder
+                .push(" AND c.state != ")
+                .push_bind(REVIEW_CARD_STATE);
+        }
+        if let Some(GetReviewCardFilterRequest::Query(ref query)) = filter {
+            query_builder.push(" AND n.id IN (");
+            Evaluator::new(query)
+                .with_time(requested_date)
+                .with_saved_searches(get_saved_search_queries(db).await?)
+                .push_note_ids_query(&mut query_builder)?;
+            query_builder.push(")");
+        }
+    }
+    // Order cards. Studied and new cards are ordered separately, so only the first of each is fetched, unless the order depends on the other cards.
+    query_builder
+        .push(") SELECT * FROM (SELECT * FROM candidate WHERE state != ")
+        .push_bind(NEW_CARD_STATE);
+    push_order_by(
+        &mut query_builder,
+        study_order,
+        false,
+        requested_date.timestamp(),
+    );
+    query_builder
+        .push(") UNION ALL SELECT * FROM (SELECT * FROM candidate WHERE state = ")
+        .push_bind(NEW_CARD_STATE);
+    push_order_by(
+        &mut query_builder,
+        study_order,
+        true,
+        requested_date.timestamp(),
+    );
+    query_builder.push(")");
+    let query = query_builder.build_query_as();
+    // The filter's query may use functions that are only registered on search connections
+    let review_cards: Vec<ReviewCard> = query
+        .fetch_all(&mut *acquire_search_connection(db).await?)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+
+    let note_tags = if is_ordered_in_memory(study_order, false)
+        || is_ordered_in_memory(study_order, true)
+    {
+        let note_ids = review_cards
+            .iter()
+            .map(|card| card.note_id)
+            .chain(
+                cards_studied_on_requested_date
+                    .iter()
+                    .map(|(_, note_id, _, _)| *note_id),
+            )
+            .unique()
+            .collect::<Vec<_>>();
+        let note_tag_names: Vec<(NoteId, String)> = sqlx::query_as(
+            r"SELECT nt.note_id, t.name FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE nt.note_id IN (SELECT value FROM json_each(?))",
+        )
+        .bind(Value::from(note_ids).to_string())
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+        note_tag_names.into_iter().into_group_map()
+    } else {
+        HashMap::new()
+    };
+    let mut tags_last_studied_at: HashMap<&str, i64> = HashMap::new();
+    for (_, note_id, _, reviewed_at) in &cards_studied_on_requested_date {
+        let last_studied_at = tags_last_studied_at
+            .entry(get_note_tag(&note_tags, *note_id))
+            .or_insert(*reviewed_at);
+        *last_studied_at = (*last_studied_at).max(*reviewed_at);
+    }
+    let review_card_opt = choose_review_card(
+        review_cards,
+        study_order,
+        &note_tags,
+        &tags_last_studied_at,
+        requested_date.timestamp(),
+        &mut rand::thread_rng(),
+    );
+    if let Some(ReviewCard {
+        note_id,
+        parser_name,
+        card_order,
+        card_back_type,
+        card_id,
+        ..
+    }) = review_card_opt
+    {
+        let parser = find_parser(parser_name.as_str(), all_parsers)?;
+        // Card front rendered path
+        let mut card_front_rendered_path =
+            parser.get_output_rendered_dir(RenderOutputDirectoryType::Card);
+        card_front_rendered_path.push(parser.get_output_filename(
+            RenderOutputType::Card(card_order as usize, CardSide::Front),
+            note_id,
+        ));
+
+        // Note raw path
+        let mut note_raw_path =
+            get_output_raw_dir(parser.get_parser_name(), RenderOutputType::Note, None);
+        note_raw_path.push(parser.get_output_filename(RenderOutputType::Note, note_id));
+        note_raw_path.set_extension(parser.file_extension());
+
+        let card_back_rendered_path = match card_back_type {
+            BackType::FullNote => {
+                // Note rendered path
+                let mut note_rendered_path =
+                    parser.get_output_rendered_dir(RenderOutputDirectoryType::Note);
+                note_rendered_path
+                    .push(parser.get_output_filename(RenderOutputType::Note, note_id));
+                CardBackRenderedPath::Note(note_rendered_path)
+            }
+            BackType::OnlyAnswered => {
+                // Card back rendered path
+                let mut card_back_rendered_path =
+                    parser.get_output_rendered_dir(RenderOutputDirectoryType::Card);
+                card_back_rendered_path.push(parser.get_output_filename(
+                    RenderOutputType::Card(card_order as usize, CardSide::Back),
+                    note_id,
+                ));
+                CardBackRenderedPath::CardBack(card_back_rendered_path)
+            }
+        };
+        let review_card_response = GetReviewCardResponse {
+            note_id,
+            card_order,
+            card_id,
+            card_front_rendered_path,
+            card_back_rendered_path,
+            note_raw_path,
+            parser_name,
+        };
+        return Ok(Some(review_card_response));
+    }
+    Ok(None)
+}
+
+pub async fn update_filtered_tag_scheduler_data(
+    db: &SqlitePool,
+    scheduler: &dyn SrsScheduler,
+    filtered_tag: Tag,
+    updated_card: &mut Card,
+    rating: RatingId,
+    duration: Duration,
+    reviewed_at: DateTime<Utc>,
+) -> Result<(), Error> {
+    let scheduler_name = scheduler.get_scheduler_name();
+    let tag_id_str = filtered_tag.id.to_string();
+    assert!(matches!(updated_card.custom_data, Value::Object(_)));
+    let custom_data = updated_card.custom_data.as_object().unwrap();
+    let filtered_tag_scheduler_data = custom_data
+        .get(&tag_id_str)
+        .and_then(|x| x.get(scheduler_name));
+    let new_filtered_tag_scheduler_data_opt = scheduler.filtered_tag_schedule(
+        filtered_tag_scheduler_data,
+        updated_card,
+        rating,
+        reviewed_at,
+        duration,
+    )?;
+    let custom_data = updated_card.custom_data.as_object_mut().unwrap();
+    if let Some(new_filtered_tag_scheduler_data) = new_filtered_tag_scheduler_data_opt {
+        custom_data
+            .entry(tag_id_str)
+            .and_modify(|v| {
+                if let Some(tag_object) = v.as_object_mut() {
+                    tag_object.insert(
+                        scheduler_name.to_string(),
+                        new_filtered_tag_scheduler_data.clone(),
+                    );
+                }
+            })
+            .or_insert_with(|| {
+                Value::Object(serde_json::Map::from_iter([(
+                    scheduler_name.to_string(),
+                    new_filtered_tag_scheduler_data,
+                )]))
+            });
+    } else {
+        // Delete scheduler data for the filtered tag
+        if let Some(tag_object) = custom_data
+            .get_mut(&tag_id_str)
+            .and_then(|v| v.as_object_mut())
+        {
+            tag_object.remove(scheduler_name);
+            if tag_object.is_empty() {
+                custom_data.remove(&tag_id_str);
+            }
+        }
+
+        // Remove filtered tag from card
+        delete_card_tags(db, &[(updated_card.id, filtered_tag.id)]).await?;
+
+        // Delete filtered tag if there are no more notes
+        if filtered_tag.auto_delete {
+            delete_empty_tags(db, &[filtered_tag.id]).await?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_lines)]
+pub async fn rate_card(
+    db: &SqlitePool,
+    scheduler: &dyn SrsScheduler,
+    RatingSubmission {
+        card_id,
+        rating,
+        duration,
+        tag_id,
+    }: RatingSubmission,
+    reviewed_at: DateTime<Utc>,
+) -> Result<UndoEntry, Error> {
+    // Validate input
+    let filtered_tag_opt = if let Some(tag_id) = tag_id {
+        let tag: Tag = sqlx::query_as(r"SELECT * FROM tag WHERE id = ?")
+            .bind(tag_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+        if tag.query.is_none() {
+            return Err(Error::Library(LibraryError::Tag(
+                TagErrorKind::InvalidInput("Supplied tag id is not a filtered tag.".to_string()),
+            )));
+        }
+        Some(tag)
+    } else {
+        None
+    };
+
+    let card: Card = sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+        .bind(card_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+
+    let mut undo_entry = UndoEntry {
+        cards: vec![card.clone()],
+        ..Default::default()
+    };
+
+    // Apply the card's preset
+    let config = read_external_config()?;
+    let preset_name = get_note_preset(db, &config, card.note_id).await?;
+    let config = config.apply_preset(preset_name.as_deref());
+    let card = apply_preset_to_card(&config, preset_name.as_deref(), card);
+
+    // Get review logs for this card
+    let mut review_logs: Vec<ReviewLog> =
+        sqlx::query_as(r"SELECT * FROM review_log WHERE card_id = ? ORDER BY reviewed_at ASC")
+            .bind(card_id)
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+
+    // Schedule card
+    let latest_review_log = review_logs.last().cloned();
+    let (mut updated_card, new_review_log) =
+        scheduler.schedule(&card, latest_review_log, rating, reviewed_at, duration)?;
+    // Validate scheduler's output
+    assert!(matches!(updated_card.custom_data, Value::Object(_)));
+    assert!(matches!(new_review_log.custom_data, Value::Object(_)));
+
+    // The card becomes a leech when this review takes its lapses over the threshold
+    review_logs.push(new_review_log.clone());
+    let lapses = review_logs
+        .iter()
+        .filter(|review_log| scheduler.is_lapse(review_log))
+        .count() as u32;
+    let is_new_leech =
+        scheduler.is_lapse(&new_review_log) && lapses == config.leech.lapses_threshold + 1;
+
+    // Smart schedule
+    let siblings: Vec<Card> = sqlx::query_as(r"SELECT * FROM card WHERE note_id = ? AND id != ?")
+        .bind(card.note_id)
+        .bind(card.id)
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    // Siblings may be buried below
+    undo_entry.cards.extend(siblings.iter().cloned());
+    let mut siblings_with_review_logs = vec![];
+    // Get latest reviews for this card
+    for card in siblings {
+        let review_logs: Vec<ReviewLog> =
+            sqlx::query_as(r"SELECT * FROM review_log WHERE card_id = ? ORDER BY reviewed_at ASC")
+                .bind(card.id)
+                .fetch_all(db)
+                .await
+                .map_err(|e| Error::Sqlx { source: e })?;
+        siblings_with_review_logs.push((card, review_logs));
+    }
+    updated_card.due = scheduler
+        .smart_schedule(
+            &config,
+            &(updated_card.clone(), review_logs),
+            &siblings_with_review_logs,
+            reviewed_at,
+        )
+        .await?;
+
+    // Siblings are not buried when studying a filtered tag, since all of its cards should be studied
+    let is_filtered_study = filtered_tag_opt.is_some();
+
+    // Update filtered tag scheduler data
+    if let Some(filtered_tag) = filtered_tag_opt {
+        let filtered_tag_id = filtered_tag.id;
+        update_filtered_tag_scheduler_data(
+            db,
+            scheduler,
+            filtered_tag.clone(),
+            &mut updated_card,
+            rating,
+            duration,
+            reviewed_at,
+        )
+        .await?;
+        // Record if the card was removed from the filtered tag, or if the tag was deleted
+        let card_tag_opt: Option<(TagId,)> =
+            sqlx::query_as(r"SELECT tag_id FROM card_tag WHERE card_id = ? AND tag_id = ?")
+                .bind(card.id)
+                .bind(filtered_tag_id)
+                .fetch_optional(db)
+                .await
+                .map_err(|e| Error::Sqlx { source: e })?;
+        if card_tag_opt.is_none() {
+            undo_entry
+                .removed_card_tags
+                .push((card.id, filtered_tag_id));
+        }
+        let tag_opt: Option<(TagId,)> = sqlx::query_as(r"SELECT id FROM tag WHERE id = ?")
+            .bind(filtered_tag_id)
+            .fetch_optional(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+        if tag_opt.is_none() {
+            undo_entry.deleted_tags.push(filtered_tag);
+        }
+    }
+
+    // Add entry to review_log
+    let insert_result =
+        sqlx::query(r"INSERT INTO review_log (card_id, reviewed_at, rating, scheduler_name, scheduled_time, duration, previous_state, custom_data) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(new_review_log.card_id)
+            .bind(new_review_log.reviewed_at.timestamp())
+            .bind(new_review_log.rating)
+            .bind(new_review_log.scheduler_name)
+            .bind(new_review_log.scheduled_time)
+            .bind(new_review_log.duration)
+            .bind(new_review_log.previous_state)
+            .bind(&new_review_log.custom_data)
+            .execute(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    undo_entry
+        .review_log_ids
+        .push(insert_result.last_insert_rowid());
+
+    // Update card with all new properties from updated_card
+    let _update_card_result = sqlx::query(
+        r"UPDATE card SET due = ?, stability = ?, difficulty = ?, state = ?, updated_at = ?, custom_data = ? WHERE id = ?",
+    )
+    .bind(updated_card.due.timestamp())
+    .bind(updated_card.stability)
+    .bind(updated_card.difficulty)
+    .bind(updated_card.state)
+    .bind(updated_card.updated_at.timestamp())
+    .bind(updated_card.custom_data)
+    .bind(card.id)
+    .execute(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+
+    if is_new_leech {
+        apply_leech_actions(db, &config, &card, lapses, reviewed_at, &mut undo_entry).await?;
+    }
+
+    if !is_filtered_study {
+        bury_siblings(db, &config, &card, reviewed_at).await?;
+    }
+
+    Ok(undo_entry)
+}
+
+/// Records that a card became a leech, then tags its note and suspends it if configured to.
+async fn apply_leech_actions(
+    db: &SqlitePool,
+    config: &SparesExternalConfig,
+    card: &Card,
+    lapses: u32,
+    at: DateTime<Utc>,
+    undo_entry: &mut UndoEntry,
+) -> Result<(), Error> {
+    let insert_result =
+        sqlx::query(r"INSERT INTO leech_event (card_id, created_at, lapses) VALUES (?, ?, ?)")
+            .bind(card.id)
+            .bind(at.timestamp())
+            .bind(lapses)
+            .execute(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    undo_entry
+        .leech_event_ids
+        .push(insert_result.last_insert_rowid());
+    if config.leech.auto_suspend {
+        let _suspend_result =
+            sqlx::query(r"UPDATE card SET special_state = ?, updated_at = ? WHERE id = ?")
+                .bind(SpecialState::Suspended)
+                .bind(at.timestamp())
+                .bind(card.id)
+                .execute(db)
+                .await
+                .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    if config.leech.auto_tag {
+        let tag_undo_entry = tag_note(db, card.note_id, config.leech.tag_name.clone()).await?;
+        undo_entry
+            .added_note_tags
+            .extend(tag_undo_entry.added_note_tags);
+        undo_entry
+            .created_tag_ids
+            .extend(tag_undo_entry.created_tag_ids);
+    }
+    Ok(())
+}
+
+/// Buries the siblings of a card that are due today until the next day, so that cards of the same note are not studied together.
+async fn bury_siblings(
+    db: &SqlitePool,
+    config: &SparesExternalConfig,
+    card: &Card,
+    reviewed_at: DateTime<Utc>,
+) -> Result<(), Error> {
+    let state_condition = match (config.bury_new_siblings, config.bury_review_siblings) {
+        (false, false) => return Ok(()),
+        (true, false) => "AND state = ?",
+        (false, true) => "AND state != ?",
+        (true, true) => "",
+    };
+    let (_, card_due_limit) = get_start_end_local_date(&reviewed_at);
+    let query_str = format!(
+        r"UPDATE card SET special_state = ?, updated_at = ?
+        WHERE note_id = ?
+          AND id != ?
+          AND special_state IS NULL
+          AND due <= ?
+          {}",
+        state_condition
+    );
+    let mut query = sqlx::query(&query_str)
+        .bind(SpecialState::SchedulerBuried)
+        .bind(reviewed_at.timestamp())
+        .bind(card.note_id)
+        .bind(card.id)
+        .bind(card_due_limit.timestamp());
+    if !state_condition.is_empty() {
+        query = query.bind(NEW_CARD_STATE);
+    }
+    let _bury_result = query
+        .execute(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(())
+}
+
+pub async fn bury_card(
+    db: &SqlitePool,
+    scheduler: &dyn SrsScheduler,
+    card_id: CardId,
+    at: DateTime<Utc>,
+) -> Result<UndoEntry, Error> {
+    let card: Card = sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+        .bind(card_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+
+    if let Some(special_state) = card.special_state {
+        match special_state {
+            SpecialState::Suspended => {
+                return Err(Error::Library(LibraryError::Scheduler(
+                    SchedulerErrorKind::Suspended,
+                )));
+            }
+            SpecialState::UserBuried | SpecialState::SchedulerBuried => {
+                return Err(Error::Library(LibraryError::Scheduler(
+                    SchedulerErrorKind::AlreadyBuried,
+                )));
+            }
+        }
+    }
+
+    let undo_entry = UndoEntry {
+        cards: vec![card.clone()],
+        ..Default::default()
+    };
+
+    let Card {
+        id: _,
+        note_id: _,
+        order: _,
+        back_type: _,
+        created_at: _,
+        updated_at: _,
+        due,
+        stability,
+        difficulty,
+        desired_retention: _,
+        special_state,
+        state,
+        custom_data: _,
+    } = scheduler.bury(&card)?;
+
+    // Update card with all new properties from updated_card
+    let _update_card_result = sqlx::query(
+        r"UPDATE card SET due = ?, stability = ?, difficulty = ?, special_state = ?, state = ?, updated_at = ? WHERE id = ?",
+    )
+    .bind(due.timestamp())
+    .bind(stability)
+    .bind(difficulty)
+    .bind(special_state)
+    .bind(state)
+    .bind(at.timestamp())
+    .bind(card_id)
+    .execute(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(undo_entry)
+}
+
+async fn suspend_cards(
+    db: &SqlitePool,
+    card_ids: Vec<CardId>,
+    at: DateTime<Utc>,
+) -> Result<UndoEntry, Error> {
+    let mut undo_entry = UndoEntry::default();
+    for card_id in &card_ids {
+        let card: Card = sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+            .bind(card_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+        undo_entry.cards.push(card);
+    }
+    let request = UpdateCardRequest {
+        selector: CardsSelector::Ids(card_ids),
+        desired_retention: None,
+        special_state: Some(Some(SpecialStateUpdate::Suspended)),
+    };
+    let _card_responses = update_card(db, request, at).await?;
+    Ok(undo_entry)
+}
+
+async fn tag_note(db: &SqlitePool, note_id: NoteId, tag_name: String) -> Result<UndoEntry, Error> {
+    let mut undo_entry = UndoEntry::default();
+    let tag_opt: Option<(TagId,)> = sqlx::query_as(r"SELECT id FROM tag WHERE name = ?")
+        .bind(&tag_name)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    if let Some((tag_id,)) = tag_opt {
+        let note_tag_opt: Option<(TagId,)> =
+            sqlx::query_as(r"SELECT tag_id FROM note_tag WHERE note_id = ? AND tag_id = ?")
+                .bind(note_id)
+                .bind(tag_id)
+                .fetch_optional(db)
+                .await
+                .map_err(|e| Error::Sqlx { source: e })?;
+        if note_tag_opt.is_some() {
+            // The note already has this tag
+            return Ok(undo_entry);
+        }
+    }
+    update_tags(db, None, Some(&vec![tag_name.clone()]), note_id).await?;
+    let (tag_id,): (TagId,) = sqlx::query_as(r"SELECT id FROM tag WHERE name = ?")
+        .bind(&tag_name)
+        .fetch_one(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    undo_entry.added_note_tags.push((note_id, tag_id));
+    if tag_opt.is_none() {
+        undo_entry.created_tag_ids.push(tag_id);
+    }
+    Ok(undo_entry)
+}
+
+// Note that `reviewed_at` is not present in the request body since we don't want the user to be able to edit it. However, for testing purposes, we still want to be able to mimic calling this function on different days, so it is included as an argument.
+pub async fn submit_study_action(
+    db: &SqlitePool,
+    body: SubmitStudyActionRequest,
+    at: DateTime<Utc>,
+) -> Result<SubmitStudyActionResponse, Error> {
+    let SubmitStudyActionRequest {
+        scheduler_name,
+        action,
+    } = body;
+
+    let scheduler = get_scheduler_from_string(scheduler_name.as_str())?;
+
+    let config = read_external_config()?;
+    let mut message = None;
+    match action {
+        StudyAction::Rate(rating_submission) => {
+            let undo_entry = rate_card(db, scheduler.as_ref(), rating_submission, at).await?;
+            push_undo_entry(db, &undo_entry, at).await?;
+        }
+        StudyAction::Bury { card_id } => {
+            let undo_entry = bury_card(db, scheduler.as_ref(), card_id, at).await?;
+            push_undo_entry(db, &undo_entry, at).await?;
+        }
+        StudyAction::Suspend { card_ids } => {
+            let undo_entry = suspend_cards(db, card_ids, at).await?;
+            push_undo_entry(db, &undo_entry, at).await?;
+        }
+        StudyAction::TagNote { note_id, tag_name } => {
+            let undo_entry = tag_note(db, note_id, tag_name).await?;
+            push_undo_entry(db, &undo_entry, at).await?;
+        }
+        StudyAction::Advance { count } => {
+            message = Some(scheduler.advance(db, &config, count, at).await?);
+        }
+        StudyAction::Postpone { count } => {
+            message = Some(scheduler.postpone(db, &config, count, at).await?);
+        }
+        StudyAction::Reschedule => {
+            let cards: Vec<Card> =
+                sqlx::query_as(r"SELECT * FROM card WHERE special_state IS NULL")
+                    .fetch_all(db)
+                    .await
+                    .map_err(|e| Error::Sqlx { source: e })?;
+            // Get all review logs for cards
+            let all_review_logs: Vec<ReviewLog> =
+                sqlx::query_as(r"SELECT * FROM review_log ORDER BY reviewed_at ASC")
+                    .fetch_all(db)
+                    .await
+                    .map_err(|e| Error::Sqlx { source: e })?;
+            let grouped_review_logs = all_review_logs
+                .into_iter()
+                .map(|rl| (rl.card_id, rl))
+                .into_group_map();
+            // New cards have no review history to replay. Cards are grouped by their preset, which is shared by siblings.
+            let note_presets = get_note_presets(db, &config).await?;
+            let preset_cards_with_review_logs = cards
+                .into_iter()
+                .filter_map(|card| {
+                    let preset_name = note_presets.get(&card.note_id).cloned();
+                    grouped_review_logs.get(&card.id).map(|review_logs| {
+                        let card = apply_preset_to_card(&config, preset_name.as_deref(), card);
+                        (preset_name, (card, review_logs.clone()))
+                    })
+                })
+                .into_group_map();
+            for (preset_name, cards_with_review_logs) in preset_cards_with_review_logs {
+                let preset_config = config.apply_preset(preset_name.as_deref());
+                scheduler
+                    .reschedule(db, &preset_config, cards_with_review_logs, at)
+                    .await?;
+            }
+        }
+        StudyAction::OptimizeParameters => {
+            message = Some(scheduler.optimize_parameters(db, at).await?);
+        }
+        StudyAction::Undo => {
+            undo_study_action(db).await?;
+        }
+    }
+    Ok(SubmitStudyActionResponse { message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{
+            card::{get_cards, get_leech_events},
+            note::{create_notes, tests::tests::create_note_helper},
+            parser::tests::create_parser_helper,
+            statistics::get_statistics,
+            tag::create_tag,
+        },
+        model::Card,
+        parsers::get_all_parsers,
+        schema::{
+            FilterOptions,
+            note::{CreateNoteRequest, CreateNotesRequest, NoteResponse},
+            review::StatisticsRequest,
+            tag::CreateTagRequest,
+        },
+    };
+    use serde_json::Map;
+
+    async fn create_note(pool: &sqlx::SqlitePool) -> (NoteResponse, Vec<Card>) {
+        // Create note
+        let created_notes = create_note_helper(pool).await;
+        let last_note = created_notes.last().unwrap();
+
+        // Get card_id for note
+        let cards_res: Result<Vec<Card>, sqlx::Error> =
+            sqlx::query_as(r"SELECT * FROM card WHERE note_id = ? ORDER BY due")
+                .bind(last_note.id)
+                .fetch_all(pool)
+                .await;
+        assert!(cards_res.is_ok());
+        let cards = cards_res.unwrap();
+        assert!(!cards.is_empty());
+
+        (last_note.clone(), cards)
+    }
+
+    async fn create_note_with_data(
+        pool: &sqlx::SqlitePool,
+        data: &str,
+        tags: Vec<String>,
+        at: DateTime<Utc>,
+    ) -> NoteId {
+        let parser = create_parser_helper(pool, "markdown").await;
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: vec![CreateNoteRequest {
+                data: data.to_string(),
+                keywords: vec![],
+                tags,
+                is_suspended: false,
+                custom_data: Map::new(),
+            }],
+        };
+        let create_notes_response = create_notes(pool, request, at, &get_all_parsers())
+            .await
+            .unwrap();
+        create_notes_response.notes[0].id
+    }
+
+    async fn get_note_cards(pool: &sqlx::SqlitePool, note_id: NoteId) -> Vec<Card> {
+        sqlx::query_as(r"SELECT * FROM card WHERE note_id = ? ORDER BY id")
+            .bind(note_id)
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_get_and_update_review(pool: sqlx::SqlitePool) -> () {
+        // Create note
+        let (_note, _cards) = create_note(&pool).await;
+        let now = Utc::now();
+
+        // Get review
+        let review_res = get_review_card(
+            &pool,
+            GetReviewCardRequest { filter: None },
+            now,
+            &get_all_parsers(),
+        )
+        .await;
+        assert!(review_res.is_ok());
+        let review_card_opt = review_res.unwrap();
+        assert!(review_card_opt.is_some());
+        let review_card = review_card_opt.unwrap();
+        // These assertions can't be made because all notes are created at the same time, so the cards are also created at the same time, so they are due at the same time. Thus, the order of cards being due is not guaranteed to be the same.
+        // assert_eq!(review_card.note_id, note_id);
+        // assert_eq!(review_card.card_id, card_id);
+        // assert_eq!(
+        //     review_card
+        //         .card_rendered_path
+        //         .file_stem()
+        //         .unwrap()
+        //         .to_str()
+        //         .unwrap(),
+        //     format!("{:0>4}-{:0>1}", note_id, 1)
+        // );
+        assert_eq!(review_card.parser_name, "markdown".to_string());
+
+        // Get old card
+        let old_card_res: Result<Card, sqlx::Error> =
+            sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+                .bind(review_card.card_id)
+                .fetch_one(&pool)
+                .await;
+        assert!(old_card_res.is_ok());
+        let old_card = old_card_res.unwrap();
+
+        // Get statistics
+        let request = StatisticsRequest {
+            scheduler_name: "fsrs".to_string(),
+            date: now,
+        };
+        let statistics_res = get_statistics(&pool, request).await;
+        assert!(statistics_res.is_ok());
+        let statistics_response = statistics_res.unwrap();
+        assert_eq!(
+            statistics_response.due_count_by_state.get(&NEW_CARD_STATE),
+            Some(&3)
+        );
+        assert_eq!(
+            statistics_response
+                .due_count_by_state
+                .iter()
+                .map(|(_, x)| x)
+                .sum::<u32>(),
+            3
+        );
+        assert_eq!(statistics_response.advance_safe_count, 0);
+        assert_eq!(statistics_response.postpone_safe_count, 0);
+
+        // Update review
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "fsrs".to_string(),
+            action: StudyAction::Rate(RatingSubmission {
+                card_id: review_card.card_id,
+                rating: 4,
+                duration: Duration::seconds(5),
+                tag_id: None,
+            }),
+        };
+        let submit_review_res = submit_study_action(&pool, request, now).await;
+        assert!(submit_review_res.is_ok());
+
+        // Check database and verify card is now due later
+        let new_card_res: Result<Card, sqlx::Error> =
+            sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+                .bind(review_card.card_id)
+                .fetch_one(&pool)
+                .await;
+        assert!(new_card_res.is_ok());
+        let new_card = new_card_res.unwrap();
+        assert!(new_card.due > old_card.due);
+    }
+
+    #[sqlx::test]
+    async fn test_reviews_daily_limit(pool: sqlx::SqlitePool) -> () {
+        create_note_helper(&pool).await;
+        let cards: Vec<Card> = sqlx::query_as(r"SELECT * FROM card ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let learning_card = &cards[0];
+        let review_card = cards
+            .iter()
+            .find(|card| card.note_id != learning_card.note_id)
+            .unwrap();
+        let now = Utc::now();
+        let rate = |card_id, rating, reviewed_at| {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: "fsrs".to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id,
+                    rating,
+                    duration: Duration::seconds(5),
+                    tag_id: None,
+                }),
+            };
+            submit_study_action(&pool, request, reviewed_at)
+        };
+        let get_review_card_id = |reviews_daily_limit| {
+            let config = SparesExternalConfig {
+                new_cards_daily_limit: 0,
+                reviews_daily_limit,
+                ..Default::default()
+            };
+            let pool = pool.clone();
+            async move {
+                get_review_card_with_config(
+                    &pool,
+                    GetReviewCardRequest { filter: None },
+                    now,
+                    &get_all_parsers(),
+                    &config,
+                )
+                .await
+                .unwrap()
+                .map(|review_card| review_card.card_id)
+            }
+        };
+
+        // Rating a card Easy a month ago makes it due in review, while failing a new card keeps it in the learning state
+        rate(review_card.id, 4, now - Duration::days(30))
+            .await
+            .unwrap();
+        rate(learning_card.id, 1, now).await.unwrap();
+
+        // Cards in learning are still studied once the limit is reached
+        assert_eq!(get_review_card_id(Some(0)).await, Some(learning_card.id));
+
+        // Reviews of cards in learning don't count towards the limit
+        rate(learning_card.id, 4, now).await.unwrap();
+        assert_eq!(get_review_card_id(Some(0)).await, None);
+        assert_eq!(get_review_card_id(Some(1)).await, Some(review_card.id));
+    }
+
+    #[sqlx::test]
+    async fn test_leitner_ignores_other_schedulers(pool: sqlx::SqlitePool) -> () {
+        create_note_helper(&pool).await;
+        let cards: Vec<Card> = sqlx::query_as(r"SELECT * FROM card ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let fsrs_card = &cards[0];
+        let fsrs_review_card = &cards[1];
+        let leitner_card = cards
+            .iter()
+            .find(|card| card.note_id != fsrs_card.note_id && card.id != fsrs_review_card.id)
+            .unwrap();
+        let now = Utc::now();
+        let lapses_threshold = read_external_config().unwrap().leech.lapses_threshold;
+
+        // Failing a card in FSRS keeps it in the learning state, while rating it Easy moves it to the review state
+        let fsrs_ratings = std::iter::repeat_n(1, lapses_threshold as usize + 2);
+        let ratings = fsrs_ratings
+            .map(|rating| ("fsrs", fsrs_card.id, rating))
+            .chain(std::iter::once(("fsrs", fsrs_review_card.id, 4)))
+            .chain(std::iter::once(("leitner", leitner_card.id, 2)));
+        for (i, (scheduler_name, card_id, rating)) in (0..).zip(ratings) {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: scheduler_name.to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id,
+                    rating,
+                    duration: Duration::seconds(5),
+                    tag_id: None,
+                }),
+            };
+            submit_study_action(&pool, request, now + Duration::minutes(i))
+                .await
+                .unwrap();
+        }
+
+        // FSRS learning reviews are not Leitner lapses
+        let leitner = get_scheduler_from_string("leitner").unwrap();
+        assert!(leitner.get_leeches(&pool).await.unwrap().is_empty());
+
+        // Only the Leitner card is advanced or postponed
+        let get_card = |card_id| {
+            sqlx::query_as::<_, Card>(r"SELECT * FROM card WHERE id = ?")
+                .bind(card_id)
+                .fetch_one(&pool)
+        };
+        let fsrs_card_due = get_card(fsrs_card.id).await.unwrap().due;
+        let fsrs_review_card_due = get_card(fsrs_review_card.id).await.unwrap().due;
+        let config = SparesExternalConfig::default();
+        let message = leitner
+            .advance(&pool, &config, 10, now + Duration::days(2))
+            .await
+            .unwrap();
+        assert_eq!(message, "Advanced 0 cards.");
+        let message = leitner
+            .postpone(&pool, &config, 10, now + Duration::days(2))
+            .await
+            .unwrap();
+        assert_eq!(message, "Postponed 1 cards.");
+        assert_eq!(get_card(fsrs_card.id).await.unwrap().due, fsrs_card_due);
+        assert_eq!(
+            get_card(fsrs_review_card.id).await.unwrap().due,
+            fsrs_review_card_due
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_sm2_ignores_other_schedulers(pool: sqlx::SqlitePool) -> () {
+        create_note_helper(&pool).await;
+        let cards: Vec<Card> = sqlx::query_as(r"SELECT * FROM card ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let fsrs_card = &cards[0];
+        let sm2_card = &cards[1];
+        let now = Utc::now();
+
+        // Rating both cards Easy moves them to the review state
+        for (scheduler_name, card_id) in [("fsrs", fsrs_card.id), ("sm2", sm2_card.id)] {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: scheduler_name.to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id,
+                    rating: 4,
+                    duration: Duration::seconds(5),
+                    tag_id: None,
+                }),
+            };
+            submit_study_action(&pool, request, now).await.unwrap();
+        }
+
+        // Only the SM-2 card is advanced
+        let get_fsrs_card = || {
+            sqlx::query_as::<_, Card>(r"SELECT * FROM card WHERE id = ?")
+                .bind(fsrs_card.id)
+                .fetch_one(&pool)
+        };
+        let fsrs_card_due = get_fsrs_card().await.unwrap().due;
+        let sm2 = get_scheduler_from_string("sm2").unwrap();
+        let message = sm2
+            .advance(&pool, &SparesExternalConfig::default(), 10, now)
+            .await
+            .unwrap();
+        assert_eq!(message, "Advanced 1 cards.");
+        assert_eq!(get_fsrs_card().await.unwrap().due, fsrs_card_due);
+    }
+
+    #[sqlx::test]
+    async fn test_reschedule_with_different_scheduler(pool: sqlx::SqlitePool) -> () {
+        let (_note, cards) = create_note(&pool).await;
+        let card_id = cards[0].id;
+        let now = Utc::now();
+
+        // Review with `fsrs`
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "fsrs".to_string(),
+            action: StudyAction::Rate(RatingSubmission {
+                card_id,
+                rating: 4,
+                duration: Duration::seconds(5),
+                tag_id: None,
+            }),
+        };
+        let submit_review_res = submit_study_action(&pool, request, now).await;
+        assert!(submit_review_res.is_ok());
+
+        // Switch to `sm2`
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "sm2".to_string(),
+            action: StudyAction::Reschedule,
+        };
+        let reschedule_res = submit_study_action(&pool, request, now).await;
+        assert!(reschedule_res.is_ok());
+
+        // `Easy` graduates a new card to a 4 day interval in SM-2.
+        let card: Card = sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+            .bind(card_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(card.custom_data.get("ease_factor").is_some());
+        assert_eq!(card.due.timestamp(), (now + Duration::days(4)).timestamp());
+    }
+
+    #[sqlx::test]
+    async fn test_bury_siblings(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let note_id = create_note_with_data(
+            &pool,
+            "First {{ cloze }} and second {{ cloze }}",
+            vec![],
+            now,
+        )
+        .await;
+        let cards = get_cards(&pool, note_id).await.unwrap();
+        assert_eq!(cards.len(), 2);
+        let card_id = cards[0].id;
+
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "fsrs".to_string(),
+            action: StudyAction::Rate(RatingSubmission {
+                card_id,
+                rating: 3,
+                duration: Duration::seconds(5),
+                tag_id: None,
+            }),
+        };
+        let submit_review_res = submit_study_action(&pool, request, now).await;
+        assert!(submit_review_res.is_ok());
+
+        // New siblings are buried by default
+        let cards = get_cards(&pool, note_id).await.unwrap();
+        for card in cards {
+            if card.id == card_id {
+                assert_eq!(card.special_state, None);
+            } else {
+                assert_eq!(card.special_state, Some(SpecialState::SchedulerBuried));
+            }
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_undo_rating(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let note_id = create_note_with_data(
+            &pool,
+            "First {{ cloze }} and second {{ cloze }}",
+            vec![],
+            now,
+        )
+        .await;
+        let old_cards = get_note_cards(&pool, note_id).await;
+
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "fsrs".to_string(),
+            action: StudyAction::Rate(RatingSubmission {
+                card_id: old_cards[0].id,
+                rating: 3,
+                duration: Duration::seconds(5),
+                tag_id: None,
+            }),
+        };
+        submit_study_action(&pool, request, now).await.unwrap();
+        undo_study_action(&pool).await.unwrap();
+
+        // The card and its buried sibling are restored
+        let new_cards = get_note_cards(&pool, note_id).await;
+        assert_eq!(
+            serde_json::to_value(&new_cards).unwrap(),
+            serde_json::to_value(&old_cards).unwrap()
+        );
+        let (review_log_count,): (u32,) = sqlx::query_as(r"SELECT COUNT(*) FROM review_log")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(review_log_count, 0);
+
+        // Each action is only undone once
+        assert!(matches!(
+            undo_study_action(&pool).await,
+            Err(Error::Library(LibraryError::Scheduler(
+                SchedulerErrorKind::NothingToUndo
+            )))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn test_undo_filtered_tag_rating(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let note_id =
+            create_note_with_data(&pool, "{{ cloze }}", vec!["undo".to_string()], now).await;
+        let request = CreateTagRequest {
+            name: "filtered".to_string(),
+            description: String::new(),
+            parent_id: None,
+            query: Some("tag=undo".to_string()),
+            auto_delete: true,
+        };
+        let tag_id = create_tag(&pool, request).await.unwrap().id;
+        let old_cards = get_note_cards(&pool, note_id).await;
+        let card_id = old_cards[0].id;
+
+        // Rating the card `Easy` removes it from the filtered tag, which deletes the tag
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "fsrs".to_string(),
+            action: StudyAction::Rate(RatingSubmission {
+                card_id,
+                rating: 4,
+                duration: Duration::seconds(5),
+                tag_id: Some(tag_id),
+            }),
+        };
+        submit_study_action(&pool, request, now).await.unwrap();
+        let tag_opt: Option<Tag> = sqlx::query_as(r"SELECT * FROM tag WHERE id = ?")
+            .bind(tag_id)
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        assert!(tag_opt.is_none());
+
+        undo_study_action(&pool).await.unwrap();
+        let new_cards = get_note_cards(&pool, note_id).await;
+        assert_eq!(
+            serde_json::to_value(&new_cards).unwrap(),
+            serde_json::to_value(&old_cards).unwrap()
+        );
+        let card_tag_opt: Option<(TagId,)> =
+            sqlx::query_as(r"SELECT tag_id FROM card_tag WHERE card_id = ?")
+                .bind(card_id)
+                .fetch_optional(&pool)
+                .await
+                .unwrap();
+        assert_eq!(card_tag_opt, Some((tag_id,)));
+    }
+
+    #[sqlx::test]
+    async fn test_undo_tag_note(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let request = SubmitStudyActionRequest {
+            scheduler_name: "fsrs".to_string(),
+            action: StudyAction::TagNote {
+                note_id,
+                tag_name: "flagged".to_string(),
+            },
+        };
+        submit_study_action(&pool, request, now).await.unwrap();
+        undo_study_action(&pool).await.unwrap();
+
+        // The tag was created by the action, so it is deleted
+        let tag_opt: Option<Tag> = sqlx::query_as(r"SELECT * FROM tag WHERE name = ?")
+            .bind("flagged")
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        assert!(tag_opt.is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_leech_actions(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let card_id = get_note_cards(&pool, note_id).await[0].id;
+        let config = read_external_config().unwrap();
+        let lapses_threshold = config.leech.lapses_threshold;
+
+        // Each failed review after the card is in a box is a lapse
+        let ratings =
+            std::iter::once(2).chain(std::iter::repeat_n(1, lapses_threshold as usize + 2));
+        for (i, rating) in (0..).zip(ratings) {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: "leitner".to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id,
+                    rating,
+                    duration: Duration::seconds(5),
+                    tag_id: None,
+                }),
+            };
+            let reviewed_at = now + Duration::minutes(i);
+            submit_study_action(&pool, request, reviewed_at)
+                .await
+                .unwrap();
+        }
+
+        // The leech event is only recorded once
+        let leech_events = get_leech_events(&pool, FilterOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(leech_events.len(), 1);
+        assert_eq!(leech_events[0].card_id, card_id);
+        assert_eq!(leech_events[0].lapses, lapses_threshold + 1);
+        let note_tag_opt: Option<(TagId,)> = sqlx::query_as(
+            r"SELECT nt.tag_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE nt.note_id = ? AND t.name = ?",
+        )
+        .bind(note_id)
+        .bind(&config.leech.tag_name)
+        .fetch_optional(&pool)
+        .await
+        .unwrap();
+        assert_eq!(note_tag_opt.is_some(), config.leech.auto_tag);
+
+        // Undoing the review that made the card a leech removes the leech event
+        undo_study_action(&pool).await.unwrap();
+        undo_study_action(&pool).await.unwrap();
+        let leech_events = get_leech_events(&pool, FilterOptions::default())
+            .await
+            .unwrap();
+        assert!(leech_events.is_empty());
+        let note_tags: Vec<(TagId,)> =
+            sqlx::query_as(r"SELECT tag_id FROM note_tag WHERE note_id = ?")
+                .bind(note_id)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert!(note_tags.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_leech_lapses_with_mixed_schedulers(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let card_id = get_note_cards(&pool, note_id).await[0].id;
+        let lapses_threshold = read_external_config().unwrap().leech.lapses_threshold;
+
+        // The SM-2 lapse has the same state and rating as a Leitner lapse
+        let ratings =
+            [("sm2", 4), ("sm2", 1), ("leitner", 2)]
+                .into_iter()
+                .chain(std::iter::repeat_n(
+                    ("leitner", 1),
+                    lapses_threshold as usize + 1,
+                ));
+        let mut leech_event_counts = Vec::new();
+        for (i, (scheduler_name, rating)) in (0..).zip(ratings) {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: scheduler_name.to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id,
+                    rating,
+                    duration: Duration::seconds(5),
+                    tag_id: None,
+                }),
+            };
+            submit_study_action(&pool, request, now + Duration::minutes(i))
+                .await
+                .unwrap();
+            let leech_events = get_leech_events(&pool, FilterOptions::default())
+                .await
+                .unwrap();
+            leech_event_counts.push(leech_events.len());
+        }
+
+        // Only the last Leitner lapse takes the card over the threshold
+        let (last, rest) = leech_event_counts.split_last().unwrap();
+        assert_eq!(*last, 1);
+        assert!(rest.iter().all(|count| *count == 0));
+    }
+
+    #[sqlx::test]
+    async fn test_search_scheduling_fields(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let studied_note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let studied_card_id = get_note_cards(&pool, studied_note_id).await[0].id;
+        let new_note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let new_card_id = get_note_cards(&pool, new_note_id).await[0].id;
+
+        // Pass, then fail the card once it is in a box
+        for (i, rating) in (0..).zip([2, 1, 2]) {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: "leitner".to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id: studied_card_id,
+                    rating,
+                    duration: Duration::seconds(5),
+                    tag_id: None,
+                }),
+            };
+            let reviewed_at = now - Duration::minutes(10 - i);
+            submit_study_action(&pool, request, reviewed_at)
+                .await
+                .unwrap();
+        }
+
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                Evaluator::new(query)
+                    .get_card_ids(&pool)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .sorted()
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(search("c.reps=3").await, vec![studied_card_id]);
+        assert_eq!(search("c.reps=0").await, vec![new_card_id]);
+        assert_eq!(search("c.lapses=1").await, vec![studied_card_id]);
+        assert!(search("c.lapses>=2").await.is_empty());
+        assert_eq!(search("c.order=1").await.len(), 2);
+        assert_eq!(
+            search("c.last_reviewed_at>2000-01-01").await,
+            vec![studied_card_id]
+        );
+        assert_eq!(search("c.due>2000-01-01").await.len(), 2);
+        // Cards that have not been reviewed have a retrievability of 0
+        assert_eq!(search("c.retrievability>0.9").await, vec![studied_card_id]);
+        assert_eq!(search("c.retrievability<=0").await, vec![new_card_id]);
+    }
+
+    #[sqlx::test]
+    async fn test_search_review_history(pool: sqlx::SqlitePool) -> () {
+        let now = Utc::now();
+        let studied_note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let studied_card_id = get_note_cards(&pool, studied_note_id).await[0].id;
+        let new_note_id = create_note_with_data(&pool, "{{ cloze }}", vec![], now).await;
+        let new_card_id = get_note_cards(&pool, new_note_id).await[0].id;
+
+        // Fail the card twice, then pass it
+        for (i, (rating, duration)) in (0..).zip([(1, 5), (1, 30), (2, 10)]) {
+            let request = SubmitStudyActionRequest {
+                scheduler_name: "leitner".to_string(),
+                action: StudyAction::Rate(RatingSubmission {
+                    card_id: studied_card_id,
+                    rating,
+                    duration: Duration::seconds(duration),
+                    tag_id: None,
+                }),
+            };
+            let reviewed_at = now - Duration::minutes(10 - i);
+            submit_study_action(&pool, request, reviewed_at)
+                .await
+                .unwrap();
+        }
+
+        let search = |query: &'static str| {
+            let pool = pool.clone();
+            async move {
+                Evaluator::new(query)
+                    .get_card_ids(&pool)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .sorted()
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            search("r.count(rating=1, reviewed_at>-30d)>=2").await,
+            vec![studied_card_id]
+        );
+        assert!(
+            search("r.count(rating=1, reviewed_at<-1d)>=1")
+                .await
+                .is_empty()
+        );
+        assert_eq!(search("r.count=0").await, vec![new_card_id]);
+        assert_eq!(search("r.last.rating=2").await, vec![studied_card_id]);
+        assert!(search("r.last.rating=1").await.is_empty());
+        assert_eq!(search("r.avg_duration>12").await, vec![studied_card_id]);
+        assert!(search("r.avg_duration(rating=2)>12").await.is_empty());
+        assert_eq!(search("r.scheduler=leitner").await, vec![studied_card_id]);
+        assert!(search("r.scheduler=fsrs").await.is_empty());
+        assert_eq!(
+            Evaluator::new("sort:r.count(rating=1) desc")
+                .get_card_ids(&pool)
+                .await
+                .unwrap(),
+            vec![studied_card_id, new_card_id]
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use crate::{
+    config::{NewCardOrder, ReviewOrder, StudyOrderConfig},
+    model::{CardId, NEW_CARD_STATE, NoteId, StateId},
+    parsers::BackType,
+};
+use rand::{Rng, seq::SliceRandom};
+use sqlx::{FromRow, QueryBuilder, Sqlite};
+use std::collections::HashMap;
+
+const SECONDS_PER_DAY: f64 = 86400.;
+
+/// A card that can be studied, along with the data needed to order it.
+#[derive(Clone, Debug, Default, FromRow)]
+pub(super) struct ReviewCard {
+    pub note_id: NoteId,
+    pub parser_name: String,
+    pub card_order: u32,
+    pub card_back_type: BackType,
+    pub card_id: CardId,
+    pub state: StateId,
+    pub due: i64,
+    pub stability: f64,
+    pub difficulty: f64,
+    pub note_created_at: i64,
+    pub last_reviewed_at: Option<i64>,
+}
+
+/// Returns the tag used to interleave a note's cards, which is the first of its tags alphabetically.
+pub(super) fn get_note_tag(note_tags: &HashMap<NoteId, Vec<String>>, note_id: NoteId) -> &str {
+    note_tags
+        .get(&note_id)
+        .and_then(|tags| tags.iter().min())
+        .map_or("", String::as_str)
+}
+
+/// The number of stabilities that have passed since the card was last reviewed. Retrievability decreases as this increases.
+#[allow(clippy::cast_precision_loss)]
+fn get_elapsed_stabilities(card: &ReviewCard, at: i64) -> f64 {
+    if card.stability <= 0. {
+        return f64::INFINITY;
+    }
+    let elapsed_days = card
+        .last_reviewed_at
+        .map_or(0, |last_reviewed_at| at - last_reviewed_at) as f64
+        / SECONDS_PER_DAY;
+    elapsed_days / card.stability
+}
+
+fn due_key(card: &ReviewCard) -> (i64, i64, u32) {
+    (card.due, card.note_created_at, card.card_order)
+}
+
+/// Whether every candidate card is needed to choose the next card, since the order depends on more than the card itself.
+pub(super) fn is_ordered_in_memory(config: &StudyOrderConfig, is_new: bool) -> bool {
+    if is_new {
+        config.new_card_order == NewCardOrder::TagPriority
+    } else {
+        config.review_order == ReviewOrder::InterleaveTags
+    }
+}
+
+/// Pushes an `ORDER BY` clause that matches [`choose_review_card`], so only the first card needs to be fetched. Orders where [`is_ordered_in_memory`] is true are left to [`choose_review_card`].
+pub(super) fn push_order_by(
+    query_builder: &mut QueryBuilder<'_, Sqlite>,
+    config: &StudyOrderConfig,
+    is_new: bool,
+    at: i64,
+) {
+    if is_ordered_in_memory(config, is_new) {
+        return;
+    }
+    query_builder.push(" ORDER BY ");
+    if is_new {
+        match config.new_card_order {
+            NewCardOrder::NoteCreated => {
+                query_builder.push("note_created_at ASC, card_order ASC, due ASC");
+            }
+            NewCardOrder::Random => {
+                query_builder.push("RANDOM()");
+            }
+            NewCardOrder::TagPriority => unreachable!(),
+        }
+    } else {
+        match config.review_order {
+            ReviewOrder::Due => {
+                query_builder.push("due ASC, note_created_at ASC, card_order ASC");
+            }
+            ReviewOrder::Retrievability => {
+                // See `get_elapsed_stabilities`
+                query_builder
+                    .push("CASE WHEN stability <= 0 THEN 9e999 ELSE (")
+                    .push_bind(at)
+                    .push(" - COALESCE(last_reviewed_at, ")
+                    .push_bind(at)
+                    .push(")) / ")
+                    .push_bind(SECONDS_PER_DAY)
+                    .push(" / stability END DESC, due ASC, note_created_at ASC, card_order ASC");
+            }
+            ReviewOrder::Difficulty => {
+                query_builder.push("difficulty DESC, due ASC, note_created_at ASC, card_order ASC");
+            }
+            ReviewOrder::Random => {
+                query_builder.push("RANDOM()");
+            }
+            ReviewOrder::InterleaveTags => unreachable!(),
+        }
+    }
+    query_builder.push(" LIMIT 1");
+}
+
+fn choose_card_studied_before<'a>(
+    cards: Vec<ReviewCard>,
+    review_order: ReviewOrder,
+    note_tags: &'a HashMap<NoteId, Vec<String>>,
+    tags_last_studied_at: &HashMap<&'a str, i64>,
+    at: i64,
+    rng: &mut impl Rng,
+) -> Option<ReviewCard> {
+    match review_order {
+        ReviewOrder::Due => cards.into_iter().min_by_key(due_key),
+        ReviewOrder::Retrievability => cards.into_iter().max_by(|a, b| {
+            get_elapsed_stabilities(a, at)
+                .total_cmp(&get_elapsed_stabilities(b, at))
+                .then_with(|| due_key(b).cmp(&due_key(a)))
+        }),
+        ReviewOrder::Difficulty => cards.into_iter().max_by(|a, b| {
+            a.difficulty
+                .total_cmp(&b.difficulty)
+                .then_with(|| due_key(b).cmp(&due_key(a)))
+        }),
+        ReviewOrder::Random => cards.choose(rng).cloned(),
+        ReviewOrder::InterleaveTags => {
+            let next_tag = cards
+                .iter()
+                .map(|card| get_note_tag(note_tags, card.note_id))
+                .min_by_key(|tag| (tags_last_studied_at.get(tag), *tag))?;
+            cards
+                .into_iter()
+                .filter(|card| get_note_tag(note_tags, card.note_id) == next_tag)
+                .min_by_key(due_key)
+        }
+    }
+}
+
+fn choose_new_card(
+    cards: Vec<ReviewCard>,
+    config: &StudyOrderConfig,
+    note_tags: &HashMap<NoteId, Vec<String>>,
+    rng: &mut impl Rng,
+) -> Option<ReviewCard> {
+    match config.new_card_order {
+        NewCardOrder::NoteCreated => cards
+            .into_iter()
+            .min_by_key(|card| (card.note_created_at, card.card_order, card.due)),
+        NewCardOrder::Random => cards.choose(rng).cloned(),
+        NewCardOrder::TagPriority => {
+            let priority_count = config.new_card_tag_priority.len();
+            let get_priority = |card: &ReviewCard| {
+                note_tags
+                    .get(&card.note_id)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|tag| {
+                        config
+                            .new_card_tag_priority
+                            .iter()
+                            .position(|priority_tag| priority_tag == tag)
+                    })
+                    .min()
+                    .unwrap_or(priority_count)
+            };
+            cards
+                .into_iter()
+                .min_by_key(|card| (get_priority(card), card.note_created_at, card.card_order))
+        }
+    }
+}
+
+/// Chooses the next card to study.
+///
+/// `cards` only needs to contain the first card of each kind when the order was already applied by [`push_order_by`]. `note_tags` and `tags_last_studied_at` are only used by [`ReviewOrder::InterleaveTags`] and [`NewCardOrder::TagPriority`].
+pub(super) fn choose_review_card<'a>(
+    cards: Vec<ReviewCard>,
+    config: &StudyOrderConfig,
+    note_tags: &'a HashMap<NoteId, Vec<String>>,
+    tags_last_studied_at: &HashMap<&'a str, i64>,
+    at: i64,
+    rng: &mut impl Rng,
+) -> Option<ReviewCard> {
+    let (new_cards, review_cards): (Vec<_>, Vec<_>) = cards
+        .into_iter()
+        .partition(|card| card.state == NEW_CARD_STATE);
+    let next_review_card = choose_card_studied_before(
+        review_cards,
+        config.review_order,
+        note_tags,
+        tags_last_studied_at,
+        at,
+        rng,
+    );
+    let next_new_card = choose_new_card(new_cards, config, note_tags, rng);
+    match (next_review_card, next_new_card) {
+        (Some(next_review_card), Some(next_new_card)) => {
+            if next_new_card.due < next_review_card.due {
+                Some(next_new_card)
+            } else {
+                Some(next_review_card)
+            }
+        }
+        (next_review_card, next_new_card) => next_review_card.or(next_new_card),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    const NOW: i64 = 1_700_000_000;
+    const DAY: i64 = 86400;
+
+    fn card(
+        card_id: CardId,
+        note_id: NoteId,
+        due: i64,
+        stability: f64,
+        difficulty: f64,
+    ) -> ReviewCard {
+        ReviewCard {
+            note_id,
+            card_id,
+            state: 2,
+            due,
+            stability,
+            difficulty,
+            note_created_at: NOW - 100 * DAY + note_id,
+            last_reviewed_at: Some(due - (stability * SECONDS_PER_DAY) as i64),
+            ..Default::default()
+        }
+    }
+
+    fn choose(
+        cards: &[ReviewCard],
+        config: &StudyOrderConfig,
+        note_tags: &HashMap<NoteId, Vec<String>>,
+        tags_last_studied_at: &HashMap<&str, i64>,
+    ) -> CardId {
+        let mut rng = StdRng::seed_from_u64(42);
+        choose_review_card(
+            cards.to_vec(),
+            config,
+            note_tags,
+            tags_last_studied_at,
+            NOW,
+            &mut rng,
+        )
+        .unwrap()
+        .card_id
+    }
+
+    #[test]
+    fn test_review_order() {
+        let cards = vec![
+            // Overdue by 10 days with a stability of 100 days
+            card(1, 1, NOW - 10 * DAY, 100., 5.),
+            // Overdue by 2 days with a stability of 2 days
+            card(2, 2, NOW - 2 * DAY, 2., 3.),
+            card(3, 3, NOW - DAY, 10., 8.),
+        ];
+        let note_tags = HashMap::new();
+        let tags_last_studied_at = HashMap::new();
+        let order = |review_order| StudyOrderConfig {
+            review_order,
+            ..Default::default()
+        };
+        assert_eq!(
+            choose(
+                &cards,
+                &order(ReviewOrder::Due),
+                &note_tags,
+                &tags_last_studied_at
+            ),
+            1
+        );
+        assert_eq!(
+            choose(
+                &cards,
+                &order(ReviewOrder::Retrievability),
+                &note_tags,
+                &tags_last_studied_at
+            ),
+            2
+        );
+        assert_eq!(
+            choose(
+                &cards,
+                &order(ReviewOrder::Difficulty),
+                &note_tags,
+                &tags_last_studied_at
+            ),
+            3
+        );
+        let random_card_id = choose(
+            &cards,
+            &order(ReviewOrder::Random),
+            &note_tags,
+            &tags_last_studied_at,
+        );
+        assert!((1..=3).contains(&random_card_id));
+    }
+
+    #[test]
+    fn test_interleave_tags() {
+        let cards = vec![
+            card(1, 1, NOW - 3 * DAY, 1., 5.),
+            card(2, 2, NOW - 2 * DAY, 1., 5.),
+            card(3, 3, NOW - DAY, 1., 5.),
+        ];
+        let note_tags = HashMap::from([
+            (1, vec!["math".to_string()]),
+            (2, vec!["math".to_string()]),
+            (3, vec!["vocab".to_string()]),
+        ]);
+        let config = StudyOrderConfig {
+            review_order: ReviewOrder::InterleaveTags,
+            ..Default::default()
+        };
+        // Tags that have not been studied yet are chosen first.
+        let tags_last_studied_at = HashMap::from([("math", NOW - 60)]);
+        assert_eq!(
+            choose(&cards, &config, &note_tags, &tags_last_studied_at),
+            3
+        );
+        let tags_last_studied_at = HashMap::from([("math", NOW - 60), ("vocab", NOW - 30)]);
+        assert_eq!(
+            choose(&cards, &config, &note_tags, &tags_last_studied_at),
+            1
+        );
+    }
+
+    #[test]
+    fn test_new_card_order() {
+        let new_card = |card_id: CardId, note_id: NoteId| ReviewCard {
+            state: NEW_CARD_STATE,
+            last_reviewed_at: None,
+            ..card(card_id, note_id, NOW - 100 * DAY + note_id, 0., 0.)
+        };
+        let cards = vec![new_card(1, 1), new_card(2, 2), new_card(3, 3)];
+        let note_tags = HashMap::from([
+            (1, vec!["math".to_string()]),
+            (3, vec!["vocab".to_string(), "verbs".to_string()]),
+        ]);
+        let tags_last_studied_at = HashMap::new();
+        let config = StudyOrderConfig::default();
+        assert_eq!(
+            choose(&cards, &config, &note_tags, &tags_last_studied_at),
+            1
+        );
+        let config = StudyOrderConfig {
+            new_card_order: NewCardOrder::TagPriority,
+            new_card_tag_priority: vec!["verbs".to_string(), "math".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            choose(&cards, &config, &note_tags, &tags_last_studied_at),
+            3
+        );
+    }
+
+    #[test]
+    fn test_mix_new_cards_and_reviews() {
+        let cards = vec![
+            card(1, 1, NOW - DAY, 1., 5.),
+            ReviewCard {
+                state: NEW_CARD_STATE,
+                ..card(2, 2, NOW - 2 * DAY, 0., 0.)
+            },
+        ];
+        let note_tags = HashMap::new();
+        let tags_last_studied_at = HashMap::new();
+        let config = StudyOrderConfig::default();
+        assert_eq!(
+            choose(&cards, &config, &note_tags, &tags_last_studied_at),
+            2
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_order_by_matches_choose(pool: sqlx::SqlitePool) -> () {
+        let cards = vec![
+            card(1, 1, NOW - 10 * DAY, 100., 5.),
+            card(2, 2, NOW - 2 * DAY, 2., 3.),
+            card(3, 3, NOW - DAY, 10., 8.),
+            ReviewCard {
+                last_reviewed_at: None,
+                ..card(4, 4, NOW - 3 * DAY, 1., 1.)
+            },
+            ReviewCard {
+                state: NEW_CARD_STATE,
+                ..card(5, 6, NOW - DAY, 0., 0.)
+            },
+            ReviewCard {
+                state: NEW_CARD_STATE,
+                ..card(6, 5, NOW - DAY, 0., 0.)
+            },
+        ];
+        let orders = [
+            ReviewOrder::Due,
+            ReviewOrder::Retrievability,
+            ReviewOrder::Difficulty,
+        ];
+        for review_order in orders {
+            let config = StudyOrderConfig {
+                review_order,
+                ..Default::default()
+            };
+            for is_new in [false, true] {
+                let mut query_builder = QueryBuilder::new(
+                    "WITH candidate(card_id, state, due, stability, difficulty, note_created_at, card_order, last_reviewed_at) AS (",
+                );
+                query_builder.push_values(&cards, |mut b, card| {
+                    b.push_bind(card.card_id)
+                        .push_bind(card.state)
+                        .push_bind(card.due)
+                        .push_bind(card.stability)
+                        .push_bind(card.difficulty)
+                        .push_bind(card.note_created_at)
+                        .push_bind(card.card_order)
+                        .push_bind(card.last_reviewed_at);
+                });
+                query_builder
+                    .push(") SELECT card_id FROM candidate WHERE (state = ")
+                    .push_bind(NEW_CARD_STATE)
+                    .push(") = ")
+                    .push_bind(is_new);
+                push_order_by(&mut query_builder, &config, is_new, NOW);
+                let (card_id,): (CardId,) = query_builder
+                    .build_query_as()
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+                let expected_cards = cards
+                    .iter()
+                    .filter(|card| (card.state == NEW_CARD_STATE) == is_new)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    card_id,
+                    choose(&expected_cards, &config, &HashMap::new(), &HashMap::new())
+                );
+            }
+        }
+    }
+}
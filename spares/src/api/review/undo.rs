@@ -0,0 +1,155 @@
+use crate::{
+    Error, LibraryError, SchedulerErrorKind,
+    model::{Card, CardId, NoteId, Tag, TagId},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqlitePool, types::Json};
+
+/// The number of study actions that can be undone. Older actions are forgotten.
+const UNDO_STACK_SIZE: i64 = 50;
+
+/// The changes made by a study action. Undoing the action reverts these changes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UndoEntry {
+    /// The cards as they were before the action.
+    pub cards: Vec<Card>,
+    /// The ids of the review logs created by the action.
+    pub review_log_ids: Vec<i64>,
+    /// The card tags removed by the action.
+    pub removed_card_tags: Vec<(CardId, TagId)>,
+    /// The tags deleted by the action.
+    pub deleted_tags: Vec<Tag>,
+    /// The note tags added by the action.
+    pub added_note_tags: Vec<(NoteId, TagId)>,
+    /// The tags created by the action.
+    pub created_tag_ids: Vec<TagId>,
+    /// The ids of the leech events created by the action.
+    pub leech_event_ids: Vec<i64>,
+}
+
+pub(super) async fn push_undo_entry(
+    db: &SqlitePool,
+    entry: &UndoEntry,
+    at: DateTime<Utc>,
+) -> Result<(), Error> {
+    let _insert_result = sqlx::query(r"INSERT INTO undo_entry (created_at, data) VALUES (?, ?)")
+        .bind(at.timestamp())
+        .bind(Json(entry))
+        .execute(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let _delete_result = sqlx::query(
+        r"DELETE FROM undo_entry WHERE id NOT IN (SELECT id FROM undo_entry ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(UNDO_STACK_SIZE)
+    .execute(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(())
+}
+
+/// Reverts the most recent study action that has not been undone yet.
+pub async fn undo_study_action(db: &SqlitePool) -> Result<(), Error> {
+    let (entry_id, Json(entry)): (i64, Json<UndoEntry>) =
+        sqlx::query_as(r"SELECT id, data FROM undo_entry ORDER BY id DESC LIMIT 1")
+            .fetch_optional(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?
+            .ok_or(Error::Library(LibraryError::Scheduler(
+                SchedulerErrorKind::NothingToUndo,
+            )))?;
+    let UndoEntry {
+        cards,
+        review_log_ids,
+        removed_card_tags,
+        deleted_tags,
+        added_note_tags,
+        created_tag_ids,
+        leech_event_ids,
+    } = entry;
+
+    let mut transaction = db.begin().await.map_err(|e| Error::Sqlx { source: e })?;
+    // Restore deleted tags before the card tags that reference them
+    for tag in deleted_tags {
+        let _insert_result = sqlx::query(
+            r"INSERT INTO tag (id, name, description, parent_id, query, auto_delete) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(tag.id)
+        .bind(tag.name)
+        .bind(tag.description)
+        .bind(tag.parent_id)
+        .bind(tag.query)
+        .bind(tag.auto_delete)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    for (card_id, tag_id) in removed_card_tags {
+        let _insert_result = sqlx::query(r"INSERT INTO card_tag (card_id, tag_id) VALUES (?, ?)")
+            .bind(card_id)
+            .bind(tag_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    for leech_event_id in leech_event_ids {
+        let _delete_result = sqlx::query(r"DELETE FROM leech_event WHERE id = ?")
+            .bind(leech_event_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    for review_log_id in review_log_ids {
+        let _delete_result = sqlx::query(r"DELETE FROM review_log WHERE id = ?")
+            .bind(review_log_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    for card in cards {
+        let _update_card_result = sqlx::query(
+            r"UPDATE card SET updated_at = ?, due = ?, stability = ?, difficulty = ?, desired_retention = ?, special_state = ?, state = ?, custom_data = ? WHERE id = ?",
+        )
+        .bind(card.updated_at.timestamp())
+        .bind(card.due.timestamp())
+        .bind(card.stability)
+        .bind(card.difficulty)
+        .bind(card.desired_retention)
+        .bind(card.special_state)
+        .bind(card.state)
+        .bind(card.custom_data)
+        .bind(card.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    for (note_id, tag_id) in added_note_tags {
+        let _delete_result = sqlx::query(r"DELETE FROM note_tag WHERE note_id = ? AND tag_id = ?")
+            .bind(note_id)
+            .bind(tag_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    for tag_id in created_tag_ids {
+        let _delete_result = sqlx::query(
+            r"DELETE FROM tag WHERE id = ? AND NOT EXISTS (SELECT 1 FROM note_tag WHERE note_tag.tag_id = tag.id)",
+        )
+        .bind(tag_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    }
+    let _delete_result = sqlx::query(r"DELETE FROM undo_entry WHERE id = ?")
+        .bind(entry_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    transaction
+        .commit()
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(())
+}
@@ -0,0 +1,409 @@
+use crate::{
+    Error, LibraryError,
+    model::SavedSearch,
+    schema::{
+        FilterOptions,
+        saved_search::{CreateSavedSearchRequest, SavedSearchResponse, UpdateSavedSearchRequest},
+    },
+    search::{evaluator::Evaluator, lexer::Lexer},
+};
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+
+const SAVED_SEARCHES_DEFAULT_LIMIT: usize = 100;
+
+pub async fn create_saved_search(
+    db: &SqlitePool,
+    body: CreateSavedSearchRequest,
+) -> Result<SavedSearchResponse, Error> {
+    verify_saved_search_name(db, &body.name).await?;
+    let mut saved_searches = get_saved_search_queries(db).await?;
+    saved_searches.insert(body.name.clone(), body.query.clone());
+    verify_saved_search_query(&body.query, saved_searches)?;
+
+    let (id,): (i64,) =
+        sqlx::query_as(r"INSERT INTO saved_search (name, query) VALUES (?, ?) RETURNING id")
+            .bind(&body.name)
+            .bind(&body.query)
+            .fetch_one(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    let saved_search = SavedSearch {
+        id,
+        name: body.name,
+        query: body.query,
+    };
+    Ok(SavedSearchResponse::new(&saved_search))
+}
+
+pub async fn get_saved_search(db: &SqlitePool, id: i64) -> Result<SavedSearchResponse, Error> {
+    let saved_search: SavedSearch = sqlx::query_as(r"SELECT * FROM saved_search WHERE id = ?")
+        .bind(id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(SavedSearchResponse::new(&saved_search))
+}
+
+pub async fn get_saved_search_by_name(
+    db: &SqlitePool,
+    name: &str,
+) -> Result<SavedSearchResponse, Error> {
+    let saved_search: SavedSearch = sqlx::query_as(r"SELECT * FROM saved_search WHERE name = ?")
+        .bind(name)
+        .fetch_one(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(SavedSearchResponse::new(&saved_search))
+}
+
+pub async fn update_saved_search(
+    db: &SqlitePool,
+    body: UpdateSavedSearchRequest,
+    id: i64,
+) -> Result<SavedSearchResponse, Error> {
+    let existing_saved_search: SavedSearch =
+        sqlx::query_as(r"SELECT * FROM saved_search WHERE id = ?")
+            .bind(id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    // Update (if empty, use old value)
+    let new_name = body
+        .name
+        .clone()
+        .unwrap_or_else(|| existing_saved_search.name.clone());
+    let new_query = body
+        .query
+        .clone()
+        .unwrap_or_else(|| existing_saved_search.query.clone());
+    if new_name != existing_saved_search.name {
+        verify_saved_search_name(db, &new_name).await?;
+        // Renaming would break the queries that reference this saved search
+        verify_saved_search_unused(db, &existing_saved_search.name, "rename").await?;
+    }
+    let mut saved_searches = get_saved_search_queries(db).await?;
+    saved_searches.remove(&existing_saved_search.name);
+    saved_searches.insert(new_name.clone(), new_query.clone());
+    verify_saved_search_query(&new_query, saved_searches)?;
+
+    let _update_result = sqlx::query(r"UPDATE saved_search SET name = ?, query = ? WHERE id = ?")
+        .bind(&new_name)
+        .bind(&new_query)
+        .bind(id)
+        .execute(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let updated_item = SavedSearch {
+        id,
+        name: new_name,
+        query: new_query,
+    };
+    Ok(SavedSearchResponse::new(&updated_item))
+}
+
+pub async fn delete_saved_search(db: &SqlitePool, id: i64) -> Result<(), Error> {
+    let (name,): (String,) = sqlx::query_as(r"SELECT name FROM saved_search WHERE id = ?")
+        .bind(id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    verify_saved_search_unused(db, &name, "delete").await?;
+    let _query_result = sqlx::query(r"DELETE FROM saved_search WHERE id = ?")
+        .bind(id)
+        .execute(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(())
+}
+
+pub async fn list_saved_searches(
+    db: &SqlitePool,
+    opts: FilterOptions,
+) -> Result<Vec<SavedSearchResponse>, Error> {
+    let limit = opts.limit.unwrap_or(SAVED_SEARCHES_DEFAULT_LIMIT);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+    let items = sqlx::query_as(r"SELECT * FROM saved_search ORDER by id LIMIT ? OFFSET ?")
+        .bind(limit as u32)
+        .bind(offset as u32)
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let responses = items
+        .iter()
+        .map(SavedSearchResponse::new)
+        .collect::<Vec<SavedSearchResponse>>();
+    Ok(responses)
+}
+
+/// Returns the query of every saved search, by name.
+pub async fn get_saved_search_queries(db: &SqlitePool) -> Result<HashMap<String, String>, Error> {
+    let saved_searches: Vec<(String, String)> =
+        sqlx::query_as(r"SELECT name, query FROM saved_search")
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    Ok(saved_searches.into_iter().collect())
+}
+
+async fn verify_saved_search_name(db: &SqlitePool, name: &str) -> Result<(), Error> {
+    if name.trim().is_empty() {
+        return Err(Error::Library(LibraryError::Search(
+            "A saved search's name cannot be empty.".to_string(),
+        )));
+    }
+    let existing_saved_search: Option<(i64,)> =
+        sqlx::query_as(r"SELECT id FROM saved_search WHERE name = ?")
+            .bind(name)
+            .fetch_optional(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    if existing_saved_search.is_some() {
+        return Err(Error::Library(LibraryError::Search(
+            "A saved search with this name already exists.".to_string(),
+        )));
+    }
+    Ok(())
+}
+
+/// Verifies that the query is valid and that it does not reference itself through other saved searches. `saved_searches` must contain the saved search being verified.
+fn verify_saved_search_query(
+    query: &str,
+    saved_searches: HashMap<String, String>,
+) -> Result<(), Error> {
+    Evaluator::new(query)
+        .with_saved_searches(saved_searches)
+        .check()
+}
+
+/// Verifies that no other saved search or filtered tag references the saved search.
+async fn verify_saved_search_unused(
+    db: &SqlitePool,
+    name: &str,
+    action: &str,
+) -> Result<(), Error> {
+    let saved_searches = get_saved_search_queries(db).await?;
+    let filtered_tags: Vec<(String, String)> =
+        sqlx::query_as(r"SELECT name, query FROM tag WHERE query IS NOT NULL")
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    let references_saved_search = |query: &str| {
+        Lexer::new(query)
+            .extract_saved_search_dependencies()
+            .is_ok_and(|dependencies| dependencies.iter().any(|dependency| dependency == name))
+    };
+    let mut dependents = saved_searches
+        .iter()
+        .filter(|(_, query)| references_saved_search(query))
+        .map(|(dependent_name, _)| format!("saved search `{dependent_name}`"))
+        .chain(
+            filtered_tags
+                .iter()
+                .filter(|(_, query)| references_saved_search(query))
+                .map(|(tag_name, _)| format!("filtered tag `{tag_name}`")),
+        )
+        .collect::<Vec<_>>();
+    if dependents.is_empty() {
+        return Ok(());
+    }
+    dependents.sort_unstable();
+    Err(Error::Library(LibraryError::Search(format!(
+        "Cannot {} saved search `{}` since it is used by {}.",
+        action,
+        name,
+        dependents.join(", ")
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{
+            note::create_notes,
+            parser::tests::create_parser_helper,
+            tag::{create_tag, get_tag_by_name},
+        },
+        model::CardTag,
+        parsers::get_all_parsers,
+        schema::{
+            note::{CreateNoteRequest, CreateNotesRequest},
+            tag::CreateTagRequest,
+        },
+    };
+    use chrono::Utc;
+    use serde_json::Map;
+
+    async fn create_saved_search_helper(
+        pool: &SqlitePool,
+        name: &str,
+        query: &str,
+    ) -> Result<SavedSearchResponse, Error> {
+        let request = CreateSavedSearchRequest {
+            name: name.to_string(),
+            query: query.to_string(),
+        };
+        create_saved_search(pool, request).await
+    }
+
+    #[sqlx::test]
+    async fn test_saved_search_crud(pool: SqlitePool) -> () {
+        let saved_search = create_saved_search_helper(&pool, "math", "tag=math")
+            .await
+            .unwrap();
+        assert_eq!(saved_search.query, "tag=math");
+        assert_eq!(
+            get_saved_search(&pool, saved_search.id).await.unwrap().name,
+            "math"
+        );
+        assert_eq!(
+            get_saved_search_by_name(&pool, "math").await.unwrap().id,
+            saved_search.id
+        );
+
+        // Names are unique and queries must be valid
+        assert!(
+            create_saved_search_helper(&pool, "math", "dog")
+                .await
+                .is_err()
+        );
+        assert!(create_saved_search_helper(&pool, "", "dog").await.is_err());
+        assert!(
+            create_saved_search_helper(&pool, "invalid", "tag=")
+                .await
+                .is_err()
+        );
+        assert!(
+            create_saved_search_helper(&pool, "unknown", "saved:other")
+                .await
+                .is_err()
+        );
+
+        let request = UpdateSavedSearchRequest {
+            name: None,
+            query: Some("tag=math or tag=physics".to_string()),
+        };
+        let updated = update_saved_search(&pool, request, saved_search.id)
+            .await
+            .unwrap();
+        assert_eq!(updated.name, "math");
+        assert_eq!(updated.query, "tag=math or tag=physics");
+
+        let saved_searches = list_saved_searches(&pool, FilterOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(saved_searches.len(), 1);
+
+        delete_saved_search(&pool, saved_search.id).await.unwrap();
+        assert!(get_saved_search(&pool, saved_search.id).await.is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_saved_search_references(pool: SqlitePool) -> () {
+        let math = create_saved_search_helper(&pool, "math", "tag=math")
+            .await
+            .unwrap();
+        let new_math = create_saved_search_helper(&pool, "new math", "saved:math c.state=0")
+            .await
+            .unwrap();
+
+        // Cycles are rejected
+        let request = UpdateSavedSearchRequest {
+            name: None,
+            query: Some("saved:\"new math\"".to_string()),
+        };
+        assert!(update_saved_search(&pool, request, math.id).await.is_err());
+        assert!(
+            create_saved_search_helper(&pool, "self", "saved:self")
+                .await
+                .is_err()
+        );
+
+        // Saved searches that are referenced cannot be renamed or deleted
+        let request = UpdateSavedSearchRequest {
+            name: Some("algebra".to_string()),
+            query: None,
+        };
+        assert!(update_saved_search(&pool, request, math.id).await.is_err());
+        assert!(delete_saved_search(&pool, math.id).await.is_err());
+        delete_saved_search(&pool, new_math.id).await.unwrap();
+        delete_saved_search(&pool, math.id).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_saved_search_in_queries(pool: SqlitePool) -> () {
+        let parser = create_parser_helper(&pool, "markdown").await;
+        let request = CreateNotesRequest {
+            parser_id: parser.id,
+            requests: ["math", "physics", "history"]
+                .into_iter()
+                .map(|tag| CreateNoteRequest {
+                    data: format!("{{{{ {tag} }}}}"),
+                    keywords: vec![],
+                    tags: vec![tag.to_string()],
+                    is_suspended: false,
+                    custom_data: Map::new(),
+                })
+                .collect(),
+        };
+        let notes = create_notes(&pool, request, Utc::now(), &get_all_parsers())
+            .await
+            .unwrap()
+            .notes;
+        create_saved_search_helper(&pool, "science", "tag=math or tag=physics sort:id desc")
+            .await
+            .unwrap();
+        create_saved_search_helper(&pool, "not math", "saved:science -tag=math")
+            .await
+            .unwrap();
+
+        let mut note_ids = Evaluator::new("saved:science")
+            .get_note_ids(&pool)
+            .await
+            .unwrap();
+        note_ids.sort_unstable();
+        assert_eq!(note_ids, vec![notes[0].id, notes[1].id]);
+        let note_ids = Evaluator::new("saved:\"not math\" or tag=history sort:id")
+            .get_note_ids(&pool)
+            .await
+            .unwrap();
+        assert_eq!(note_ids, vec![notes[1].id, notes[2].id]);
+        assert!(
+            Evaluator::new("saved:unknown")
+                .get_note_ids(&pool)
+                .await
+                .is_err()
+        );
+
+        // Filtered tags can use saved searches
+        let request = CreateTagRequest {
+            name: "filtered science".to_string(),
+            description: String::new(),
+            parent_id: None,
+            query: Some("saved:science".to_string()),
+            auto_delete: false,
+        };
+        let tag = create_tag(&pool, request).await.unwrap();
+        let card_tags: Vec<CardTag> = sqlx::query_as(r"SELECT * FROM card_tag WHERE tag_id = ?")
+            .bind(tag.id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(card_tags.len(), 2);
+
+        // Filtered tags cannot depend on other filtered tags through saved searches
+        create_saved_search_helper(&pool, "filtered", "tag=\"filtered science\"")
+            .await
+            .unwrap();
+        let request = CreateTagRequest {
+            name: "filtered 2".to_string(),
+            description: String::new(),
+            parent_id: None,
+            query: Some("saved:filtered".to_string()),
+            auto_delete: false,
+        };
+        assert!(create_tag(&pool, request).await.is_err());
+        assert!(get_tag_by_name(&pool, "filtered 2").await.is_err());
+    }
+}
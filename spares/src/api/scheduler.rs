@@ -1,6 +1,254 @@
-use crate::{Error, schedulers::get_scheduler_from_string, schema::review::Rating};
+use crate::{
+    Error, LibraryError, SchedulerErrorKind,
+    config::read_external_config,
+    model::{Card, DEFAULT_DESIRED_RETENTION, ReviewLog},
+    schedulers::{SrsScheduler, get_scheduler_from_string},
+    schema::review::{Rating, SimulateRequest, SimulateResponse, SimulationResult},
+};
+use chrono::{DateTime, Duration, Utc};
+
+/// Number of cards that are simulated for each desired retention. Every new card is assumed to follow the average of these cards.
+const SIMULATED_CARD_COUNT: u32 = 200;
+/// Upper bound on the number of reviews simulated for a single card.
+const MAX_REVIEWS_PER_CARD: u32 = 100;
+/// Maximum number of days that can be simulated.
+const MAX_SIMULATED_DAYS: u32 = 3650;
+/// Maximum number of desired retentions that can be compared in a single simulation.
+const MAX_SIMULATED_RETENTIONS: usize = 20;
+const DEFAULT_SIMULATED_RETENTIONS: [f64; 10] =
+    [0.70, 0.73, 0.76, 0.79, 0.82, 0.85, 0.88, 0.91, 0.94, 0.97];
 
 pub fn get_scheduler_ratings(scheduler_name: &str) -> Result<Vec<Rating>, Error> {
     let scheduler = get_scheduler_from_string(scheduler_name)?;
     Ok(scheduler.get_ratings())
 }
+
+/// The average review count, study time, and retrievability of a card, indexed by the number of days since it was first studied.
+struct CardAverages {
+    review_counts: Vec<f64>,
+    study_times: Vec<f64>,
+    retrievabilities: Vec<f64>,
+}
+
+#[allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn simulate_card_averages(
+    scheduler: &dyn SrsScheduler,
+    days: u32,
+    desired_retention: f64,
+    start: DateTime<Utc>,
+) -> Result<CardAverages, Error> {
+    let days = days as usize;
+    let mut averages = CardAverages {
+        review_counts: vec![0.; days],
+        study_times: vec![0.; days],
+        retrievabilities: vec![0.; days],
+    };
+    let num_reviews = u32::try_from(days)
+        .unwrap_or(u32::MAX)
+        .clamp(1, MAX_REVIEWS_PER_CARD);
+    let mut rng = rand::thread_rng();
+    for _ in 0..SIMULATED_CARD_COUNT {
+        let review_history = scheduler
+            .generate_review_history(1, num_reviews, start, desired_retention, &mut rng)
+            .swap_remove(0);
+
+        // Replay the review history to get the state of the card after each review.
+        let mut card = Card {
+            desired_retention,
+            ..Card::new(start)
+        };
+        let mut previous_review_log: Option<ReviewLog> = None;
+        let mut card_states = Vec::new();
+        for (rating_submission, reviewed_at) in review_history {
+            let day = (reviewed_at - start).num_days();
+            if day >= days as i64 {
+                break;
+            }
+            let (new_card, new_review_log) = scheduler.schedule(
+                &card,
+                previous_review_log,
+                rating_submission.rating,
+                reviewed_at,
+                rating_submission.duration,
+            )?;
+            averages.review_counts[day as usize] += 1.;
+            averages.study_times[day as usize] += rating_submission.duration.num_seconds() as f64;
+            card_states.push((new_card.clone(), reviewed_at));
+            card = new_card;
+            previous_review_log = Some(new_review_log);
+        }
+
+        let mut card_states_iter = card_states.iter().peekable();
+        let mut current_state = None;
+        for (day, retrievability) in averages.retrievabilities.iter_mut().enumerate() {
+            let day_end = start + Duration::days(day as i64 + 1);
+            while let Some(state) = card_states_iter.next_if(|(_, at)| *at < day_end) {
+                current_state = Some(state);
+            }
+            if let Some((card, last_reviewed_at)) = current_state {
+                *retrievability += scheduler.get_retrievability(card, *last_reviewed_at, day_end);
+            }
+        }
+    }
+
+    let sample_count = f64::from(SIMULATED_CARD_COUNT);
+    for values in [
+        &mut averages.review_counts,
+        &mut averages.study_times,
+        &mut averages.retrievabilities,
+    ] {
+        for value in values.iter_mut() {
+            *value /= sample_count;
+        }
+    }
+    Ok(averages)
+}
+
+/// Projects the daily workload and the knowledge retained when studying new cards every day with different desired retentions.
+///
+/// A sample of cards is simulated using the scheduler's model. Since every new card is expected to behave like the average of this sample, the workload on a given day is the sum of the workload of the cards introduced on each previous day.
+#[allow(clippy::cast_precision_loss)]
+pub fn simulate_desired_retention(
+    scheduler_name: &str,
+    request: SimulateRequest,
+    at: DateTime<Utc>,
+) -> Result<SimulateResponse, Error> {
+    let scheduler = get_scheduler_from_string(scheduler_name)?;
+    let SimulateRequest {
+        days,
+        new_cards_per_day,
+        desired_retentions,
+    } = request;
+    if !(1..=MAX_SIMULATED_DAYS).contains(&days) {
+        return Err(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::InvalidInput(format!(
+                "Days must be between 1 and {MAX_SIMULATED_DAYS}. Received `{days}`."
+            )),
+        )));
+    }
+    let new_cards_per_day = match new_cards_per_day {
+        Some(new_cards_per_day) => new_cards_per_day,
+        None => read_external_config()?.new_cards_daily_limit,
+    };
+    let desired_retentions =
+        desired_retentions.unwrap_or_else(|| DEFAULT_SIMULATED_RETENTIONS.to_vec());
+    if desired_retentions.len() > MAX_SIMULATED_RETENTIONS {
+        return Err(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::InvalidInput(format!(
+                "At most {MAX_SIMULATED_RETENTIONS} desired retentions can be simulated. Received {}.",
+                desired_retentions.len()
+            )),
+        )));
+    }
+    if let Some(invalid_retention) = desired_retentions
+        .iter()
+        .find(|retention| **retention <= 0. || **retention >= 1.)
+    {
+        return Err(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::InvalidDesiredRetention(*invalid_retention),
+        )));
+    }
+
+    let new_cards_per_day = f64::from(new_cards_per_day);
+    let results = desired_retentions
+        .into_iter()
+        .map(|desired_retention| {
+            let averages = simulate_card_averages(scheduler.as_ref(), days, desired_retention, at)?;
+            let cumulative_sum = |values: &[f64]| {
+                values
+                    .iter()
+                    .scan(0., |total, value| {
+                        *total += value * new_cards_per_day;
+                        Some(*total)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let review_counts = cumulative_sum(&averages.review_counts);
+            let study_times = cumulative_sum(&averages.study_times)
+                .into_iter()
+                .map(|seconds| Duration::seconds(seconds.round() as i64))
+                .collect::<Vec<_>>();
+            let knowledge_retained =
+                averages.retrievabilities.iter().sum::<f64>() * new_cards_per_day;
+            Ok(SimulationResult {
+                desired_retention,
+                review_counts,
+                study_times,
+                knowledge_retained,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let study_time_per_card_retained = |result: &SimulationResult| {
+        let total_study_time = result
+            .study_times
+            .iter()
+            .map(Duration::num_seconds)
+            .sum::<i64>();
+        total_study_time as f64 / result.knowledge_retained.max(f64::EPSILON)
+    };
+    let recommended_retention = results
+        .iter()
+        .min_by(|a, b| study_time_per_card_retained(a).total_cmp(&study_time_per_card_retained(b)))
+        .map_or(DEFAULT_DESIRED_RETENTION, |result| result.desired_retention);
+    Ok(SimulateResponse {
+        results,
+        recommended_retention,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_desired_retention() {
+        let request = SimulateRequest {
+            days: 30,
+            new_cards_per_day: Some(10),
+            desired_retentions: Some(vec![0.8, 0.95]),
+        };
+        let response = simulate_desired_retention("fsrs", request, Utc::now()).unwrap();
+        assert_eq!(response.results.len(), 2);
+        let (low, high) = (&response.results[0], &response.results[1]);
+        assert_eq!(low.review_counts.len(), 30);
+        // New cards are always studied on the first day.
+        assert!(low.review_counts[0] >= 10.);
+        // A higher retention requires more reviews and retains more knowledge.
+        assert!(high.review_counts.iter().sum::<f64>() > low.review_counts.iter().sum::<f64>());
+        assert!(high.knowledge_retained > low.knowledge_retained);
+        assert!([0.8, 0.95].contains(&response.recommended_retention));
+    }
+
+    #[test]
+    fn test_simulate_invalid_desired_retention() {
+        let request = SimulateRequest {
+            days: 30,
+            new_cards_per_day: Some(10),
+            desired_retentions: Some(vec![1.2]),
+        };
+        assert!(simulate_desired_retention("fsrs", request, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_simulate_invalid_input() {
+        for days in [0, MAX_SIMULATED_DAYS + 1] {
+            let request = SimulateRequest {
+                days,
+                new_cards_per_day: Some(10),
+                desired_retentions: Some(vec![0.9]),
+            };
+            assert!(simulate_desired_retention("fsrs", request, Utc::now()).is_err());
+        }
+        let request = SimulateRequest {
+            days: 30,
+            new_cards_per_day: Some(10),
+            desired_retentions: Some(vec![0.9; MAX_SIMULATED_RETENTIONS + 1]),
+        };
+        assert!(simulate_desired_retention("fsrs", request, Utc::now()).is_err());
+    }
+}
@@ -1,16 +1,26 @@
 use crate::{
-    Error,
+    Error, LibraryError, SchedulerErrorKind,
+    api::preset::{apply_preset_to_card, get_note_presets},
     config::read_external_config,
     helpers::get_start_end_local_date,
-    model::{Card, NEW_CARD_STATE, SpecialState, StateId},
-    schedulers::get_scheduler_from_string,
-    schema::review::{StatisticsRequest, StatisticsResponse},
+    model::{Card, CardId, NEW_CARD_STATE, ReviewLog, SpecialState, StateId},
+    schedulers::{SrsScheduler, get_scheduler_from_string},
+    schema::review::{
+        ForecastDay, ForecastRequest, ForecastResponse, StatisticsRequest, StatisticsResponse,
+    },
 };
-use chrono::{Duration, Local, Utc};
+use chrono::{DateTime, Duration, Local, Utc};
 use itertools::Itertools;
 use sqlx::sqlite::SqlitePool;
 use std::collections::HashMap;
 
+/// Number of times each card's reviews are simulated when forecasting. The forecast is the average of these simulations.
+const FORECAST_SAMPLE_COUNT: u32 = 20;
+/// Upper bound on the number of reviews simulated for a single card.
+const MAX_FORECAST_REVIEWS_PER_CARD: u32 = 100;
+/// Maximum number of days that can be forecast.
+const MAX_FORECAST_DAYS: u32 = 3650;
+
 #[allow(clippy::cast_possible_wrap)]
 #[allow(clippy::too_many_lines, reason = "off by a few")]
 pub async fn get_statistics(
@@ -137,3 +147,275 @@ pub async fn get_statistics(
         postpone_safe_count,
     })
 }
+
+/// Returns the average duration of past reviews in seconds.
+#[allow(clippy::cast_precision_loss)]
+fn get_average_duration<'a>(review_logs: impl Iterator<Item = &'a ReviewLog>) -> Option<f64> {
+    let (count, total) = review_logs.fold((0_u32, 0_i64), |(count, total), review_log| {
+        (count + 1, total + review_log.duration)
+    });
+    (count > 0).then(|| total as f64 / f64::from(count))
+}
+
+/// The cards and review history that a forecast is simulated from.
+///
+/// This is loaded separately from running the forecast, since the forecast is CPU bound and should be run on a blocking thread.
+pub struct ForecastInput {
+    /// The scheduler of each card's latest review, along with `scheduler_name`.
+    schedulers: HashMap<String, Box<dyn SrsScheduler>>,
+    /// The scheduler used for new cards.
+    scheduler_name: String,
+    /// Cards with their presets applied, along with their review logs sorted from oldest to newest.
+    cards: Vec<(Card, Vec<ReviewLog>)>,
+    average_new_duration: Option<f64>,
+    average_review_duration: Option<f64>,
+    days: u32,
+    new_cards_per_day: u32,
+    at: DateTime<Utc>,
+}
+
+/// Loads the data needed by `get_forecast()`.
+pub async fn get_forecast_input(
+    db: &SqlitePool,
+    request: ForecastRequest,
+    at: DateTime<Utc>,
+) -> Result<ForecastInput, Error> {
+    let ForecastRequest {
+        scheduler_name,
+        days,
+        new_cards_per_day,
+    } = request;
+    if !(1..=MAX_FORECAST_DAYS).contains(&days) {
+        return Err(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::InvalidInput(format!(
+                "Days must be between 1 and {MAX_FORECAST_DAYS}. Received `{days}`."
+            )),
+        )));
+    }
+    let config = read_external_config()?;
+    let new_cards_per_day = new_cards_per_day.unwrap_or(config.new_cards_daily_limit);
+
+    let cards: Vec<Card> = sqlx::query_as(
+        r"SELECT * FROM card WHERE special_state IS NULL OR special_state != ? ORDER BY due ASC, id ASC",
+    )
+    .bind(SpecialState::Suspended)
+    .fetch_all(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+    let review_logs: Vec<ReviewLog> =
+        sqlx::query_as(r"SELECT * FROM review_log ORDER BY reviewed_at ASC")
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+    let average_new_duration = get_average_duration(
+        review_logs
+            .iter()
+            .filter(|review_log| review_log.previous_state == NEW_CARD_STATE),
+    );
+    let average_review_duration = get_average_duration(
+        review_logs
+            .iter()
+            .filter(|review_log| review_log.previous_state != NEW_CARD_STATE),
+    );
+    let mut grouped_review_logs: HashMap<CardId, Vec<ReviewLog>> = review_logs
+        .into_iter()
+        .map(|review_log| (review_log.card_id, review_log))
+        .into_group_map();
+    let note_presets = get_note_presets(db, &config).await?;
+    let cards = cards
+        .into_iter()
+        .map(|card| {
+            let card_review_logs = grouped_review_logs.remove(&card.id).unwrap_or_default();
+            let preset_name = note_presets.get(&card.note_id).map(String::as_str);
+            (
+                apply_preset_to_card(&config, preset_name, card),
+                card_review_logs,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let schedulers = cards
+        .iter()
+        .filter_map(|(_, card_review_logs)| card_review_logs.last())
+        .map(|review_log| review_log.scheduler_name.as_str())
+        .chain(std::iter::once(scheduler_name.as_str()))
+        .unique()
+        .map(|name| Ok((name.to_string(), get_scheduler_from_string(name)?)))
+        .collect::<Result<HashMap<_, _>, Error>>()?;
+    Ok(ForecastInput {
+        schedulers,
+        scheduler_name,
+        cards,
+        average_new_duration,
+        average_review_duration,
+        days,
+        new_cards_per_day,
+        at,
+    })
+}
+
+/// Forecasts the number of reviews and the study time of each of the next `days` days.
+///
+/// Each card is reviewed on the day it is due with ratings sampled from the scheduler of its latest review at the card's desired retention, so cards that come due again within the forecast are reviewed again. New cards are introduced at the daily limit in the order they are due, using the requested scheduler. The study time of each review is the card's average review duration, falling back to the average duration of all reviews of cards in the same state.
+#[allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+pub fn get_forecast(input: ForecastInput) -> Result<ForecastResponse, Error> {
+    let ForecastInput {
+        schedulers,
+        scheduler_name,
+        cards,
+        average_new_duration,
+        average_review_duration,
+        days,
+        new_cards_per_day,
+        at,
+    } = input;
+
+    let today = at.with_timezone(&Local).date_naive();
+    let (start_of_today, _) = get_start_end_local_date(&at);
+    let get_day =
+        |date: DateTime<Utc>| (date.with_timezone(&Local).date_naive() - today).num_days();
+    let days_count = days as usize;
+    let num_reviews = days.clamp(1, MAX_FORECAST_REVIEWS_PER_CARD);
+    let mut review_counts = vec![0.; days_count];
+    let mut study_times = vec![0.; days_count];
+    let mut rng = rand::thread_rng();
+    let mut new_card_index = 0;
+    for (card, card_review_logs) in cards {
+        let first_review_at = if card.state == NEW_CARD_STATE {
+            if new_cards_per_day == 0 {
+                continue;
+            }
+            let day = new_card_index / new_cards_per_day;
+            new_card_index += 1;
+            (start_of_today + Duration::days(i64::from(day))).max(at)
+        } else if card.special_state.is_some() {
+            // Buried cards are unburied tomorrow
+            card.due.max(start_of_today + Duration::days(1))
+        } else {
+            card.due.max(at)
+        };
+        if get_day(first_review_at) >= days_count as i64 {
+            continue;
+        }
+        let card_scheduler_name = card_review_logs
+            .last()
+            .map_or(scheduler_name.as_str(), |review_log| {
+                review_log.scheduler_name.as_str()
+            });
+        let scheduler = schedulers[card_scheduler_name].as_ref();
+        let card_average_duration = get_average_duration(card_review_logs.iter());
+
+        for _ in 0..FORECAST_SAMPLE_COUNT {
+            let review_history = scheduler
+                .generate_review_history(
+                    1,
+                    num_reviews,
+                    first_review_at,
+                    card.desired_retention,
+                    &mut rng,
+                )
+                .swap_remove(0);
+            let mut current_card = card.clone();
+            let mut previous_review_log = card_review_logs.last().cloned();
+            let mut reviewed_at = first_review_at;
+            for (rating_submission, _) in review_history {
+                let day = get_day(reviewed_at);
+                if day >= days_count as i64 {
+                    break;
+                }
+                let average_duration = if current_card.state == NEW_CARD_STATE {
+                    average_new_duration
+                } else {
+                    average_review_duration
+                };
+                let duration_seconds = card_average_duration
+                    .or(average_duration)
+                    .unwrap_or(rating_submission.duration.num_seconds() as f64);
+                let (new_card, new_review_log) = scheduler.schedule(
+                    &current_card,
+                    previous_review_log,
+                    rating_submission.rating,
+                    reviewed_at,
+                    Duration::seconds(duration_seconds.round() as i64),
+                )?;
+                review_counts[day as usize] += 1.;
+                study_times[day as usize] += duration_seconds;
+                reviewed_at = new_card.due.max(reviewed_at);
+                current_card = new_card;
+                previous_review_log = Some(new_review_log);
+            }
+        }
+    }
+
+    let sample_count = f64::from(FORECAST_SAMPLE_COUNT);
+    let forecast_days = review_counts
+        .into_iter()
+        .zip(study_times)
+        .enumerate()
+        .map(|(day, (review_count, study_time))| ForecastDay {
+            date: today + chrono::Days::new(day as u64),
+            review_count: review_count / sample_count,
+            study_time: Duration::seconds((study_time / sample_count).round() as i64),
+        })
+        .collect::<Vec<_>>();
+    Ok(ForecastResponse {
+        days: forecast_days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::note::tests::tests::create_note_helper;
+
+    #[sqlx::test]
+    async fn test_forecast(pool: SqlitePool) -> () {
+        let _notes = create_note_helper(&pool).await;
+        let (card_count,): (u32,) = sqlx::query_as(r"SELECT COUNT(*) FROM card")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(card_count > 1);
+
+        let request = ForecastRequest {
+            scheduler_name: "fsrs".to_string(),
+            days: 30,
+            new_cards_per_day: Some(1),
+        };
+        let input = get_forecast_input(&pool, request, Utc::now())
+            .await
+            .unwrap();
+        let response = get_forecast(input).unwrap();
+        assert_eq!(response.days.len(), 30);
+        // 1 new card is introduced each day, and it may be reviewed again on the same day.
+        assert!(response.days[0].review_count >= 1.);
+        assert!(response.days[0].study_time > Duration::zero());
+        // Cards introduced earlier come due again later in the forecast.
+        let total_reviews = response
+            .days
+            .iter()
+            .map(|day| day.review_count)
+            .sum::<f64>();
+        assert!(total_reviews > f64::from(card_count));
+    }
+
+    #[sqlx::test]
+    async fn test_forecast_invalid_days(pool: SqlitePool) -> () {
+        for days in [0, MAX_FORECAST_DAYS + 1] {
+            let request = ForecastRequest {
+                scheduler_name: "fsrs".to_string(),
+                days,
+                new_cards_per_day: Some(1),
+            };
+            assert!(
+                get_forecast_input(&pool, request, Utc::now())
+                    .await
+                    .is_err()
+            );
+        }
+    }
+}
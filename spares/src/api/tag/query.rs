@@ -1,13 +1,18 @@
 use crate::{
     Error, LibraryError, TagErrorKind,
-    api::card::create_card_tags,
+    api::{card::create_card_tags, saved_search::get_saved_search_queries},
     model::TagId,
-    search::{evaluator::Evaluator, lexer::Lexer},
+    search::{evaluator::Evaluator, lexer::Lexer, saved_search::expand_saved_searches},
 };
 use sqlx::sqlite::SqlitePool;
 
 pub async fn verify_filtered_tag_query(db: &SqlitePool, query: &str) -> Result<(), Error> {
-    let mut lexer = Lexer::new(query);
+    // Saved searches are expanded first, so the tags they depend on are found too
+    let saved_searches = get_saved_search_queries(db).await?;
+    let expanded_query = expand_saved_searches(query, &saved_searches).map_err(|e| {
+        Error::Library(LibraryError::Tag(TagErrorKind::InvalidInput(e.to_string())))
+    })?;
+    let mut lexer = Lexer::new(&expanded_query);
     let tag_dependencies = lexer.extract_tag_dependencies().map_err(|e| {
         Error::Library(LibraryError::Tag(TagErrorKind::InvalidInput(e.to_string())))
     })?;
@@ -5,7 +5,7 @@ use crate::{
         parser::tests::create_parser_helper,
         tests::{GenerateNotesRequest, NUM_DAYS_TO_SIMULATE_KEY, START_DATE_KEY, SimulatedReview},
     },
-    model::{Card, ReviewLog},
+    model::{Card, DEFAULT_DESIRED_RETENTION, ReviewLog},
     parsers::{
         BackReveal, ClozeGrouping, ClozeGroupingSettings, ClozeSettings, FrontConceal,
         NoteSettingsKeys, Parseable, construct_cloze_string, find_parser, get_all_parsers,
@@ -328,6 +328,7 @@ pub async fn generate_notes(
             card_count,
             *num_reviews,
             first_review_date,
+            DEFAULT_DESIRED_RETENTION,
             &mut rng,
         );
 
@@ -369,8 +370,13 @@ pub fn generate_review_logs(
     let num_siblings = 1;
     let num_reviews = rng.gen_range(3..=5);
     let first_review_date = initial_card.created_at;
-    let review_histories_all =
-        scheduler.generate_review_history(num_siblings, num_reviews, first_review_date, &mut rng);
+    let review_histories_all = scheduler.generate_review_history(
+        num_siblings,
+        num_reviews,
+        first_review_date,
+        initial_card.desired_retention,
+        &mut rng,
+    );
     let review_histories = &review_histories_all[0];
     let (card, review_logs) =
         review_histories
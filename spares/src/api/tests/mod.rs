@@ -381,7 +381,7 @@ async fn simulate_reviews(
                 },
             };
             let advance_res = submit_study_action(&pool, request, requested_date).await;
-            assert!(advance_res.is_ok());
+            assert!(advance_res.unwrap().message.is_some());
             let request = StatisticsRequest {
                 scheduler_name: scheduler_name.to_string(),
                 date: requested_date,
@@ -408,7 +408,7 @@ async fn simulate_reviews(
                 },
             };
             let postpone_res = submit_study_action(&pool, request, requested_date).await;
-            assert!(postpone_res.is_ok());
+            assert!(postpone_res.unwrap().message.is_some());
             let request = StatisticsRequest {
                 scheduler_name: scheduler_name.to_string(),
                 date: requested_date,
@@ -1,11 +1,17 @@
 use crate::parsers::image_occlusion::ImageOcclusionConfig;
+use crate::parsers::impls::asciidoc::AsciidocParserConfig;
+use crate::parsers::impls::custom::CustomParserDefinition;
 use crate::parsers::impls::markdown::MarkdownParserConfig;
+use crate::parsers::impls::org::OrgParserConfig;
+use crate::parsers::impls::rst::RstParserConfig;
+use crate::parsers::impls::source::SourceParserConfig;
 use crate::parsers::overlapper::OverlapperConfig;
+use crate::parsers::{get_builtin_parsers, is_valid_parser_name};
 use crate::{Error, LibraryError};
 use chrono::{DateTime, Duration, NaiveDate, Utc, Weekday};
 use etcetera::{AppStrategy, AppStrategyArgs, choose_app_strategy};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{create_dir_all, read_to_string, write};
 use std::path::PathBuf;
 use toml_edit::DocumentMut;
@@ -111,6 +117,9 @@ pub fn get_env_config(env: Environment) -> EnvironmentConfig {
 pub struct SparesInternalConfig {
     pub last_unburied: DateTime<Utc>,
     pub linked_notes_generated: bool,
+    /// Parameters fitted to the review history by the `fsrs` scheduler.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fsrs_parameters: Option<FsrsParametersConfig>,
     // #[serde_as(as = "serde_with::DurationSeconds<i64>")]
     // pub fuzz_range: Duration,
     // #[serde_as(as = "serde_with::DurationSeconds<i64>")]
@@ -122,13 +131,23 @@ impl Default for SparesInternalConfig {
         Self {
             last_unburied: DateTime::<Utc>::MIN_UTC,
             linked_notes_generated: false,
+            fsrs_parameters: None,
             // fuzz_range: Duration::days(4),
             // reschedule_range: Duration::weeks(1),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FsrsParametersConfig {
+    pub weights: Vec<f64>,
+    /// When the weights were fitted.
+    pub fitted_at: DateTime<Utc>,
+    /// The number of reviews that the weights were fitted to.
+    pub review_count: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct EasyDaysConfig {
     /// With default settings for [`self.days_to_workload_percentage`], this makes your workload (reviews per day) more consistent.
@@ -187,33 +206,133 @@ impl Default for EasyDaysConfig {
 //     }
 // }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct LeechConfig {
-    // pub auto_tag: bool,
-    // pub tag_name: String,
-    /// The number of lapses after which to tag a card as a leech.
+    /// Whether to add `tag_name` to the note of a card when it becomes a leech.
+    pub auto_tag: bool,
+    pub tag_name: String,
+    /// Whether to suspend a card when it becomes a leech.
+    pub auto_suspend: bool,
+    /// The number of lapses after which a card is a leech.
     pub lapses_threshold: u32,
 }
 
 impl Default for LeechConfig {
     fn default() -> Self {
         Self {
-            // auto_tag: true,
-            // tag_name: "leech".to_string(),
+            auto_tag: true,
+            tag_name: "leech".to_string(),
+            auto_suspend: false,
             lapses_threshold: 8,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde_with::serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LeitnerConfig {
+    /// The interval of each box. Passed cards move up a box and failed cards move back to the first box.
+    #[serde_as(as = "Vec<serde_with::DurationSeconds<i64>>")]
+    pub box_intervals: Vec<Duration>,
+}
+
+impl Default for LeitnerConfig {
+    fn default() -> Self {
+        Self {
+            box_intervals: [1, 2, 4, 8, 16, 32].map(Duration::days).to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub leitner: LeitnerConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 #[serde(default)]
 pub struct ParserConfig {
+    pub asciidoc: AsciidocParserConfig,
     pub markdown: MarkdownParserConfig,
+    pub org: OrgParserConfig,
+    pub rst: RstParserConfig,
+    pub source: SourceParserConfig,
+    /// Parsers defined with `[[parser.custom]]` tables. These are used like the built-in parsers, without recompiling.
+    pub custom: Vec<CustomParserDefinition>,
+}
+
+/// Order of cards that have been studied before.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewOrder {
+    /// Earliest due date first.
+    #[default]
+    Due,
+    /// Lowest retrievability first. Retrievability is estimated from the time since the last review relative to the card's stability.
+    Retrievability,
+    /// Highest difficulty first.
+    Difficulty,
+    Random,
+    /// Alternates between tags, choosing the tag that was studied least recently. Cards of the same tag are ordered by their due date. A note's tag is the first of its tags alphabetically.
+    InterleaveTags,
+}
+
+/// Order of new cards.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewCardOrder {
+    /// Cards from older notes first, so notes that depend on previous knowledge are shown in the right order.
+    #[default]
+    NoteCreated,
+    Random,
+    /// Cards whose notes have a tag earlier in [`StudyOrderConfig::new_card_tag_priority`] first. Ties are broken by the note's creation date.
+    TagPriority,
+}
+
+/// New cards and cards that have been studied before are ordered separately. Out of the next card of each, the one that is due first is shown.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StudyOrderConfig {
+    pub review_order: ReviewOrder,
+    pub new_card_order: NewCardOrder,
+    /// Tag names, from highest to lowest priority. Used by [`NewCardOrder::TagPriority`].
+    pub new_card_tag_priority: Vec<String>,
 }
 
+/// Scheduling options that override the global options for the cards of certain tags. This is similar to Anki's deck options.
 #[serde_with::serde_as]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PresetConfig {
+    /// Names of the tags that use this preset. Descendants of these tags also use this preset, unless they are attached to a different preset.
+    ///
+    /// If a note's tags use different presets, the preset whose name comes first alphabetically is used.
+    pub tags: Vec<String>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum_interval: Option<Duration>,
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum_interval: Option<Duration>,
+    /// New cards studied with this preset are counted separately from the global limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_cards_daily_limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lapses_threshold: Option<u32>,
+    /// Overrides the desired retention of each card.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_retention: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bury_new_siblings: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bury_review_siblings: Option<bool>,
+}
+
+#[serde_with::serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct SparesExternalConfig {
     #[serde_as(as = "serde_with::DurationSeconds<i64>")]
@@ -222,10 +341,20 @@ pub struct SparesExternalConfig {
     #[serde_as(as = "serde_with::DurationSeconds<i64>")]
     pub minimum_interval: Duration,
     pub new_cards_daily_limit: u32,
+    /// The maximum number of cards in review to study each day. Cards that are being learned or relearned are not limited. There is no limit if this is not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reviews_daily_limit: Option<u32>,
+    pub study_order: StudyOrderConfig,
+    /// Whether new cards are buried until the next day when one of their siblings is studied.
+    pub bury_new_siblings: bool,
+    /// Whether cards that have been studied before are buried until the next day when one of their siblings is studied.
+    pub bury_review_siblings: bool,
     pub flagged_tag_name: String,
     pub easy_days: EasyDaysConfig,
     // pub disperse_siblings: DisperseSiblingsConfig,
     pub leech: LeechConfig,
+    pub presets: BTreeMap<String, PresetConfig>,
+    pub scheduler: SchedulerConfig,
     pub parser: ParserConfig,
     pub image_occlusion: ImageOcclusionConfig,
     pub overlapper: OverlapperConfig,
@@ -237,10 +366,16 @@ impl Default for SparesExternalConfig {
             maximum_interval: Duration::days(180),
             minimum_interval: Duration::days(2),
             new_cards_daily_limit: 20,
+            reviews_daily_limit: None,
+            study_order: StudyOrderConfig::default(),
+            bury_new_siblings: true,
+            bury_review_siblings: true,
             flagged_tag_name: "flagged".to_string(),
             easy_days: EasyDaysConfig::default(),
             // disperse_siblings: DisperseSiblingsConfig::default(),
             leech: LeechConfig::default(),
+            presets: BTreeMap::default(),
+            scheduler: SchedulerConfig::default(),
             parser: ParserConfig::default(),
             image_occlusion: ImageOcclusionConfig::default(),
             overlapper: OverlapperConfig::default(),
@@ -249,6 +384,33 @@ impl Default for SparesExternalConfig {
 }
 
 impl SparesExternalConfig {
+    /// Returns the config with the overrides of the given preset applied.
+    #[must_use]
+    pub fn apply_preset(&self, preset_name: Option<&str>) -> Self {
+        let mut config = self.clone();
+        if let Some(preset) = preset_name.and_then(|name| self.presets.get(name)) {
+            if let Some(maximum_interval) = preset.maximum_interval {
+                config.maximum_interval = maximum_interval;
+            }
+            if let Some(minimum_interval) = preset.minimum_interval {
+                config.minimum_interval = minimum_interval;
+            }
+            if let Some(new_cards_daily_limit) = preset.new_cards_daily_limit {
+                config.new_cards_daily_limit = new_cards_daily_limit;
+            }
+            if let Some(lapses_threshold) = preset.lapses_threshold {
+                config.leech.lapses_threshold = lapses_threshold;
+            }
+            if let Some(bury_new_siblings) = preset.bury_new_siblings {
+                config.bury_new_siblings = bury_new_siblings;
+            }
+            if let Some(bury_review_siblings) = preset.bury_review_siblings {
+                config.bury_review_siblings = bury_review_siblings;
+            }
+        }
+        config
+    }
+
     fn validate(&mut self) -> Result<(), String> {
         for (weekday, workload_percentage) in &self.easy_days.days_to_workload_percentage {
             if !(&0_f64..=&1.).contains(&workload_percentage) {
@@ -267,6 +429,66 @@ impl SparesExternalConfig {
             return Err("Each day cannot have 0 workload.".to_string());
         }
 
+        if self.leech.auto_tag && self.leech.tag_name.trim().is_empty() {
+            return Err("Leech tag name cannot be empty.".to_string());
+        }
+
+        let mut preset_tags = HashSet::new();
+        for (preset_name, preset) in &self.presets {
+            if let Some(desired_retention) = preset.desired_retention {
+                if desired_retention <= 0. || desired_retention >= 1. {
+                    return Err(format!(
+                        "Preset {}'s desired retention must be between 0 and 1.",
+                        preset_name
+                    ));
+                }
+            }
+            for tag_name in &preset.tags {
+                if !preset_tags.insert(tag_name) {
+                    return Err(format!(
+                        "Tag {} is attached to more than one preset.",
+                        tag_name
+                    ));
+                }
+            }
+        }
+
+        let builtin_parser_names = get_builtin_parsers()
+            .into_iter()
+            .map(|parser_fn| parser_fn().get_parser_name())
+            .collect::<HashSet<_>>();
+        let mut custom_parser_names = HashSet::new();
+        for definition in &self.parser.custom {
+            if !is_valid_parser_name(&definition.name) {
+                return Err(format!(
+                    "Parser name {:?} is invalid. Only lowercase letters and dashes are allowed.",
+                    definition.name
+                ));
+            }
+            if builtin_parser_names.contains(definition.name.as_str())
+                || !custom_parser_names.insert(definition.name.as_str())
+            {
+                return Err(format!(
+                    "Parser {} is defined more than once.",
+                    definition.name
+                ));
+            }
+            definition.validate()?;
+        }
+
+        if self.scheduler.leitner.box_intervals.is_empty() {
+            return Err("Leitner scheduler must have at least 1 box.".to_string());
+        }
+        if self
+            .scheduler
+            .leitner
+            .box_intervals
+            .iter()
+            .any(|interval| *interval <= Duration::zero())
+        {
+            return Err("Leitner box intervals must be positive.".to_string());
+        }
+
         // Add missing days
         for weekday in [
             Weekday::Mon,
@@ -162,10 +162,16 @@ pub enum SchedulerErrorKind {
     AlreadyBuried,
     #[error("Cannot bury suspended card.")]
     Suspended,
+    #[error("There are no study actions to undo.")]
+    NothingToUndo,
     #[error("Invalid state. Received `{0}`.")]
     InvalidState(StateId),
     #[error("Invalid rating. Received `{0}`.")]
     InvalidRating(RatingId),
+    #[error("Desired retention must be between 0 and 1. Received `{0}`.")]
+    InvalidDesiredRetention(f64),
+    #[error("{0}")]
+    InvalidInput(String),
     #[error("`{scheduler_name}` scheduler returned an error: {error}")]
     Custom {
         scheduler_name: String,
@@ -14,6 +14,8 @@ pub type RatingId = u32;
 pub type CustomData = Map<String, Value>;
 
 pub const NEW_CARD_STATE: StateId = 0;
+/// The state of cards that have been learned. Every scheduler uses this id for cards in review.
+pub const REVIEW_CARD_STATE: StateId = 2;
 pub const DEFAULT_DESIRED_RETENTION: f64 = 0.9;
 pub const NOTE_ID_KEY: &str = "note-id";
 
@@ -123,6 +125,14 @@ pub struct Tag {
     pub auto_delete: bool,
 }
 
+/// A named query that can be referenced inside other queries with `saved:name`.
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+}
+
 #[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
 pub struct NoteTag {
     pub note_id: NoteId,
@@ -186,3 +196,14 @@ impl ReviewLog {
         }
     }
 }
+
+/// Recorded when a card's lapses go over the leech threshold.
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct LeechEvent {
+    pub id: i64,
+    pub card_id: CardId,
+    #[serde(with = "ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    /// The number of lapses when the card became a leech.
+    pub lapses: u32,
+}
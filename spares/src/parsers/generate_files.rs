@@ -49,6 +49,7 @@ pub enum RenderOutputType {
     Card(usize, CardSide),
 }
 
+/// `output_rendered_filepath` ends with the parser's [`Parseable::rendered_file_extension`], so files rendered in a different format, such as a pdf instead of html, are rendered again.
 pub fn file_in_cache(
     render: bool,
     force_render: bool,
@@ -47,7 +47,7 @@ mod test;
 const CLOZE_SETTINGS_KEY: &str = "data-cloze-settings";
 const CLOZES_GROUP_ID: &str = "clozes-group";
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ImageOcclusionConfig {
     pub cloze_to_answer_color: String,
@@ -0,0 +1,144 @@
+use crate::{DelimiterErrorKind, parsers::ClozeMatch};
+use unscanny::Scanner;
+
+/// Finds clozes delimited by the strings in a parser's definition. Clozes can be nested, unless the start and end delimiters are the same.
+pub struct ClozeParser<'a> {
+    s: Scanner<'a>,
+    cloze_start: &'a str,
+    cloze_end: &'a str,
+    comment_start: &'a str,
+    comment_end: &'a str,
+}
+
+impl<'a> ClozeParser<'a> {
+    pub fn new(
+        input: &'a str,
+        (cloze_start, cloze_end): (&'a str, &'a str),
+        (comment_start, comment_end): (&'a str, &'a str),
+    ) -> Self {
+        Self {
+            s: Scanner::new(input),
+            cloze_start,
+            cloze_end,
+            comment_start,
+            comment_end,
+        }
+    }
+
+    /// Returns clozes, ordered by their starting delim position. This means for nested clozes, the outer cloze will be returned first, then the inner cloze.
+    pub fn parse(mut self) -> Result<Vec<ClozeMatch>, DelimiterErrorKind> {
+        let mut all_clozes = Vec::new();
+        let mut current_clozes = Vec::new();
+        loop {
+            let cursor_start = self.s.cursor();
+            // Handle cloze closing. This is checked first so delimiters that are the same toggle a cloze.
+            if !current_clozes.is_empty() && self.s.eat_if(self.cloze_end) {
+                let (start_match, settings_match) = current_clozes.pop().unwrap();
+                all_clozes.push(ClozeMatch {
+                    start_match,
+                    end_match: cursor_start..self.s.cursor(),
+                    settings_match,
+                });
+            }
+            // Handle cloze opening
+            else if self.s.eat_if(self.cloze_start) {
+                let mut settings_match = 0..0;
+                if self.s.eat_if('[') {
+                    let settings_start_idx = self.s.cursor();
+                    self.s.eat_until(']');
+                    settings_match = settings_start_idx..self.s.cursor();
+                    self.s.eat_if(']');
+                }
+                current_clozes.push((cursor_start..self.s.cursor(), settings_match));
+            }
+            // Comment
+            else if !self.comment_start.is_empty() && self.s.eat_if(self.comment_start) {
+                if self.comment_end.is_empty() {
+                    self.s.eat_until('\n');
+                } else {
+                    self.s.eat_until(self.comment_end);
+                    self.s.eat_if(self.comment_end);
+                }
+            }
+            // Escaped character
+            else if self.s.eat_if('\\') {
+                self.s.eat();
+            } else if self.s.eat().is_none() {
+                break;
+            }
+        }
+        if !current_clozes.is_empty() {
+            return Err(DelimiterErrorKind::EndMatchNotFound {
+                src: self.s.string().to_string(),
+            });
+        }
+        all_clozes.sort_by_key(|x| x.start_match.start);
+        Ok(all_clozes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOZE_DELIMS: (&str, &str) = ("<<", ">>");
+    const COMMENT_DELIMS: (&str, &str) = ("%", "");
+
+    #[test]
+    fn test_clozes() {
+        let input = "Test <<basic>> and <<[o:1] outer <<inner>>>>";
+        let clozes = ClozeParser::new(input, CLOZE_DELIMS, COMMENT_DELIMS).parse();
+        assert_eq!(
+            clozes.unwrap(),
+            vec![
+                ClozeMatch {
+                    start_match: 5..7,
+                    end_match: 12..14,
+                    settings_match: 0..0,
+                },
+                ClozeMatch {
+                    start_match: 19..26,
+                    end_match: 42..44,
+                    settings_match: 22..25,
+                },
+                ClozeMatch {
+                    start_match: 33..35,
+                    end_match: 40..42,
+                    settings_match: 0..0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_delimiters() {
+        let input = "Test ==[o:1]first== and ==second==";
+        let clozes = ClozeParser::new(input, ("==", "=="), COMMENT_DELIMS).parse();
+        let clozes = clozes
+            .unwrap()
+            .into_iter()
+            .map(|x| &input[x.start_match.end..x.end_match.start])
+            .collect::<Vec<_>>();
+        assert_eq!(clozes, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "% <<line>>\n\\<<escaped>>";
+        let clozes = ClozeParser::new(input, CLOZE_DELIMS, COMMENT_DELIMS).parse();
+        assert_eq!(clozes.unwrap(), vec![]);
+        let input = "/* <<block>> */";
+        let clozes = ClozeParser::new(input, CLOZE_DELIMS, ("/*", "*/")).parse();
+        assert_eq!(clozes.unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_unterminated_cloze() {
+        let input = "Test <<basic";
+        let clozes = ClozeParser::new(input, CLOZE_DELIMS, COMMENT_DELIMS).parse();
+        assert!(matches!(
+            clozes,
+            Err(DelimiterErrorKind::EndMatchNotFound { .. })
+        ));
+    }
+}
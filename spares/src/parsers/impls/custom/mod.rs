@@ -0,0 +1,575 @@
+use crate::{
+    Error, LibraryError,
+    config::read_external_config,
+    parsers::{
+        ClozeHiddenReplacement, ClozeMatch, ClozeReplacement, ConstructFileDataType,
+        ConstructImageOcclusionType, GenerateNoteFilesRequest, NoteImportAction, NotePart,
+        NoteSettingsKeys, Parseable, RegexMatch, RenderOutputType,
+        generate_files::CardSide,
+        get_default_template_path, get_output_raw_dir,
+        image_occlusion::{ImageOcclusionData, construct_image_occlusion_from_image},
+        impls::run_export_command,
+    },
+    schema::note::LinkedNote,
+};
+use cloze_parser::ClozeParser;
+use fancy_regex::Regex;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+mod cloze_parser;
+
+/// A parser defined in the config file, like
+///
+/// ```toml
+/// [[parser.custom]]
+/// name = "djot"
+/// file_extension = "dj"
+/// comment_start = "{%"
+/// comment_end = "%}"
+/// cloze_start = "{="
+/// cloze_end = "=}"
+/// linked_notes_regex = '\[([^\]]*)\]\[li[0-9]*\]'
+/// export_command = ["djot", "{input}", "-o", "{output}"]
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CustomParserDefinition {
+    /// Only lowercase letters and dashes are allowed, since this is used as a directory name.
+    pub name: String,
+    pub file_extension: String,
+    pub comment_start: String,
+    /// Comments end at the end of the line if this is empty.
+    #[serde(default)]
+    pub comment_end: String,
+    /// Cloze settings are written in brackets right after this, like `{=[o:1]`.
+    pub cloze_start: String,
+    pub cloze_end: String,
+    /// Defaults to `comment_start` followed by `#`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setting_start: Option<String>,
+    /// Defaults to `comment_end`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setting_end: Option<String>,
+    /// The first capture group is the keyword of the linked note. Notes can not link to other notes if this is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linked_notes_regex: Option<String>,
+    /// Defaults to `template.{file_extension}` in the `templates` directory of the parser's config directory, like the built-in parsers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<PathBuf>,
+    /// Command used to render a file. `{input}` and `{output}` are replaced with the path of the file and the rendered file.
+    pub export_command: Vec<String>,
+}
+
+impl CustomParserDefinition {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.file_extension.is_empty() {
+            return Err(format!(
+                "Parser {}'s file extension cannot be empty.",
+                self.name
+            ));
+        }
+        if self.comment_start.trim().is_empty() {
+            return Err(format!(
+                "Parser {}'s comment start cannot be empty.",
+                self.name
+            ));
+        }
+        if self.cloze_start.is_empty() || self.cloze_end.is_empty() {
+            return Err(format!(
+                "Parser {}'s cloze delimiters cannot be empty.",
+                self.name
+            ));
+        }
+        if let Some(linked_notes_regex) = &self.linked_notes_regex {
+            let regex = Regex::new(linked_notes_regex).map_err(|e| {
+                format!(
+                    "Parser {}'s linked notes regex is invalid: {}",
+                    self.name, e
+                )
+            })?;
+            if regex.captures_len() < 2 {
+                return Err(format!(
+                    "Parser {}'s linked notes regex must capture the keyword.",
+                    self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn setting_start(&self) -> String {
+        self.setting_start
+            .clone()
+            .unwrap_or_else(|| format!("{} #", self.comment_start))
+    }
+
+    fn setting_end(&self) -> &str {
+        self.setting_end.as_deref().unwrap_or(&self.comment_end)
+    }
+}
+
+/// Returns the parsers defined in the config file.
+///
+/// The definitions are cached in a process-wide `OnceLock` the first time they are read successfully, so they can be borrowed for the rest of the process. Adding, removing or editing a custom parser in the config file requires restarting the server.
+///
+/// If the config file can not be read, the error is logged and no custom parsers are returned. The config file is read again on the next call, so the parsers are loaded once it is fixed.
+pub fn get_custom_parser_definitions() -> &'static [CustomParserDefinition] {
+    static DEFINITIONS: OnceLock<Vec<CustomParserDefinition>> = OnceLock::new();
+    if let Some(definitions) = DEFINITIONS.get() {
+        return definitions;
+    }
+    match read_external_config() {
+        Ok(config) => DEFINITIONS.get_or_init(|| config.parser.custom),
+        Err(e) => {
+            error!("Failed to read custom parsers from the config file: {}", e);
+            &[]
+        }
+    }
+}
+
+/// Parser for a markup language defined in the config file. See [`CustomParserDefinition`].
+///
+/// Linked notes can not be rewritten without knowing the language, so the notes they match are listed in comments after the note.
+#[derive(Clone, Debug)]
+pub struct CustomParser {
+    definition: &'static CustomParserDefinition,
+}
+
+impl CustomParser {
+    pub fn new(definition: &'static CustomParserDefinition) -> Self {
+        Self { definition }
+    }
+
+    fn construct_delimited(start: &str, data: &str, end: &str) -> String {
+        // Add trailing newline (POSIX convention)
+        if end.is_empty() {
+            format!("{start} {data}\n")
+        } else {
+            format!("{start} {data} {end}\n")
+        }
+    }
+
+    fn get_linked_notes_string(
+        &self,
+        note_data: &str,
+        linked_notes_opt: Option<&Vec<LinkedNote>>,
+    ) -> String {
+        let Some(linked_notes) = linked_notes_opt.filter(|x| !x.is_empty()) else {
+            return note_data.to_string();
+        };
+        let items = linked_notes
+            .iter()
+            .map(|linked_note_request| {
+                let LinkedNote {
+                    searched_keyword,
+                    linked_note_id,
+                    matched_keyword,
+                } = linked_note_request;
+                assert_eq!(linked_note_id.is_some(), matched_keyword.is_some());
+                match (linked_note_id, matched_keyword) {
+                    (None, None) => self.construct_comment(&format!("{}: -", searched_keyword)),
+                    (Some(linked_note_id), Some(matched_keyword)) => {
+                        let mut note_raw_path = get_output_raw_dir(
+                            self.get_parser_name(),
+                            RenderOutputType::Note,
+                            None,
+                        );
+                        note_raw_path.push(
+                            self.get_output_filename(RenderOutputType::Note, *linked_note_id),
+                        );
+                        note_raw_path.set_extension(self.file_extension());
+                        self.construct_comment(&format!(
+                            "{} -> {}: {}",
+                            searched_keyword,
+                            matched_keyword,
+                            note_raw_path.display()
+                        ))
+                    }
+                    (None, Some(_)) | (Some(_), None) => unreachable!(),
+                }
+            })
+            .collect::<String>();
+        format!("{}\n\n{}", note_data, items.trim_end())
+    }
+}
+
+impl Parseable for CustomParser {
+    fn get_parser_name(&self) -> &'static str {
+        self.definition.name.as_str()
+    }
+
+    fn get_linked_notes(&self, data: &str) -> Result<Vec<Range<usize>>, LibraryError> {
+        let Some(linked_notes_regex) = &self.definition.linked_notes_regex else {
+            return Ok(Vec::new());
+        };
+        let linked_notes_regex = Regex::new(linked_notes_regex)
+            .map_err(|e| LibraryError::InvalidConfig(e.to_string()))?;
+        let linked_notes_data = linked_notes_regex
+            .captures_iter(data)
+            .filter_map(|c| c.unwrap().get(1).map(|x| x.start()..x.end()))
+            .collect::<Vec<_>>();
+        Ok(linked_notes_data)
+    }
+
+    fn get_settings(&self, data: &str) -> Result<Vec<RegexMatch>, LibraryError> {
+        let setting_end = self.definition.setting_end();
+        let regex_string = if setting_end.is_empty() {
+            format!(
+                r"(?m)^{} ([^\n]*)$",
+                fancy_regex::escape(&self.definition.setting_start())
+            )
+        } else {
+            format!(
+                r"(?m)^{} ([^\n]*) {}$",
+                fancy_regex::escape(&self.definition.setting_start()),
+                fancy_regex::escape(setting_end)
+            )
+        };
+        let settings_regex = Regex::new(&regex_string).unwrap();
+        let settings_data = settings_regex
+            .captures_iter(data)
+            .map(|c| c.unwrap())
+            .map(|c| RegexMatch {
+                match_range: c.get(0).map(|x| x.start()..x.end()).unwrap(),
+                capture_range: c.get(1).map(|x| x.start()..x.end()).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        Ok(settings_data)
+    }
+
+    fn get_clozes(&self, data: &str) -> Result<Vec<ClozeMatch>, LibraryError> {
+        let CustomParserDefinition {
+            cloze_start,
+            cloze_end,
+            comment_start,
+            comment_end,
+            ..
+        } = self.definition;
+        ClozeParser::new(data, (cloze_start, cloze_end), (comment_start, comment_end))
+            .parse()
+            .map_err(LibraryError::Delimiter)
+    }
+
+    fn construct_cloze(&self, cloze_settings_string: &str, _data: &str) -> (String, String) {
+        let cloze_settings_string_with_delim = if cloze_settings_string.is_empty() {
+            cloze_settings_string.to_string()
+        } else {
+            format!("[{}]", cloze_settings_string)
+        };
+        let cloze_start = format!(
+            "{}{}",
+            self.definition.cloze_start, cloze_settings_string_with_delim
+        );
+        let cloze_end = self.definition.cloze_end.clone();
+        (cloze_start, cloze_end)
+    }
+
+    fn construct_setting(&self, data: &str) -> String {
+        Self::construct_delimited(
+            &self.definition.setting_start(),
+            data,
+            self.definition.setting_end(),
+        )
+    }
+
+    fn construct_comment(&self, data: &str) -> String {
+        Self::construct_delimited(
+            &self.definition.comment_start,
+            data,
+            &self.definition.comment_end,
+        )
+    }
+
+    fn extract_comment<'a>(&self, data: &'a str) -> &'a str {
+        let CustomParserDefinition {
+            comment_start,
+            comment_end,
+            ..
+        } = self.definition;
+        data.strip_prefix(comment_start.as_str())
+            .and_then(|x| x.strip_suffix(comment_end.as_str()))
+            .map_or(data, |x| x.trim())
+    }
+
+    fn template_path(&self) -> PathBuf {
+        self.definition.template.clone().unwrap_or_else(|| {
+            get_default_template_path(self.get_parser_name(), self.file_extension())
+        })
+    }
+
+    #[allow(clippy::let_and_return, reason = "Make note vs card data explicit")]
+    #[allow(clippy::too_many_lines, reason = "File data is long")]
+    fn construct_file_data(
+        &self,
+        output_type: ConstructFileDataType,
+        request: &GenerateNoteFilesRequest,
+        note_import_action: &NoteImportAction,
+    ) -> String {
+        let GenerateNoteFilesRequest {
+            note_id,
+            note_data,
+            keywords,
+            linked_notes,
+            custom_data,
+            tags,
+        } = request;
+        let keywords_str = keywords.join(", ");
+        let tags_str = tags.join(", ");
+        let NoteSettingsKeys {
+            action: action_key,
+            action_add: action_add_key,
+            settings_key_value_delim,
+            custom_data: custom_data_key,
+            note_id: note_id_key,
+            ..
+        } = self.note_settings_keys();
+        match output_type {
+            ConstructFileDataType::Note => {
+                let note_data =
+                    self.get_linked_notes_string(note_data.as_str(), linked_notes.as_ref());
+                let custom_data_str = if custom_data.is_empty() {
+                    String::new()
+                } else {
+                    let custom_data_str_content = serde_json::to_string(custom_data).unwrap();
+                    let custom_data_string = format!(
+                        "{}{} {}",
+                        custom_data_key.get_write(),
+                        settings_key_value_delim,
+                        custom_data_str_content.as_str(),
+                    );
+                    self.construct_setting(custom_data_string.as_str())
+                };
+                let action_string = if matches!(note_import_action, NoteImportAction::Update(_)) {
+                    String::new()
+                } else {
+                    let action_value = match note_import_action {
+                        NoteImportAction::Add => action_add_key,
+                        NoteImportAction::Update(_) | NoteImportAction::Delete(_) => unreachable!(),
+                    };
+                    self.construct_setting(&format!(
+                        "{}{} {}",
+                        action_key.get_write(),
+                        settings_key_value_delim,
+                        action_value.get_write(),
+                    ))
+                };
+                let note_id_string = format!(
+                    "{}{} {}",
+                    note_id_key.get_write(),
+                    settings_key_value_delim,
+                    note_id
+                );
+                let keywords_string =
+                    format!("keywords{} {}", settings_key_value_delim, keywords_str);
+                let tags_string = format!("tags{} {}", settings_key_value_delim, tags_str);
+                let lines = [
+                    "\n".to_string(),
+                    action_string,
+                    self.construct_setting(&note_id_string),
+                    self.construct_setting(&keywords_string),
+                    self.construct_setting(&tags_string),
+                    custom_data_str,
+                    self.construct_comment("spares: note start"),
+                    note_data,
+                    "\n".to_string(),
+                    self.construct_comment("spares: note end"),
+                    "\n".to_string(),
+                ];
+                let note_file_data = lines.into_iter().collect::<String>();
+                note_file_data
+            }
+            ConstructFileDataType::Card(card_order, card_data, side) => {
+                let mut image_occlusion_order: usize = 1;
+                let card_data = card_data
+                    .data
+                    .iter()
+                    .map(|p| match p {
+                        NotePart::ClozeData(d, cloze_replacement) => self
+                            .construct_cloze_replacement(
+                                &ClozeReplacement::parse(side, cloze_replacement, d),
+                                side,
+                            ),
+                        NotePart::SurroundingData(d) => d.clone(),
+                        NotePart::ImageOcclusion { data, .. } => {
+                            let image_occlusion = self.construct_image_occlusion(
+                                data,
+                                ConstructImageOcclusionType::Card {
+                                    side,
+                                    note_id: *note_id,
+                                    card_order,
+                                    image_occlusion_order,
+                                },
+                            );
+                            image_occlusion_order += 1;
+                            image_occlusion
+                        }
+                        NotePart::ClozeStart(_) | NotePart::ClozeEnd(_) => String::new(),
+                    })
+                    .collect::<String>();
+                // The markup of the language is unknown, so the card's settings are written as plain text.
+                let mut lines = vec![format!("note-id{} {}", settings_key_value_delim, note_id)];
+                if !keywords_str.is_empty() {
+                    lines.push(format!(
+                        "keywords{} {}",
+                        settings_key_value_delim, keywords_str
+                    ));
+                }
+                lines.extend(vec![
+                    format!("tags{} {}", settings_key_value_delim, tags_str),
+                    String::new(),
+                    card_data,
+                ]);
+                let card_file_data = lines.join("\n");
+                card_file_data
+            }
+        }
+    }
+
+    fn construct_cloze_replacement(
+        &self,
+        cloze_replacement: &ClozeReplacement,
+        side: CardSide,
+    ) -> String {
+        match cloze_replacement {
+            ClozeReplacement::Hidden(cloze_replacement) => match cloze_replacement {
+                ClozeHiddenReplacement::ToAnswer { hint } => {
+                    if let Some(hint) = hint {
+                        format!("[_____({})]", hint)
+                    } else {
+                        "[_____]".to_string()
+                    }
+                }
+                ClozeHiddenReplacement::NotToAnswer => match side {
+                    CardSide::Front => "[_____(no answer)]".to_string(),
+                    CardSide::Back => "[_____]".to_string(),
+                },
+            },
+            ClozeReplacement::Reveal(data) => format!("[{}]", data),
+        }
+    }
+
+    fn construct_image_occlusion(
+        &self,
+        image_occlusion_data: &ImageOcclusionData,
+        output_type: ConstructImageOcclusionType,
+    ) -> String {
+        fn construct_image(file_path: &Path, _caption: &str) -> String {
+            format!("{}\n", file_path.display())
+        }
+        construct_image_occlusion_from_image(
+            self,
+            construct_image,
+            image_occlusion_data,
+            output_type,
+        )
+    }
+
+    fn file_extension(&self) -> &'static str {
+        self.definition.file_extension.as_str()
+    }
+
+    fn render_file(
+        &self,
+        _aux_dir: &Path,
+        output_text_filepath: &Path,
+        _output_rendered_dir: &Path,
+        output_rendered_filepath: &Path,
+    ) -> Result<std::process::Output, Error> {
+        run_export_command(
+            self.get_parser_name(),
+            &self.definition.export_command,
+            output_text_filepath,
+            output_rendered_filepath,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomParser, CustomParserDefinition};
+    use crate::{
+        parsers::{NotePart, Parseable, get_cards},
+        schema::note::LinkedNote,
+    };
+
+    fn get_parser() -> CustomParser {
+        let definition = CustomParserDefinition {
+            name: "djot".to_string(),
+            file_extension: "dj".to_string(),
+            comment_start: "{%".to_string(),
+            comment_end: "%}".to_string(),
+            cloze_start: "{=".to_string(),
+            cloze_end: "=}".to_string(),
+            setting_start: None,
+            setting_end: None,
+            linked_notes_regex: Some(r"\[([^\]]*)\]\[li[0-9]*\]".to_string()),
+            template: None,
+            export_command: Vec::new(),
+        };
+        assert!(definition.validate().is_ok());
+        CustomParser::new(Box::leak(Box::new(definition)))
+    }
+
+    #[test]
+    fn test_custom_linked_notes() {
+        let parser = get_parser();
+        let note_data =
+            "Linking to [keyword 1][li] and [keyword 2][li2], but not [docs](https://djot.net)";
+        let linked_notes = parser
+            .get_linked_notes(note_data)
+            .unwrap()
+            .into_iter()
+            .map(|x| &note_data[x])
+            .collect::<Vec<_>>();
+        assert_eq!(linked_notes, vec!["keyword 1", "keyword 2"]);
+        let new_note_data = parser.get_linked_notes_string(
+            note_data,
+            Some(&vec![LinkedNote {
+                searched_keyword: "keyword 2".to_string(),
+                linked_note_id: None,
+                matched_keyword: None,
+            }]),
+        );
+        assert_eq!(
+            new_note_data,
+            format!("{}\n\n{{% keyword 2: - %}}", note_data)
+        );
+    }
+
+    #[test]
+    fn test_custom_settings_and_comments() {
+        let parser = get_parser();
+        let data = "{% # note-id: 1 %}\n{% # tags: math %}\n{% spares: note start %}\n";
+        let settings = parser
+            .get_settings(data)
+            .unwrap()
+            .into_iter()
+            .map(|x| &data[x.capture_range])
+            .collect::<Vec<_>>();
+        assert_eq!(settings, vec!["note-id: 1", "tags: math"]);
+        assert_eq!(
+            parser.construct_setting("tags: math"),
+            "{% # tags: math %}\n"
+        );
+        // The hash of a note file is read from its last line.
+        let hash_line = parser.construct_comment("hash: abc");
+        assert_eq!(parser.extract_comment(hash_line.trim_end()), "hash: abc");
+    }
+
+    #[test]
+    fn test_custom_get_cards() {
+        let parser = get_parser();
+        let cards = get_cards(&parser, None, "a {=b=} c {% {=d=} %}", true, false).unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(
+            cards[0].data[1],
+            NotePart::ClozeStart("{=[o:1]".to_string())
+        );
+        assert_eq!(cards[0].data[3], NotePart::ClozeEnd("=}".to_string()));
+    }
+}
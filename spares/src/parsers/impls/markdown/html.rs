@@ -0,0 +1,54 @@
+use fancy_regex::Regex;
+use pulldown_cmark::{Event, Options, Parser, html};
+
+const STYLE: &str = "body { max-width: 48em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; line-height: 1.5; } mark { padding: 0 0.2em; } img { max-width: 100%; }";
+
+/// Renders a markdown file as an html document, without any external tools.
+///
+/// Images are linked by their path rather than embedded, so the document can only be viewed on the machine it was rendered on.
+///
+/// Math is not rendered. It is kept with `\(` `\)` and `\[` `\]` delimiters in spans with the `math` class, so it can be rendered by the viewer with a library like `KaTeX` or `MathJax`. `head` is added to the document's head, which can be used to load such a library.
+pub(crate) fn render_html(markdown: &str, head: Option<&str>) -> String {
+    let markdown = replace_pandoc_syntax(markdown);
+    let options = Options::ENABLE_MATH
+        | Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+    let parser = Parser::new_ext(&markdown, options).map(|event| match event {
+        Event::InlineMath(math) => Event::InlineMath(format!(r"\({}\)", math).into()),
+        Event::DisplayMath(math) => Event::DisplayMath(format!(r"\[{}\]", math).into()),
+        _ => event,
+    });
+    let mut body = String::new();
+    html::push_html(&mut body, parser);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>{}</style>\n{}</head>\n<body>\n{}</body>\n</html>\n",
+        STYLE,
+        head.map(|x| format!("{}\n", x)).unwrap_or_default(),
+        body
+    )
+}
+
+/// Card files use some of pandoc's extensions to markdown, which are not part of the commonmark spec. These are replaced with html.
+fn replace_pandoc_syntax(markdown: &str) -> String {
+    let highlight_regex = Regex::new(r"\[([^\]\n]*)\]\{\.mark\}").unwrap();
+    let markdown = highlight_regex.replace_all(markdown, "<mark>$1</mark>");
+    let rule_regex = Regex::new(r"(?m)^\$\\hrulefill\$$").unwrap();
+    rule_regex.replace_all(&markdown, "<hr>").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_html;
+
+    #[test]
+    fn test_render_html() {
+        let markdown = "---\nfontsize: 14pt\n---\nThe answer is [_____]{.mark}, where $x^2 < 1$.\n\n$\\hrulefill$\n\n$$\ny = 2\n$$\n";
+        let html = render_html(markdown, None);
+        assert!(!html.contains("fontsize"));
+        assert!(html.contains("<mark>_____</mark>"));
+        assert!(html.contains(r#"<span class="math math-inline">\(x^2 &lt; 1\)</span>"#));
+        assert!(html.contains(r#"<span class="math math-display">\["#));
+        assert!(html.contains("<hr>"));
+    }
+}
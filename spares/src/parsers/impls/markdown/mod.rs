@@ -13,19 +13,49 @@ use crate::{
 };
 use cloze_parser::ClozeParser;
 use fancy_regex::{Captures, Regex};
+use log::error;
 use serde::{Deserialize, Serialize};
 use std::{
+    fs::{read_to_string, write},
     ops::Range,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, ExitStatus, Output},
+    sync::OnceLock,
 };
 
 mod cloze_parser;
+mod html;
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 #[serde(default)]
 pub struct MarkdownParserConfig {
+    pub renderer: MarkdownRenderer,
+    /// Used by [`MarkdownRenderer::Pandoc`].
     pub defaults_file: Option<String>,
+    /// Added to the head of html files rendered by [`MarkdownRenderer::Builtin`], such as to load a library that renders math.
+    pub html_head: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkdownRenderer {
+    /// Renders pdf files with pandoc.
+    #[default]
+    Pandoc,
+    /// Renders html files without any external tools. Math is left for the viewer to render.
+    Builtin,
+}
+
+impl MarkdownRenderer {
+    /// If the config file can not be read, the error is logged and the default renderer is used.
+    fn from_config() -> Self {
+        read_external_config()
+            .inspect_err(|e| {
+                error!("Failed to read the markdown renderer from the config file: {e}");
+            })
+            .map(|config| config.parser.markdown.renderer)
+            .unwrap_or_default()
+    }
 }
 
 /// Inspired by <https://github.com/st3v3nmw/obsidian-spaced-repetition>.
@@ -33,12 +63,19 @@ pub struct MarkdownParserConfig {
 /// Adheres to the [CommonMark Spec](https://commonmark.org/help/).
 ///
 /// See <https://allefeld.github.io/nerd-notes/Markdown/A%20writer's%20guide%20to%20Pandoc's%20Markdown.html>
-#[derive(Clone, Copy, Debug, Default)]
-pub struct MarkdownParser {}
+#[derive(Clone, Debug, Default)]
+pub struct MarkdownParser {
+    /// Read from the config file the first time it is needed, so files are always rendered in the format that their extension says.
+    renderer: OnceLock<MarkdownRenderer>,
+}
 
 impl MarkdownParser {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    fn renderer(&self) -> MarkdownRenderer {
+        *self.renderer.get_or_init(MarkdownRenderer::from_config)
     }
 }
 
@@ -125,150 +162,13 @@ impl Parseable for MarkdownParser {
             .map_or(data, |x| x.trim())
     }
 
-    #[allow(clippy::let_and_return, reason = "Make note vs card data explicit")]
-    #[allow(clippy::too_many_lines, reason = "File data is long")]
     fn construct_file_data(
         &self,
         output_type: ConstructFileDataType,
         request: &GenerateNoteFilesRequest,
         note_import_action: &NoteImportAction,
     ) -> String {
-        let GenerateNoteFilesRequest {
-            note_id,
-            note_data,
-            keywords,
-            linked_notes,
-            custom_data,
-            tags,
-        } = request;
-        let keywords_str = keywords.join(", ");
-        let tags_str = tags.join(", ");
-        let NoteSettingsKeys {
-            action: action_key,
-            action_add: action_add_key,
-            settings_key_value_delim,
-            custom_data: custom_data_key,
-            note_id: note_id_key,
-            ..
-        } = self.note_settings_keys();
-        match output_type {
-            ConstructFileDataType::Note => {
-                let note_data =
-                    get_linked_notes_string(self, note_data.as_str(), linked_notes.as_ref());
-                let custom_data_str = if custom_data.is_empty() {
-                    String::new()
-                } else {
-                    let custom_data_str_content = serde_json::to_string(custom_data).unwrap();
-                    let custom_data_string = format!(
-                        "{}{} {}",
-                        custom_data_key.get_write(),
-                        settings_key_value_delim,
-                        custom_data_str_content.as_str(),
-                    );
-                    self.construct_setting(custom_data_string.as_str())
-                    // let delim_with_space = format!("{} ", settings_delim);
-                    // let custom_data_str_content = custom_data
-                    //     .iter()
-                    //     // `v.as_str()` removes the extra quotes around the value. See <https://stackoverflow.com/questions/72345657/how-do-i-get-the-string-value-of-a-json-value-without-quotes>.
-                    //     .filter_map(|(k, v)| v.as_str().map(|s| (k, s.to_string())))
-                    //     .map(|(k, v)| format!("{}{} {}", k, settings_key_value_delim, v))
-                    //     .collect::<Vec<_>>()
-                    //     .join(delim_with_space.as_str());
-                    // format!(
-                    //     "{}{}",
-                    //     self.construct_comment("Custom data"),
-                    //     self.construct_setting(custom_data_str_content.as_str()),
-                    // )
-                };
-                let action_string = if matches!(note_import_action, NoteImportAction::Update(_)) {
-                    String::new()
-                } else {
-                    let action_value = match note_import_action {
-                        NoteImportAction::Add => action_add_key,
-                        NoteImportAction::Update(_) | NoteImportAction::Delete(_) => unreachable!(),
-                    };
-                    self.construct_setting(&format!(
-                        "{}{} {}",
-                        action_key.get_write(),
-                        settings_key_value_delim,
-                        action_value.get_write(),
-                    ))
-                };
-                let note_id_string = format!(
-                    "{}{} {}",
-                    note_id_key.get_write(),
-                    settings_key_value_delim,
-                    note_id
-                );
-                let keywords_string =
-                    format!("keywords{} {}", settings_key_value_delim, keywords_str);
-                let tags_string = format!("tags{} {}", settings_key_value_delim, tags_str);
-                let lines = [
-                    // self.construct_comment("spares: start"),
-                    // "\n".to_string(),
-                    "\n".to_string(),
-                    action_string,
-                    self.construct_setting(&note_id_string),
-                    self.construct_setting(&keywords_string),
-                    self.construct_setting(&tags_string),
-                    custom_data_str,
-                    self.construct_comment("spares: note start"),
-                    note_data.to_string(),
-                    "\n".to_string(),
-                    self.construct_comment("spares: note end"),
-                    "\n".to_string(),
-                    // "\n".to_string(),
-                    // self.construct_comment("spares: end"),
-                ];
-                let note_file_data = lines.into_iter().collect::<String>();
-                note_file_data
-            }
-            ConstructFileDataType::Card(card_order, card_data, side) => {
-                let mut image_occlusion_order: usize = 1;
-                let card_data = card_data
-                    .data
-                    .iter()
-                    .map(|p| match p {
-                        NotePart::ClozeData(d, cloze_replacement) => self
-                            .construct_cloze_replacement(
-                                &ClozeReplacement::parse(side, cloze_replacement, d),
-                                side,
-                            ),
-                        NotePart::SurroundingData(d) => d.to_string(),
-                        NotePart::ImageOcclusion { data, .. } => {
-                            let image_occlusion = self.construct_image_occlusion(
-                                data,
-                                ConstructImageOcclusionType::Card {
-                                    side,
-                                    note_id: *note_id,
-                                    card_order,
-                                    image_occlusion_order,
-                                },
-                            );
-                            image_occlusion_order += 1;
-                            image_occlusion
-                        }
-                        NotePart::ClozeStart(_) | NotePart::ClozeEnd(_) => String::new(),
-                    })
-                    .collect::<String>();
-                let mut lines = vec![format!("- note-id{} {}", settings_key_value_delim, note_id)];
-                if !keywords_str.is_empty() {
-                    lines.push(format!(
-                        "- keywords{} {}",
-                        settings_key_value_delim, keywords_str
-                    ));
-                }
-                lines.extend(vec![
-                    format!("- tags{} {}", settings_key_value_delim, tags_str),
-                    String::new(),
-                    "$\\hrulefill$".to_string(),
-                    String::new(),
-                    card_data.to_string(),
-                ]);
-                let card_file_data = lines.join("\n");
-                card_file_data
-            }
-        }
+        construct_file_data(self, output_type, request, note_import_action)
     }
 
     fn construct_cloze_replacement(
@@ -327,6 +227,13 @@ impl Parseable for MarkdownParser {
         "md"
     }
 
+    fn rendered_file_extension(&self) -> &'static str {
+        match self.renderer() {
+            MarkdownRenderer::Pandoc => "pdf",
+            MarkdownRenderer::Builtin => "html",
+        }
+    }
+
     fn render_file(
         &self,
         _aux_dir: &Path,
@@ -334,13 +241,29 @@ impl Parseable for MarkdownParser {
         _output_rendered_dir: &Path,
         output_rendered_filepath: &Path,
     ) -> Result<std::process::Output, Error> {
+        let config = read_external_config()?;
+        if self.renderer() == MarkdownRenderer::Builtin {
+            let markdown = read_to_string(output_text_filepath).map_err(|e| Error::Io {
+                description: format!("Failed to read {}", output_text_filepath.display()),
+                source: e,
+            })?;
+            let html = html::render_html(&markdown, config.parser.markdown.html_head.as_deref());
+            write(output_rendered_filepath, html).map_err(|e| Error::Io {
+                description: format!("Failed to write {}", output_rendered_filepath.display()),
+                source: e,
+            })?;
+            return Ok(Output {
+                status: ExitStatus::default(),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            });
+        }
         // Output is rendered as a pdf. This is because some formats, like png, do not support text selection. Other formats, such as svg, do not have popular viewers on all platforms.
         let mut base_command = Command::new("pandoc");
         let mut command = base_command
             .arg("-o")
             .arg(output_rendered_filepath)
             .arg(output_text_filepath);
-        let config = read_external_config().unwrap();
         if let Some(defaults_file) = config.parser.markdown.defaults_file {
             command = command.arg("--defaults").arg(defaults_file);
         }
@@ -351,6 +274,152 @@ impl Parseable for MarkdownParser {
     }
 }
 
+/// Also used by parsers that store notes as markdown, with `parser`'s settings and comments.
+#[allow(clippy::let_and_return, reason = "Make note vs card data explicit")]
+#[allow(clippy::too_many_lines, reason = "File data is long")]
+pub(crate) fn construct_file_data(
+    parser: &dyn Parseable,
+    output_type: ConstructFileDataType,
+    request: &GenerateNoteFilesRequest,
+    note_import_action: &NoteImportAction,
+) -> String {
+    let GenerateNoteFilesRequest {
+        note_id,
+        note_data,
+        keywords,
+        linked_notes,
+        custom_data,
+        tags,
+    } = request;
+    let keywords_str = keywords.join(", ");
+    let tags_str = tags.join(", ");
+    let NoteSettingsKeys {
+        action: action_key,
+        action_add: action_add_key,
+        settings_key_value_delim,
+        custom_data: custom_data_key,
+        note_id: note_id_key,
+        ..
+    } = parser.note_settings_keys();
+    match output_type {
+        ConstructFileDataType::Note => {
+            let note_data =
+                get_linked_notes_string(parser, note_data.as_str(), linked_notes.as_ref());
+            let custom_data_str = if custom_data.is_empty() {
+                String::new()
+            } else {
+                let custom_data_str_content = serde_json::to_string(custom_data).unwrap();
+                let custom_data_string = format!(
+                    "{}{} {}",
+                    custom_data_key.get_write(),
+                    settings_key_value_delim,
+                    custom_data_str_content.as_str(),
+                );
+                parser.construct_setting(custom_data_string.as_str())
+                // let delim_with_space = format!("{} ", settings_delim);
+                // let custom_data_str_content = custom_data
+                //     .iter()
+                //     // `v.as_str()` removes the extra quotes around the value. See <https://stackoverflow.com/questions/72345657/how-do-i-get-the-string-value-of-a-json-value-without-quotes>.
+                //     .filter_map(|(k, v)| v.as_str().map(|s| (k, s.to_string())))
+                //     .map(|(k, v)| format!("{}{} {}", k, settings_key_value_delim, v))
+                //     .collect::<Vec<_>>()
+                //     .join(delim_with_space.as_str());
+                // format!(
+                //     "{}{}",
+                //     parser.construct_comment("Custom data"),
+                //     parser.construct_setting(custom_data_str_content.as_str()),
+                // )
+            };
+            let action_string = if matches!(note_import_action, NoteImportAction::Update(_)) {
+                String::new()
+            } else {
+                let action_value = match note_import_action {
+                    NoteImportAction::Add => action_add_key,
+                    NoteImportAction::Update(_) | NoteImportAction::Delete(_) => unreachable!(),
+                };
+                parser.construct_setting(&format!(
+                    "{}{} {}",
+                    action_key.get_write(),
+                    settings_key_value_delim,
+                    action_value.get_write(),
+                ))
+            };
+            let note_id_string = format!(
+                "{}{} {}",
+                note_id_key.get_write(),
+                settings_key_value_delim,
+                note_id
+            );
+            let keywords_string = format!("keywords{} {}", settings_key_value_delim, keywords_str);
+            let tags_string = format!("tags{} {}", settings_key_value_delim, tags_str);
+            let lines = [
+                // parser.construct_comment("spares: start"),
+                // "\n".to_string(),
+                "\n".to_string(),
+                action_string,
+                parser.construct_setting(&note_id_string),
+                parser.construct_setting(&keywords_string),
+                parser.construct_setting(&tags_string),
+                custom_data_str,
+                parser.construct_comment("spares: note start"),
+                note_data.to_string(),
+                "\n".to_string(),
+                parser.construct_comment("spares: note end"),
+                "\n".to_string(),
+                // "\n".to_string(),
+                // parser.construct_comment("spares: end"),
+            ];
+            let note_file_data = lines.into_iter().collect::<String>();
+            note_file_data
+        }
+        ConstructFileDataType::Card(card_order, card_data, side) => {
+            let mut image_occlusion_order: usize = 1;
+            let card_data = card_data
+                .data
+                .iter()
+                .map(|p| match p {
+                    NotePart::ClozeData(d, cloze_replacement) => parser
+                        .construct_cloze_replacement(
+                            &ClozeReplacement::parse(side, cloze_replacement, d),
+                            side,
+                        ),
+                    NotePart::SurroundingData(d) => d.to_string(),
+                    NotePart::ImageOcclusion { data, .. } => {
+                        let image_occlusion = parser.construct_image_occlusion(
+                            data,
+                            ConstructImageOcclusionType::Card {
+                                side,
+                                note_id: *note_id,
+                                card_order,
+                                image_occlusion_order,
+                            },
+                        );
+                        image_occlusion_order += 1;
+                        image_occlusion
+                    }
+                    NotePart::ClozeStart(_) | NotePart::ClozeEnd(_) => String::new(),
+                })
+                .collect::<String>();
+            let mut lines = vec![format!("- note-id{} {}", settings_key_value_delim, note_id)];
+            if !keywords_str.is_empty() {
+                lines.push(format!(
+                    "- keywords{} {}",
+                    settings_key_value_delim, keywords_str
+                ));
+            }
+            lines.extend(vec![
+                format!("- tags{} {}", settings_key_value_delim, tags_str),
+                String::new(),
+                "$\\hrulefill$".to_string(),
+                String::new(),
+                card_data.to_string(),
+            ]);
+            let card_file_data = lines.join("\n");
+            card_file_data
+        }
+    }
+}
+
 /// <https://pandoc.org/MANUAL.html?pandocs-markdown#reference-links>
 fn get_linked_notes_regex() -> Regex {
     Regex::new(r"(?m)\[([^\]]*)\]\[li([^\]]*)?\]").unwrap()
@@ -1,3 +1,43 @@
+use crate::{Error, LibraryError};
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+pub mod asciidoc;
+pub mod custom;
 pub mod latex;
 pub mod markdown;
+pub mod org;
+pub mod rst;
+pub mod source;
 pub mod typst;
+
+/// Runs the `export_command` from a parser's config. `{input}` and `{output}` in its arguments are replaced with the path of the text file and the rendered file.
+pub(crate) fn run_export_command(
+    parser_name: &str,
+    export_command: &[String],
+    output_text_filepath: &Path,
+    output_rendered_filepath: &Path,
+) -> Result<Output, Error> {
+    let export_command = export_command
+        .iter()
+        .map(|arg| {
+            arg.replace("{input}", &output_text_filepath.display().to_string())
+                .replace("{output}", &output_rendered_filepath.display().to_string())
+        })
+        .collect::<Vec<_>>();
+    let Some((program, args)) = export_command.split_first() else {
+        return Err(Error::Library(LibraryError::InvalidConfig(format!(
+            "`parser.{}.export_command` must not be empty.",
+            parser_name
+        ))));
+    };
+    Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| Error::Io {
+            description: format!("Failed to run {} command", program),
+            source: e,
+        })
+}
@@ -0,0 +1,226 @@
+use crate::parsers::ClozeMatch;
+use unscanny::Scanner;
+
+pub struct ClozeParser<'de> {
+    s: Scanner<'de>,
+    math_mode: bool,
+}
+
+impl<'a> ClozeParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            s: Scanner::new(input),
+            math_mode: false,
+        }
+    }
+
+    fn at_line_start(&self, cursor: usize) -> bool {
+        cursor == 0 || self.s.get(0..cursor).ends_with('\n')
+    }
+
+    /// Returns clozes, ordered by their starting delim position. This means for nested clozes, the outer cloze will be returned first, then the inner cloze.
+    pub fn next_cloze(&mut self) -> Option<Vec<ClozeMatch>> {
+        let mut nesting_level = 0;
+        let mut all_clozes = Vec::new();
+        let mut current_clozes = Vec::new();
+
+        loop {
+            let cursor_start = self.s.cursor();
+            match self.s.eat() {
+                // Comment block
+                Some('#')
+                    if self.at_line_start(cursor_start)
+                        && self.s.eat_if_ignore_case("+begin_comment") =>
+                {
+                    self.s.eat_until_ignore_case("#+end_comment");
+                }
+                // Comment or keyword line
+                Some('#') if self.at_line_start(cursor_start) && self.s.at([' ', '+', '\n']) => {
+                    self.s.eat_until('\n');
+                }
+                // Handle math mode transitions
+                Some('\\') if !self.math_mode && (self.s.eat_if('(') || self.s.eat_if('[')) => {
+                    self.math_mode = true;
+                }
+                Some('\\') if self.math_mode && (self.s.eat_if(')') || self.s.eat_if(']')) => {
+                    self.math_mode = false;
+                }
+                // Escaped character
+                Some('\\') => {
+                    self.s.eat(); // Consume backslash
+                }
+                Some('$') if !self.math_mode => {
+                    self.s.eat_if('$');
+                    self.math_mode = true;
+                }
+                Some('$') if self.math_mode => {
+                    self.s.eat_if('$');
+                    self.math_mode = false;
+                }
+                // Handle cloze opening
+                Some('{') if self.s.eat_if('{') && !self.math_mode => {
+                    let mut settings = None;
+                    if self.s.eat_if('[') {
+                        let settings_start_idx = self.s.cursor();
+                        self.s.eat_until(']');
+                        settings = Some(settings_start_idx..self.s.cursor());
+                    }
+                    let cursor_end = self.s.cursor();
+                    current_clozes.push((
+                        cursor_start..settings.clone().map_or(cursor_end, |x| x.end + 1),
+                        settings.filter(|x| !x.is_empty()).unwrap_or_default(),
+                    ));
+                }
+                // Handle cloze closing
+                Some('}') if self.s.eat_if('}') && !self.math_mode => {
+                    nesting_level -= 1;
+                    if let Some((opener_range, settings)) = current_clozes.pop() {
+                        all_clozes.push(ClozeMatch {
+                            start_match: opener_range,
+                            end_match: cursor_start..self.s.cursor(),
+                            settings_match: settings.clone(),
+                        });
+                    }
+                    if nesting_level == 0 {
+                        break;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    break;
+                }
+            }
+        }
+
+        if all_clozes.is_empty() {
+            None
+        } else {
+            assert!(current_clozes.is_empty());
+            all_clozes.sort_by_key(|x| x.start_match.start);
+            Some(all_clozes)
+        }
+    }
+}
+
+trait ScannerExt {
+    fn eat_if_ignore_case(&mut self, pat: &str) -> bool;
+    fn eat_until_ignore_case(&mut self, pat: &str);
+}
+
+impl ScannerExt for Scanner<'_> {
+    fn eat_if_ignore_case(&mut self, pat: &str) -> bool {
+        let matches = self
+            .after()
+            .get(..pat.len())
+            .is_some_and(|x| x.eq_ignore_ascii_case(pat));
+        if matches {
+            self.jump(self.cursor() + pat.len());
+        }
+        matches
+    }
+
+    fn eat_until_ignore_case(&mut self, pat: &str) {
+        while !self.done() && !self.eat_if_ignore_case(pat) {
+            self.eat();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    fn get_all_clozes(input: &str) -> Vec<Vec<ClozeMatch>> {
+        let mut parser = ClozeParser::new(input);
+        let mut all_clozes = Vec::new();
+        while let Some(cloze) = parser.next_cloze() {
+            all_clozes.push(cloze);
+        }
+        all_clozes
+    }
+
+    #[test]
+    fn test_basic_cloze() {
+        assert_eq!(
+            get_all_clozes("Test {{[o:1] basic}} cloze"),
+            vec![vec![ClozeMatch {
+                start_match: 5..12,
+                end_match: 18..20,
+                settings_match: 8..11,
+            }]]
+        );
+    }
+
+    #[test]
+    fn test_comment_line() {
+        let input = "# {{basic}} cloze\nTest {{cloze}}\n  # {{not a comment}}";
+        assert_eq!(
+            get_all_clozes(input),
+            vec![vec![
+                ClozeMatch {
+                    start_match: 23..25,
+                    end_match: 30..32,
+                    settings_match: Range::default(),
+                },
+                ClozeMatch {
+                    start_match: 37..39,
+                    end_match: 52..54,
+                    settings_match: Range::default(),
+                }
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_comment_block() {
+        let input = "#+BEGIN_COMMENT\n{{basic}}\n#+END_COMMENT\n{{cloze}}";
+        assert_eq!(
+            get_all_clozes(input),
+            vec![vec![ClozeMatch {
+                start_match: 40..42,
+                end_match: 47..49,
+                settings_match: Range::default(),
+            }]]
+        );
+    }
+
+    #[test]
+    fn test_escaped_character() {
+        let expected: Vec<Vec<ClozeMatch>> = vec![];
+        assert_eq!(get_all_clozes("Test \\{{[] basic}} cloze"), expected);
+    }
+
+    #[test]
+    fn test_math_mode() {
+        let input = "Test \\({{2}}\\) and \\[ x^{{2}} \\] {{[o:1] $3^{2^{2}}$}}";
+        assert_eq!(
+            get_all_clozes(input),
+            vec![vec![ClozeMatch {
+                start_match: 33..40,
+                end_match: 52..54,
+                settings_match: 36..39,
+            }]]
+        );
+    }
+
+    #[test]
+    fn test_nested_cloze() {
+        let input = "Test {{[o:1] outer {{inner}}}} complex";
+        assert_eq!(
+            get_all_clozes(input),
+            vec![vec![
+                ClozeMatch {
+                    start_match: 5..12,
+                    end_match: 28..30,
+                    settings_match: 8..11,
+                },
+                ClozeMatch {
+                    start_match: 19..21,
+                    end_match: 26..28,
+                    settings_match: Range::default(),
+                }
+            ]]
+        );
+    }
+}
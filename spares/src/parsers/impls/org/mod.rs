@@ -0,0 +1,504 @@
+use crate::{
+    Error, LibraryError,
+    config::{get_cache_dir, read_external_config},
+    parsers::{
+        ClozeHiddenReplacement, ClozeMatch, ClozeReplacement, ConstructFileDataType,
+        ConstructImageOcclusionType, GenerateNoteFilesRequest, NoteImportAction, NotePart,
+        NoteSettingsKeys, Parseable, RegexMatch, RenderOutputDirectoryType, RenderOutputType,
+        generate_files::CardSide,
+        get_output_raw_dir,
+        image_occlusion::{ImageOcclusionData, construct_image_occlusion_from_image},
+        impls::run_export_command,
+    },
+    schema::note::LinkedNote,
+};
+use cloze_parser::ClozeParser;
+use fancy_regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+mod cloze_parser;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OrgParserConfig {
+    /// Command used to render an org file. `{input}` and `{output}` are replaced with the path of the org file and the rendered file. For example, `["emacs", "--batch", "{input}", "--eval", "(rename-file (org-latex-export-to-pdf) \"{output}\" t)"]`.
+    pub export_command: Vec<String>,
+}
+
+impl Default for OrgParserConfig {
+    fn default() -> Self {
+        Self {
+            export_command: ["pandoc", "-o", "{output}", "{input}"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// Parser for [Org Mode](https://orgmode.org/) files.
+///
+/// Clozes use the same syntax as the markdown parser. Settings are written as `#+spares:` keywords and linked notes are written as internal links, like `[[li][keyword]]`.
+///
+/// See <https://orgmode.org/worg/org-syntax.html>
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrgParser {}
+
+impl OrgParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Parseable for OrgParser {
+    fn get_parser_name(&self) -> &'static str {
+        "org"
+    }
+
+    fn get_linked_notes(&self, data: &str) -> Result<Vec<Range<usize>>, LibraryError> {
+        let linked_notes_regex = get_linked_notes_regex();
+        let linked_notes_data = linked_notes_regex
+            .captures_iter(data)
+            .filter_map(|c| c.unwrap().get(2).map(|x| x.start()..x.end()))
+            .collect::<Vec<_>>();
+        Ok(linked_notes_data)
+    }
+
+    fn get_settings(&self, data: &str) -> Result<Vec<RegexMatch>, LibraryError> {
+        let settings_regex = Regex::new(r"(?m)^#\+spares: ([^\n]*)$").unwrap();
+        let settings_data = settings_regex
+            .captures_iter(data)
+            .map(|c| c.unwrap())
+            .map(|c| RegexMatch {
+                match_range: c.get(0).map(|x| x.start()..x.end()).unwrap(),
+                capture_range: c.get(1).map(|x| x.start()..x.end()).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        Ok(settings_data)
+    }
+
+    fn get_clozes(&self, data: &str) -> Result<Vec<ClozeMatch>, LibraryError> {
+        let mut all_clozes = Vec::new();
+        let mut cloze_parser = ClozeParser::new(data);
+        while let Some(cloze) = cloze_parser.next_cloze() {
+            all_clozes.push(cloze.clone());
+        }
+        Ok(all_clozes.into_iter().flatten().collect::<Vec<_>>())
+    }
+
+    fn construct_cloze(&self, cloze_settings_string: &str, _data: &str) -> (String, String) {
+        let cloze_settings_string_with_delim = if cloze_settings_string.is_empty() {
+            cloze_settings_string.to_string()
+        } else {
+            format!("[{}]", cloze_settings_string)
+        };
+        let cloze_start = format!("{{{{{}", cloze_settings_string_with_delim);
+        let cloze_end = "}}".to_string();
+        (cloze_start, cloze_end)
+    }
+
+    fn construct_setting(&self, data: &str) -> String {
+        format!("#+spares: {data}\n")
+    }
+
+    fn construct_comment(&self, data: &str) -> String {
+        // Add trailing newline (POSIX convention)
+        format!("# {data}\n")
+    }
+
+    fn extract_comment<'a>(&self, data: &'a str) -> &'a str {
+        data.strip_prefix("# ").map_or(data, |x| x.trim())
+    }
+
+    #[allow(clippy::let_and_return, reason = "Make note vs card data explicit")]
+    #[allow(clippy::too_many_lines, reason = "File data is long")]
+    fn construct_file_data(
+        &self,
+        output_type: ConstructFileDataType,
+        request: &GenerateNoteFilesRequest,
+        note_import_action: &NoteImportAction,
+    ) -> String {
+        let GenerateNoteFilesRequest {
+            note_id,
+            note_data,
+            keywords,
+            linked_notes,
+            custom_data,
+            tags,
+        } = request;
+        let keywords_str = keywords.join(", ");
+        let tags_str = tags.join(", ");
+        let NoteSettingsKeys {
+            action: action_key,
+            action_add: action_add_key,
+            settings_key_value_delim,
+            custom_data: custom_data_key,
+            note_id: note_id_key,
+            ..
+        } = self.note_settings_keys();
+        match output_type {
+            ConstructFileDataType::Note => {
+                let note_data =
+                    get_linked_notes_string(self, note_data.as_str(), linked_notes.as_ref());
+                let custom_data_str = if custom_data.is_empty() {
+                    String::new()
+                } else {
+                    let custom_data_str_content = serde_json::to_string(custom_data).unwrap();
+                    let custom_data_string = format!(
+                        "{}{} {}",
+                        custom_data_key.get_write(),
+                        settings_key_value_delim,
+                        custom_data_str_content.as_str(),
+                    );
+                    self.construct_setting(custom_data_string.as_str())
+                };
+                let action_string = if matches!(note_import_action, NoteImportAction::Update(_)) {
+                    String::new()
+                } else {
+                    let action_value = match note_import_action {
+                        NoteImportAction::Add => action_add_key,
+                        NoteImportAction::Update(_) | NoteImportAction::Delete(_) => unreachable!(),
+                    };
+                    self.construct_setting(&format!(
+                        "{}{} {}",
+                        action_key.get_write(),
+                        settings_key_value_delim,
+                        action_value.get_write(),
+                    ))
+                };
+                let note_id_string = format!(
+                    "{}{} {}",
+                    note_id_key.get_write(),
+                    settings_key_value_delim,
+                    note_id
+                );
+                let keywords_string =
+                    format!("keywords{} {}", settings_key_value_delim, keywords_str);
+                let tags_string = format!("tags{} {}", settings_key_value_delim, tags_str);
+                let lines = [
+                    "\n".to_string(),
+                    action_string,
+                    self.construct_setting(&note_id_string),
+                    self.construct_setting(&keywords_string),
+                    self.construct_setting(&tags_string),
+                    custom_data_str,
+                    self.construct_comment("spares: note start"),
+                    note_data,
+                    "\n".to_string(),
+                    self.construct_comment("spares: note end"),
+                    "\n".to_string(),
+                ];
+                let note_file_data = lines.into_iter().collect::<String>();
+                note_file_data
+            }
+            ConstructFileDataType::Card(card_order, card_data, side) => {
+                let mut image_occlusion_order: usize = 1;
+                let card_data = card_data
+                    .data
+                    .iter()
+                    .map(|p| match p {
+                        NotePart::ClozeData(d, cloze_replacement) => self
+                            .construct_cloze_replacement(
+                                &ClozeReplacement::parse(side, cloze_replacement, d),
+                                side,
+                            ),
+                        NotePart::SurroundingData(d) => d.clone(),
+                        NotePart::ImageOcclusion { data, .. } => {
+                            let image_occlusion = self.construct_image_occlusion(
+                                data,
+                                ConstructImageOcclusionType::Card {
+                                    side,
+                                    note_id: *note_id,
+                                    card_order,
+                                    image_occlusion_order,
+                                },
+                            );
+                            image_occlusion_order += 1;
+                            image_occlusion
+                        }
+                        NotePart::ClozeStart(_) | NotePart::ClozeEnd(_) => String::new(),
+                    })
+                    .collect::<String>();
+                let mut lines = vec![format!("- note-id{} {}", settings_key_value_delim, note_id)];
+                if !keywords_str.is_empty() {
+                    lines.push(format!(
+                        "- keywords{} {}",
+                        settings_key_value_delim, keywords_str
+                    ));
+                }
+                lines.extend(vec![
+                    format!("- tags{} {}", settings_key_value_delim, tags_str),
+                    String::new(),
+                    "-----".to_string(),
+                    String::new(),
+                    card_data,
+                ]);
+                let card_file_data = lines.join("\n");
+                card_file_data
+            }
+        }
+    }
+
+    fn construct_cloze_replacement(
+        &self,
+        cloze_replacement: &ClozeReplacement,
+        side: CardSide,
+    ) -> String {
+        match cloze_replacement {
+            ClozeReplacement::Hidden(cloze_replacement) => match cloze_replacement {
+                ClozeHiddenReplacement::ToAnswer { hint } => {
+                    if let Some(hint) = hint {
+                        format!("*[_____({})]*", hint)
+                    } else {
+                        "*[_____]*".to_string()
+                    }
+                }
+                ClozeHiddenReplacement::NotToAnswer => match side {
+                    CardSide::Front => "*[_____(no answer)]*".to_string(),
+                    CardSide::Back => "*[_____]*".to_string(),
+                },
+            },
+            ClozeReplacement::Reveal(data) => format!("*[{}]*", data),
+        }
+    }
+
+    fn construct_image_occlusion(
+        &self,
+        image_occlusion_data: &ImageOcclusionData,
+        output_type: ConstructImageOcclusionType,
+    ) -> String {
+        fn construct_image(file_path: &Path, caption: &str) -> String {
+            format!("#+CAPTION: {}\n[[file:{}]]\n", caption, file_path.display())
+        }
+        construct_image_occlusion_from_image(
+            self,
+            construct_image,
+            image_occlusion_data,
+            output_type,
+        )
+    }
+
+    fn get_output_rendered_dir(&self, _output_type: RenderOutputDirectoryType) -> PathBuf {
+        if cfg!(feature = "testing") {
+            return get_cache_dir();
+        }
+        std::env::var("ORG_OUT_DIR")
+            .ok()
+            .map(PathBuf::from)
+            .filter(|dir| dir.exists())
+            .unwrap_or_else(get_cache_dir)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "org"
+    }
+
+    fn render_file(
+        &self,
+        _aux_dir: &Path,
+        output_text_filepath: &Path,
+        _output_rendered_dir: &Path,
+        output_rendered_filepath: &Path,
+    ) -> Result<std::process::Output, Error> {
+        let config = read_external_config()?;
+        run_export_command(
+            self.get_parser_name(),
+            &config.parser.org.export_command,
+            output_text_filepath,
+            output_rendered_filepath,
+        )
+    }
+}
+
+/// Linked notes are internal links whose target is `li`, optionally followed by a number. The targets are defined at the end of the note.
+///
+/// See <https://orgmode.org/manual/Internal-Links.html>
+fn get_linked_notes_regex() -> Regex {
+    Regex::new(r"\[\[li([0-9]*)\]\[([^\]]*)\]\]").unwrap()
+}
+
+fn get_linked_notes_string(
+    parser: &dyn Parseable,
+    note_data: &str,
+    linked_notes_opt: Option<&Vec<LinkedNote>>,
+) -> String {
+    if let Some(linked_notes) = linked_notes_opt {
+        // Order all linked notes in `note_data` sequentially
+        let mut count = 0;
+        let linked_notes_regex = get_linked_notes_regex();
+        let new_note_data = linked_notes_regex.replace_all(note_data, |caps: &Captures| {
+            count += 1;
+            format!("[[li{}][{}]]", count, &caps[2])
+        });
+
+        let items = linked_notes
+            .iter()
+            .enumerate()
+            .map(|(i, linked_note_request)| {
+                let LinkedNote {
+                    searched_keyword,
+                    linked_note_id,
+                    matched_keyword,
+                } = linked_note_request;
+                assert_eq!(linked_note_id.is_some(), matched_keyword.is_some());
+                match (linked_note_id, matched_keyword) {
+                    (None, None) => format!("- <<li{}>> -", i + 1),
+                    (Some(linked_note_id), Some(matched_keyword)) => {
+                        let mut note_raw_path = get_output_raw_dir(
+                            parser.get_parser_name(),
+                            RenderOutputType::Note,
+                            None,
+                        );
+                        note_raw_path.push(
+                            parser.get_output_filename(RenderOutputType::Note, *linked_note_id),
+                        );
+                        note_raw_path.set_extension(parser.file_extension());
+                        format!(
+                            "- <<li{}>> [[file:{}][{} -> {}]]",
+                            i + 1,
+                            note_raw_path.display(),
+                            searched_keyword,
+                            matched_keyword,
+                        )
+                    }
+                    (None, Some(_)) | (Some(_), None) => unreachable!(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("{}\n\n{}", new_note_data, items)
+    } else {
+        note_data.to_string()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        parsers::{
+            BackReveal, BackType, CardData, ClozeGrouping, ClozeHiddenReplacement, FrontConceal,
+            NotePart, Parseable, get_cards, impls::org::OrgParser,
+        },
+        schema::note::LinkedNote,
+    };
+
+    use super::get_linked_notes_string;
+
+    #[test]
+    fn test_org_linked_notes() {
+        let parser: Box<dyn Parseable> = Box::new(OrgParser::new());
+        let note_data = "Third {{[o:1] Cloze here, linking to [[li][keyword 1]], [[li][keyword 1.5]], and [[li][keyword 2]], but not [[https://orgmode.org][org]] }}";
+        let linked_notes_res = parser.get_linked_notes(note_data);
+        assert!(linked_notes_res.is_ok());
+        let linked_notes = linked_notes_res
+            .unwrap()
+            .into_iter()
+            .map(|x| &note_data[x])
+            .collect::<Vec<_>>();
+        assert_eq!(linked_notes, vec!["keyword 1", "keyword 1.5", "keyword 2"]);
+    }
+
+    #[test]
+    fn test_org_get_linked_notes_string() {
+        let parser: Box<dyn Parseable> = Box::new(OrgParser::new());
+        let original_note_data =
+            "Third {{[o:1] Cloze here, linking to [[li][keyword 1]] and [[li][keyword 2]] }}";
+        let linked_notes_opt = Some(vec![
+            LinkedNote {
+                searched_keyword: "keyword 1".to_string(),
+                linked_note_id: Some(1),
+                matched_keyword: Some("keyword 1".to_string()),
+            },
+            LinkedNote {
+                searched_keyword: "keyword 2".to_string(),
+                linked_note_id: None,
+                matched_keyword: None,
+            },
+        ]);
+        let new_note_data = get_linked_notes_string(
+            parser.as_ref(),
+            original_note_data,
+            linked_notes_opt.as_ref(),
+        );
+        let expected_new_note_data = "Third {{[o:1] Cloze here, linking to [[li1][keyword 1]] and [[li2][keyword 2]] }}\n\n- <<li1>> [[file:/tmp/spares/data/notes/org/0001.org][keyword 1 -> keyword 1]]\n- <<li2>> -";
+        assert_eq!(new_note_data, expected_new_note_data);
+        // Numbered links are still found when the file is read again.
+        assert_eq!(parser.get_linked_notes(&new_note_data).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_org_settings() {
+        let parser: Box<dyn Parseable> = Box::new(OrgParser::new());
+        let data =
+            "#+title: Notes\n#+spares: note-id: 1\n#+spares: tags: math\n# spares: note start\n";
+        let settings = parser
+            .get_settings(data)
+            .unwrap()
+            .into_iter()
+            .map(|x| &data[x.capture_range])
+            .collect::<Vec<_>>();
+        assert_eq!(settings, vec!["note-id: 1", "tags: math"]);
+    }
+
+    #[test]
+    fn test_get_cards_basic_1_org() {
+        let data = r"a {{ b }} c";
+        let parser: Box<dyn Parseable> = Box::new(OrgParser::new());
+        let cards_res = get_cards(parser.as_ref(), None, data, true, false);
+        assert!(cards_res.is_ok());
+        if let Ok(cards) = cards_res {
+            let expected = vec![CardData {
+                order: Some(1),
+                grouping: ClozeGrouping::Auto(1),
+                is_suspended: None,
+                front_conceal: FrontConceal::OnlyGrouping,
+                back_reveal: BackReveal::FullNote,
+                back_type: BackType::FullNote,
+                data: vec![
+                    NotePart::SurroundingData("a ".to_string()),
+                    NotePart::ClozeStart("{{[o:1]".to_string()),
+                    NotePart::ClozeData(
+                        " b ".to_string(),
+                        ClozeHiddenReplacement::ToAnswer { hint: None },
+                    ),
+                    NotePart::ClozeEnd("}}".to_string()),
+                    NotePart::SurroundingData(" c".to_string()),
+                ],
+            }];
+            assert_eq!(cards, expected);
+        }
+        let cards_res = get_cards(parser.as_ref(), None, data, false, false);
+        assert!(cards_res.is_ok());
+        if let Ok(cards) = cards_res {
+            assert_eq!(cards.len(), 1);
+            assert_eq!(cards[0].order, None);
+            assert_eq!(cards[0].data[1], NotePart::ClozeStart("{{".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_get_cards_comments_org() {
+        // Clozes in comment lines and comment blocks are not cards
+        let data = "# {{ x }}\n#+begin_comment\n{{ y }}\n#+end_comment\na {{ b }} c";
+        let parser: Box<dyn Parseable> = Box::new(OrgParser::new());
+        let cards = get_cards(parser.as_ref(), None, data, true, false).unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(
+            cards[0].data[0],
+            NotePart::SurroundingData(
+                "# {{ x }}\n#+begin_comment\n{{ y }}\n#+end_comment\na ".to_string()
+            )
+        );
+        assert_eq!(
+            cards[0].data[2],
+            NotePart::ClozeData(
+                " b ".to_string(),
+                ClozeHiddenReplacement::ToAnswer { hint: None },
+            )
+        );
+    }
+}
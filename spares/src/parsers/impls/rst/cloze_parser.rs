@@ -0,0 +1,136 @@
+use crate::{DelimiterErrorKind, parsers::ClozeMatch};
+use unscanny::Scanner;
+
+pub const CLOZE_START: &str = ":cl:`";
+pub const CLOZE_END: &str = "`";
+
+/// Finds clozes written with the `cl` role, like ``:cl:`[o:1] text` ``. Clozes can not be nested.
+pub struct ClozeParser<'de> {
+    s: Scanner<'de>,
+}
+
+impl<'a> ClozeParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            s: Scanner::new(input),
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.s.before().is_empty() || self.s.before().ends_with('\n')
+    }
+
+    /// Comments are explicit markup blocks that are not directives, targets, footnotes, citations, or substitution definitions.
+    fn at_comment(&self) -> bool {
+        let line = self.s.after().lines().next().unwrap_or_default();
+        line.strip_prefix("..").is_some_and(|x| {
+            (x.is_empty() || x.starts_with(' '))
+                && !x.contains("::")
+                && !x.trim_start().starts_with(['_', '[', '|'])
+        })
+    }
+}
+
+impl Iterator for ClozeParser<'_> {
+    type Item = Result<ClozeMatch, DelimiterErrorKind>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let cursor_start = self.s.cursor();
+            // Comment
+            if self.at_line_start() && self.at_comment() {
+                self.s.eat_until('\n');
+            }
+            // Escaped character
+            else if self.s.eat_if('\\') {
+                self.s.eat();
+            }
+            // Inline literal
+            else if self.s.eat_if("``") {
+                self.s.eat_until("``");
+                self.s.eat_if("``");
+            }
+            // Math
+            else if self.s.eat_if(":math:`") {
+                self.s.eat_until('`');
+                self.s.eat_if('`');
+            }
+            // Handle cloze
+            else if self.s.eat_if(CLOZE_START) {
+                let mut settings_match = 0..0;
+                if self.s.eat_if('[') {
+                    let settings_start_idx = self.s.cursor();
+                    self.s.eat_until(']');
+                    settings_match = settings_start_idx..self.s.cursor();
+                    self.s.eat_if(']');
+                    self.s.eat_if(' ');
+                }
+                let start_match = cursor_start..self.s.cursor();
+                self.s.eat_until(CLOZE_END);
+                let end_match_start = self.s.cursor();
+                if !self.s.eat_if(CLOZE_END) {
+                    return Some(Err(DelimiterErrorKind::EndMatchNotFound {
+                        src: self.s.string().to_string(),
+                    }));
+                }
+                return Some(Ok(ClozeMatch {
+                    start_match,
+                    end_match: end_match_start..self.s.cursor(),
+                    settings_match,
+                }));
+            } else if self.s.eat().is_none() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clozes() {
+        let input = "Test :cl:`basic` and :cl:`[o:1] ordered`";
+        let clozes = ClozeParser::new(input).collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            clozes.unwrap(),
+            vec![
+                ClozeMatch {
+                    start_match: 5..10,
+                    end_match: 15..16,
+                    settings_match: 0..0,
+                },
+                ClozeMatch {
+                    start_match: 21..32,
+                    end_match: 39..40,
+                    settings_match: 27..30,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skipped() {
+        let input = ".. :cl:`comment`\n``:cl:`literal```\n:math:`x` \\:cl:`escaped`\n.. note:: :cl:`directive`";
+        let clozes = ClozeParser::new(input).collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            clozes.unwrap(),
+            vec![ClozeMatch {
+                start_match: 70..75,
+                end_match: 84..85,
+                settings_match: 0..0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_cloze() {
+        let input = "Test :cl:`basic";
+        let clozes = ClozeParser::new(input).collect::<Result<Vec<_>, _>>();
+        assert!(matches!(
+            clozes,
+            Err(DelimiterErrorKind::EndMatchNotFound { .. })
+        ));
+    }
+}
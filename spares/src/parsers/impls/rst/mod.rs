@@ -0,0 +1,464 @@
+use crate::{
+    Error, LibraryError,
+    config::{get_cache_dir, read_external_config},
+    parsers::{
+        ClozeHiddenReplacement, ClozeMatch, ClozeReplacement, ConstructFileDataType,
+        ConstructImageOcclusionType, GenerateNoteFilesRequest, NoteImportAction, NotePart,
+        NoteSettingsKeys, Parseable, RegexMatch, RenderOutputDirectoryType, RenderOutputType,
+        generate_files::CardSide,
+        get_output_raw_dir,
+        image_occlusion::{ImageOcclusionData, construct_image_occlusion_from_image},
+        impls::run_export_command,
+    },
+    schema::note::LinkedNote,
+};
+use cloze_parser::{CLOZE_END, CLOZE_START, ClozeParser};
+use fancy_regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+mod cloze_parser;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RstParserConfig {
+    /// Command used to render a reStructuredText file. `{input}` and `{output}` are replaced with the path of the reStructuredText file and the rendered file.
+    pub export_command: Vec<String>,
+}
+
+impl Default for RstParserConfig {
+    fn default() -> Self {
+        Self {
+            export_command: ["rst2pdf", "{input}", "-o", "{output}"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// Parser for [reStructuredText](https://docutils.sourceforge.io/rst.html) files.
+///
+/// Clozes use the `cl` role, like ``:cl:`[o:1] text` ``, which is defined in the template so it can be styled. Settings are written in comments starting with `#` and linked notes are written as hyperlink references, like `` `keyword <li_>`_ ``.
+///
+/// See <https://docutils.sourceforge.io/docs/ref/rst/restructuredtext.html>
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RstParser {}
+
+impl RstParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Parseable for RstParser {
+    fn get_parser_name(&self) -> &'static str {
+        "rst"
+    }
+
+    fn get_linked_notes(&self, data: &str) -> Result<Vec<Range<usize>>, LibraryError> {
+        let linked_notes_regex = get_linked_notes_regex();
+        let linked_notes_data = linked_notes_regex
+            .captures_iter(data)
+            .filter_map(|c| c.unwrap().get(1).map(|x| x.start()..x.end()))
+            .collect::<Vec<_>>();
+        Ok(linked_notes_data)
+    }
+
+    fn get_settings(&self, data: &str) -> Result<Vec<RegexMatch>, LibraryError> {
+        let settings_regex = Regex::new(r"(?m)^\.\. # ([^\n]*)$").unwrap();
+        let settings_data = settings_regex
+            .captures_iter(data)
+            .map(|c| c.unwrap())
+            .map(|c| RegexMatch {
+                match_range: c.get(0).map(|x| x.start()..x.end()).unwrap(),
+                capture_range: c.get(1).map(|x| x.start()..x.end()).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        Ok(settings_data)
+    }
+
+    fn get_clozes(&self, data: &str) -> Result<Vec<ClozeMatch>, LibraryError> {
+        ClozeParser::new(data)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LibraryError::Delimiter)
+    }
+
+    fn construct_cloze(&self, cloze_settings_string: &str, _data: &str) -> (String, String) {
+        let cloze_settings_string_with_delim = if cloze_settings_string.is_empty() {
+            cloze_settings_string.to_string()
+        } else {
+            format!("[{}] ", cloze_settings_string)
+        };
+        let cloze_start = format!("{}{}", CLOZE_START, cloze_settings_string_with_delim);
+        let cloze_end = CLOZE_END.to_string();
+        (cloze_start, cloze_end)
+    }
+
+    fn construct_setting(&self, data: &str) -> String {
+        format!(".. # {data}\n")
+    }
+
+    fn construct_comment(&self, data: &str) -> String {
+        // Add trailing newline (POSIX convention)
+        format!(".. {data}\n")
+    }
+
+    fn extract_comment<'a>(&self, data: &'a str) -> &'a str {
+        data.strip_prefix("..").map_or(data, |x| x.trim())
+    }
+
+    #[allow(clippy::let_and_return, reason = "Make note vs card data explicit")]
+    #[allow(clippy::too_many_lines, reason = "File data is long")]
+    fn construct_file_data(
+        &self,
+        output_type: ConstructFileDataType,
+        request: &GenerateNoteFilesRequest,
+        note_import_action: &NoteImportAction,
+    ) -> String {
+        let GenerateNoteFilesRequest {
+            note_id,
+            note_data,
+            keywords,
+            linked_notes,
+            custom_data,
+            tags,
+        } = request;
+        let keywords_str = keywords.join(", ");
+        let tags_str = tags.join(", ");
+        let NoteSettingsKeys {
+            action: action_key,
+            action_add: action_add_key,
+            settings_key_value_delim,
+            custom_data: custom_data_key,
+            note_id: note_id_key,
+            ..
+        } = self.note_settings_keys();
+        match output_type {
+            ConstructFileDataType::Note => {
+                let note_data =
+                    get_linked_notes_string(self, note_data.as_str(), linked_notes.as_ref());
+                let custom_data_str = if custom_data.is_empty() {
+                    String::new()
+                } else {
+                    let custom_data_str_content = serde_json::to_string(custom_data).unwrap();
+                    let custom_data_string = format!(
+                        "{}{} {}",
+                        custom_data_key.get_write(),
+                        settings_key_value_delim,
+                        custom_data_str_content.as_str(),
+                    );
+                    self.construct_setting(custom_data_string.as_str())
+                };
+                let action_string = if matches!(note_import_action, NoteImportAction::Update(_)) {
+                    String::new()
+                } else {
+                    let action_value = match note_import_action {
+                        NoteImportAction::Add => action_add_key,
+                        NoteImportAction::Update(_) | NoteImportAction::Delete(_) => unreachable!(),
+                    };
+                    self.construct_setting(&format!(
+                        "{}{} {}",
+                        action_key.get_write(),
+                        settings_key_value_delim,
+                        action_value.get_write(),
+                    ))
+                };
+                let note_id_string = format!(
+                    "{}{} {}",
+                    note_id_key.get_write(),
+                    settings_key_value_delim,
+                    note_id
+                );
+                let keywords_string =
+                    format!("keywords{} {}", settings_key_value_delim, keywords_str);
+                let tags_string = format!("tags{} {}", settings_key_value_delim, tags_str);
+                let lines = [
+                    "\n".to_string(),
+                    action_string,
+                    self.construct_setting(&note_id_string),
+                    self.construct_setting(&keywords_string),
+                    self.construct_setting(&tags_string),
+                    custom_data_str,
+                    self.construct_comment("spares: note start"),
+                    note_data,
+                    "\n".to_string(),
+                    self.construct_comment("spares: note end"),
+                    "\n".to_string(),
+                ];
+                let note_file_data = lines.into_iter().collect::<String>();
+                note_file_data
+            }
+            ConstructFileDataType::Card(card_order, card_data, side) => {
+                let mut image_occlusion_order: usize = 1;
+                let card_data = card_data
+                    .data
+                    .iter()
+                    .map(|p| match p {
+                        NotePart::ClozeData(d, cloze_replacement) => self
+                            .construct_cloze_replacement(
+                                &ClozeReplacement::parse(side, cloze_replacement, d),
+                                side,
+                            ),
+                        NotePart::SurroundingData(d) => d.clone(),
+                        NotePart::ImageOcclusion { data, .. } => {
+                            let image_occlusion = self.construct_image_occlusion(
+                                data,
+                                ConstructImageOcclusionType::Card {
+                                    side,
+                                    note_id: *note_id,
+                                    card_order,
+                                    image_occlusion_order,
+                                },
+                            );
+                            image_occlusion_order += 1;
+                            image_occlusion
+                        }
+                        NotePart::ClozeStart(_) | NotePart::ClozeEnd(_) => String::new(),
+                    })
+                    .collect::<String>();
+                let mut lines = vec![format!("- note-id{} {}", settings_key_value_delim, note_id)];
+                if !keywords_str.is_empty() {
+                    lines.push(format!(
+                        "- keywords{} {}",
+                        settings_key_value_delim, keywords_str
+                    ));
+                }
+                lines.extend(vec![
+                    format!("- tags{} {}", settings_key_value_delim, tags_str),
+                    String::new(),
+                    "----".to_string(),
+                    String::new(),
+                    card_data,
+                ]);
+                let card_file_data = lines.join("\n");
+                card_file_data
+            }
+        }
+    }
+
+    fn construct_cloze_replacement(
+        &self,
+        cloze_replacement: &ClozeReplacement,
+        side: CardSide,
+    ) -> String {
+        match cloze_replacement {
+            ClozeReplacement::Hidden(cloze_replacement) => match cloze_replacement {
+                ClozeHiddenReplacement::ToAnswer { hint } => {
+                    if let Some(hint) = hint {
+                        format!("{}[_____({})]{}", CLOZE_START, hint, CLOZE_END)
+                    } else {
+                        format!("{}[_____]{}", CLOZE_START, CLOZE_END)
+                    }
+                }
+                ClozeHiddenReplacement::NotToAnswer => match side {
+                    CardSide::Front => format!("{}[_____(no answer)]{}", CLOZE_START, CLOZE_END),
+                    CardSide::Back => format!("{}[_____]{}", CLOZE_START, CLOZE_END),
+                },
+            },
+            ClozeReplacement::Reveal(data) => format!("{}[{}]{}", CLOZE_START, data, CLOZE_END),
+        }
+    }
+
+    fn construct_image_occlusion(
+        &self,
+        image_occlusion_data: &ImageOcclusionData,
+        output_type: ConstructImageOcclusionType,
+    ) -> String {
+        fn construct_image(file_path: &Path, caption: &str) -> String {
+            format!(".. figure:: {}\n\n   {}\n", file_path.display(), caption)
+        }
+        construct_image_occlusion_from_image(
+            self,
+            construct_image,
+            image_occlusion_data,
+            output_type,
+        )
+    }
+
+    fn get_output_rendered_dir(&self, _output_type: RenderOutputDirectoryType) -> PathBuf {
+        if cfg!(feature = "testing") {
+            return get_cache_dir();
+        }
+        std::env::var("RST_OUT_DIR")
+            .ok()
+            .map(PathBuf::from)
+            .filter(|dir| dir.exists())
+            .unwrap_or_else(get_cache_dir)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "rst"
+    }
+
+    fn render_file(
+        &self,
+        _aux_dir: &Path,
+        output_text_filepath: &Path,
+        _output_rendered_dir: &Path,
+        output_rendered_filepath: &Path,
+    ) -> Result<std::process::Output, Error> {
+        let config = read_external_config()?;
+        run_export_command(
+            self.get_parser_name(),
+            &config.parser.rst.export_command,
+            output_text_filepath,
+            output_rendered_filepath,
+        )
+    }
+}
+
+/// Linked notes are hyperlink references whose target is `li`, optionally followed by a number. The targets are defined at the end of the note.
+///
+/// See <https://docutils.sourceforge.io/docs/ref/rst/restructuredtext.html#embedded-uris-and-aliases>
+fn get_linked_notes_regex() -> Regex {
+    Regex::new(r"`([^`<]*) <li([0-9]*)_>`_").unwrap()
+}
+
+fn get_linked_notes_string(
+    parser: &dyn Parseable,
+    note_data: &str,
+    linked_notes_opt: Option<&Vec<LinkedNote>>,
+) -> String {
+    if let Some(linked_notes) = linked_notes_opt {
+        // Order all linked notes in `note_data` sequentially
+        let mut count = 0;
+        let linked_notes_regex = get_linked_notes_regex();
+        let new_note_data = linked_notes_regex.replace_all(note_data, |caps: &Captures| {
+            count += 1;
+            format!("`{} <li{}_>`_", &caps[1], count)
+        });
+
+        let items = linked_notes
+            .iter()
+            .enumerate()
+            .map(|(i, linked_note_request)| {
+                let LinkedNote {
+                    searched_keyword,
+                    linked_note_id,
+                    matched_keyword,
+                } = linked_note_request;
+                assert_eq!(linked_note_id.is_some(), matched_keyword.is_some());
+                match (linked_note_id, matched_keyword) {
+                    (None, None) => format!(".. _li{}: -", i + 1),
+                    (Some(linked_note_id), Some(matched_keyword)) => {
+                        let mut note_raw_path = get_output_raw_dir(
+                            parser.get_parser_name(),
+                            RenderOutputType::Note,
+                            None,
+                        );
+                        note_raw_path.push(
+                            parser.get_output_filename(RenderOutputType::Note, *linked_note_id),
+                        );
+                        note_raw_path.set_extension(parser.file_extension());
+                        // Targets can not have titles, so the keywords are kept in a comment.
+                        format!(
+                            ".. {} -> {}\n.. _li{}: {}",
+                            searched_keyword,
+                            matched_keyword,
+                            i + 1,
+                            note_raw_path.display(),
+                        )
+                    }
+                    (None, Some(_)) | (Some(_), None) => unreachable!(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("{}\n\n{}", new_note_data, items)
+    } else {
+        note_data.to_string()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        parsers::{NotePart, Parseable, get_cards, impls::rst::RstParser},
+        schema::note::LinkedNote,
+    };
+
+    use super::get_linked_notes_string;
+
+    #[test]
+    fn test_rst_linked_notes() {
+        let parser: Box<dyn Parseable> = Box::new(RstParser::new());
+        let note_data = "Third :cl:`[o:1] Cloze here`, linking to `keyword 1 <li_>`_, `keyword 1.5 <li_>`_, and `keyword 2 <li_>`_, but not `docs <https://docutils.sourceforge.io>`_";
+        let linked_notes_res = parser.get_linked_notes(note_data);
+        assert!(linked_notes_res.is_ok());
+        let linked_notes = linked_notes_res
+            .unwrap()
+            .into_iter()
+            .map(|x| &note_data[x])
+            .collect::<Vec<_>>();
+        assert_eq!(linked_notes, vec!["keyword 1", "keyword 1.5", "keyword 2"]);
+    }
+
+    #[test]
+    fn test_rst_get_linked_notes_string() {
+        let parser: Box<dyn Parseable> = Box::new(RstParser::new());
+        let original_note_data = "Linking to `keyword 1 <li_>`_ and `keyword 2 <li_>`_";
+        let linked_notes_opt = Some(vec![
+            LinkedNote {
+                searched_keyword: "keyword 1".to_string(),
+                linked_note_id: Some(1),
+                matched_keyword: Some("keyword 1".to_string()),
+            },
+            LinkedNote {
+                searched_keyword: "keyword 2".to_string(),
+                linked_note_id: None,
+                matched_keyword: None,
+            },
+        ]);
+        let new_note_data = get_linked_notes_string(
+            parser.as_ref(),
+            original_note_data,
+            linked_notes_opt.as_ref(),
+        );
+        let expected_new_note_data = "Linking to `keyword 1 <li1_>`_ and `keyword 2 <li2_>`_\n\n.. keyword 1 -> keyword 1\n.. _li1: /tmp/spares/data/notes/rst/0001.rst\n.. _li2: -";
+        assert_eq!(new_note_data, expected_new_note_data);
+    }
+
+    #[test]
+    fn test_rst_settings_and_comments() {
+        let parser: Box<dyn Parseable> = Box::new(RstParser::new());
+        let data = ".. # note-id: 1\n.. # tags: math\n.. spares: note start\n";
+        let settings = parser
+            .get_settings(data)
+            .unwrap()
+            .into_iter()
+            .map(|x| &data[x.capture_range])
+            .collect::<Vec<_>>();
+        assert_eq!(settings, vec!["note-id: 1", "tags: math"]);
+        // The hash of a note file is read from its last line.
+        let hash_line = parser.construct_comment("hash: abc");
+        assert_eq!(parser.extract_comment(hash_line.trim_end()), "hash: abc");
+    }
+
+    #[test]
+    fn test_rst_cloze_settings() {
+        let parser: Box<dyn Parseable> = Box::new(RstParser::new());
+        let (cloze_start, cloze_end) = parser.construct_cloze("o:1", "");
+        let data = format!("Test {}basic{}", cloze_start, cloze_end);
+        let clozes = parser.get_clozes(&data).unwrap();
+        assert_eq!(clozes.len(), 1);
+        assert_eq!(&data[clozes[0].settings_match.clone()], "o:1");
+        assert_eq!(&data[clozes[0].start_match.clone()], cloze_start);
+        assert_eq!(&data[clozes[0].end_match.clone()], cloze_end);
+    }
+
+    #[test]
+    fn test_rst_get_cards() {
+        let parser: Box<dyn Parseable> = Box::new(RstParser::new());
+        let cards = get_cards(parser.as_ref(), None, "a :cl:`b` c", true, false).unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(
+            cards[0].data[1],
+            NotePart::ClozeStart(":cl:`[o:1] ".to_string())
+        );
+        assert_eq!(cards[0].data[3], NotePart::ClozeEnd("`".to_string()));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{
+    Error, LibraryError,
+    config::read_external_config,
+    model::CustomData,
+    parsers::{
+        ClozeMatch, ClozeReplacement, ConstructFileDataType, ConstructImageOcclusionType,
+        GenerateNoteFilesRequest, NoteImportAction, NoteRawData, Parseable, RegexMatch,
+        RenderOutputDirectoryType,
+        generate_files::CardSide,
+        image_occlusion::ImageOcclusionData,
+        impls::markdown::{self, MarkdownParser},
+    },
+};
+use fancy_regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceParserConfig {
+    /// The line comment prefix for each file extension that notes can be imported from.
+    pub comments: BTreeMap<String, String>,
+}
+
+impl Default for SourceParserConfig {
+    fn default() -> Self {
+        let comment_prefixes = [
+            (
+                "//",
+                &[
+                    "c", "cc", "cpp", "cs", "dart", "go", "h", "hpp", "java", "js", "jsx", "kt",
+                    "rs", "scala", "swift", "ts", "tsx", "zig",
+                ][..],
+            ),
+            (
+                "#",
+                &["bash", "jl", "nix", "pl", "py", "r", "rb", "sh", "zsh"][..],
+            ),
+            ("--", &["hs", "lua", "sql"][..]),
+            (";;", &["clj", "el", "lisp"][..]),
+        ];
+        let comments = comment_prefixes
+            .into_iter()
+            .flat_map(|(prefix, extensions)| {
+                extensions
+                    .iter()
+                    .map(move |extension| (extension.to_string(), prefix.to_string()))
+            })
+            .collect();
+        Self { comments }
+    }
+}
+
+/// Parser for notes written in the line comments of source code, like
+///
+/// ```python
+/// # spares: note start
+/// # `str.partition` returns {{a 3-tuple}}, even if the separator is not found.
+/// # spares: note end
+/// ```
+///
+/// Settings are written in comments starting with `#`, like `# # tags: python`. Everything else in a note, including clozes and linked notes, uses the markdown parser's syntax. Each imported note's custom data records the file and line it was found on.
+///
+/// Notes are stored and rendered as markdown, so files generated for them use the markdown parser's syntax.
+#[derive(Clone, Debug, Default)]
+pub struct SourceParser {
+    /// The line comment prefix of the file being imported. If this is `None`, the markdown parser's comments are used.
+    comment_prefix: Option<String>,
+    file_path: Option<PathBuf>,
+    /// Used to render files, so the renderer is read from the config file once.
+    markdown_parser: MarkdownParser,
+}
+
+impl SourceParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_comment_prefix(comment_prefix: &str, file_path: Option<PathBuf>) -> Self {
+        Self {
+            comment_prefix: Some(comment_prefix.to_string()),
+            file_path,
+            markdown_parser: MarkdownParser::new(),
+        }
+    }
+
+    fn comment_prefix_regex(comment_prefix: &str) -> String {
+        format!(r"^[ \t]*{}", fancy_regex::escape(comment_prefix))
+    }
+}
+
+impl Parseable for SourceParser {
+    fn get_parser_name(&self) -> &'static str {
+        "source"
+    }
+
+    fn get_notes_data(&self, data: &str) -> Result<Vec<NoteRawData>, LibraryError> {
+        let Some(comment_prefix) = &self.comment_prefix else {
+            return MarkdownParser::new().get_notes_data(data);
+        };
+        // Comments can be indented, so the start and end comments are matched by line.
+        let comment_regex = Self::comment_prefix_regex(comment_prefix);
+        let regex_string = format!(
+            r"(?m){0} spares: note start[ \t]*\n((?s:.*?))\n{0} spares: note end[ \t]*$",
+            comment_regex
+        );
+        let notes_regex = Regex::new(&regex_string).unwrap();
+        let notes_data = notes_regex
+            .captures_iter(data)
+            .map(|c| c.unwrap())
+            .map(|c| NoteRawData {
+                metadata: None,
+                data: c.get(1).map(|x| x.start()..x.end()).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        Ok(notes_data)
+    }
+
+    fn extract_note_data(&self, data: &str) -> String {
+        let Some(comment_prefix) = self.comment_prefix.as_deref() else {
+            return data.trim().to_string();
+        };
+        data.lines()
+            .map(|line| {
+                // Only remove the first space, so indentation in the note is kept
+                let line = line.trim_start();
+                let line = line.strip_prefix(comment_prefix).unwrap_or(line);
+                line.strip_prefix(' ').unwrap_or(line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string()
+    }
+
+    fn note_custom_data(&self, data: &str, note_range: Range<usize>) -> CustomData {
+        let mut custom_data = CustomData::new();
+        if let Some(file_path) = &self.file_path {
+            // The line of the first line of the note's data
+            let line = data[..note_range.start].matches('\n').count() + 1;
+            custom_data.insert(
+                "source".to_string(),
+                json!({ "file": file_path.display().to_string(), "line": line }),
+            );
+        }
+        custom_data
+    }
+
+    fn for_file(&self, file_path: &Path) -> Option<Box<dyn Parseable>> {
+        let config = read_external_config().ok()?;
+        let extension = file_path.extension()?.to_str()?;
+        let comment_prefix = config.parser.source.comments.get(extension)?;
+        let file_path = std::fs::canonicalize(file_path).unwrap_or(file_path.to_path_buf());
+        Some(Box::new(Self::with_comment_prefix(
+            comment_prefix,
+            Some(file_path),
+        )))
+    }
+
+    fn matches_file(&self, file_path: &Path) -> bool {
+        // Only files with a configured comment prefix are searched, so generated markdown files are left to the markdown parser.
+        self.for_file(file_path).is_some()
+    }
+
+    fn get_linked_notes(&self, data: &str) -> Result<Vec<Range<usize>>, LibraryError> {
+        MarkdownParser::new().get_linked_notes(data)
+    }
+
+    fn get_settings(&self, data: &str) -> Result<Vec<RegexMatch>, LibraryError> {
+        let Some(comment_prefix) = &self.comment_prefix else {
+            return MarkdownParser::new().get_settings(data);
+        };
+        let settings_regex = Regex::new(&format!(
+            r"(?m){} # ([^\n]*)$",
+            Self::comment_prefix_regex(comment_prefix)
+        ))
+        .unwrap();
+        let settings_data = settings_regex
+            .captures_iter(data)
+            .map(|c| c.unwrap())
+            .map(|c| RegexMatch {
+                match_range: c.get(0).map(|x| x.start()..x.end()).unwrap(),
+                capture_range: c.get(1).map(|x| x.start()..x.end()).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        Ok(settings_data)
+    }
+
+    fn start_end_regex(&self) -> Regex {
+        if self.comment_prefix.is_none() {
+            return MarkdownParser::new().start_end_regex();
+        }
+        // Source files do not need start and end comments, since notes are only searched for in comments.
+        Regex::new(r"(?s)\A(.*)\z").unwrap()
+    }
+
+    fn get_clozes(&self, data: &str) -> Result<Vec<ClozeMatch>, LibraryError> {
+        MarkdownParser::new().get_clozes(data)
+    }
+
+    fn construct_cloze(&self, cloze_settings_string: &str, data: &str) -> (String, String) {
+        MarkdownParser::new().construct_cloze(cloze_settings_string, data)
+    }
+
+    fn construct_cloze_replacement(
+        &self,
+        cloze_replacement: &ClozeReplacement,
+        side: CardSide,
+    ) -> String {
+        MarkdownParser::new().construct_cloze_replacement(cloze_replacement, side)
+    }
+
+    fn construct_setting(&self, data: &str) -> String {
+        match &self.comment_prefix {
+            Some(comment_prefix) => format!("{comment_prefix} # {data}\n"),
+            None => MarkdownParser::new().construct_setting(data),
+        }
+    }
+
+    fn construct_comment(&self, data: &str) -> String {
+        match &self.comment_prefix {
+            Some(comment_prefix) => format!("{comment_prefix} {data}\n"),
+            None => MarkdownParser::new().construct_comment(data),
+        }
+    }
+
+    fn extract_comment<'a>(&self, data: &'a str) -> &'a str {
+        match &self.comment_prefix {
+            Some(comment_prefix) => data
+                .trim_start()
+                .strip_prefix(comment_prefix.as_str())
+                .map_or(data, |x| x.trim()),
+            None => MarkdownParser::new().extract_comment(data),
+        }
+    }
+
+    fn construct_image_occlusion(
+        &self,
+        image_occlusion_data: &ImageOcclusionData,
+        output_type: ConstructImageOcclusionType,
+    ) -> String {
+        MarkdownParser::new().construct_image_occlusion(image_occlusion_data, output_type)
+    }
+
+    fn construct_file_data(
+        &self,
+        output_type: ConstructFileDataType,
+        request: &GenerateNoteFilesRequest,
+        note_import_action: &NoteImportAction,
+    ) -> String {
+        markdown::construct_file_data(self, output_type, request, note_import_action)
+    }
+
+    fn get_output_rendered_dir(&self, output_type: RenderOutputDirectoryType) -> PathBuf {
+        MarkdownParser::new().get_output_rendered_dir(output_type)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        MarkdownParser::new().file_extension()
+    }
+
+    fn rendered_file_extension(&self) -> &'static str {
+        self.markdown_parser.rendered_file_extension()
+    }
+
+    fn render_file(
+        &self,
+        aux_dir: &Path,
+        output_text_filepath: &Path,
+        output_rendered_dir: &Path,
+        output_rendered_filepath: &Path,
+    ) -> Result<std::process::Output, Error> {
+        self.markdown_parser.render_file(
+            aux_dir,
+            output_text_filepath,
+            output_rendered_dir,
+            output_rendered_filepath,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceParser;
+    use crate::{
+        adapters::get_adapter_from_string,
+        parsers::{Parseable, get_notes},
+    };
+    use indoc::indoc;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_source_get_notes() {
+        let parser: Box<dyn Parseable> = Box::new(SourceParser::with_comment_prefix(
+            "#",
+            Some(PathBuf::from("/repo/strings.py")),
+        ));
+        let adapter = get_adapter_from_string("spares").unwrap();
+        let data = indoc! {r#"
+            def split(value: str):
+                # spares: note start
+                # `str.partition` returns {{a 3-tuple}},
+                # even if the separator is not found.
+                # spares: note end
+                return value.partition(",")
+
+            # # tags: python
+            # spares: note start
+            # `str.split` with {{no arguments}} splits on runs of whitespace.
+            # spares: note end
+        "#};
+        let notes = get_notes(parser.as_ref(), None, data, adapter.as_ref(), false).unwrap();
+        assert_eq!(notes.len(), 2);
+        let (settings, note_data) = &notes[0];
+        assert_eq!(
+            note_data.as_deref(),
+            Some(
+                "`str.partition` returns {{[o:1]a 3-tuple}},\neven if the separator is not found."
+            )
+        );
+        assert_eq!(
+            settings.custom_data.get("source"),
+            Some(&json!({ "file": "/repo/strings.py", "line": 3 }))
+        );
+        let (settings, note_data) = &notes[1];
+        assert_eq!(
+            note_data.as_deref(),
+            Some("`str.split` with {{[o:1]no arguments}} splits on runs of whitespace.")
+        );
+        assert_eq!(settings.tags, vec!["python".to_string()]);
+        assert_eq!(
+            settings.custom_data.get("source"),
+            Some(&json!({ "file": "/repo/strings.py", "line": 10 }))
+        );
+    }
+
+    #[test]
+    fn test_source_comments() {
+        let parser = SourceParser::with_comment_prefix("//", None);
+        let comment = parser.construct_comment("hash: abc");
+        assert_eq!(comment, "// hash: abc\n");
+        assert_eq!(parser.extract_comment("    // hash: abc"), "hash: abc");
+        assert_eq!(parser.extract_comment("let x = 1;"), "let x = 1;");
+        // Files generated for notes use markdown.
+        let parser = SourceParser::new();
+        assert_eq!(
+            parser.construct_comment("hash: abc"),
+            "<!--- hash: abc --->\n"
+        );
+    }
+}
@@ -69,6 +69,26 @@ pub trait Parseable: Send + Sync {
         Ok(notes_data)
     }
 
+    /// Returns a note's data from the text between its start and end comments. This can be overridden to remove markup around each line, such as comment markers.
+    fn extract_note_data(&self, data: &str) -> String {
+        data.trim().to_string()
+    }
+
+    /// Custom data that is added to a note found at `note_range` of `data`, unless the note's settings already contain the key.
+    fn note_custom_data(&self, _data: &str, _note_range: Range<usize>) -> CustomData {
+        CustomData::new()
+    }
+
+    /// Returns a parser that is adapted to the file at `file_path` for importing it, such as one that uses the file's comment syntax. If `None` is returned, this parser is used as is.
+    fn for_file(&self, _file_path: &Path) -> Option<Box<dyn Parseable>> {
+        None
+    }
+
+    /// Whether this parser is tried when automatically determining the parser of the file at `file_path`.
+    fn matches_file(&self, _file_path: &Path) -> bool {
+        true
+    }
+
     // Nested clozes make it so "data" can NOT be split into disjoint segment of NotePart::Data and NotePart::Cloze. This is because what a cloze really represents is that you want to see everything else *besides* what is in the cloze.
     fn get_linked_notes(&self, data: &str) -> Result<Vec<Range<usize>>, LibraryError>;
 
@@ -207,6 +227,11 @@ pub trait Parseable: Send + Sync {
 
     fn file_extension(&self) -> &'static str;
 
+    /// The extension of the files created by `render_file`.
+    fn rendered_file_extension(&self) -> &'static str {
+        "pdf"
+    }
+
     fn template_contents(&self) -> Result<TemplateData, std::io::Error> {
         let body_placeholder = self.construct_comment("spares: note body");
         if cfg!(feature = "testing") {
@@ -217,21 +242,17 @@ pub trait Parseable: Send + Sync {
                 body_placeholder,
             });
         }
-        let mut template_path: PathBuf = get_config_dir();
-        let parser_name = self.get_parser_name();
-        template_path.push(parser_name);
-        template_path.push("templates");
-        let file_extension = self.file_extension();
-        let template_filename = format!("template.{}", file_extension);
-        template_path.push(template_filename.as_str());
-
-        let template_contents = read_to_string(&template_path)?;
+        let template_contents = read_to_string(self.template_path())?;
         Ok(TemplateData {
             template_contents,
             body_placeholder,
         })
     }
 
+    fn template_path(&self) -> PathBuf {
+        get_default_template_path(self.get_parser_name(), self.file_extension())
+    }
+
     // This can be overridden for a specific parser, so it is in the trait.
     fn get_output_rendered_dir(&self, _output_type: RenderOutputDirectoryType) -> PathBuf {
         get_cache_dir()
@@ -248,41 +269,75 @@ pub trait Parseable: Send + Sync {
 
     // This is separated from the get_.*_dir functions since for syncing notes, cards are rendering in /tmp, where the file name is needed, but not the rest of the filepath.
     fn get_output_filename(&self, output_type: RenderOutputType, note_id: NoteId) -> String {
+        let extension = self.rendered_file_extension();
         match output_type {
             RenderOutputType::Note => {
-                format!("{:0>4}.pdf", note_id)
+                format!("{:0>4}.{}", note_id, extension)
             }
             RenderOutputType::Card(card_order, side) => match side {
                 CardSide::Front => {
-                    format!("{:0>4}-{:0>1}-front.pdf", note_id, card_order)
+                    format!("{:0>4}-{:0>1}-front.{}", note_id, card_order, extension)
                 }
                 CardSide::Back => {
-                    format!("{:0>4}-{:0>1}-back.pdf", note_id, card_order)
+                    format!("{:0>4}-{:0>1}-back.{}", note_id, card_order, extension)
                 }
             },
         }
     }
 }
 
+pub(crate) fn get_default_template_path(parser_name: &str, file_extension: &str) -> PathBuf {
+    let mut template_path: PathBuf = get_config_dir();
+    template_path.push(parser_name);
+    template_path.push("templates");
+    let template_filename = format!("template.{}", file_extension);
+    template_path.push(template_filename.as_str());
+    template_path
+}
+
 pub fn validate_parser(parser: &dyn Parseable) -> Option<String> {
-    // Ensure that the parser name only contains lowercase and dashes to make sure it is safe to use as a directory name.
-    if parser
-        .get_parser_name()
-        .chars()
-        .any(|c| !(c.is_ascii_lowercase() || c == '-'))
-    {
+    if !is_valid_parser_name(parser.get_parser_name()) {
         return Some("Invalid characters returned from `parser.get_parser_name()`. Only lowercase letters and dashes are allowed.".to_string());
     }
     None
 }
 
-pub fn get_all_parsers() -> Vec<fn() -> Box<dyn Parseable>> {
+/// Ensure that the parser name only contains lowercase and dashes to make sure it is safe to use as a directory name.
+pub(crate) fn is_valid_parser_name(parser_name: &str) -> bool {
+    !parser_name.is_empty()
+        && parser_name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c == '-')
+}
+
+/// Parsers defined in the config file are constructed from their definition, so they can not be function pointers.
+pub type ParserConstructor = Box<dyn Fn() -> Box<dyn Parseable> + Send + Sync>;
+
+/// Returns the built-in parsers, followed by the parsers defined in the config file.
+pub fn get_all_parsers() -> Vec<ParserConstructor> {
+    let custom_parsers = impls::custom::get_custom_parser_definitions().iter().map(
+        |definition| -> ParserConstructor {
+            Box::new(move || Box::new(impls::custom::CustomParser::new(definition)))
+        },
+    );
+    get_builtin_parsers()
+        .into_iter()
+        .map(|parser_fn| -> ParserConstructor { Box::new(parser_fn) })
+        .chain(custom_parsers)
+        .collect()
+}
+
+pub(crate) fn get_builtin_parsers() -> Vec<fn() -> Box<dyn Parseable>> {
     // NOTE: Add parser here
     // Also run: `spares_cli add parser --name="NAME"`
     let all_parsers: Vec<fn() -> Box<dyn Parseable>> = vec![
+        || Box::new(impls::asciidoc::AsciidocParser::new()),
         || Box::new(impls::latex::LatexParserExerciseSolution::new()),
         || Box::new(impls::latex::LatexParserNote::new()),
         || Box::new(impls::markdown::MarkdownParser::new()),
+        || Box::new(impls::org::OrgParser::new()),
+        || Box::new(impls::rst::RstParser::new()),
+        || Box::new(impls::source::SourceParser::new()),
         || Box::new(impls::typst::TypstParser::new()),
     ];
     all_parsers
@@ -290,7 +345,7 @@ pub fn get_all_parsers() -> Vec<fn() -> Box<dyn Parseable>> {
 
 pub fn find_parser(
     parser_str: &str,
-    all_parsers: &[fn() -> Box<dyn Parseable>],
+    all_parsers: &[ParserConstructor],
 ) -> Result<Box<dyn Parseable>, Error> {
     let matching_parsers = all_parsers
         .iter()
@@ -112,6 +112,9 @@ pub fn get_notes(
             adapter,
             &note_c.data,
         );
+        for (key, value) in parser.note_custom_data(&data, note_c.data.clone()) {
+            local_settings.custom_data.entry(key).or_insert(value);
+        }
 
         // Complete note
         let note_data = complete_note(
@@ -156,7 +159,7 @@ fn complete_note(
     }
 
     // Strip whitespace
-    let note_data = data.trim().to_string();
+    let note_data = parser.extract_note_data(data);
 
     // Convert note to different parser, if requested, _and_
     // Parse cards so they can be validated
@@ -105,6 +105,7 @@ use super::utils::get_fuzz_range;
 
 /// Expects input review logs to be sorted. The last one should be the latest one.
 pub fn disperse_siblings_distance(
+    parameters: &rs_fsrs::Parameters,
     card_siblings: &[(Card, Vec<ReviewLog>)],
     minimum_interval: Duration,
     maximum_interval: Duration,
@@ -113,7 +114,14 @@ pub fn disperse_siblings_distance(
     let mut reviewed_ats = Vec::new();
     let now = Utc::now();
     for (card, review_logs) in card_siblings {
-        let due_range = get_due_range(card, review_logs, maximum_interval, minimum_interval, now);
+        let due_range = get_due_range(
+            parameters,
+            card,
+            review_logs,
+            maximum_interval,
+            minimum_interval,
+            now,
+        );
         due_ranges.push((Some(card.id), due_range));
         let latest_review_log = review_logs.last();
         if let Some(review_log) = latest_review_log {
@@ -148,6 +156,7 @@ pub fn disperse_siblings_distance(
 }
 
 fn get_due_range(
+    parameters: &rs_fsrs::Parameters,
     card: &Card,
     review_logs: &[ReviewLog],
     maximum_interval: Duration,
@@ -159,7 +168,7 @@ fn get_due_range(
         maximum_interval: maximum_interval.num_days() as i32,
         // We are manually using `get_fuzz_range()` here, so we don't want to enable fuzz when getting the next interval.
         enable_fuzz: false,
-        ..Default::default()
+        ..parameters.clone()
     };
     let last_elapsed_time = review_logs
         .iter()
@@ -374,6 +383,7 @@ mod tests {
                 .collect::<Vec<_>>();
             let config = read_external_config().unwrap();
             let result = disperse_siblings_distance(
+                &rs_fsrs::Parameters::default(),
                 &card_siblings,
                 config.minimum_interval,
                 config.maximum_interval,
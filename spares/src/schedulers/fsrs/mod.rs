@@ -15,15 +15,19 @@
 //! - This is a combination of `Easy days` and `Disperse siblings`.
 mod disperse;
 mod easy_days;
+mod optimizer;
 mod reposition;
 mod utils;
 
 use crate::{
     Error, LibraryError, SchedulerErrorKind,
-    config::{SparesExternalConfig, read_external_config},
+    config::{SparesExternalConfig, read_internal_config, write_internal_config},
     helpers::{FractionalDays, get_start_end_local_date},
-    model::{Card, RatingId, ReviewLog},
-    schedulers::{SrsScheduler, stepped_range_inclusive},
+    model::{Card, RatingId, ReviewLog, StateId},
+    schedulers::{
+        SrsScheduler, generate_review_history_from_weights, get_cards_over_lapses_threshold,
+        stepped_range_inclusive,
+    },
     schema::review::{Rating, RatingSubmission},
 };
 use async_trait::async_trait;
@@ -32,11 +36,8 @@ use disperse::disperse_siblings_distance;
 use indexmap::IndexMap;
 use itertools::Itertools;
 use log::info;
-use rand::{
-    Rng,
-    distributions::{Distribution, WeightedIndex},
-    rngs::ThreadRng,
-};
+use optimizer::fit_weights;
+use rand::{distributions::Distribution, rngs::ThreadRng};
 use reposition::{MoveCardAction, get_safe_cards, move_cards};
 use rs_fsrs::State;
 use sqlx::SqlitePool;
@@ -46,16 +47,46 @@ use utils::{
     rating_to_number, state_to_number,
 };
 
-pub use rs_fsrs::FSRS;
+use optimizer::get_parameters;
+use rs_fsrs::FSRS;
 use serde_json::{Map, Number, Value};
 
+#[derive(Clone, Debug)]
+pub struct Fsrs {
+    parameters: rs_fsrs::Parameters,
+}
+
+impl Fsrs {
+    pub const fn new(parameters: rs_fsrs::Parameters) -> Self {
+        Self { parameters }
+    }
+
+    /// Returns an FSRS instance that targets the given retention.
+    fn get_fsrs(&self, desired_retention: f64) -> FSRS {
+        FSRS::new(rs_fsrs::Parameters {
+            request_retention: desired_retention,
+            ..self.parameters.clone()
+        })
+    }
+}
+
+impl Default for Fsrs {
+    fn default() -> Self {
+        Self::new(rs_fsrs::Parameters::default())
+    }
+}
+
 // NOTE: Make sure to pass time data as a `Duration` instead of an integer representing days.
 #[async_trait]
-impl SrsScheduler for FSRS {
+impl SrsScheduler for Fsrs {
     fn get_scheduler_name(&self) -> &'static str {
         "fsrs"
     }
 
+    fn load_fitted_parameters(&mut self) {
+        self.parameters = get_parameters();
+    }
+
     fn get_ratings(&self) -> Vec<Rating> {
         rs_fsrs::Rating::iter()
             .map(|fsrs_rating| Rating {
@@ -66,98 +97,49 @@ impl SrsScheduler for FSRS {
     }
 
     async fn get_leeches(&self, db: &SqlitePool) -> Result<Vec<Card>, Error> {
-        let cards_lapses: Vec<(i64, u32)> = sqlx::query_as(
-            r"SELECT card_id, COUNT(*)
-            FROM review_log
-            WHERE state = ? AND rating = ?
-            GROUP BY card_id",
+        get_cards_over_lapses_threshold(
+            db,
+            self.get_scheduler_name(),
+            state_to_number(State::Review),
+            rating_to_number(rs_fsrs::Rating::Again),
         )
-        .bind(state_to_number(State::Review))
-        .bind(rating_to_number(rs_fsrs::Rating::Again))
-        .fetch_all(db)
         .await
-        .map_err(|e| Error::Sqlx { source: e })?;
-        let config = read_external_config()?;
-        let cards_leeches = cards_lapses
-            .into_iter()
-            .filter(|(_card_id, lapses)| *lapses > config.leech.lapses_threshold)
-            .collect::<Vec<_>>();
-        let mut query = sqlx::query_as(
-            r"SELECT * FROM card
-             WHERE note_id IN (?)
-             AND state = ?
-             AND special_state IS NULL
-           ORDER BY card.due ASC",
-        );
-        for (card_id, _) in cards_leeches {
-            query = query.bind(card_id);
-        }
-        let cards = query
-            .fetch_all(db)
-            .await
-            .map_err(|e| Error::Sqlx { source: e })?;
-        Ok(cards)
     }
 
-    // Note that this can be optimized to account for the correlation between retention amongst siblings. The ratings can also be chosen better to better reflect human tendencies.
+    fn get_lapse_state_and_rating(&self) -> (StateId, RatingId) {
+        (
+            state_to_number(State::Review),
+            rating_to_number(rs_fsrs::Rating::Again),
+        )
+    }
+
     fn generate_review_history(
         &self,
         num_siblings: u32,
         num_reviews: u32,
         first_review_date: DateTime<Utc>,
-        mut rng: &mut ThreadRng,
+        desired_retention: f64,
+        rng: &mut ThreadRng,
     ) -> Vec<Vec<(RatingSubmission, DateTime<Utc>)>> {
         // Again = 1, Hard = 2, Good = 3, Easy = 4,
-        // let weights = [0, 0, 0, 3]; // Weights favoring 3
-        let weights = [1, 3, 5, 3]; // Weights favoring 3
-        let dist = WeightedIndex::new(weights).unwrap();
-        let ratings: Vec<u32> = (1..=num_reviews)
-            .map(|_| (dist.sample(&mut rng) + 1) as u32) // Add 1 to map to 1..=4
-            .collect::<Vec<_>>();
-
-        let mut all_review_histories = Vec::new();
-        for card_id in 1..=num_siblings {
-            let (_card, review_logs) =
-                ratings
-                    .iter()
-                    .fold((Card::new(first_review_date), Vec::new()), |acc, rating| {
-                        let (card, mut review_logs): (_, Vec<ReviewLog>) = acc;
-                        let duration = Duration::new(rng.gen_range(5..=60), 0).unwrap();
-                        let previous_review_log = review_logs.last();
-                        let reviewed_at = previous_review_log.map_or_else(
-                            || first_review_date,
-                            |rl| rl.reviewed_at + Duration::new(rl.scheduled_time, 0).unwrap(),
-                        );
-                        let (new_card, new_review_log) = <FSRS as SrsScheduler>::schedule(
-                            self,
-                            &card,
-                            previous_review_log.cloned(),
-                            *rating,
-                            reviewed_at,
-                            duration,
-                        )
-                        .unwrap();
-                        assert_eq!(new_review_log.reviewed_at, reviewed_at);
-                        review_logs.push(new_review_log);
-                        (new_card, review_logs)
-                    });
-            let card_review_history = review_logs
-                .into_iter()
-                .map(|review_log| {
-                    (
-                        RatingSubmission {
-                            card_id: i64::from(card_id),
-                            rating: review_log.rating,
-                            duration: Duration::seconds(review_log.duration),
-                            tag_id: None,
-                        },
-                        review_log.reviewed_at,
-                    )
-                })
-                .collect::<Vec<_>>();
-            all_review_histories.push(card_review_history);
-        }
-        all_review_histories
+        // Cards are reviewed when they are due, so they are forgotten `1 - desired_retention` of the time. The remaining ratings favor 3.
+        let recalled_weights = [3., 5., 3.];
+        let recalled_total: f64 = recalled_weights.iter().sum();
+        let weights = [
+            1. - desired_retention,
+            desired_retention * recalled_weights[0] / recalled_total,
+            desired_retention * recalled_weights[1] / recalled_total,
+            desired_retention * recalled_weights[2] / recalled_total,
+        ];
+        generate_review_history_from_weights(
+            self,
+            num_siblings,
+            num_reviews,
+            first_review_date,
+            desired_retention,
+            &weights,
+            rng,
+        )
     }
 
     fn schedule(
@@ -174,7 +156,9 @@ impl SrsScheduler for FSRS {
         let last_review = previous_review_log.map_or(DateTime::<Utc>::MIN_UTC, |r| r.reviewed_at);
         let card_fsrs = card_to_fsrs_card(card, state, last_review);
         // This returns 4 versions of the card, from which we select one depending on the rating chosen by the user.
-        let record_log_fsrs = self.repeat(card_fsrs, reviewed_at);
+        let record_log_fsrs = self
+            .get_fsrs(card.desired_retention)
+            .repeat(card_fsrs, reviewed_at);
         let rating = number_to_rating(rating).ok_or(Error::Library(LibraryError::Scheduler(
             SchedulerErrorKind::InvalidRating(rating),
         )))?;
@@ -189,6 +173,17 @@ impl SrsScheduler for FSRS {
         Ok((new_card, new_review_log))
     }
 
+    fn get_retrievability(
+        &self,
+        card: &Card,
+        last_reviewed_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> f64 {
+        number_to_state(card.state).map_or(0., |state| {
+            card_to_fsrs_card(card, state, last_reviewed_at).get_retrievability(at)
+        })
+    }
+
     fn filtered_tag_schedule(
         &self,
         filtered_tag_scheduler_data: Option<&Value>,
@@ -345,6 +340,40 @@ impl SrsScheduler for FSRS {
         .await
     }
 
+    async fn optimize_parameters(
+        &self,
+        db: &SqlitePool,
+        at: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let review_logs: Vec<ReviewLog> = sqlx::query_as(
+            r"SELECT * FROM review_log
+            WHERE scheduler_name = ?
+            ORDER BY card_id ASC, reviewed_at ASC",
+        )
+        .bind(self.get_scheduler_name())
+        .fetch_all(db)
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+        let review_histories = review_logs
+            .into_iter()
+            .chunk_by(|review_log| review_log.card_id)
+            .into_iter()
+            .map(|(_card_id, review_logs)| review_logs.collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let fit_result = fit_weights(&review_histories)?;
+
+        let mut internal_config = read_internal_config()?;
+        internal_config.fsrs_parameters = Some(fit_result.to_config(at));
+        write_internal_config(&internal_config)?;
+
+        let message = format!(
+            "Fitted parameters to {} reviews. Log loss decreased from {:.4} to {:.4}.",
+            fit_result.review_count, fit_result.initial_loss, fit_result.final_loss
+        );
+        info!("{}", message);
+        Ok(message)
+    }
+
     /// PROPOSAL:
     /// - If no easy days and yes siblings, then old disperse siblings
     /// - If yes easy days and yes siblings, then:
@@ -367,6 +396,7 @@ impl SrsScheduler for FSRS {
         let mut all_cards = siblings_with_review_logs.to_vec();
         all_cards.push(data.clone());
         let dispersed_siblings = disperse_siblings_distance(
+            &self.parameters,
             &all_cards,
             config.minimum_interval,
             config.maximum_interval,
@@ -396,7 +426,7 @@ impl SrsScheduler for FSRS {
             maximum_interval: config.maximum_interval.num_days() as i32,
             // We are manually using `get_fuzz_range()` here, so we don't want to enable fuzz when getting the next interval.
             enable_fuzz: false,
-            ..Default::default()
+            ..self.parameters.clone()
         };
         let last_elapsed_time = main_review_logs
             .iter()
@@ -0,0 +1,323 @@
+//! Fits FSRS weights to the local review history.
+//!
+//! Each card's review history is replayed with a candidate set of weights, mirroring the state transitions of `rs_fsrs`'s basic scheduler. The loss is the binary cross entropy between the predicted retrievability and whether the card was recalled, averaged over every review that happens at least a day after the previous one. The weights are then minimized with Adam, using central finite differences for the gradient, and are clamped to the same bounds that `fsrs-rs` uses.
+use super::utils::number_to_rating;
+use crate::{
+    Error, LibraryError, SchedulerErrorKind,
+    config::{FsrsParametersConfig, read_internal_config},
+    model::ReviewLog,
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use rayon::prelude::*;
+use rs_fsrs::{Parameters, Rating, State};
+
+pub type Weights = [f64; 19];
+
+/// The minimum number of reviews, that happen at least a day after the previous review, required to fit the weights.
+pub const MIN_REVIEW_COUNT: u32 = 64;
+
+const ITERATIONS: i32 = 250;
+const LEARNING_RATE: f64 = 0.01;
+const BETA_1: f64 = 0.9;
+const BETA_2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+/// Relative step size used to estimate the gradient, in terms of the width of each weight's bounds.
+const FINITE_DIFFERENCE_STEP: f64 = 1e-4;
+
+/// Lower and upper bounds for each weight.
+// Taken from `fsrs-rs`'s `parameter_clipper`.
+const WEIGHT_BOUNDS: [(f64, f64); 19] = [
+    (0.01, 100.),
+    (0.01, 100.),
+    (0.01, 100.),
+    (0.01, 100.),
+    (1., 10.),
+    (0.001, 4.),
+    (0.001, 4.),
+    (0.001, 0.75),
+    (0., 4.5),
+    (0., 0.8),
+    (0.001, 3.5),
+    (0.001, 5.),
+    (0.001, 0.25),
+    (0.001, 0.9),
+    (0., 4.),
+    (0., 1.),
+    (1., 6.),
+    (0., 2.),
+    (0., 2.),
+];
+
+/// Returns the parameters that the `fsrs` scheduler should use, which includes the fitted weights if the parameters have been optimized.
+///
+/// The default parameters are used if the internal config can't be read.
+pub fn get_parameters() -> Parameters {
+    let fitted_weights = read_internal_config()
+        .inspect_err(|e| error!("Failed to read fitted FSRS parameters: {e}"))
+        .ok()
+        .and_then(|config| config.fsrs_parameters)
+        .and_then(|fsrs_parameters| Weights::try_from(fsrs_parameters.weights).ok());
+    let default_parameters = Parameters::default();
+    Parameters {
+        w: fitted_weights.unwrap_or(default_parameters.w),
+        ..default_parameters
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ReviewItem {
+    rating: Rating,
+    /// Days since the previous review. This is `None` for the first review.
+    elapsed_days: Option<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FitResult {
+    pub weights: Weights,
+    /// Number of reviews used to compute the loss.
+    pub review_count: u32,
+    pub initial_loss: f64,
+    pub final_loss: f64,
+}
+
+impl FitResult {
+    pub fn to_config(&self, fitted_at: DateTime<Utc>) -> FsrsParametersConfig {
+        FsrsParametersConfig {
+            weights: self.weights.to_vec(),
+            fitted_at,
+            review_count: self.review_count,
+        }
+    }
+}
+
+fn to_review_items(review_logs: &[ReviewLog]) -> Result<Vec<ReviewItem>, Error> {
+    let mut previous_reviewed_at = None;
+    review_logs
+        .iter()
+        .map(|review_log| {
+            let rating = number_to_rating(review_log.rating).ok_or(Error::Library(
+                LibraryError::Scheduler(SchedulerErrorKind::InvalidRating(review_log.rating)),
+            ))?;
+            let elapsed_days = previous_reviewed_at
+                .map(|previous: DateTime<Utc>| (review_log.reviewed_at - previous).num_days());
+            previous_reviewed_at = Some(review_log.reviewed_at);
+            Ok(ReviewItem {
+                rating,
+                elapsed_days,
+            })
+        })
+        .collect()
+}
+
+/// Replays a card's reviews, returning the sum of the log loss and the number of reviews included in it.
+#[allow(clippy::cast_precision_loss)]
+fn replay(parameters: &Parameters, items: &[ReviewItem]) -> (f64, u32) {
+    let mut state = State::New;
+    let mut stability = 0.;
+    let mut difficulty = 0.;
+    let mut loss = 0.;
+    let mut count = 0;
+    for item in items {
+        let rating = item.rating;
+        if let Some(elapsed_days) = item.elapsed_days.filter(|days| *days >= 1) {
+            if state != State::New {
+                let retrievability = Parameters::forgetting_curve(elapsed_days as f64, stability)
+                    .clamp(0.0001, 0.9999);
+                loss -= if rating == Rating::Again {
+                    (1. - retrievability).ln()
+                } else {
+                    retrievability.ln()
+                };
+                count += 1;
+            }
+        }
+        match state {
+            State::New => {
+                difficulty = parameters.init_difficulty(rating);
+                stability = parameters.init_stability(rating);
+                state = if rating == Rating::Easy {
+                    State::Review
+                } else {
+                    State::Learning
+                };
+            }
+            State::Learning | State::Relearning => {
+                difficulty = parameters.next_difficulty(difficulty, rating);
+                stability = parameters.short_term_stability(stability, rating);
+                if matches!(rating, Rating::Good | Rating::Easy) {
+                    state = State::Review;
+                }
+            }
+            State::Review => {
+                let elapsed_days = item.elapsed_days.unwrap_or(0);
+                let retrievability = Parameters::forgetting_curve(elapsed_days as f64, stability);
+                if rating == Rating::Again {
+                    stability =
+                        parameters.next_forget_stability(difficulty, stability, retrievability);
+                    state = State::Relearning;
+                } else {
+                    stability = parameters.next_recall_stability(
+                        difficulty,
+                        stability,
+                        retrievability,
+                        rating,
+                    );
+                }
+                difficulty = parameters.next_difficulty(difficulty, rating);
+            }
+        }
+        stability = stability.clamp(0.01, 36500.);
+    }
+    (loss, count)
+}
+
+fn mean_loss(weights: &Weights, histories: &[Vec<ReviewItem>]) -> f64 {
+    let parameters = Parameters {
+        w: *weights,
+        ..Default::default()
+    };
+    let (loss, count) = histories
+        .par_iter()
+        .map(|items| replay(&parameters, items))
+        .reduce(|| (0., 0), |(l1, c1), (l2, c2)| (l1 + l2, c1 + c2));
+    loss / f64::from(count.max(1))
+}
+
+fn clip_weights(weights: &mut Weights) {
+    for (weight, (lower, upper)) in weights.iter_mut().zip(WEIGHT_BOUNDS) {
+        *weight = weight.clamp(lower, upper);
+    }
+    // Initial stabilities should increase with the rating.
+    for i in 1..4 {
+        weights[i] = weights[i].max(weights[i - 1]);
+    }
+}
+
+/// Fits the weights to the review history of each card. The review logs for each card must be sorted by `reviewed_at`.
+pub fn fit_weights(review_histories: &[Vec<ReviewLog>]) -> Result<FitResult, Error> {
+    let histories = review_histories
+        .iter()
+        .map(|review_logs| to_review_items(review_logs))
+        .collect::<Result<Vec<_>, _>>()?;
+    let default_parameters = Parameters::default();
+    let review_count: u32 = histories
+        .iter()
+        .map(|items| replay(&default_parameters, items).1)
+        .sum();
+    if review_count < MIN_REVIEW_COUNT {
+        return Err(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::Custom {
+                scheduler_name: "fsrs".to_string(),
+                error: format!(
+                    "At least {} reviews are needed to optimize parameters, but only {} were found.",
+                    MIN_REVIEW_COUNT, review_count
+                ),
+            },
+        )));
+    }
+
+    let initial_weights = default_parameters.w;
+    let initial_loss = mean_loss(&initial_weights, &histories);
+    let mut weights = initial_weights;
+    let mut best = (initial_weights, initial_loss);
+    let mut first_moment = [0.; 19];
+    let mut second_moment = [0.; 19];
+    for iteration in 1..=ITERATIONS {
+        let mut gradient = [0.; 19];
+        for (i, (lower, upper)) in WEIGHT_BOUNDS.iter().enumerate() {
+            let step = FINITE_DIFFERENCE_STEP * (upper - lower);
+            let mut weights_plus = weights;
+            weights_plus[i] += step;
+            let mut weights_minus = weights;
+            weights_minus[i] -= step;
+            gradient[i] = (mean_loss(&weights_plus, &histories)
+                - mean_loss(&weights_minus, &histories))
+                / (2. * step);
+        }
+        for (i, (lower, upper)) in WEIGHT_BOUNDS.iter().enumerate() {
+            first_moment[i] = BETA_1.mul_add(first_moment[i], (1. - BETA_1) * gradient[i]);
+            second_moment[i] =
+                BETA_2.mul_add(second_moment[i], (1. - BETA_2) * gradient[i].powi(2));
+            let first_moment_hat = first_moment[i] / (1. - BETA_1.powi(iteration));
+            let second_moment_hat = second_moment[i] / (1. - BETA_2.powi(iteration));
+            weights[i] -= LEARNING_RATE * (upper - lower) * first_moment_hat
+                / (second_moment_hat.sqrt() + EPSILON);
+        }
+        clip_weights(&mut weights);
+        let loss = mean_loss(&weights, &histories);
+        if loss < best.1 {
+            best = (weights, loss);
+        }
+    }
+
+    let (weights, final_loss) = best;
+    Ok(FitResult {
+        weights,
+        review_count,
+        initial_loss,
+        final_loss,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedulers::fsrs::utils::rating_to_number;
+    use chrono::Duration;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    /// Simulates a user whose memory follows `weights`, always rating recalled cards as `Good`.
+    fn simulate_review_logs(weights: Weights, card_count: usize) -> Vec<Vec<ReviewLog>> {
+        let fsrs = rs_fsrs::FSRS::new(Parameters {
+            w: weights,
+            ..Default::default()
+        });
+        let mut rng = StdRng::seed_from_u64(42);
+        let start = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        (0..card_count)
+            .map(|_| {
+                let mut card = rs_fsrs::Card::new();
+                let mut now = start;
+                let mut review_logs = Vec::new();
+                for _ in 0..8 {
+                    let rating = if card.state == State::New
+                        || rng.r#gen::<f64>() < card.get_retrievability(now)
+                    {
+                        Rating::Good
+                    } else {
+                        Rating::Again
+                    };
+                    card = fsrs.next(card, now, rating).card;
+                    review_logs.push(ReviewLog {
+                        reviewed_at: now,
+                        rating: rating_to_number(rating),
+                        ..ReviewLog::new()
+                    });
+                    now = card.due.max(now + Duration::days(1));
+                }
+                review_logs
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_weights_improves_loss() {
+        let mut true_weights = Parameters::default().w;
+        true_weights[2] = 1.;
+        true_weights[8] = 1.;
+        let review_logs = simulate_review_logs(true_weights, 200);
+        let fit_result = fit_weights(&review_logs).unwrap();
+        assert!(fit_result.review_count >= MIN_REVIEW_COUNT);
+        assert!(fit_result.final_loss < fit_result.initial_loss);
+        for (weight, (lower, upper)) in fit_result.weights.iter().zip(WEIGHT_BOUNDS) {
+            assert!((lower..=upper).contains(weight));
+        }
+    }
+
+    #[test]
+    fn test_fit_weights_not_enough_reviews() {
+        let review_logs = simulate_review_logs(Parameters::default().w, 1);
+        assert!(fit_weights(&review_logs).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! # Leitner
+//! A Leitner box scheduler with Pass and Fail ratings.
+//!
+//! Each card is in a box, which is stored in `Card.custom_data`. Passing a card moves it to the next box and failing it moves it back to the first box. A card is due once its box's interval has passed since its last review. The box intervals are configured with `scheduler.leitner.box_intervals`.
+use crate::{
+    Error, LibraryError, SchedulerErrorKind,
+    config::{LeitnerConfig, SparesExternalConfig, read_external_config},
+    helpers::{FractionalDays, get_start_end_local_date},
+    model::{Card, NEW_CARD_STATE, RatingId, ReviewLog, StateId},
+    schedulers::{
+        SrsScheduler, generate_review_history_from_weights, get_cards_over_lapses_threshold,
+    },
+    schema::review::{Rating, RatingSubmission},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::ThreadRng;
+use serde_json::{Map, Value, json};
+use sqlx::SqlitePool;
+
+/// Cards in a box use the same state id as reviewed cards in the other schedulers.
+const REVIEW_STATE: StateId = 2;
+/// Cards that are advanced or postponed by less than this proportion of their interval are considered safe to move.
+const SAFE_MOVE_PROPORTION: f64 = 0.1;
+/// The probability of recalling a card when it is due. The Leitner system does not model memory, so this is only used to estimate retrievability.
+const RETENTION_AT_DUE: f64 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LeitnerRating {
+    Fail = 1,
+    Pass = 2,
+}
+
+impl LeitnerRating {
+    const ALL: [Self; 2] = [Self::Fail, Self::Pass];
+
+    fn from_id(id: RatingId) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|rating| *rating as RatingId == id)
+    }
+}
+
+/// Returns the box that the card is in, starting from 1. New cards and cards that were scheduled by a different scheduler are not in a box, so this returns 0.
+fn get_box(card: &Card) -> usize {
+    card.custom_data
+        .get("box")
+        .and_then(Value::as_u64)
+        .map_or(0, |box_number| box_number as usize)
+}
+
+#[derive(Clone, Debug)]
+pub struct Leitner {
+    box_intervals: Vec<Duration>,
+}
+
+impl Leitner {
+    pub fn new(box_intervals: Vec<Duration>) -> Self {
+        Self { box_intervals }
+    }
+
+    /// Returns the interval of a card's box, clamping boxes that were removed from the config to the last box.
+    fn get_interval(&self, card: &Card) -> Duration {
+        let box_index = get_box(card).clamp(1, self.box_intervals.len()) - 1;
+        self.box_intervals[box_index]
+    }
+
+    /// Returns the cards in a box that were last reviewed with this scheduler.
+    async fn get_review_cards(
+        &self,
+        db: &SqlitePool,
+        requested_date: DateTime<Utc>,
+        due_after: bool,
+    ) -> Result<Vec<Card>, Error> {
+        let (_, card_due_limit) = get_start_end_local_date(&requested_date);
+        let query_str = if due_after {
+            r"SELECT * FROM card
+           WHERE due > ?
+             AND state = ?
+             AND special_state IS NULL
+             AND (SELECT rl.scheduler_name FROM review_log rl
+                  WHERE rl.card_id = card.id
+                  ORDER BY rl.reviewed_at DESC
+                  LIMIT 1) = ?
+           ORDER BY card.due ASC"
+        } else {
+            r"SELECT * FROM card
+           WHERE due <= ?
+             AND state = ?
+             AND special_state IS NULL
+             AND (SELECT rl.scheduler_name FROM review_log rl
+                  WHERE rl.card_id = card.id
+                  ORDER BY rl.reviewed_at DESC
+                  LIMIT 1) = ?
+           ORDER BY card.due ASC"
+        };
+        sqlx::query_as(query_str)
+            .bind(card_due_limit.timestamp())
+            .bind(REVIEW_STATE)
+            .bind(self.get_scheduler_name())
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })
+    }
+
+    fn postponed_due(card: &Card, requested_date: DateTime<Utc>) -> DateTime<Utc> {
+        card.due.max(requested_date) + Duration::days(1)
+    }
+
+    async fn update_due(db: &SqlitePool, card: &Card, due: DateTime<Utc>) -> Result<(), Error> {
+        let _update_card_result = sqlx::query(r"UPDATE card SET due = ? WHERE id = ?")
+            .bind(due.timestamp())
+            .bind(card.id)
+            .execute(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+        Ok(())
+    }
+}
+
+impl Default for Leitner {
+    fn default() -> Self {
+        let box_intervals = read_external_config().map_or_else(
+            |_| LeitnerConfig::default().box_intervals,
+            |config| config.scheduler.leitner.box_intervals,
+        );
+        Self::new(box_intervals)
+    }
+}
+
+#[async_trait]
+impl SrsScheduler for Leitner {
+    fn get_scheduler_name(&self) -> &'static str {
+        "leitner"
+    }
+
+    fn get_ratings(&self) -> Vec<Rating> {
+        LeitnerRating::ALL
+            .into_iter()
+            .map(|rating| Rating {
+                id: rating as RatingId,
+                description: format!("{:?}", rating),
+            })
+            .collect::<Vec<_>>()
+    }
+
+    async fn get_leeches(&self, db: &SqlitePool) -> Result<Vec<Card>, Error> {
+        // A lapse is a failed review of a card that was already in a box.
+        get_cards_over_lapses_threshold(
+            db,
+            self.get_scheduler_name(),
+            REVIEW_STATE,
+            LeitnerRating::Fail as RatingId,
+        )
+        .await
+    }
+
+    fn get_lapse_state_and_rating(&self) -> (StateId, RatingId) {
+        (REVIEW_STATE, LeitnerRating::Fail as RatingId)
+    }
+
+    fn generate_review_history(
+        &self,
+        num_siblings: u32,
+        num_reviews: u32,
+        first_review_date: DateTime<Utc>,
+        desired_retention: f64,
+        rng: &mut ThreadRng,
+    ) -> Vec<Vec<(RatingSubmission, DateTime<Utc>)>> {
+        // Fail = 1, Pass = 2
+        let weights = [1. - desired_retention, desired_retention];
+        generate_review_history_from_weights(
+            self,
+            num_siblings,
+            num_reviews,
+            first_review_date,
+            desired_retention,
+            &weights,
+            rng,
+        )
+    }
+
+    fn schedule(
+        &self,
+        card: &Card,
+        _previous_review_log: Option<ReviewLog>,
+        rating: RatingId,
+        reviewed_at: DateTime<Utc>,
+        duration: Duration,
+    ) -> Result<(Card, ReviewLog), Error> {
+        let leitner_rating = LeitnerRating::from_id(rating).ok_or(Error::Library(
+            LibraryError::Scheduler(SchedulerErrorKind::InvalidRating(rating)),
+        ))?;
+        let new_box = match leitner_rating {
+            LeitnerRating::Fail => 1,
+            LeitnerRating::Pass => (get_box(card) + 1).min(self.box_intervals.len()),
+        };
+        let interval = self.box_intervals[new_box - 1];
+        let new_card = Card {
+            updated_at: reviewed_at,
+            due: reviewed_at + interval,
+            stability: interval.num_fractional_days(),
+            state: REVIEW_STATE,
+            custom_data: json!({ "box": new_box }),
+            ..card.clone()
+        };
+        let new_review_log = ReviewLog {
+            id: 1,
+            card_id: card.id,
+            reviewed_at,
+            rating,
+            scheduler_name: self.get_scheduler_name().to_string(),
+            scheduled_time: interval.num_seconds(),
+            duration: duration.num_seconds(),
+            previous_state: card.state,
+            custom_data: Value::Object(Map::new()),
+        };
+        Ok((new_card, new_review_log))
+    }
+
+    fn get_retrievability(
+        &self,
+        card: &Card,
+        last_reviewed_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> f64 {
+        if card.state == NEW_CARD_STATE {
+            return 0.;
+        }
+        let interval = (card.due - last_reviewed_at).num_fractional_days();
+        if interval <= 0. {
+            return 1.;
+        }
+        RETENTION_AT_DUE.powf((at - last_reviewed_at).num_fractional_days() / interval)
+    }
+
+    fn filtered_tag_schedule(
+        &self,
+        _filtered_tag_scheduler_data: Option<&Value>,
+        _card: &Card,
+        rating: RatingId,
+        _reviewed_at: DateTime<Utc>,
+        _duration: Duration,
+    ) -> Result<Option<Value>, Error> {
+        // Shown until it is passed.
+        let rating = LeitnerRating::from_id(rating).ok_or(Error::Library(
+            LibraryError::Scheduler(SchedulerErrorKind::InvalidRating(rating)),
+        ))?;
+        match rating {
+            LeitnerRating::Fail => Ok(Some(Value::Object(Map::new()))),
+            LeitnerRating::Pass => Ok(None),
+        }
+    }
+
+    async fn get_advance_safe_count(
+        &self,
+        db: &SqlitePool,
+        requested_date: DateTime<Utc>,
+    ) -> Result<u32, Error> {
+        let cards = self.get_review_cards(db, requested_date, true).await?;
+        let safe_count = cards
+            .iter()
+            .filter(|card| {
+                (card.due - requested_date).num_fractional_days()
+                    < self.get_interval(card).num_fractional_days() * SAFE_MOVE_PROPORTION
+            })
+            .count();
+        Ok(safe_count as u32)
+    }
+
+    async fn get_postpone_safe_count(
+        &self,
+        db: &SqlitePool,
+        requested_date: DateTime<Utc>,
+    ) -> Result<u32, Error> {
+        let cards = self.get_review_cards(db, requested_date, false).await?;
+        let safe_count = cards
+            .iter()
+            .filter(|card| {
+                (Self::postponed_due(card, requested_date) - card.due).num_fractional_days()
+                    < self.get_interval(card).num_fractional_days() * SAFE_MOVE_PROPORTION
+            })
+            .count();
+        Ok(safe_count as u32)
+    }
+
+    /// Advances the cards that are due the soonest to `requested_date`. Their boxes are not changed.
+    async fn advance(
+        &self,
+        db: &SqlitePool,
+        _config: &SparesExternalConfig,
+        count: u32,
+        requested_date: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let cards = self.get_review_cards(db, requested_date, true).await?;
+        let cards = cards.into_iter().take(count as usize).collect::<Vec<_>>();
+        for card in &cards {
+            Self::update_due(db, card, requested_date).await?;
+        }
+        Ok(format!("Advanced {} cards.", cards.len()))
+    }
+
+    /// Postpones the cards that were most recently due by a day. Their boxes are not changed.
+    async fn postpone(
+        &self,
+        db: &SqlitePool,
+        _config: &SparesExternalConfig,
+        count: u32,
+        requested_date: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let mut cards = self.get_review_cards(db, requested_date, false).await?;
+        cards.reverse();
+        let cards = cards.into_iter().take(count as usize).collect::<Vec<_>>();
+        for card in &cards {
+            Self::update_due(db, card, Self::postponed_due(card, requested_date)).await?;
+        }
+        Ok(format!("Postponed {} cards.", cards.len()))
+    }
+
+    /// Cards are always due exactly when their box's interval has passed, so this does not load balance or disperse siblings.
+    async fn smart_schedule(
+        &self,
+        _config: &SparesExternalConfig,
+        data: &(Card, Vec<ReviewLog>),
+        _siblings_with_review_logs: &[(Card, Vec<ReviewLog>)],
+        _at: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, Error> {
+        Ok(data.0.due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(
+        scheduler: &Leitner,
+        card: &Card,
+        rating: LeitnerRating,
+        reviewed_at: DateTime<Utc>,
+    ) -> Card {
+        scheduler
+            .schedule(
+                card,
+                None,
+                rating as RatingId,
+                reviewed_at,
+                Duration::seconds(10),
+            )
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_boxes() {
+        let scheduler = Leitner::new(vec![Duration::days(1), Duration::days(3)]);
+        let now = Utc::now();
+        let card = Card::new(now);
+
+        let card = review(&scheduler, &card, LeitnerRating::Pass, now);
+        assert_eq!(get_box(&card), 1);
+        assert_eq!(card.due, now + Duration::days(1));
+
+        let card = review(&scheduler, &card, LeitnerRating::Pass, now);
+        assert_eq!(get_box(&card), 2);
+        assert_eq!(card.due, now + Duration::days(3));
+
+        // Passing a card in the last box keeps it in the last box.
+        let card = review(&scheduler, &card, LeitnerRating::Pass, now);
+        assert_eq!(get_box(&card), 2);
+
+        let card = review(&scheduler, &card, LeitnerRating::Fail, now);
+        assert_eq!(get_box(&card), 1);
+        assert_eq!(card.due, now + Duration::days(1));
+    }
+
+    #[test]
+    fn test_invalid_rating() {
+        let scheduler = Leitner::new(LeitnerConfig::default().box_intervals);
+        let now = Utc::now();
+        let card = Card::new(now);
+        assert!(
+            scheduler
+                .schedule(&card, None, 3, now, Duration::zero())
+                .is_err()
+        );
+    }
+}
@@ -1,15 +1,22 @@
-use crate::config::SparesExternalConfig;
-use crate::model::{Card, RatingId, ReviewLog, SpecialState};
+use crate::api::preset::get_note_presets;
+use crate::config::{SparesExternalConfig, read_external_config};
+use crate::model::{Card, CardId, NoteId, RatingId, ReviewLog, SpecialState, StateId};
 use crate::schema::review::{Rating, RatingSubmission};
 use crate::{Error, LibraryError, SchedulerErrorKind};
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use itertools::Itertools;
-use rand::rngs::ThreadRng;
+use rand::{
+    Rng,
+    distributions::{Distribution, WeightedIndex},
+    rngs::ThreadRng,
+};
 use serde_json::Value;
 use sqlx::SqlitePool;
 
 mod fsrs;
+mod leitner;
+mod sm2;
 
 pub fn stepped_range_inclusive(start: Duration, end: Duration, step: Duration) -> Vec<Duration> {
     let mut intervals = Vec::new();
@@ -21,20 +28,145 @@ pub fn stepped_range_inclusive(start: Duration, end: Duration, step: Duration) -
     intervals
 }
 
+/// Generates review histories for [`SrsScheduler::generate_review_history`]. Ratings are sampled from `rating_weights`, where the first weight corresponds to the rating with an id of 1.
+// Note that this can be optimized to account for the correlation between retention amongst siblings. The ratings can also be chosen better to better reflect human tendencies.
+#[allow(clippy::missing_panics_doc)]
+pub fn generate_review_history_from_weights<S: SrsScheduler + ?Sized>(
+    scheduler: &S,
+    num_siblings: u32,
+    num_reviews: u32,
+    first_review_date: DateTime<Utc>,
+    desired_retention: f64,
+    rating_weights: &[f64],
+    mut rng: &mut ThreadRng,
+) -> Vec<Vec<(RatingSubmission, DateTime<Utc>)>> {
+    let dist = WeightedIndex::new(rating_weights).unwrap();
+    let ratings: Vec<RatingId> = (1..=num_reviews)
+        .map(|_| (dist.sample(&mut rng) + 1) as RatingId) // Add 1 to map to rating ids
+        .collect::<Vec<_>>();
+
+    let initial_card = Card {
+        desired_retention,
+        ..Card::new(first_review_date)
+    };
+    let mut all_review_histories = Vec::new();
+    for card_id in 1..=num_siblings {
+        let (_card, review_logs) =
+            ratings
+                .iter()
+                .fold((initial_card.clone(), Vec::new()), |acc, rating| {
+                    let (card, mut review_logs): (_, Vec<ReviewLog>) = acc;
+                    let duration = Duration::new(rng.gen_range(5..=60), 0).unwrap();
+                    let previous_review_log = review_logs.last();
+                    let reviewed_at = previous_review_log.map_or_else(
+                        || first_review_date,
+                        |rl| rl.reviewed_at + Duration::new(rl.scheduled_time, 0).unwrap(),
+                    );
+                    let (new_card, new_review_log) = scheduler
+                        .schedule(
+                            &card,
+                            previous_review_log.cloned(),
+                            *rating,
+                            reviewed_at,
+                            duration,
+                        )
+                        .unwrap();
+                    assert_eq!(new_review_log.reviewed_at, reviewed_at);
+                    review_logs.push(new_review_log);
+                    (new_card, review_logs)
+                });
+        let card_review_history = review_logs
+            .into_iter()
+            .map(|review_log| {
+                (
+                    RatingSubmission {
+                        card_id: i64::from(card_id),
+                        rating: review_log.rating,
+                        duration: Duration::seconds(review_log.duration),
+                        tag_id: None,
+                    },
+                    review_log.reviewed_at,
+                )
+            })
+            .collect::<Vec<_>>();
+        all_review_histories.push(card_review_history);
+    }
+    all_review_histories
+}
+
+/// Returns the cards that have lapsed more times than the leech threshold of their preset, where a lapse is a review by `scheduler_name` of a card in `review_state` that is rated `fail_rating`. Reviews by other schedulers are ignored, since their states and ratings can have different meanings. This can be used to implement [`SrsScheduler::get_leeches`].
+pub async fn get_cards_over_lapses_threshold(
+    db: &SqlitePool,
+    scheduler_name: &str,
+    review_state: StateId,
+    fail_rating: RatingId,
+) -> Result<Vec<Card>, Error> {
+    let cards_lapses: Vec<(CardId, NoteId, u32)> = sqlx::query_as(
+        r"SELECT rl.card_id, c.note_id, COUNT(*)
+        FROM review_log rl
+        JOIN card c ON rl.card_id = c.id
+        WHERE rl.scheduler_name = ? AND rl.previous_state = ? AND rl.rating = ? AND c.special_state IS NULL
+        GROUP BY rl.card_id",
+    )
+    .bind(scheduler_name)
+    .bind(review_state)
+    .bind(fail_rating)
+    .fetch_all(db)
+    .await
+    .map_err(|e| Error::Sqlx { source: e })?;
+    let config = read_external_config()?;
+    let note_presets = get_note_presets(db, &config).await?;
+    let mut cards = Vec::new();
+    for (card_id, note_id, lapses) in cards_lapses {
+        let lapses_threshold = config
+            .apply_preset(note_presets.get(&note_id).map(String::as_str))
+            .leech
+            .lapses_threshold;
+        if lapses <= lapses_threshold {
+            continue;
+        }
+        let card: Card = sqlx::query_as(r"SELECT * FROM card WHERE id = ?")
+            .bind(card_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+        cards.push(card);
+    }
+    cards.sort_by_key(|card| card.due);
+    Ok(cards)
+}
+
 #[async_trait]
 pub trait SrsScheduler: Send + Sync {
     fn get_scheduler_name(&self) -> &'static str;
 
+    /// Replaces the default parameters with the ones fitted to the review history, if the scheduler supports fitting them.
+    ///
+    /// This is called once by `get_scheduler_from_string()`, so constructing a scheduler with `Default` never reads from disk.
+    fn load_fitted_parameters(&mut self) {}
+
     fn get_ratings(&self) -> Vec<Rating>;
 
     async fn get_leeches(&self, db: &SqlitePool) -> Result<Vec<Card>, Error>;
 
-    /// Returns a rating and when it was reviewed at
+    /// The previous state and rating of a lapse, which is when a card that was learned is forgotten. Cards that lapse too many times are leeches.
+    fn get_lapse_state_and_rating(&self) -> (StateId, RatingId);
+
+    /// Whether the review is a lapse. Reviews made by other schedulers are never lapses, since their states and ratings can mean something else.
+    fn is_lapse(&self, review_log: &ReviewLog) -> bool {
+        let (lapse_state, lapse_rating) = self.get_lapse_state_and_rating();
+        review_log.scheduler_name == self.get_scheduler_name()
+            && review_log.previous_state == lapse_state
+            && review_log.rating == lapse_rating
+    }
+
+    /// Returns a rating and when it was reviewed at. Cards are reviewed when they are due and ratings are chosen so that cards are recalled about `desired_retention` of the time.
     fn generate_review_history(
         &self,
         num_siblings: u32,
         num_reviews: u32,
         first_review_date: DateTime<Utc>,
+        desired_retention: f64,
         rng: &mut ThreadRng,
     ) -> Vec<Vec<(RatingSubmission, DateTime<Utc>)>>;
 
@@ -48,6 +180,14 @@ pub trait SrsScheduler: Send + Sync {
         duration: Duration,
     ) -> Result<(Card, ReviewLog), Error>;
 
+    /// Returns the probability of recalling the card at `at`, given that it was last reviewed at `last_reviewed_at`.
+    fn get_retrievability(
+        &self,
+        card: &Card,
+        last_reviewed_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> f64;
+
     /// Returns `Ok(None)` if the card should no longer be in the filtered deck.
     fn filtered_tag_schedule(
         &self,
@@ -94,6 +234,20 @@ pub trait SrsScheduler: Send + Sync {
         requested_date: DateTime<Utc>,
     ) -> Result<String, Error>;
 
+    /// Fits the scheduler's parameters to the review history. Returns a message describing the result.
+    async fn optimize_parameters(
+        &self,
+        _db: &SqlitePool,
+        _at: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        Err(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::Custom {
+                scheduler_name: self.get_scheduler_name().to_string(),
+                error: "Optimizing parameters is not supported.".to_string(),
+            },
+        )))
+    }
+
     // - User requests Reschedule -> Compute memory state for each card -> (some combination of applying easy days and dispersing siblings to determine card's new due date) -> Update card (including due date)
     async fn reschedule(
         &self,
@@ -105,8 +259,16 @@ pub trait SrsScheduler: Send + Sync {
         // Recompute parameters
         cards_with_review_logs.iter_mut().try_for_each(
             |(card, review_logs)| -> Result<(), Error> {
-                let new_card = self.compute_memory_state(review_logs.clone())?;
-                (card.stability, card.difficulty) = (new_card.stability, new_card.difficulty);
+                let new_card = self.compute_memory_state(card, review_logs.clone())?;
+                // The state and custom data are also replaced since they may have been set by a different scheduler.
+                *card = Card {
+                    due: new_card.due,
+                    stability: new_card.stability,
+                    difficulty: new_card.difficulty,
+                    state: new_card.state,
+                    custom_data: new_card.custom_data,
+                    ..card.clone()
+                };
                 Ok(())
             },
         )?;
@@ -139,8 +301,8 @@ pub trait SrsScheduler: Send + Sync {
                 .bind(updated_card.difficulty)
                 .bind(updated_card.state)
                 .bind(updated_card.custom_data.clone())
-                .bind(updated_card.id)
                 .bind(updated_card.updated_at.timestamp())
+                .bind(updated_card.id)
                 .execute(db)
                 .await
                 .map_err(|e| Error::Sqlx { source: e })?;
@@ -162,34 +324,72 @@ pub trait SrsScheduler: Send + Sync {
         at: DateTime<Utc>,
     ) -> Result<DateTime<Utc>, Error>;
 
-    fn compute_memory_state(&self, review_logs: Vec<ReviewLog>) -> Result<Card, Error> {
+    /// Converts the rating of a review log, which may have been created by a different scheduler, to one of this scheduler's ratings.
+    ///
+    /// Ratings are assumed to be ordered from worst to best, where `1` is a failed review. Passing ratings are mapped linearly, so a scheduler with only Pass and Fail maps Pass to Good when converting to FSRS's ratings.
+    fn convert_rating(&self, review_log: &ReviewLog) -> RatingId {
+        let rating_count = self.get_ratings().len() as RatingId;
+        if review_log.scheduler_name == self.get_scheduler_name() || review_log.rating <= 1 {
+            return review_log.rating.min(rating_count);
+        }
+        let source_rating_count = get_all_schedulers()
+            .into_iter()
+            .map(|scheduler_fn| scheduler_fn())
+            .find(|scheduler| scheduler.get_scheduler_name() == review_log.scheduler_name)
+            .map_or(rating_count, |scheduler| {
+                scheduler.get_ratings().len() as RatingId
+            });
+        if source_rating_count == rating_count {
+            return review_log.rating.min(rating_count);
+        }
+        let passing_index = (review_log.rating - 2).min(source_rating_count.saturating_sub(2));
+        2 + (2 * passing_index + 1) * (rating_count - 1) / (2 * (source_rating_count - 1))
+    }
+
+    /// Replays the review logs, which may have been created by a different scheduler, to compute the card's state.
+    fn compute_memory_state(
+        &self,
+        card: &Card,
+        review_logs: Vec<ReviewLog>,
+    ) -> Result<Card, Error> {
         assert!(!review_logs.is_empty());
         let first_review = review_logs.first().unwrap().reviewed_at;
-        let (final_card, _final_review_log) =
-            review_logs
-                .into_iter()
-                .fold((Card::new(first_review), None), |acc, review_log| {
-                    let (card, previous_review_log) = acc;
-                    let duration = Duration::new(review_log.duration, 0).unwrap();
-                    let (new_card, new_review_log) = self
-                        .schedule(
-                            &card,
-                            previous_review_log,
-                            review_log.rating,
-                            review_log.reviewed_at,
-                            duration,
-                        )
-                        .unwrap();
-                    (new_card, Some(new_review_log))
-                });
+        let initial_card = Card {
+            desired_retention: card.desired_retention,
+            ..Card::new(first_review)
+        };
+        let (final_card, _final_review_log) = review_logs.into_iter().try_fold(
+            (initial_card, None),
+            |acc, review_log| -> Result<_, Error> {
+                let (card, previous_review_log) = acc;
+                let duration = Duration::new(review_log.duration, 0).unwrap();
+                let (new_card, new_review_log) = self.schedule(
+                    &card,
+                    previous_review_log,
+                    self.convert_rating(&review_log),
+                    review_log.reviewed_at,
+                    duration,
+                )?;
+                Ok((new_card, Some(new_review_log)))
+            },
+        )?;
         Ok(final_card)
     }
 }
 
-fn get_all_schedulers() -> Vec<fn() -> Box<dyn SrsScheduler>> {
+/// The scheduler name, previous state and rating of a lapse for each scheduler. This matches `get_lapse_state_and_rating()`, so searches can count lapses without constructing every scheduler. See `test_lapse_definitions`.
+pub(crate) const LAPSE_DEFINITIONS: [(&str, StateId, RatingId); 3] =
+    [("fsrs", 2, 1), ("sm2", 2, 1), ("leitner", 2, 1)];
+
+pub(crate) fn get_all_schedulers() -> Vec<fn() -> Box<dyn SrsScheduler>> {
     // NOTE: Add scheduler here
+    // Also add its lapse to `LAPSE_DEFINITIONS`
     // Also run: `spares_cli add scheduler --name="NAME"`
-    let all_schedulers: Vec<fn() -> Box<dyn SrsScheduler>> = vec![|| Box::<fsrs::FSRS>::default()];
+    let all_schedulers: Vec<fn() -> Box<dyn SrsScheduler>> = vec![
+        || Box::<fsrs::Fsrs>::default(),
+        || Box::<sm2::Sm2>::default(),
+        || Box::<leitner::Leitner>::default(),
+    ];
     all_schedulers
 }
 
@@ -207,7 +407,9 @@ pub fn get_scheduler_from_string(scheduler_str: &str) -> Result<Box<dyn SrsSched
     // Not possible. See `test_schedulers_validation`
     // if matching_schedulers.len() > 1 {
     // }
-    Ok(matching_schedulers[0]())
+    let mut scheduler = matching_schedulers[0]();
+    scheduler.load_fitted_parameters();
+    Ok(scheduler)
 }
 
 #[cfg(test)]
@@ -231,4 +433,38 @@ mod tests {
             all_scheduler_names.iter().unique().count()
         );
     }
+
+    #[test]
+    fn test_lapse_definitions() {
+        let lapse_definitions = get_all_schedulers()
+            .into_iter()
+            .map(|scheduler_fn| {
+                let scheduler = scheduler_fn();
+                let (lapse_state, lapse_rating) = scheduler.get_lapse_state_and_rating();
+                (scheduler.get_scheduler_name(), lapse_state, lapse_rating)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lapse_definitions, LAPSE_DEFINITIONS);
+    }
+
+    #[test]
+    fn test_convert_rating() {
+        let review_log = |scheduler_name: &str, rating: RatingId| ReviewLog {
+            scheduler_name: scheduler_name.to_string(),
+            rating,
+            ..ReviewLog::new()
+        };
+        let fsrs = get_scheduler_from_string("fsrs").unwrap();
+        let leitner = get_scheduler_from_string("leitner").unwrap();
+        // Pass and Fail to Again, Hard, Good, Easy
+        assert_eq!(fsrs.convert_rating(&review_log("leitner", 1)), 1);
+        assert_eq!(fsrs.convert_rating(&review_log("leitner", 2)), 3);
+        // Again, Hard, Good, Easy to Pass and Fail
+        assert_eq!(leitner.convert_rating(&review_log("fsrs", 1)), 1);
+        for rating in 2..=4 {
+            assert_eq!(leitner.convert_rating(&review_log("sm2", rating)), 2);
+        }
+        // Same number of ratings
+        assert_eq!(fsrs.convert_rating(&review_log("sm2", 2)), 2);
+    }
 }
@@ -0,0 +1,549 @@
+//! # SM-2
+//! A classic SM-2 scheduler, modelled after Anki's legacy (v2) scheduler.
+//!
+//! The ease factor, the current interval, and the current learning step are stored in `Card.custom_data`. Since SM-2 does not have a memory model, `Card.stability` is set to the current interval in days.
+use crate::{
+    Error, LibraryError, SchedulerErrorKind,
+    config::SparesExternalConfig,
+    helpers::{FractionalDays, get_start_end_local_date},
+    model::{Card, NEW_CARD_STATE, RatingId, ReviewLog, StateId},
+    schedulers::{
+        SrsScheduler, generate_review_history_from_weights, get_cards_over_lapses_threshold,
+    },
+    schema::review::{Rating, RatingSubmission},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::ThreadRng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::SqlitePool;
+
+const LEARNING_STATE: StateId = 1;
+const REVIEW_STATE: StateId = 2;
+const RELEARNING_STATE: StateId = 3;
+
+const LEARNING_STEPS_MINUTES: [i64; 2] = [1, 10];
+const RELEARNING_STEPS_MINUTES: [i64; 1] = [10];
+const GRADUATING_INTERVAL_DAYS: f64 = 1.;
+const EASY_INTERVAL_DAYS: f64 = 4.;
+const MAXIMUM_INTERVAL_DAYS: f64 = 36500.;
+const STARTING_EASE: f64 = 2.5;
+const MINIMUM_EASE: f64 = 1.3;
+const EASY_BONUS: f64 = 1.3;
+const HARD_INTERVAL_FACTOR: f64 = 1.2;
+/// The new interval after a lapse, as a proportion of the previous interval.
+const LAPSE_INTERVAL_FACTOR: f64 = 0.;
+/// Cards that are advanced or postponed by less than this proportion of their interval are considered safe to move.
+const SAFE_MOVE_PROPORTION: f64 = 0.1;
+/// The probability of recalling a card when it is due. SM-2 does not model memory, so this is only used to estimate retrievability.
+const RETENTION_AT_DUE: f64 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sm2Rating {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Sm2Rating {
+    const ALL: [Self; 4] = [Self::Again, Self::Hard, Self::Good, Self::Easy];
+
+    fn from_id(id: RatingId) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|rating| *rating as RatingId == id)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct Sm2Data {
+    ease_factor: f64,
+    /// Current interval in days.
+    interval: f64,
+    /// Index of the current learning or relearning step.
+    step: usize,
+}
+
+impl Sm2Data {
+    /// Reads the data from the card. Cards that were scheduled by a different scheduler fall back to the starting ease and the last scheduled interval.
+    fn from_card(card: &Card, previous_review_log: Option<&ReviewLog>) -> Self {
+        serde_json::from_value(card.custom_data.clone()).unwrap_or_else(|_| Self {
+            ease_factor: STARTING_EASE,
+            interval: previous_review_log.map_or(0., |review_log| {
+                Duration::seconds(review_log.scheduled_time).num_fractional_days()
+            }),
+            step: 0,
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+/// Returns the SM-2 interval of a card in review, falling back to 1 day.
+fn get_interval(card: &Card) -> Duration {
+    let interval = serde_json::from_value::<Sm2Data>(card.custom_data.clone())
+        .map_or(GRADUATING_INTERVAL_DAYS, |data| data.interval)
+        .max(GRADUATING_INTERVAL_DAYS);
+    Duration::fractional_days(interval)
+}
+
+/// Moves a card through its (re)learning steps. Returns the new state and the delay until the card is due.
+fn next_learning_step(
+    data: &mut Sm2Data,
+    rating: Sm2Rating,
+    steps_minutes: &[i64],
+    learning_state: StateId,
+    graduating_interval: f64,
+    easy_interval: f64,
+) -> (StateId, Duration) {
+    match rating {
+        Sm2Rating::Again => {
+            data.step = 0;
+            (learning_state, Duration::minutes(steps_minutes[0]))
+        }
+        Sm2Rating::Hard => {
+            let step = data.step.min(steps_minutes.len() - 1);
+            (learning_state, Duration::minutes(steps_minutes[step]))
+        }
+        Sm2Rating::Good => {
+            data.step += 1;
+            if data.step < steps_minutes.len() {
+                return (learning_state, Duration::minutes(steps_minutes[data.step]));
+            }
+            data.step = 0;
+            data.interval = graduating_interval;
+            (REVIEW_STATE, Duration::fractional_days(graduating_interval))
+        }
+        Sm2Rating::Easy => {
+            data.step = 0;
+            data.interval = easy_interval;
+            (REVIEW_STATE, Duration::fractional_days(easy_interval))
+        }
+    }
+}
+
+/// Schedules a card in review. Returns the new state and the delay until the card is due.
+fn next_review(data: &mut Sm2Data, rating: Sm2Rating, elapsed: Duration) -> (StateId, Duration) {
+    let interval = data.interval.max(GRADUATING_INTERVAL_DAYS);
+    let days_late = (elapsed.num_fractional_days() - interval).max(0.);
+    let hard_interval = (interval * HARD_INTERVAL_FACTOR).max(interval + 1.).round();
+    let good_interval = ((interval + days_late / 2.) * data.ease_factor)
+        .max(hard_interval + 1.)
+        .round();
+    let easy_interval = ((interval + days_late) * data.ease_factor * EASY_BONUS)
+        .max(good_interval + 1.)
+        .round();
+    let (state, new_interval) = match rating {
+        Sm2Rating::Again => {
+            data.ease_factor -= 0.2;
+            data.step = 0;
+            data.interval = (interval * LAPSE_INTERVAL_FACTOR).max(GRADUATING_INTERVAL_DAYS);
+            return (
+                RELEARNING_STATE,
+                Duration::minutes(RELEARNING_STEPS_MINUTES[0]),
+            );
+        }
+        Sm2Rating::Hard => {
+            data.ease_factor -= 0.15;
+            (REVIEW_STATE, hard_interval)
+        }
+        Sm2Rating::Good => (REVIEW_STATE, good_interval),
+        Sm2Rating::Easy => {
+            data.ease_factor += 0.15;
+            (REVIEW_STATE, easy_interval)
+        }
+    };
+    data.ease_factor = data.ease_factor.max(MINIMUM_EASE);
+    data.interval = new_interval.min(MAXIMUM_INTERVAL_DAYS);
+    (state, Duration::fractional_days(data.interval))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sm2;
+
+impl Sm2 {
+    /// Returns the cards in review that were last reviewed with this scheduler.
+    async fn get_review_cards(
+        db: &SqlitePool,
+        requested_date: DateTime<Utc>,
+        due_after: bool,
+    ) -> Result<Vec<Card>, Error> {
+        let (_, card_due_limit) = get_start_end_local_date(&requested_date);
+        let query_str = if due_after {
+            r"SELECT * FROM card
+           WHERE due > ?
+             AND state = ?
+             AND special_state IS NULL
+             AND (SELECT rl.scheduler_name FROM review_log rl
+                  WHERE rl.card_id = card.id
+                  ORDER BY rl.reviewed_at DESC
+                  LIMIT 1) = ?
+           ORDER BY card.due ASC"
+        } else {
+            r"SELECT * FROM card
+           WHERE due <= ?
+             AND state = ?
+             AND special_state IS NULL
+             AND (SELECT rl.scheduler_name FROM review_log rl
+                  WHERE rl.card_id = card.id
+                  ORDER BY rl.reviewed_at DESC
+                  LIMIT 1) = ?
+           ORDER BY card.due ASC"
+        };
+        sqlx::query_as(query_str)
+            .bind(card_due_limit.timestamp())
+            .bind(REVIEW_STATE)
+            .bind(Self.get_scheduler_name())
+            .fetch_all(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })
+    }
+
+    /// The proportion of the interval that a card would be reviewed early by if it was advanced to `requested_date`.
+    fn advance_proportion(card: &Card, requested_date: DateTime<Utc>) -> f64 {
+        (card.due - requested_date).num_fractional_days() / get_interval(card).num_fractional_days()
+    }
+
+    /// The proportion of the interval that a card would be overdue by if it was postponed to tomorrow.
+    fn postpone_proportion(card: &Card, requested_date: DateTime<Utc>) -> f64 {
+        let (_, card_due_limit) = get_start_end_local_date(&requested_date);
+        let interval = get_interval(card);
+        let last_reviewed_at = card.due - interval;
+        (card_due_limit + Duration::days(1) - last_reviewed_at).num_fractional_days()
+            / interval.num_fractional_days()
+            - 1.
+    }
+
+    async fn update_due(db: &SqlitePool, card: &Card, due: DateTime<Utc>) -> Result<(), Error> {
+        let _update_card_result = sqlx::query(r"UPDATE card SET due = ? WHERE id = ?")
+            .bind(due.timestamp())
+            .bind(card.id)
+            .execute(db)
+            .await
+            .map_err(|e| Error::Sqlx { source: e })?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SrsScheduler for Sm2 {
+    fn get_scheduler_name(&self) -> &'static str {
+        "sm2"
+    }
+
+    fn get_ratings(&self) -> Vec<Rating> {
+        Sm2Rating::ALL
+            .into_iter()
+            .map(|rating| Rating {
+                id: rating as RatingId,
+                description: format!("{:?}", rating),
+            })
+            .collect::<Vec<_>>()
+    }
+
+    async fn get_leeches(&self, db: &SqlitePool) -> Result<Vec<Card>, Error> {
+        get_cards_over_lapses_threshold(
+            db,
+            self.get_scheduler_name(),
+            REVIEW_STATE,
+            Sm2Rating::Again as RatingId,
+        )
+        .await
+    }
+
+    fn get_lapse_state_and_rating(&self) -> (StateId, RatingId) {
+        (REVIEW_STATE, Sm2Rating::Again as RatingId)
+    }
+
+    fn generate_review_history(
+        &self,
+        num_siblings: u32,
+        num_reviews: u32,
+        first_review_date: DateTime<Utc>,
+        desired_retention: f64,
+        rng: &mut ThreadRng,
+    ) -> Vec<Vec<(RatingSubmission, DateTime<Utc>)>> {
+        // Again = 1, Hard = 2, Good = 3, Easy = 4,
+        let recalled_weights = [3., 5., 3.];
+        let recalled_total: f64 = recalled_weights.iter().sum();
+        let weights = [
+            1. - desired_retention,
+            desired_retention * recalled_weights[0] / recalled_total,
+            desired_retention * recalled_weights[1] / recalled_total,
+            desired_retention * recalled_weights[2] / recalled_total,
+        ];
+        generate_review_history_from_weights(
+            self,
+            num_siblings,
+            num_reviews,
+            first_review_date,
+            desired_retention,
+            &weights,
+            rng,
+        )
+    }
+
+    fn schedule(
+        &self,
+        card: &Card,
+        previous_review_log: Option<ReviewLog>,
+        rating: RatingId,
+        reviewed_at: DateTime<Utc>,
+        duration: Duration,
+    ) -> Result<(Card, ReviewLog), Error> {
+        let sm2_rating = Sm2Rating::from_id(rating).ok_or(Error::Library(
+            LibraryError::Scheduler(SchedulerErrorKind::InvalidRating(rating)),
+        ))?;
+        let mut data = Sm2Data::from_card(card, previous_review_log.as_ref());
+        let (state, delay) = match card.state {
+            NEW_CARD_STATE | LEARNING_STATE => next_learning_step(
+                &mut data,
+                sm2_rating,
+                &LEARNING_STEPS_MINUTES,
+                LEARNING_STATE,
+                GRADUATING_INTERVAL_DAYS,
+                EASY_INTERVAL_DAYS,
+            ),
+            RELEARNING_STATE => {
+                let interval = data.interval.max(GRADUATING_INTERVAL_DAYS);
+                next_learning_step(
+                    &mut data,
+                    sm2_rating,
+                    &RELEARNING_STEPS_MINUTES,
+                    RELEARNING_STATE,
+                    interval,
+                    interval + 1.,
+                )
+            }
+            REVIEW_STATE => {
+                let elapsed = previous_review_log.as_ref().map_or_else(
+                    || Duration::fractional_days(data.interval),
+                    |review_log| reviewed_at - review_log.reviewed_at,
+                );
+                next_review(&mut data, sm2_rating, elapsed)
+            }
+            state => {
+                return Err(Error::Library(LibraryError::Scheduler(
+                    SchedulerErrorKind::InvalidState(state),
+                )));
+            }
+        };
+        let new_card = Card {
+            updated_at: reviewed_at,
+            due: reviewed_at + delay,
+            stability: data.interval,
+            state,
+            custom_data: data.to_value(),
+            ..card.clone()
+        };
+        let new_review_log = ReviewLog {
+            id: 1,
+            card_id: card.id,
+            reviewed_at,
+            rating,
+            scheduler_name: self.get_scheduler_name().to_string(),
+            scheduled_time: delay.num_seconds(),
+            duration: duration.num_seconds(),
+            previous_state: card.state,
+            custom_data: Value::Object(Map::new()),
+        };
+        Ok((new_card, new_review_log))
+    }
+
+    fn get_retrievability(
+        &self,
+        card: &Card,
+        last_reviewed_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> f64 {
+        if card.state == NEW_CARD_STATE {
+            return 0.;
+        }
+        let interval = (card.due - last_reviewed_at).num_fractional_days();
+        if interval <= 0. {
+            return 1.;
+        }
+        RETENTION_AT_DUE.powf((at - last_reviewed_at).num_fractional_days() / interval)
+    }
+
+    fn filtered_tag_schedule(
+        &self,
+        _filtered_tag_scheduler_data: Option<&Value>,
+        _card: &Card,
+        rating: RatingId,
+        _reviewed_at: DateTime<Utc>,
+        _duration: Duration,
+    ) -> Result<Option<Value>, Error> {
+        // Shown until it is rated `Good` or `Easy`.
+        let rating = Sm2Rating::from_id(rating).ok_or(Error::Library(LibraryError::Scheduler(
+            SchedulerErrorKind::InvalidRating(rating),
+        )))?;
+        match rating {
+            Sm2Rating::Again | Sm2Rating::Hard => Ok(Some(Value::Object(Map::new()))),
+            Sm2Rating::Good | Sm2Rating::Easy => Ok(None),
+        }
+    }
+
+    async fn get_advance_safe_count(
+        &self,
+        db: &SqlitePool,
+        requested_date: DateTime<Utc>,
+    ) -> Result<u32, Error> {
+        let cards = Self::get_review_cards(db, requested_date, true).await?;
+        let safe_count = cards
+            .iter()
+            .filter(|card| Self::advance_proportion(card, requested_date) < SAFE_MOVE_PROPORTION)
+            .count();
+        Ok(safe_count as u32)
+    }
+
+    async fn get_postpone_safe_count(
+        &self,
+        db: &SqlitePool,
+        requested_date: DateTime<Utc>,
+    ) -> Result<u32, Error> {
+        let cards = Self::get_review_cards(db, requested_date, false).await?;
+        let safe_count = cards
+            .iter()
+            .filter(|card| Self::postpone_proportion(card, requested_date) < SAFE_MOVE_PROPORTION)
+            .count();
+        Ok(safe_count as u32)
+    }
+
+    async fn advance(
+        &self,
+        db: &SqlitePool,
+        _config: &SparesExternalConfig,
+        count: u32,
+        requested_date: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let mut cards = Self::get_review_cards(db, requested_date, true).await?;
+        // Advance the cards that are reviewed the least early relative to their interval first.
+        cards.sort_by(|a, b| {
+            Self::advance_proportion(a, requested_date)
+                .total_cmp(&Self::advance_proportion(b, requested_date))
+        });
+        let cards = cards.into_iter().take(count as usize).collect::<Vec<_>>();
+        for card in &cards {
+            Self::update_due(db, card, requested_date).await?;
+        }
+        Ok(format!("Advanced {} cards.", cards.len()))
+    }
+
+    async fn postpone(
+        &self,
+        db: &SqlitePool,
+        _config: &SparesExternalConfig,
+        count: u32,
+        requested_date: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let (_, card_due_limit) = get_start_end_local_date(&requested_date);
+        let mut cards = Self::get_review_cards(db, requested_date, false).await?;
+        // Postpone the cards that will be the least overdue relative to their interval first.
+        cards.sort_by(|a, b| {
+            Self::postpone_proportion(a, requested_date)
+                .total_cmp(&Self::postpone_proportion(b, requested_date))
+        });
+        let cards = cards.into_iter().take(count as usize).collect::<Vec<_>>();
+        for card in &cards {
+            let interval = get_interval(card);
+            let last_reviewed_at = card.due - interval;
+            let new_due = (last_reviewed_at
+                + Duration::fractional_days(
+                    interval.num_fractional_days() * (1. + SAFE_MOVE_PROPORTION),
+                ))
+            .max(card_due_limit + Duration::seconds(1));
+            Self::update_due(db, card, new_due).await?;
+        }
+        Ok(format!("Postponed {} cards.", cards.len()))
+    }
+
+    async fn smart_schedule(
+        &self,
+        config: &SparesExternalConfig,
+        data: &(Card, Vec<ReviewLog>),
+        _siblings_with_review_logs: &[(Card, Vec<ReviewLog>)],
+        _at: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, Error> {
+        let (card, review_logs) = data;
+        let Some(last_review_log) = review_logs.last() else {
+            return Ok(card.due);
+        };
+        Ok(card
+            .due
+            .min(last_review_log.reviewed_at + config.maximum_interval))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(
+        card: &Card,
+        previous_review_log: Option<ReviewLog>,
+        rating: Sm2Rating,
+        reviewed_at: DateTime<Utc>,
+    ) -> (Card, ReviewLog) {
+        Sm2.schedule(
+            card,
+            previous_review_log,
+            rating as RatingId,
+            reviewed_at,
+            Duration::seconds(10),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_learning_steps_and_graduation() {
+        let now = Utc::now();
+        let card = Card::new(now);
+        let (card, review_log) = review(&card, None, Sm2Rating::Good, now);
+        assert_eq!(card.state, LEARNING_STATE);
+        assert_eq!(card.due, now + Duration::minutes(10));
+
+        let now = card.due;
+        let (card, review_log) = review(&card, Some(review_log), Sm2Rating::Good, now);
+        assert_eq!(card.state, REVIEW_STATE);
+        assert_eq!(card.due, now + Duration::days(1));
+
+        // Good: 1 day * 2.5 = 2.5 days, which is rounded to 3 days.
+        let now = card.due;
+        let (card, _review_log) = review(&card, Some(review_log), Sm2Rating::Good, now);
+        assert_eq!(card.due, now + Duration::days(3));
+        let data: Sm2Data = serde_json::from_value(card.custom_data).unwrap();
+        assert!((data.ease_factor - STARTING_EASE).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_lapse() {
+        let now = Utc::now();
+        let card = Card::new(now);
+        let (card, review_log) = review(&card, None, Sm2Rating::Easy, now);
+        assert_eq!(card.state, REVIEW_STATE);
+        assert_eq!(card.due, now + Duration::days(4));
+
+        let now = card.due;
+        let (card, review_log) = review(&card, Some(review_log), Sm2Rating::Again, now);
+        assert_eq!(card.state, RELEARNING_STATE);
+        let data: Sm2Data = serde_json::from_value(card.custom_data.clone()).unwrap();
+        assert!((data.ease_factor - (STARTING_EASE - 0.2)).abs() < f64::EPSILON);
+
+        let now = card.due;
+        let (card, _review_log) = review(&card, Some(review_log), Sm2Rating::Good, now);
+        assert_eq!(card.state, REVIEW_STATE);
+        assert_eq!(card.due, now + Duration::days(1));
+    }
+
+    #[test]
+    fn test_invalid_rating() {
+        let now = Utc::now();
+        let card = Card::new(now);
+        assert!(Sm2.schedule(&card, None, 5, now, Duration::zero()).is_err());
+    }
+}
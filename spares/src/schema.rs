@@ -88,6 +88,42 @@ pub mod tag {
     }
 }
 
+pub mod saved_search {
+    use crate::model::SavedSearch;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct CreateSavedSearchRequest {
+        pub name: String,
+        pub query: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct UpdateSavedSearchRequest {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub query: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct SavedSearchResponse {
+        pub id: i64,
+        pub name: String,
+        pub query: String,
+    }
+
+    impl SavedSearchResponse {
+        pub fn new(saved_search: &SavedSearch) -> Self {
+            Self {
+                id: saved_search.id,
+                name: saved_search.name.clone(),
+                query: saved_search.query.clone(),
+            }
+        }
+    }
+}
+
 pub mod note {
     use super::card::CardResponse;
     use crate::{
@@ -97,7 +133,7 @@ pub mod note {
     };
     use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
-    use std::path::PathBuf;
+    use std::{ops::Range, path::PathBuf};
 
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub enum GenerateFilesNoteIds {
@@ -125,10 +161,39 @@ pub mod note {
 
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub enum SearchNotesResponse {
-        Notes(Vec<(NoteResponse, String)>),
+        /// Each note is returned with its parser's name and, for full text searches, a snippet of the matched text.
+        Notes(Vec<(NoteResponse, String, Option<String>)>),
         Cards(Vec<(CardResponse, String)>),
     }
 
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ExplainSearchResponse {
+        /// The parsed query, after saved searches are expanded.
+        pub tree: String,
+        /// The generated query, with a `?` placeholder for each bound value.
+        pub sql: String,
+        pub binds: Vec<serde_json::Value>,
+        /// The number of results returned by the query.
+        pub count: i64,
+        /// The number of results matched by each top level clause on its own.
+        pub clauses: Vec<ClauseExplanation>,
+        pub warnings: Vec<SearchWarning>,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ClauseExplanation {
+        pub clause: String,
+        pub count: i64,
+    }
+
+    /// A part of the query that is valid, but may not do what was intended.
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+    pub struct SearchWarning {
+        pub message: String,
+        /// The byte range of the query that the warning applies to.
+        pub span: Range<usize>,
+    }
+
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub struct SearchKeywordRequest {
         pub keyword: String,
@@ -310,6 +375,15 @@ pub mod card {
     pub struct GetLeechesRequest {
         pub scheduler_name: String,
     }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct LeechEventResponse {
+        pub id: i64,
+        pub card_id: CardId,
+        pub note_id: NoteId,
+        pub created_at: DateTime<Utc>,
+        pub lapses: u32,
+    }
 }
 
 pub mod review {
@@ -366,18 +440,58 @@ pub mod review {
         pub tag_id: Option<TagId>,
     }
 
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    pub struct SimulateRequest {
+        /// Number of days to simulate, between 1 and 3650.
+        pub days: u32,
+        /// Number of new cards studied each day. Defaults to `new_cards_daily_limit`.
+        pub new_cards_per_day: Option<u32>,
+        /// Desired retentions to compare, up to 20 at a time. Defaults to values between 70% and 97%.
+        pub desired_retentions: Option<Vec<f64>>,
+    }
+
+    #[serde_with::serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct SimulationResult {
+        pub desired_retention: f64,
+        /// Expected number of reviews on each simulated day.
+        pub review_counts: Vec<f64>,
+        /// Expected study time on each simulated day.
+        #[serde_as(as = "Vec<serde_with::DurationSeconds<i64>>")]
+        pub study_times: Vec<Duration>,
+        /// Expected number of cards that can be recalled at the end of the simulation.
+        pub knowledge_retained: f64,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct SimulateResponse {
+        pub results: Vec<SimulationResult>,
+        /// The desired retention that requires the least study time for each card retained.
+        pub recommended_retention: f64,
+    }
+
     /// See <https://ankiweb.net/shared/info/759844606>
     // Note that this enum is reserved for actions that require work to be done when the action is called. Values that are for future actions, like load balancing when rescheduling, are stored in `SparesExternalConfig`. This is because changing the boolean `load_balance` will not have any immediate impact. However, when another card is submitted for review and rescheduled, then `load_balance` will impact the outcome.
     #[derive(Debug, Deserialize, Serialize)]
     pub enum StudyAction {
         Rate(RatingSubmission),
-        // `SuspendCard` is not included here since it is not specifically a study action. For
-        // example, you may want to suspend a card because you don't care about remembering its
-        // contents anymore. On the other hand, burying is specific to reviewing. You only bury a
-        // card that is scheduled to be reviewed today, but you don't want to review it today.
+        // Burying is specific to reviewing. You only bury a card that is scheduled to be reviewed
+        // today, but you don't want to review it today.
         Bury {
             card_id: CardId,
         },
+        // Suspending a card is not specifically a study action. For example, you may want to
+        // suspend a card because you don't care about remembering its contents anymore. It is
+        // included here so suspending cards while reviewing can be undone. Use `/api/cards`
+        // otherwise.
+        Suspend {
+            card_ids: Vec<CardId>,
+        },
+        /// Adds a tag to a note while reviewing it. For example, to mark it to be modified later.
+        TagNote {
+            note_id: NoteId,
+            tag_name: String,
+        },
         /// When you want to review your material ahead of time. For example, before a test.
         Advance {
             count: u32,
@@ -392,7 +506,10 @@ pub mod review {
         /// 3. Update the scheduler's parameters
         // Replaces `ApplyEasyDays`
         Reschedule,
-        // Undo,
+        /// Fits the scheduler's parameters to the review history. Run `Reschedule` afterwards to apply the new parameters to existing cards.
+        OptimizeParameters,
+        /// Reverts the most recent `Rate`, `Bury`, `Suspend`, or `TagNote` action.
+        Undo,
     }
 
     #[derive(Debug, Deserialize, Serialize)]
@@ -401,6 +518,37 @@ pub mod review {
         pub action: StudyAction,
     }
 
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    pub struct SubmitStudyActionResponse {
+        /// A summary of the action's result, for actions that have one. For example, how well `OptimizeParameters` fit the review history.
+        pub message: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ForecastRequest {
+        pub scheduler_name: String,
+        /// Number of days to forecast, starting from today, between 1 and 3650.
+        pub days: u32,
+        /// Number of new cards studied each day. Defaults to `new_cards_daily_limit`.
+        pub new_cards_per_day: Option<u32>,
+    }
+
+    #[serde_with::serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ForecastDay {
+        pub date: NaiveDate,
+        /// Expected number of reviews, including new cards.
+        pub review_count: f64,
+        /// Expected study time, based on the duration of past reviews.
+        #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+        pub study_time: Duration,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ForecastResponse {
+        pub days: Vec<ForecastDay>,
+    }
+
     #[derive(Debug, Deserialize, Serialize)]
     pub struct StatisticsRequest {
         pub scheduler_name: String,
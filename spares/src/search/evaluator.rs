@@ -1,74 +1,138 @@
 use crate::{
     LibraryError,
+    api::saved_search::get_saved_search_queries,
+    helpers::get_start_end_local_date,
     model::{Card, CardId, Note, NoteId},
-    search::{Atom, Op, TokenTree, parser::Parser},
+    schedulers::LAPSE_DEFINITIONS,
+    schema::note::{ClauseExplanation, ExplainSearchResponse, SearchWarning},
+    search::{
+        Atom, Op, Query, QueryReturnItemType, RELATIVE_DATE_UNITS, REVIEW_FIELD_PREFIX, SortClause,
+        SortDirection, TokenKind, TokenTree,
+        functions::{FUZZY_MATCH_THRESHOLD, acquire_search_connection},
+        lexer::Lexer,
+        parser::Parser,
+        saved_search::expand_saved_searches,
+    },
 };
+use chrono::{DateTime, Days, Duration, Months, Utc};
 use miette::{Error, Report, miette};
-use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use rs_fsrs::Parameters;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool, pool::PoolConnection};
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+/// The field that is compared when a comparison does not name one, such as `dog`.
+const DEFAULT_FIELD: &str = "data";
+/// Marks the start of a matched term in a full text search snippet.
+const FTS_MATCH_START: &str = "**";
+/// Marks the end of a matched term in a full text search snippet.
+const FTS_MATCH_END: &str = "**";
+const FTS_SNIPPET_ELLIPSIS: &str = "...";
+/// The maximum number of words in a full text search snippet.
+const FTS_SNIPPET_TOKENS: u32 = 16;
 
 pub struct Evaluator<'de> {
-    // whole: &'de str,
-    parser: Parser<'de>,
+    input: &'de str,
+    /// Relative dates, such as `-7d` or `today`, and fields that depend on the current time, such as `c.retrievability`, are evaluated at this time.
+    at: DateTime<Utc>,
+    /// The query of each saved search, by name. If these are not given, they are loaded from the database when the search is run.
+    saved_searches: Option<HashMap<String, String>>,
 }
 
 impl<'de> Evaluator<'de> {
     pub fn new(input: &'de str) -> Self {
         Self {
-            // whole: input,
-            parser: Parser::new(input),
+            input,
+            at: Utc::now(),
+            saved_searches: None,
+        }
+    }
+
+    /// Evaluates relative dates and fields that depend on the current time at `at` instead of the current time.
+    #[must_use]
+    pub fn with_time(mut self, at: DateTime<Utc>) -> Self {
+        self.at = at;
+        self
+    }
+
+    /// Uses these saved searches for `saved:name` references, instead of loading them from the database.
+    #[must_use]
+    pub fn with_saved_searches(mut self, saved_searches: HashMap<String, String>) -> Self {
+        self.saved_searches = Some(saved_searches);
+        self
+    }
+
+    async fn load_saved_searches(mut self, db: &SqlitePool) -> Result<Self, crate::Error> {
+        if self.saved_searches.is_none() {
+            self.saved_searches = Some(get_saved_search_queries(db).await?);
         }
+        Ok(self)
     }
 
-    fn evaluate(self, internal_output_type: EvaluatorReturnItemType) -> Result<String, Report> {
-        let token_tree = self.parser.parse_expression()?;
-        let mut context = EvaluationContext::new();
-        context.root_context = true;
-        token_tree.evaluate(&mut context)?;
-        Ok(context.build_query(internal_output_type))
+    fn evaluate(self, internal_output_type: EvaluatorReturnItemType) -> Result<SqlQuery, Report> {
+        self.build_query(false, internal_output_type)
     }
 
     fn evaluate_with_parser(
         self,
         internal_output_type: EvaluatorReturnItemType,
-    ) -> Result<String, Report> {
-        let token_tree = self.parser.parse_expression()?;
-        let mut context = EvaluationContext::new();
-        context.root_context = true;
-        context.table_requirements.needs_parser = true;
-        token_tree.evaluate(&mut context)?;
-        Ok(context.build_query(internal_output_type))
+    ) -> Result<SqlQuery, Report> {
+        self.build_query(true, internal_output_type)
+    }
+
+    fn build_query(
+        self,
+        needs_parser: bool,
+        internal_output_type: EvaluatorReturnItemType,
+    ) -> Result<SqlQuery, Report> {
+        let input = expand_saved_searches(self.input, &self.saved_searches.unwrap_or_default())?;
+        let query = Parser::new(&input).parse_query()?;
+        evaluate_query(&query, self.at, needs_parser, internal_output_type)
     }
 
-    pub async fn get_notes(self, db: &SqlitePool) -> Result<Vec<(Note, String)>, crate::Error> {
+    /// Returns the matching notes with their parser's name. If the query uses full text search, the notes are ranked by relevance and include a snippet of the matched text.
+    pub async fn get_notes(
+        self,
+        db: &SqlitePool,
+    ) -> Result<Vec<(Note, String, Option<String>)>, crate::Error> {
         #[derive(sqlx::FromRow)]
         struct EnrichedNote {
             #[sqlx(flatten)]
             note: Note,
             #[sqlx(rename = "name")]
             parser_name: String,
+            #[sqlx(default)]
+            snippet: Option<String>,
         }
-        let query_str = self
+        let mut query_builder = self
+            .load_saved_searches(db)
+            .await?
             .evaluate_with_parser(EvaluatorReturnItemType::Notes)
-            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?;
-        dbg!(&query_str);
-        let enriched_cards: Vec<EnrichedNote> = sqlx::query_as(&query_str)
-            .fetch_all(db)
+            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?
+            .into_query_builder();
+        let mut connection = acquire_search_connection(db).await?;
+        let enriched_cards: Vec<EnrichedNote> = query_builder
+            .build_query_as()
+            .fetch_all(&mut *connection)
             .await
             .map_err(|e| crate::Error::Sqlx { source: e })?;
         let result = enriched_cards
             .into_iter()
-            .map(|x| (x.note, x.parser_name))
+            .map(|x| (x.note, x.parser_name, x.snippet))
             .collect::<Vec<_>>();
         Ok(result)
     }
 
     pub async fn get_note_ids(self, db: &SqlitePool) -> Result<Vec<NoteId>, crate::Error> {
-        let query_str = self
+        let mut query_builder = self
+            .load_saved_searches(db)
+            .await?
             .evaluate(EvaluatorReturnItemType::NoteIds)
-            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?;
-        dbg!(&query_str);
-        let note_ids_tups: Vec<(NoteId,)> = sqlx::query_as(&query_str)
-            .fetch_all(db)
+            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?
+            .into_query_builder();
+        let mut connection = acquire_search_connection(db).await?;
+        let note_ids_tups: Vec<(NoteId,)> = query_builder
+            .build_query_as()
+            .fetch_all(&mut *connection)
             .await
             .map_err(|e| crate::Error::Sqlx { source: e })?;
         Ok(note_ids_tups.into_iter().map(|(x,)| x).collect::<Vec<_>>())
@@ -82,12 +146,16 @@ impl<'de> Evaluator<'de> {
             #[sqlx(rename = "name")]
             parser_name: String,
         }
-        let query_str = self
+        let mut query_builder = self
+            .load_saved_searches(db)
+            .await?
             .evaluate_with_parser(EvaluatorReturnItemType::Cards)
-            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?;
-        dbg!(&query_str);
-        let enriched_cards: Vec<EnrichedCard> = sqlx::query_as(&query_str)
-            .fetch_all(db)
+            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?
+            .into_query_builder();
+        let mut connection = acquire_search_connection(db).await?;
+        let enriched_cards: Vec<EnrichedCard> = query_builder
+            .build_query_as()
+            .fetch_all(&mut *connection)
             .await
             .map_err(|e| crate::Error::Sqlx { source: e })?;
         let result = enriched_cards
@@ -98,16 +166,223 @@ impl<'de> Evaluator<'de> {
     }
 
     pub async fn get_card_ids(self, db: &SqlitePool) -> Result<Vec<CardId>, crate::Error> {
-        let query_str = self
+        let mut query_builder = self
+            .load_saved_searches(db)
+            .await?
             .evaluate(EvaluatorReturnItemType::CardIds)
-            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?;
-        dbg!(&query_str);
-        let card_ids_tups: Vec<(CardId,)> = sqlx::query_as(&query_str)
-            .fetch_all(db)
+            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?
+            .into_query_builder();
+        let mut connection = acquire_search_connection(db).await?;
+        let card_ids_tups: Vec<(CardId,)> = query_builder
+            .build_query_as()
+            .fetch_all(&mut *connection)
             .await
             .map_err(|e| crate::Error::Sqlx { source: e })?;
         Ok(card_ids_tups.into_iter().map(|(x,)| x).collect::<Vec<_>>())
     }
+
+    /// Returns an error if the query is invalid.
+    pub(crate) fn check(self) -> Result<(), crate::Error> {
+        self.evaluate(EvaluatorReturnItemType::NoteIds)
+            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?;
+        Ok(())
+    }
+
+    /// Explains how the query is run: the parsed query, the generated query with its bound values, the number of results matched by each top level clause, and any warnings.
+    pub async fn explain(
+        self,
+        db: &SqlitePool,
+        output_type: QueryReturnItemType,
+    ) -> Result<ExplainSearchResponse, crate::Error> {
+        let to_error = |e: Report| crate::Error::Library(LibraryError::Search(e.to_string()));
+        let evaluator = self.load_saved_searches(db).await?;
+        let warnings = get_warnings(evaluator.input);
+        let input = expand_saved_searches(
+            evaluator.input,
+            &evaluator.saved_searches.unwrap_or_default(),
+        )
+        .map_err(to_error)?;
+        let mut query = Parser::new(&input).parse_query().map_err(to_error)?;
+        query.expression = normalize(query.expression);
+        let (internal_output_type, ids_output_type) = match output_type {
+            QueryReturnItemType::Notes => (
+                EvaluatorReturnItemType::Notes,
+                EvaluatorReturnItemType::NoteIds,
+            ),
+            QueryReturnItemType::Cards => (
+                EvaluatorReturnItemType::Cards,
+                EvaluatorReturnItemType::CardIds,
+            ),
+        };
+        let sql_query =
+            evaluate_query(&query, evaluator.at, true, internal_output_type).map_err(to_error)?;
+
+        let mut connection = acquire_search_connection(db).await?;
+        let ids_query =
+            evaluate_query(&query, evaluator.at, false, ids_output_type).map_err(to_error)?;
+        let count = count_results(&mut connection, ids_query).await?;
+        let clause_trees = match &query.expression {
+            TokenTree::Cons(Op::And | Op::Or, trees) => trees.iter().collect::<Vec<_>>(),
+            TokenTree::Atom(Atom::Nil) => Vec::new(),
+            expression => vec![expression],
+        };
+        let mut clauses = Vec::new();
+        for clause_tree in clause_trees {
+            let clause_query = Query {
+                expression: clause_tree.clone(),
+                sort: Vec::new(),
+                limit: None,
+                offset: None,
+            };
+            let clause_ids_query =
+                evaluate_query(&clause_query, evaluator.at, false, ids_output_type)
+                    .map_err(to_error)?;
+            clauses.push(ClauseExplanation {
+                clause: clause_tree.to_string(),
+                count: count_results(&mut connection, clause_ids_query).await?,
+            });
+        }
+
+        Ok(ExplainSearchResponse {
+            tree: query.expression.to_string(),
+            sql: sql_query.sql(),
+            binds: sql_query
+                .values()
+                .into_iter()
+                .map(SqlValue::to_json)
+                .collect(),
+            count,
+            clauses,
+            warnings,
+        })
+    }
+
+    /// Pushes a query selecting the ids of the matching notes, so it can be used as a subquery. Saved searches must be given with [`Evaluator::with_saved_searches`] for the query to reference them.
+    pub fn push_note_ids_query(
+        self,
+        query_builder: &mut QueryBuilder<'_, Sqlite>,
+    ) -> Result<(), crate::Error> {
+        let query = self
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .map_err(|e| crate::Error::Library(LibraryError::Search(e.to_string())))?;
+        query.push_to(query_builder);
+        Ok(())
+    }
+}
+
+/// A value that is bound to a query parameter, rather than being spliced into the query.
+#[derive(Debug, Clone, PartialEq)]
+enum SqlValue {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+}
+
+impl fmt::Display for SqlValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlValue::Integer(n) => write!(f, "{n}"),
+            SqlValue::Float(n) => write!(f, "{n}"),
+            SqlValue::String(s) => write!(f, "{s}"),
+            SqlValue::Boolean(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+impl SqlValue {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            SqlValue::Integer(n) => serde_json::Value::from(*n),
+            SqlValue::Float(n) => serde_json::Value::from(*n),
+            SqlValue::String(s) => serde_json::Value::from(s.as_str()),
+            SqlValue::Boolean(b) => serde_json::Value::from(*b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SqlPart {
+    Sql(String),
+    Bind(SqlValue),
+}
+
+/// A query (or part of one) where every user provided value is a bound parameter.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct SqlQuery {
+    parts: Vec<SqlPart>,
+}
+
+impl SqlQuery {
+    fn new(sql: &str) -> Self {
+        let mut query = Self::default();
+        query.push(sql);
+        query
+    }
+
+    fn push(&mut self, sql: &str) -> &mut Self {
+        self.parts.push(SqlPart::Sql(sql.to_string()));
+        self
+    }
+
+    fn push_bind(&mut self, value: SqlValue) -> &mut Self {
+        self.parts.push(SqlPart::Bind(value));
+        self
+    }
+
+    fn append(&mut self, other: &SqlQuery) -> &mut Self {
+        self.parts.extend(other.parts.iter().cloned());
+        self
+    }
+
+    /// The query string, with a `?` placeholder for each bound value.
+    fn sql(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                SqlPart::Sql(sql) => sql.as_str(),
+                SqlPart::Bind(_) => "?",
+            })
+            .collect()
+    }
+
+    fn values(&self) -> Vec<&SqlValue> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                SqlPart::Sql(_) => None,
+                SqlPart::Bind(value) => Some(value),
+            })
+            .collect()
+    }
+
+    fn push_to(self, query_builder: &mut QueryBuilder<'_, Sqlite>) {
+        for part in self.parts {
+            match part {
+                SqlPart::Sql(sql) => {
+                    query_builder.push(sql);
+                }
+                SqlPart::Bind(SqlValue::Integer(n)) => {
+                    query_builder.push_bind(n);
+                }
+                SqlPart::Bind(SqlValue::Float(n)) => {
+                    query_builder.push_bind(n);
+                }
+                SqlPart::Bind(SqlValue::String(s)) => {
+                    query_builder.push_bind(s);
+                }
+                SqlPart::Bind(SqlValue::Boolean(b)) => {
+                    query_builder.push_bind(b);
+                }
+            }
+        }
+    }
+
+    fn into_query_builder(self) -> QueryBuilder<'static, Sqlite> {
+        let mut query_builder = QueryBuilder::new("");
+        self.push_to(&mut query_builder);
+        query_builder
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -131,9 +406,15 @@ enum NoteField {
     UpdatedAt,
     ParserName,
     Tag,
+    /// A tag or any of its descendants
+    TagUnder,
+    /// The number of ancestors of a tag
+    TagDepth,
+    TagParent,
     Keyword,
     CustomData(String),
     LinkedTo,
+    Fts,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -151,10 +432,47 @@ enum CardField {
     State,
     CustomData(String),
     Rated,
+    RatedAt,
+    Due,
+    Order,
+    Reps,
+    Lapses,
+    LastReviewedAt,
+    /// Estimated at a unix timestamp, which is the evaluator's time.
+    Retrievability(i64),
+    Review(ReviewField),
+}
+
+/// A field that searches a card's review history. These start with `r.`, such as `r.last.rating` or `r.count(rating=1, reviewed_at>-30d)`.
+#[derive(Debug, Clone, PartialEq)]
+enum ReviewField {
+    /// Matches cards with any review where the column matches the comparison, such as `r.scheduler=fsrs`.
+    Any(ReviewLogColumn),
+    /// The column of the card's most recent review, such as `r.last.rating`.
+    Last(ReviewLogColumn),
+    /// An aggregate of the card's reviews that match every condition, such as `r.count(rating=1)`.
+    Aggregate(ReviewAggregate, Vec<SqlQuery>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReviewLogColumn {
+    Rating,
+    ReviewedAt,
+    Duration,
+    Scheduler,
+    PreviousState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReviewAggregate {
+    Count,
+    AvgRating,
+    AvgDuration,
 }
 
 impl Field {
-    fn from_str(input: &[&str]) -> Result<Field, Error> {
+    /// Relative dates in the conditions of a review field, such as the `-30d` in `r.count(reviewed_at>-30d)`, are relative to `at`.
+    fn from_str(input: &[&str], at: DateTime<Utc>) -> Result<Field, Error> {
         if input.is_empty() || input[0].is_empty() {
             return Ok(Self::default());
         }
@@ -174,6 +492,12 @@ impl Field {
                 input[0]
             ));
         }
+        if let Some(review_field) = normalized_value_str.strip_prefix(REVIEW_FIELD_PREFIX) {
+            return Ok(Field::Card(CardField::Review(ReviewField::from_str(
+                review_field,
+                at,
+            )?)));
+        }
         match normalized_value_str.as_str() {
             "id" => Ok(Field::Note(NoteField::Id)),
             "data" => Ok(Field::Note(NoteField::Data)),
@@ -181,6 +505,9 @@ impl Field {
             "updated_at" => Ok(Field::Note(NoteField::UpdatedAt)),
             "parser_name" => Ok(Field::Note(NoteField::ParserName)),
             "tag" => Ok(Field::Note(NoteField::Tag)),
+            "tag_under" => Ok(Field::Note(NoteField::TagUnder)),
+            "tag_depth" => Ok(Field::Note(NoteField::TagDepth)),
+            "tag_parent" => Ok(Field::Note(NoteField::TagParent)),
             "keyword" => Ok(Field::Note(NoteField::Keyword)),
             "custom_data" => {
                 // if input.len() != 2 {
@@ -192,6 +519,7 @@ impl Field {
                 )))
             }
             "linked_to" => Ok(Field::Note(NoteField::LinkedTo)),
+            "fts" => Ok(Field::Note(NoteField::Fts)),
             "c.id" => Ok(Field::Card(CardField::Id)),
             "c.created_at" => Ok(Field::Card(CardField::CreatedAt)),
             "c.updated_at" => Ok(Field::Card(CardField::UpdatedAt)),
@@ -213,84 +541,455 @@ impl Field {
                 )))
             }
             "c.rated" => Ok(Field::Card(CardField::Rated)),
+            "c.rated_at" => Ok(Field::Card(CardField::RatedAt)),
+            "c.due" => Ok(Field::Card(CardField::Due)),
+            "c.order" => Ok(Field::Card(CardField::Order)),
+            "c.reps" => Ok(Field::Card(CardField::Reps)),
+            "c.lapses" => Ok(Field::Card(CardField::Lapses)),
+            "c.last_reviewed_at" => Ok(Field::Card(CardField::LastReviewedAt)),
+            "c.retrievability" => Ok(Field::Card(CardField::Retrievability(at.timestamp()))),
             f => Err(miette!("Unrecognized field: {}", f)),
         }
     }
 }
 
+impl ReviewField {
+    fn from_str(input: &str, at: DateTime<Utc>) -> Result<ReviewField, Error> {
+        let (name, arguments) = match input.split_once('(') {
+            Some((name, arguments)) => {
+                let arguments = arguments
+                    .strip_suffix(')')
+                    .ok_or_else(|| miette!("Unterminated arguments for `r.{}`", name))?;
+                (name, Some(arguments))
+            }
+            None => (input, None),
+        };
+        if let Some(aggregate) = ReviewAggregate::from_str(name) {
+            let conditions = match arguments {
+                Some(arguments) => parse_review_conditions(arguments, at)?,
+                None => Vec::new(),
+            };
+            return Ok(ReviewField::Aggregate(aggregate, conditions));
+        }
+        if arguments.is_some() {
+            return Err(miette!(
+                "Only `r.count`, `r.avg_rating`, and `r.avg_duration` accept conditions, not `r.{}`",
+                name
+            ));
+        }
+        if let Some(column) = name
+            .strip_prefix("last.")
+            .and_then(ReviewLogColumn::from_str)
+        {
+            return Ok(ReviewField::Last(column));
+        }
+        ReviewLogColumn::from_str(name)
+            .map(ReviewField::Any)
+            .ok_or_else(|| miette!("Unrecognized field: r.{}", name))
+    }
+
+    fn get_field_type(&self) -> FieldType {
+        match self {
+            ReviewField::Any(column) | ReviewField::Last(column) => column.get_field_type(),
+            ReviewField::Aggregate(ReviewAggregate::Count, _) => FieldType::Integer,
+            ReviewField::Aggregate(
+                ReviewAggregate::AvgRating | ReviewAggregate::AvgDuration,
+                _,
+            ) => FieldType::Float,
+        }
+    }
+}
+
+impl ReviewLogColumn {
+    fn from_str(input: &str) -> Option<ReviewLogColumn> {
+        match input {
+            "rating" => Some(ReviewLogColumn::Rating),
+            "reviewed_at" => Some(ReviewLogColumn::ReviewedAt),
+            "duration" => Some(ReviewLogColumn::Duration),
+            "scheduler" => Some(ReviewLogColumn::Scheduler),
+            "previous_state" => Some(ReviewLogColumn::PreviousState),
+            _ => None,
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            ReviewLogColumn::Rating => "rl.rating",
+            ReviewLogColumn::ReviewedAt => "rl.reviewed_at",
+            ReviewLogColumn::Duration => "rl.duration",
+            ReviewLogColumn::Scheduler => "rl.scheduler_name",
+            ReviewLogColumn::PreviousState => "rl.previous_state",
+        }
+    }
+
+    fn get_field_type(self) -> FieldType {
+        match self {
+            ReviewLogColumn::Rating
+            | ReviewLogColumn::Duration
+            | ReviewLogColumn::PreviousState => FieldType::Integer,
+            ReviewLogColumn::ReviewedAt => FieldType::DateTime,
+            ReviewLogColumn::Scheduler => FieldType::String,
+        }
+    }
+}
+
+impl ReviewAggregate {
+    fn from_str(input: &str) -> Option<ReviewAggregate> {
+        match input {
+            "count" => Some(ReviewAggregate::Count),
+            "avg_rating" => Some(ReviewAggregate::AvgRating),
+            "avg_duration" => Some(ReviewAggregate::AvgDuration),
+            _ => None,
+        }
+    }
+
+    fn to_sql(self) -> &'static str {
+        match self {
+            ReviewAggregate::Count => "COUNT(*)",
+            ReviewAggregate::AvgRating => "AVG(rl.rating)",
+            ReviewAggregate::AvgDuration => "AVG(rl.duration)",
+        }
+    }
+}
+
+/// Parses comma separated conditions on a review's columns, such as `rating=1, reviewed_at>-30d`.
+fn parse_review_conditions(input: &str, at: DateTime<Utc>) -> Result<Vec<SqlQuery>, Error> {
+    let mut conditions = Vec::new();
+    let mut condition_start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                conditions.push(parse_review_condition(&input[condition_start..i], at)?);
+                condition_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !input[condition_start..].trim().is_empty() || !conditions.is_empty() {
+        conditions.push(parse_review_condition(&input[condition_start..], at)?);
+    }
+    Ok(conditions)
+}
+
+/// Parses a condition on a review's column, such as `rating=1`, into a condition on `rl`.
+fn parse_review_condition(input: &str, at: DateTime<Utc>) -> Result<SqlQuery, Error> {
+    let invalid_condition = || {
+        miette!(
+            "Expected a condition on a review, such as `rating=1`, but found `{}`",
+            input.trim()
+        )
+    };
+    let query = Parser::new(input).parse_query()?;
+    if !query.sort.is_empty() || query.limit.is_some() || query.offset.is_some() {
+        return Err(invalid_condition());
+    }
+    let TokenTree::Cons(op, trees) = &query.expression else {
+        return Err(invalid_condition());
+    };
+    let [TokenTree::Atom(Atom::Field(column_name)), value_tree] = trees.as_slice() else {
+        return Err(invalid_condition());
+    };
+    if !matches!(
+        op,
+        Op::Equal
+            | Op::GreaterThan
+            | Op::GreaterThanEqual
+            | Op::LessThan
+            | Op::LessThanEqual
+            | Op::Tilde
+    ) {
+        return Err(invalid_condition());
+    }
+    let column = ReviewLogColumn::from_str(column_name).ok_or_else(|| {
+        miette!(
+            "Unrecognized review column `{}`. Expected one of `rating`, `reviewed_at`, `duration`, `scheduler`, or `previous_state`.",
+            column_name
+        )
+    })?;
+    let column_type = column.get_field_type();
+
+    let mut value_context = EvaluationContext::new(at);
+    value_tree.evaluate(&mut value_context)?;
+    let (Some(mut value), None) = (value_context.params.pop(), value_context.params.pop()) else {
+        return Err(invalid_condition());
+    };
+    let mut value_type = value_context.value_type.ok_or_else(invalid_condition)?;
+    // Keywords, such as `today`, are dates when compared to a date column
+    if let (FieldType::DateTime, SqlValue::String(s)) = (&column_type, &value) {
+        if let Some(date) = resolve_relative_date(s, at) {
+            value = SqlValue::Integer(date.timestamp());
+            value_type = FieldType::DateTime;
+        }
+    }
+    match (&column_type, &value_type, op) {
+        (FieldType::String, FieldType::Regex, Op::Tilde) => {}
+        (_, FieldType::Regex, _) => {
+            return Err(miette!(
+                "Regular expressions can only be used with the `~` operator on text columns, such as `scheduler~/^fs/`."
+            ));
+        }
+        (FieldType::Integer, FieldType::Integer, _)
+        | (FieldType::String, _, _)
+        | (FieldType::DateTime, FieldType::DateTime, _) => {}
+        _ => {
+            return Err(miette!(
+                "The review column `{}` has a type of `{:?}`. The provided value of `{}` has a type of `{:?}` which does not match the column's type.",
+                column_name,
+                column_type,
+                value,
+                value_type
+            ));
+        }
+    }
+
+    let mut condition = SqlQuery::new(&format!("{} ", column.column()));
+    match op {
+        Op::Tilde if value_type == FieldType::Regex => {
+            condition.push("REGEXP ").push_bind(value);
+        }
+        Op::Tilde => {
+            condition
+                .push("LIKE ")
+                .push_bind(SqlValue::String(format!("%{}%", value)));
+        }
+        _ if column_type == FieldType::String => {
+            condition
+                .push(&format!("{} ", op))
+                .push_bind(SqlValue::String(value.to_string()));
+        }
+        _ => {
+            condition.push(&format!("{} ", op)).push_bind(value);
+        }
+    }
+    Ok(condition)
+}
+
 impl Field {
-    fn to_sql_str(&self, value_str: &str) -> String {
-        let with_op = move |field_name: &str| format!("{} {}", field_name, value_str);
+    /// The field's value, for fields that have a single value for each note or card. These fields can be compared with any operator and sorted by.
+    fn to_value_sql(&self) -> Option<SqlQuery> {
+        let column = |name: &str| Some(SqlQuery::new(name));
+        let json_extract = |column: &str, json_path: &str| {
+            let mut query = SqlQuery::new(&format!("json_extract({column}, "));
+            query
+                .push_bind(SqlValue::String(json_path.to_string()))
+                .push(")");
+            Some(query)
+        };
         match self {
             Field::Note(note_field) => match note_field {
-                NoteField::Id => with_op("n.id"),
-                NoteField::Data => with_op("n.data"),
-                NoteField::CreatedAt => with_op("n.created_at"),
-                NoteField::UpdatedAt => with_op("n.updated_at"),
-                NoteField::ParserName => with_op("p.name"),
-                NoteField::Tag => {
-                    // format!(
-                    //     "n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name {})",
-                    //     value_str
-                    // )
-                    format!(
-                        "c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name {} UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name {})",
-                        value_str, value_str
-                    )
-                }
-                NoteField::Keyword => with_op("n.keywords"),
-                NoteField::CustomData(json_path) => {
-                    format!("json_extract(n.custom_data, '{}') {}", json_path, value_str)
-                }
-                NoteField::LinkedTo => {
-                    format!(
-                        "EXISTS (SELECT 1 FROM note_link nl WHERE nl.parent_note_id = n.id AND nl.linked_note_id {})",
-                        value_str
-                    )
-                }
+                NoteField::Id => column("n.id"),
+                NoteField::Data => column("n.data"),
+                NoteField::CreatedAt => column("n.created_at"),
+                NoteField::UpdatedAt => column("n.updated_at"),
+                NoteField::ParserName => column("p.name"),
+                NoteField::Keyword => column("n.keywords"),
+                NoteField::CustomData(json_path) => json_extract("n.custom_data", json_path),
+                NoteField::Tag
+                | NoteField::TagUnder
+                | NoteField::TagDepth
+                | NoteField::TagParent
+                | NoteField::LinkedTo
+                | NoteField::Fts => None,
             },
             Field::Card(card_field) => match card_field {
-                CardField::Id => with_op("c.id"),
-                CardField::CreatedAt => with_op("c.created_at"),
-                CardField::UpdatedAt => with_op("c.updated_at"),
-                CardField::Stability => with_op("c.stability"),
-                CardField::Difficulty => with_op("c.difficulty"),
-                CardField::DesiredRetention => with_op("c.desired_retention"),
+                CardField::Id => column("c.id"),
+                CardField::CreatedAt => column("c.created_at"),
+                CardField::UpdatedAt => column("c.updated_at"),
+                CardField::Stability => column("c.stability"),
+                CardField::Difficulty => column("c.difficulty"),
+                CardField::DesiredRetention => column("c.desired_retention"),
                 // CardField::SpecialState => "c.special_state".to_string(),
-                CardField::Suspended => {
-                    if value_str.contains("false") {
-                        "(c.special_state IS NULL OR c.special_state != 1)".to_string()
-                    } else {
-                        "c.special_state = 1".to_string()
-                    }
+                CardField::State => column("c.state"),
+                CardField::CustomData(json_path) => json_extract("c.custom_data", json_path),
+                CardField::Due => column("c.due"),
+                CardField::Order => column("c.\"order\""),
+                CardField::Reps => {
+                    column("(SELECT COUNT(*) FROM review_log rl WHERE rl.card_id = c.id)")
                 }
-                CardField::UserBuried => {
-                    if value_str.contains("false") {
-                        "(c.special_state IS NULL OR c.special_state != 2)".to_string()
-                    } else {
-                        "c.special_state = 2".to_string()
-                    }
-                }
-                CardField::SchedulerBuried => {
-                    if value_str.contains("false") {
-                        "(c.special_state IS NULL OR c.special_state != 3)".to_string()
-                    } else {
-                        "c.special_state = 3".to_string()
+                CardField::Lapses => {
+                    // Each scheduler has its own state and rating for a lapse
+                    let mut query = SqlQuery::new(
+                        "(SELECT COUNT(*) FROM review_log rl WHERE rl.card_id = c.id AND (",
+                    );
+                    for (i, (scheduler_name, lapse_state, lapse_rating)) in
+                        LAPSE_DEFINITIONS.into_iter().enumerate()
+                    {
+                        if i > 0 {
+                            query.push(" OR ");
+                        }
+                        query
+                            .push("(rl.scheduler_name = ")
+                            .push_bind(SqlValue::String(scheduler_name.to_string()))
+                            .push(" AND rl.previous_state = ")
+                            .push_bind(SqlValue::Integer(i64::from(lapse_state)))
+                            .push(" AND rl.rating = ")
+                            .push_bind(SqlValue::Integer(i64::from(lapse_rating)))
+                            .push(")");
                     }
+                    query.push("))");
+                    Some(query)
                 }
-                CardField::State => with_op("c.state"),
-                CardField::CustomData(json_path) => {
-                    format!("json_extract(c.custom_data, '{}') {}", json_path, value_str)
+                CardField::LastReviewedAt => column(
+                    "(SELECT MAX(rl.reviewed_at) FROM review_log rl WHERE rl.card_id = c.id)",
+                ),
+                // The number of stabilities that have elapsed since the last review, which decreases as retrievability increases. See `get_elapsed_stabilities`.
+                CardField::Retrievability(at) => {
+                    let mut query = SqlQuery::new("COALESCE((");
+                    query.push_bind(SqlValue::Integer(*at)).push(
+                        " - (SELECT MAX(rl.reviewed_at) FROM review_log rl WHERE rl.card_id = c.id)) / 86400.0 / (CASE WHEN c.stability > 0 THEN c.stability END), 9e999)",
+                    );
+                    Some(query)
                 }
-                CardField::Rated => {
-                    format!(
-                        "EXISTS (SELECT 1 FROM review_log rl WHERE rl.card_id = c.id AND rl.rating {})",
-                        value_str
-                    )
+                CardField::Review(ReviewField::Last(review_column)) => column(&format!(
+                    "(SELECT {} FROM review_log rl WHERE rl.card_id = c.id ORDER BY rl.reviewed_at DESC, rl.id DESC LIMIT 1)",
+                    review_column.column()
+                )),
+                CardField::Review(ReviewField::Aggregate(aggregate, conditions)) => {
+                    let mut query = SqlQuery::new(&format!(
+                        "(SELECT {} FROM review_log rl WHERE rl.card_id = c.id",
+                        aggregate.to_sql()
+                    ));
+                    for condition in conditions {
+                        query.push(" AND ").append(condition);
+                    }
+                    query.push(")");
+                    Some(query)
                 }
+                CardField::Suspended
+                | CardField::UserBuried
+                | CardField::SchedulerBuried
+                | CardField::Rated
+                | CardField::RatedAt
+                | CardField::Review(ReviewField::Any(_)) => None,
             },
         }
     }
+
+    /// Applies `comparison` (an operator followed by its bound value) to the field.
+    fn to_sql(&self, comparison: &SqlQuery) -> SqlQuery {
+        if let Some(mut query) = self.to_value_sql() {
+            query.push(" ").append(comparison);
+            return query;
+        }
+        let is_false = matches!(comparison.values().first(), Some(SqlValue::Boolean(false)));
+        let special_state = |special_state: u8| {
+            if is_false {
+                SqlQuery::new(&format!(
+                    "(c.special_state IS NULL OR c.special_state != {special_state})"
+                ))
+            } else {
+                SqlQuery::new(&format!("c.special_state = {special_state}"))
+            }
+        };
+        let in_subquery = |subquery: &str| {
+            let mut query = SqlQuery::new(subquery);
+            query.append(comparison).push(")");
+            query
+        };
+        match self {
+            Field::Note(NoteField::Tag | NoteField::TagUnder | NoteField::TagParent) => self
+                .tag_name_to_sql(|column| {
+                    let mut condition = SqlQuery::new(&format!("{column} "));
+                    condition.append(comparison);
+                    condition
+                }),
+            Field::Note(NoteField::TagDepth) => {
+                // Top level tags have a depth of 0. Tags in a cycle are not reachable from a top level tag, so they are never matched.
+                let mut condition = SqlQuery::new(
+                    "t.id IN (WITH RECURSIVE tag_depth(id, depth) AS (SELECT id, 0 FROM tag WHERE parent_id IS NULL UNION ALL SELECT child.id, td.depth + 1 FROM tag child JOIN tag_depth td ON child.parent_id = td.id) SELECT id FROM tag_depth WHERE depth ",
+                );
+                condition.append(comparison).push(")");
+                tag_condition_to_sql(&condition)
+            }
+            Field::Note(NoteField::LinkedTo) => in_subquery(
+                "EXISTS (SELECT 1 FROM note_link nl WHERE nl.parent_note_id = n.id AND nl.linked_note_id ",
+            ),
+            Field::Note(NoteField::Fts) => {
+                in_subquery("n.id IN (SELECT rowid FROM note_fts WHERE note_fts ")
+            }
+            Field::Card(CardField::Suspended) => special_state(1),
+            Field::Card(CardField::UserBuried) => special_state(2),
+            Field::Card(CardField::SchedulerBuried) => special_state(3),
+            Field::Card(CardField::Rated) => in_subquery(
+                "EXISTS (SELECT 1 FROM review_log rl WHERE rl.card_id = c.id AND rl.rating ",
+            ),
+            Field::Card(CardField::RatedAt) => in_subquery(
+                "EXISTS (SELECT 1 FROM review_log rl WHERE rl.card_id = c.id AND rl.reviewed_at ",
+            ),
+            Field::Card(CardField::Review(ReviewField::Any(column))) => in_subquery(&format!(
+                "EXISTS (SELECT 1 FROM review_log rl WHERE rl.card_id = c.id AND {} ",
+                column.column()
+            )),
+            _ => unreachable!("fields with a value are handled above"),
+        }
+    }
+}
+
+impl Field {
+    /// Matches cards with a tag related by name to a tag satisfying `name_condition`, which is given the name column to compare.
+    fn tag_name_to_sql(&self, name_condition: impl Fn(&str) -> SqlQuery) -> SqlQuery {
+        let condition = match self {
+            Field::Note(NoteField::Tag) => name_condition("t.name"),
+            Field::Note(NoteField::TagUnder) => {
+                // `UNION` discards tags that were already found, so cycles in the tag tree end the recursion
+                let mut condition = SqlQuery::new(
+                    "t.id IN (WITH RECURSIVE descendant(id) AS (SELECT ancestor.id FROM tag ancestor WHERE ",
+                );
+                condition
+                    .append(&name_condition("ancestor.name"))
+                    .push(" UNION SELECT child.id FROM tag child JOIN descendant d ON child.parent_id = d.id) SELECT id FROM descendant)");
+                condition
+            }
+            Field::Note(NoteField::TagParent) => {
+                let mut condition =
+                    SqlQuery::new("t.parent_id IN (SELECT parent.id FROM tag parent WHERE ");
+                condition.append(&name_condition("parent.name")).push(")");
+                condition
+            }
+            _ => unreachable!("only tag fields are compared by tag name"),
+        };
+        tag_condition_to_sql(&condition)
+    }
+}
+
+/// Matches cards with a card tag or note tag (`t`) satisfying `condition`.
+fn tag_condition_to_sql(condition: &SqlQuery) -> SqlQuery {
+    // "n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name {})"
+    let mut query = SqlQuery::new(
+        "c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE ",
+    );
+    query
+        .append(condition)
+        .push(" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE ")
+        .append(condition)
+        .push(")");
+    query
+}
+
+impl Field {
+    /// Matches values that are similar to `value`. Returns `None` if the field does not support fuzzy matching.
+    fn to_fuzzy_sql(&self, value: &SqlValue) -> Option<SqlQuery> {
+        let fuzzy_condition = |column: &str| {
+            let mut condition = SqlQuery::new(&format!("fuzzy_score({column}, "));
+            condition
+                .push_bind(value.clone())
+                .push(") >= ")
+                .push_bind(SqlValue::Float(FUZZY_MATCH_THRESHOLD));
+            condition
+        };
+        match self {
+            Field::Note(NoteField::Keyword) => Some(fuzzy_condition("n.keywords")),
+            Field::Note(NoteField::Tag | NoteField::TagUnder | NoteField::TagParent) => {
+                Some(self.tag_name_to_sql(fuzzy_condition))
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -301,30 +1000,47 @@ enum FieldType {
     DateTime,
     Json,
     Boolean,
+    /// Only used for values, such as `/^a.*b$/`
+    Regex,
 }
 
 impl Field {
     fn get_field_type(&self) -> FieldType {
         match self {
             Field::Note(note_field) => match note_field {
-                NoteField::Id | NoteField::LinkedTo => FieldType::Integer,
-                NoteField::Data | NoteField::Keyword | NoteField::ParserName | NoteField::Tag => {
-                    FieldType::String
-                }
+                NoteField::Id | NoteField::LinkedTo | NoteField::TagDepth => FieldType::Integer,
+                NoteField::Data
+                | NoteField::Keyword
+                | NoteField::ParserName
+                | NoteField::Tag
+                | NoteField::TagUnder
+                | NoteField::TagParent
+                | NoteField::Fts => FieldType::String,
                 NoteField::CreatedAt | NoteField::UpdatedAt => FieldType::DateTime,
                 NoteField::CustomData(_) => FieldType::Json,
             },
             Field::Card(card_field) => match card_field {
-                CardField::Id | CardField::Rated | CardField::State => FieldType::Integer,
-                CardField::CreatedAt | CardField::UpdatedAt => FieldType::DateTime,
-                CardField::Stability | CardField::Difficulty | CardField::DesiredRetention => {
-                    FieldType::Float
-                }
+                CardField::Id
+                | CardField::Rated
+                | CardField::State
+                | CardField::Order
+                | CardField::Reps
+                | CardField::Lapses => FieldType::Integer,
+                CardField::CreatedAt
+                | CardField::UpdatedAt
+                | CardField::RatedAt
+                | CardField::Due
+                | CardField::LastReviewedAt => FieldType::DateTime,
+                CardField::Stability
+                | CardField::Difficulty
+                | CardField::DesiredRetention
+                | CardField::Retrievability(_) => FieldType::Float,
                 // CardField::SpecialState => {
                 CardField::Suspended | CardField::UserBuried | CardField::SchedulerBuried => {
                     FieldType::Boolean
                 }
                 CardField::CustomData(_) => FieldType::Json,
+                CardField::Review(review_field) => review_field.get_field_type(),
             },
         }
     }
@@ -347,7 +1063,10 @@ impl TableRequirements {
             Field::Note(note_field) => match note_field {
                 NoteField::ParserName => req.needs_parser = true,
                 // NoteField::Tag => req.needs_tag = true,
-                NoteField::Tag => req.needs_card = true,
+                NoteField::Tag
+                | NoteField::TagUnder
+                | NoteField::TagDepth
+                | NoteField::TagParent => req.needs_card = true,
                 NoteField::LinkedTo => req.needs_note_link = true,
                 _ => {}
             },
@@ -375,49 +1094,119 @@ pub enum EvaluatorReturnItemType {
     CardIds,
 }
 
-struct EvaluationContext<'a> {
-    query_builder: QueryBuilder<'a, Sqlite>,
-    params: Vec<String>,
+struct EvaluationContext {
+    query: SqlQuery,
+    params: Vec<SqlValue>,
     field: Option<Field>,
     value_type: Option<FieldType>,
     table_requirements: TableRequirements,
-    where_clauses: Vec<String>,
+    where_clauses: Vec<SqlQuery>,
     root_context: bool,
+    at: DateTime<Utc>,
+    /// The full text search queries that notes must match. Notes are ranked by how well they match these.
+    fts_queries: Vec<String>,
+    /// The values that results are sorted by, from the `sort:` clauses.
+    sort: Vec<(SqlQuery, SortDirection)>,
+    limit: Option<u32>,
+    offset: Option<u32>,
 }
 
-impl EvaluationContext<'_> {
-    fn new() -> Self {
+impl EvaluationContext {
+    fn new(at: DateTime<Utc>) -> Self {
         Self {
-            query_builder: QueryBuilder::new(""),
+            query: SqlQuery::default(),
             params: Vec::new(),
             field: None,
             value_type: None,
             table_requirements: TableRequirements::default(),
             where_clauses: Vec::new(),
             root_context: false,
+            at,
+            fts_queries: Vec::new(),
+            sort: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    fn add_query_clauses(&mut self, query: &Query) -> Result<(), Error> {
+        for SortClause {
+            field: field_str,
+            direction,
+        } in &query.sort
+        {
+            let field = Field::from_str(&[field_str], self.at)?;
+            let value = field.to_value_sql().ok_or_else(|| {
+                miette!(
+                    "Cannot sort by `{}` since it can only be used to filter results.",
+                    field_str
+                )
+            })?;
+            // Retrievability decreases as the elapsed stabilities increase, so the direction is flipped
+            let direction = match (&field, direction) {
+                (Field::Card(CardField::Retrievability(_)), SortDirection::Ascending) => {
+                    SortDirection::Descending
+                }
+                (Field::Card(CardField::Retrievability(_)), SortDirection::Descending) => {
+                    SortDirection::Ascending
+                }
+                (_, direction) => *direction,
+            };
+            self.table_requirements
+                .merge(&TableRequirements::analyze_field(field));
+            self.sort.push((value, direction));
         }
+        self.limit = query.limit;
+        self.offset = query.offset;
+        Ok(())
     }
 
-    fn build_query(&mut self, output_type: EvaluatorReturnItemType) -> String {
+    #[allow(clippy::too_many_lines)]
+    fn build_query(&mut self, output_type: EvaluatorReturnItemType) -> SqlQuery {
+        // Notes matching any of the full text searches are ranked
+        let fts_query = (!self.fts_queries.is_empty()).then(|| {
+            self.fts_queries
+                .iter()
+                .map(|fts_query| format!("({fts_query})"))
+                .collect::<Vec<_>>()
+                .join(" OR ")
+        });
+        let push_rank = |query: &mut SqlQuery, fts_query: &str| {
+            query
+                .push(", COALESCE((SELECT rank FROM note_fts WHERE note_fts MATCH ")
+                .push_bind(SqlValue::String(fts_query.to_string()))
+                .push(" AND rowid = n.id), 0) AS rank");
+        };
+
         // Start with base table
         match output_type {
             EvaluatorReturnItemType::Notes => {
-                self.query_builder
-                    .push("SELECT DISTINCT n.*, p.name FROM note n");
+                self.query.push("SELECT DISTINCT n.*, p.name");
+                if let Some(fts_query) = &fts_query {
+                    self.query
+                        .push(&format!(
+                            ", (SELECT snippet(note_fts, -1, '{FTS_MATCH_START}', '{FTS_MATCH_END}', '{FTS_SNIPPET_ELLIPSIS}', {FTS_SNIPPET_TOKENS}) FROM note_fts WHERE note_fts MATCH "
+                        ))
+                        .push_bind(SqlValue::String(fts_query.clone()))
+                        .push(" AND rowid = n.id) AS snippet");
+                    push_rank(&mut self.query, fts_query);
+                }
+                self.query.push(" FROM note n");
             }
             EvaluatorReturnItemType::NoteIds => {
-                self.query_builder.push("SELECT DISTINCT n.id FROM note n");
+                self.query.push("SELECT DISTINCT n.id FROM note n");
             }
             EvaluatorReturnItemType::Cards => {
-                self.query_builder
-                    .push("SELECT DISTINCT c.*, p.name FROM card c");
-                self.query_builder
-                    .push(" LEFT JOIN note n ON n.id = c.note_id");
+                self.query.push("SELECT DISTINCT c.*, p.name");
+                if let Some(fts_query) = &fts_query {
+                    push_rank(&mut self.query, fts_query);
+                }
+                self.query.push(" FROM card c");
+                self.query.push(" LEFT JOIN note n ON n.id = c.note_id");
             }
             EvaluatorReturnItemType::CardIds => {
-                self.query_builder.push("SELECT DISTINCT c.id FROM card c");
-                self.query_builder
-                    .push(" LEFT JOIN note n ON n.id = c.note_id");
+                self.query.push("SELECT DISTINCT c.id FROM card c");
+                self.query.push(" LEFT JOIN note n ON n.id = c.note_id");
             }
         }
 
@@ -428,38 +1217,81 @@ impl EvaluationContext<'_> {
                 EvaluatorReturnItemType::Cards | EvaluatorReturnItemType::CardIds
             )
         {
-            self.query_builder
-                .push(" LEFT JOIN card c ON n.id = c.note_id");
+            self.query.push(" LEFT JOIN card c ON n.id = c.note_id");
         }
         if self.table_requirements.needs_parser {
-            self.query_builder
-                .push(" LEFT JOIN parser p ON n.parser_id = p.id");
+            self.query.push(" LEFT JOIN parser p ON n.parser_id = p.id");
         }
         // if self.table_requirements.needs_tag {
-        //     self.query_builder
+        //     self.query
         //         .push(" LEFT JOIN note_tag nt ON n.id = nt.note_id")
         //         .push(" LEFT JOIN tag t ON nt.tag_id = t.id");
         // }
         if self.table_requirements.needs_note_link {
-            self.query_builder
+            self.query
                 .push(" LEFT JOIN note_link nl ON n.id = nl.parent_note_id");
         }
 
         // Add WHERE clause if we have conditions
         if !self.where_clauses.is_empty() {
-            self.query_builder.push(" WHERE ");
+            self.query.push(" WHERE ");
             for (i, clause) in self.where_clauses.iter().enumerate() {
                 if i > 0 {
-                    self.query_builder.push(" AND ");
+                    self.query.push(" AND ");
                 }
-                self.query_builder.push(clause);
+                self.query.append(clause);
             }
         }
 
-        self.query_builder.sql().to_string()
+        // Sort clauses are applied first, then the most relevant are first
+        let mut order_by = std::mem::take(&mut self.sort)
+            .into_iter()
+            .map(|(mut value, direction)| {
+                value.push(match direction {
+                    SortDirection::Ascending => " ASC",
+                    SortDirection::Descending => " DESC",
+                });
+                value
+            })
+            .collect::<Vec<_>>();
+        if fts_query.is_some()
+            && matches!(
+                output_type,
+                EvaluatorReturnItemType::Notes | EvaluatorReturnItemType::Cards
+            )
+        {
+            order_by.push(SqlQuery::new("rank"));
+        }
+        if !order_by.is_empty() {
+            // Break ties consistently, so pages do not overlap
+            order_by.push(SqlQuery::new(match output_type {
+                EvaluatorReturnItemType::Notes | EvaluatorReturnItemType::NoteIds => "n.id",
+                EvaluatorReturnItemType::Cards | EvaluatorReturnItemType::CardIds => "c.id",
+            }));
+            self.query.push(" ORDER BY ");
+            for (i, value) in order_by.iter().enumerate() {
+                if i > 0 {
+                    self.query.push(", ");
+                }
+                self.query.append(value);
+            }
+        }
+        if self.limit.is_some() || self.offset.is_some() {
+            // A negative limit means there is no limit
+            self.query
+                .push(" LIMIT ")
+                .push_bind(SqlValue::Integer(self.limit.map_or(-1, i64::from)));
+            if let Some(offset) = self.offset {
+                self.query
+                    .push(" OFFSET ")
+                    .push_bind(SqlValue::Integer(i64::from(offset)));
+            }
+        }
+
+        std::mem::take(&mut self.query)
     }
 
-    fn add_where_clause(&mut self, clause: String) {
+    fn add_where_clause(&mut self, clause: SqlQuery) {
         self.where_clauses.push(clause);
     }
 }
@@ -470,7 +1302,7 @@ trait Evaluate {
 
 impl Evaluate for TokenTree<'_> {
     fn evaluate(&self, context: &mut EvaluationContext) -> Result<(), Error> {
-        let requirements = self.analyze_requirements()?;
+        let requirements = self.analyze_requirements(context.at)?;
         context.table_requirements.merge(&requirements);
         match self {
             TokenTree::Atom(atom) => atom.evaluate(context),
@@ -484,11 +1316,12 @@ impl Evaluate for TokenTree<'_> {
                         return Err(miette!("Found nil atom inside op."));
                     }
                     for tree in trees {
-                        let mut inner_context = EvaluationContext::new();
+                        let mut inner_context = EvaluationContext::new(context.at);
                         tree.evaluate(&mut inner_context)?;
                         if let Some(clause) = inner_context.where_clauses.first() {
                             clauses.push(clause.clone());
                         }
+                        context.fts_queries.append(&mut inner_context.fts_queries);
                     }
                     let join_op = match op {
                         Op::And => " AND ",
@@ -497,7 +1330,15 @@ impl Evaluate for TokenTree<'_> {
                         _ => unreachable!("by outer match"),
                     };
                     if !clauses.is_empty() {
-                        context.add_where_clause(format!("({})", clauses.join(join_op)));
+                        let mut clause = SqlQuery::new("(");
+                        for (i, inner_clause) in clauses.iter().enumerate() {
+                            if i > 0 {
+                                clause.push(join_op);
+                            }
+                            clause.append(inner_clause);
+                        }
+                        clause.push(")");
+                        context.add_where_clause(clause);
                     }
                     Ok(())
                 }
@@ -513,22 +1354,23 @@ impl Evaluate for TokenTree<'_> {
                 | Op::LessThan
                 | Op::LessThanEqual
                 | Op::Equal
-                | Op::Tilde => evaluate_field_value(trees, context, *op),
+                | Op::Tilde
+                | Op::Percent => evaluate_field_value(trees, context, *op),
             },
         }
     }
 }
 
 impl TokenTree<'_> {
-    fn analyze_requirements(&self) -> Result<TableRequirements, Error> {
+    fn analyze_requirements(&self, at: DateTime<Utc>) -> Result<TableRequirements, Error> {
         match self {
-            TokenTree::Atom(Atom::Field(field)) => {
-                Ok(TableRequirements::analyze_field(Field::from_str(&[field])?))
-            }
+            TokenTree::Atom(Atom::Field(field)) => Ok(TableRequirements::analyze_field(
+                Field::from_str(&[field], at)?,
+            )),
             TokenTree::Cons(_, trees) => {
                 let mut requirements = TableRequirements::default();
                 for tree in trees {
-                    requirements.merge(&tree.analyze_requirements()?);
+                    requirements.merge(&tree.analyze_requirements(at)?);
                 }
                 Ok(requirements)
             }
@@ -537,11 +1379,128 @@ impl TokenTree<'_> {
     }
 }
 
+/// Builds the query for a parsed search query.
+fn evaluate_query(
+    query: &Query,
+    at: DateTime<Utc>,
+    needs_parser: bool,
+    internal_output_type: EvaluatorReturnItemType,
+) -> Result<SqlQuery, Report> {
+    let mut context = EvaluationContext::new(at);
+    context.root_context = true;
+    context.table_requirements.needs_parser = needs_parser;
+    query.expression.evaluate(&mut context)?;
+    context.add_query_clauses(query)?;
+    Ok(context.build_query(internal_output_type))
+}
+
+/// Rewrites a tree into an equivalent one that is easier to read: nested `and` and `or` operations are flattened, redundant groups are removed, and implied fields are named.
+fn normalize(tree: TokenTree<'_>) -> TokenTree<'_> {
+    match tree {
+        TokenTree::Atom(Atom::Field(field)) if field.is_empty() => {
+            TokenTree::Atom(Atom::Field(Cow::Borrowed(DEFAULT_FIELD)))
+        }
+        TokenTree::Atom(atom) => TokenTree::Atom(atom),
+        TokenTree::Cons(Op::Group, mut trees) if trees.len() == 1 => {
+            normalize(trees.pop().expect("by length check"))
+        }
+        TokenTree::Cons(op @ (Op::And | Op::Or), trees) => {
+            let mut flattened_trees = Vec::new();
+            for tree in trees.into_iter().map(normalize) {
+                match tree {
+                    TokenTree::Cons(inner_op, inner_trees) if inner_op == op => {
+                        flattened_trees.extend(inner_trees);
+                    }
+                    tree => flattened_trees.push(tree),
+                }
+            }
+            TokenTree::Cons(op, flattened_trees)
+        }
+        TokenTree::Cons(op, trees) => {
+            TokenTree::Cons(op, trees.into_iter().map(normalize).collect())
+        }
+    }
+}
+
+async fn count_results(
+    connection: &mut PoolConnection<Sqlite>,
+    query: SqlQuery,
+) -> Result<i64, crate::Error> {
+    let mut count_query = SqlQuery::new("SELECT COUNT(*) FROM (");
+    count_query.append(&query).push(")");
+    let (count,): (i64,) = count_query
+        .into_query_builder()
+        .build_query_as()
+        .fetch_one(&mut **connection)
+        .await
+        .map_err(|e| crate::Error::Sqlx { source: e })?;
+    Ok(count)
+}
+
+/// Finds comparisons that are valid, but may not do what was intended, such as comparing a text field with a number. Fields that are implied, such as the `data` field in `dog`, are not checked.
+fn get_warnings(input: &str) -> Vec<SearchWarning> {
+    let Ok(tokens) = Lexer::new(input).collect::<Result<Vec<_>, _>>() else {
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    for window in tokens.windows(3) {
+        let [field_token, op_token, value_token] = window else {
+            unreachable!("by window size");
+        };
+        if field_token.kind != TokenKind::Field || field_token.span.is_empty() {
+            continue;
+        }
+        let field_str = &input[field_token.span.clone()];
+        let Ok(field) = Field::from_str(&[field_str], Utc::now()) else {
+            continue;
+        };
+        let op_str = &input[op_token.span.clone()];
+        let value_str = &input[value_token.span.clone()];
+        let message = match (field.get_field_type(), &op_token.kind, &value_token.kind) {
+            (
+                FieldType::String,
+                TokenKind::Equal
+                | TokenKind::GreaterThan
+                | TokenKind::GreaterThanEqual
+                | TokenKind::LessThan
+                | TokenKind::LessThanEqual
+                | TokenKind::Tilde,
+                TokenKind::Integer(_)
+                | TokenKind::Float(_)
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::Date
+                | TokenKind::RelativeDate,
+            ) => format!("`{field_str}` is a text field, so `{value_str}` is compared as text."),
+            (FieldType::Integer | FieldType::Float | FieldType::DateTime, TokenKind::Tilde, _) => {
+                format!(
+                    "`~` compares `{field_str}` as text, so it matches any value containing `{value_str}`. Use `=` to compare exact values."
+                )
+            }
+            (
+                FieldType::Boolean,
+                TokenKind::GreaterThan
+                | TokenKind::GreaterThanEqual
+                | TokenKind::LessThan
+                | TokenKind::LessThanEqual
+                | TokenKind::Tilde,
+                _,
+            ) => format!("`{op_str}` is treated as `=` for `{field_str}`."),
+            _ => continue,
+        };
+        warnings.push(SearchWarning {
+            message,
+            span: field_token.span.start..value_token.span.end,
+        });
+    }
+    warnings
+}
+
 fn evaluate_minus(trees: &[TokenTree], context: &mut EvaluationContext) -> Result<(), Error> {
     if trees.len() != 1 {
         return Err(miette!("Minus operation requires exactly one operand"));
     }
-    let mut inner_context = EvaluationContext::new();
+    let mut inner_context = EvaluationContext::new(context.at);
     if !matches!(
         trees[0],
         TokenTree::Cons(
@@ -550,7 +1509,8 @@ fn evaluate_minus(trees: &[TokenTree], context: &mut EvaluationContext) -> Resul
                 | Op::LessThan
                 | Op::LessThanEqual
                 | Op::Equal
-                | Op::Tilde,
+                | Op::Tilde
+                | Op::Percent,
             _
         )
     ) {
@@ -558,7 +1518,9 @@ fn evaluate_minus(trees: &[TokenTree], context: &mut EvaluationContext) -> Resul
     }
     trees[0].evaluate(&mut inner_context)?;
     if let Some(clause) = inner_context.where_clauses.first() {
-        context.add_where_clause(format!("NOT ({})", clause));
+        let mut negated_clause = SqlQuery::new("NOT (");
+        negated_clause.append(clause).push(")");
+        context.add_where_clause(negated_clause);
     }
     context
         .table_requirements
@@ -570,22 +1532,25 @@ fn evaluate_colon(trees: &[TokenTree], context: &mut EvaluationContext) -> Resul
     if trees.len() != 2 {
         return Err(miette!("Colon operation requires exactly two operands"));
     }
-    let mut field_context = EvaluationContext::new();
+    let mut field_context = EvaluationContext::new(context.at);
     trees[0].evaluate(&mut field_context)?;
     if field_context.params.len() != 1 {
         return Err(miette!("Expected 1 field param in colon operator"));
     }
 
-    let mut data_context = EvaluationContext::new();
+    let mut data_context = EvaluationContext::new(context.at);
     trees[1].evaluate(&mut data_context)?;
     if data_context.params.len() != 1 {
         return Err(miette!("Expected 1 data param in colon operator"));
     }
 
-    context.field = Some(Field::from_str(&[
-        &field_context.params[0],
-        &data_context.params[0],
-    ])?);
+    context.field = Some(Field::from_str(
+        &[
+            &field_context.params[0].to_string(),
+            &data_context.params[0].to_string(),
+        ],
+        context.at,
+    )?);
 
     context
         .table_requirements
@@ -593,6 +1558,7 @@ fn evaluate_colon(trees: &[TokenTree], context: &mut EvaluationContext) -> Resul
     Ok(())
 }
 
+#[allow(clippy::cast_precision_loss, clippy::too_many_lines)]
 fn evaluate_field_value(
     trees: &[TokenTree],
     context: &mut EvaluationContext,
@@ -605,7 +1571,7 @@ fn evaluate_field_value(
     }
 
     // First tree should be the field
-    let mut field_context = EvaluationContext::new();
+    let mut field_context = EvaluationContext::new(context.at);
     if !matches!(
         trees[0],
         // `Op::Colon` is for Json
@@ -620,30 +1586,43 @@ fn evaluate_field_value(
     let field_type = field.get_field_type();
 
     // Second tree should be the value
-    let mut value_context = EvaluationContext::new();
+    let mut value_context = EvaluationContext::new(context.at);
     trees[1].evaluate(&mut value_context)?;
-    let value_type = value_context
+    let mut value_type = value_context
         .value_type
         .ok_or_else(|| miette!("Missing value"))?;
-    match op {
-        Op::Equal | Op::GreaterThan | Op::GreaterThanEqual | Op::LessThan | Op::LessThanEqual => {
-            if let Some(mut value_param) = value_context.params.pop() {
-                if matches!(op, Op::Equal) && matches!(field_type, FieldType::String) {
-                    value_param = format!("\"{}\"", value_param);
-                }
-                value_context.params.push(format!("{} {}", op, value_param));
-            }
-        }
-        Op::Tilde => {
-            if let Some(value_param) = value_context.params.pop() {
-                value_context
-                    .params
-                    .push(format!("LIKE '%{}%'", value_param));
-            }
+    let mut value = value_context
+        .params
+        .pop()
+        .ok_or_else(|| miette!("Missing value"))?;
+    // Keywords, such as `today`, are dates when compared to a date field
+    if let (FieldType::DateTime, SqlValue::String(s)) = (&field_type, &value) {
+        if let Some(date) = resolve_relative_date(s, context.at) {
+            value = SqlValue::Integer(date.timestamp());
+            value_type = FieldType::DateTime;
         }
-        Op::Group | Op::And | Op::Or | Op::Minus | Op::Colon => unreachable!(),
     }
-    let value_str = value_context.params.join(" ");
+
+    if matches!(value_type, FieldType::Regex)
+        && (op != Op::Tilde
+            || field_type != FieldType::String
+            || matches!(field, Field::Note(NoteField::Fts)))
+    {
+        return Err(miette!(
+            "Regular expressions can only be used with the `~` operator on text fields, such as `data~/^a.*b$/`."
+        ));
+    }
+    if op == Op::Percent {
+        let value = SqlValue::String(value.to_string());
+        let sql_condition = field.to_fuzzy_sql(&value).ok_or_else(|| {
+            miette!(
+                "The `%` operator is only supported by the `keyword` and `tag` fields, not `{:?}`.",
+                field
+            )
+        })?;
+        context.add_where_clause(sql_condition);
+        return Ok(());
+    }
 
     // Ensure field and value have the same type
     match (&field_type, &value_type) {
@@ -657,48 +1636,195 @@ fn evaluate_field_value(
                 "The field `{:?}` has a type of `{:?}`. The provided value of `{}` has a type of `{:?}` which does not match the field's type.",
                 field,
                 field_type,
-                value_str,
+                value,
                 value_type
             ));
         }
     }
+    // Any value can be compared to a string field
+    let value = if matches!(field_type, FieldType::String) {
+        SqlValue::String(value.to_string())
+    } else {
+        value
+    };
+    let (op, value) = if matches!(field, Field::Card(CardField::Retrievability(_))) {
+        let retrievability = match value {
+            SqlValue::Integer(n) => n as f64,
+            SqlValue::Float(n) => n,
+            SqlValue::String(_) | SqlValue::Boolean(_) => unreachable!("by type check"),
+        };
+        // Retrievability decreases as the elapsed stabilities increase, so the comparison is flipped
+        let flipped_op = match op {
+            Op::Equal => Op::Equal,
+            Op::GreaterThan => Op::LessThan,
+            Op::GreaterThanEqual => Op::LessThanEqual,
+            Op::LessThan => Op::GreaterThan,
+            Op::LessThanEqual => Op::GreaterThanEqual,
+            Op::Tilde => {
+                return Err(miette!(
+                    "The `~` operator cannot be used with retrievability."
+                ));
+            }
+            Op::Group | Op::And | Op::Or | Op::Minus | Op::Colon | Op::Percent => unreachable!(),
+        };
+        (
+            flipped_op,
+            SqlValue::Float(get_elapsed_stabilities(retrievability)),
+        )
+    } else {
+        (op, value)
+    };
 
-    let sql_condition = field.to_sql_str(&value_str);
+    let mut comparison = SqlQuery::default();
+    if matches!(field, Field::Note(NoteField::Fts)) {
+        if !matches!(op, Op::Equal | Op::Tilde) {
+            return Err(miette!(
+                "Full text search only supports the `=` and `~` operators."
+            ));
+        }
+        let fts_query = to_fts_query(&value.to_string())
+            .ok_or_else(|| miette!("Full text search requires at least one term."))?;
+        context.fts_queries.push(fts_query.clone());
+        comparison
+            .push("MATCH ")
+            .push_bind(SqlValue::String(fts_query));
+    } else {
+        match op {
+            Op::Equal
+            | Op::GreaterThan
+            | Op::GreaterThanEqual
+            | Op::LessThan
+            | Op::LessThanEqual => {
+                comparison.push(&format!("{} ", op)).push_bind(value);
+            }
+            Op::Tilde if value_type == FieldType::Regex => {
+                comparison.push("REGEXP ").push_bind(value);
+            }
+            Op::Tilde => {
+                comparison
+                    .push("LIKE ")
+                    .push_bind(SqlValue::String(format!("%{}%", value)));
+            }
+            Op::Group | Op::And | Op::Or | Op::Minus | Op::Colon | Op::Percent => {
+                unreachable!()
+            }
+        }
+    }
+
+    let sql_condition = field.to_sql(&comparison);
     context.add_where_clause(sql_condition);
     Ok(())
 }
 
+/// Resolves a date relative to `at`. This is either a keyword (`now`, `today`, `yesterday`, or `tomorrow`) or an offset with a unit, such as `-7d` or `+2w`. Days start at midnight in the local timezone.
+fn resolve_relative_date(input: &str, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let (start_of_today, _) = get_start_end_local_date(&at);
+    match input {
+        "now" => return Some(at),
+        "today" => return Some(start_of_today),
+        "yesterday" => return start_of_today.checked_sub_days(Days::new(1)),
+        "tomorrow" => return start_of_today.checked_add_days(Days::new(1)),
+        _ => {}
+    }
+    let (is_past, offset) = match input.strip_prefix('-') {
+        Some(offset) => (true, offset),
+        None => (false, input.strip_prefix('+').unwrap_or(input)),
+    };
+    let unit = RELATIVE_DATE_UNITS
+        .into_iter()
+        .find(|unit| offset.ends_with(unit))?;
+    let amount = offset.strip_suffix(unit)?.parse::<u32>().ok()?;
+    let months = match unit {
+        "mo" => Some(Months::new(amount)),
+        "y" => Some(Months::new(amount.checked_mul(12)?)),
+        _ => None,
+    };
+    if let Some(months) = months {
+        return if is_past {
+            at.checked_sub_months(months)
+        } else {
+            at.checked_add_months(months)
+        };
+    }
+    let duration = match unit {
+        "h" => Duration::hours(i64::from(amount)),
+        "d" => Duration::days(i64::from(amount)),
+        "w" => Duration::weeks(i64::from(amount)),
+        _ => return None,
+    };
+    if is_past {
+        at.checked_sub_signed(duration)
+    } else {
+        at.checked_add_signed(duration)
+    }
+}
+
+/// Converts search terms into an FTS5 query matching notes that contain every term. Each term is quoted so FTS5's query syntax is treated as text, except for a trailing `*`, which matches any word starting with the term.
+fn to_fts_query(input: &str) -> Option<String> {
+    let terms = input
+        .split_whitespace()
+        .map(|term| {
+            let (term, prefix) = match term.strip_suffix('*') {
+                Some(stripped_term) if !stripped_term.is_empty() => (stripped_term, "*"),
+                _ => (term, ""),
+            };
+            format!("\"{}\"{}", term.replace('"', "\"\""), prefix)
+        })
+        .collect::<Vec<_>>();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Solves FSRS's forgetting curve, `R = (1 + FACTOR * t / S) ^ DECAY`, for the number of stabilities (`t / S`) that have elapsed since the last review. Cards that have not been reviewed have a retrievability of 0.
+fn get_elapsed_stabilities(retrievability: f64) -> f64 {
+    if retrievability <= 0. {
+        return f64::INFINITY;
+    }
+    (retrievability.powf(1. / Parameters::DECAY) - 1.) / Parameters::FACTOR
+}
+
 impl Evaluate for Atom<'_> {
     fn evaluate(&self, context: &mut EvaluationContext) -> Result<(), Error> {
         match self {
             Atom::Field(field) => {
-                context.field = Some(Field::from_str(&[field])?);
-                context.params.push((*field).to_string());
+                context.field = Some(Field::from_str(&[field], context.at)?);
+                context.params.push(SqlValue::String((*field).to_string()));
                 Ok(())
             }
             Atom::String(s) => {
                 context.value_type = Some(FieldType::String);
-                context.params.push(s.to_string());
+                context.params.push(SqlValue::String(s.to_string()));
                 Ok(())
             }
             Atom::Integer(i) => {
                 context.value_type = Some(FieldType::Integer);
-                context.params.push(i.to_string());
+                context.params.push(SqlValue::Integer(*i));
                 Ok(())
             }
             Atom::Float(f) => {
                 context.value_type = Some(FieldType::Float);
-                context.params.push(f.to_string());
+                context.params.push(SqlValue::Float(*f));
                 Ok(())
             }
             Atom::Boolean(b) => {
                 context.value_type = Some(FieldType::Boolean);
-                context.params.push(b.to_string());
+                context.params.push(SqlValue::Boolean(*b));
                 Ok(())
             }
             Atom::DateTime(d) => {
                 context.value_type = Some(FieldType::DateTime);
-                context.params.push(d.timestamp().to_string());
+                context.params.push(SqlValue::Integer(d.timestamp()));
+                Ok(())
+            }
+            Atom::RelativeDate(s) => {
+                let date = resolve_relative_date(s, context.at)
+                    .ok_or_else(|| miette!("Invalid relative date: {}", s))?;
+                context.value_type = Some(FieldType::DateTime);
+                context.params.push(SqlValue::Integer(date.timestamp()));
+                Ok(())
+            }
+            Atom::Regex(pattern) => {
+                context.value_type = Some(FieldType::Regex);
+                context.params.push(SqlValue::String(pattern.to_string()));
                 Ok(())
             }
             Atom::Nil => Ok(()),
@@ -709,88 +1835,482 @@ impl Evaluate for Atom<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{api::note::tests::tests::create_note_helper, model::NoteId};
 
     #[test]
     fn test_misc() {
         let inputs = vec![
             (
                 "c.stability>=2.1",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.stability >= 2.1",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.stability >= ?",
+                vec![SqlValue::Float(2.1)],
             ),
             (
                 "c.stability=2.1",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.stability = 2.1",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.stability = ?",
+                vec![SqlValue::Float(2.1)],
             ),
             // Only data search - no joins needed
             (
                 "dog",
-                "SELECT DISTINCT n.id FROM note n WHERE n.data LIKE '%dog%'",
+                "SELECT DISTINCT n.id FROM note n WHERE n.data LIKE ?",
+                vec![SqlValue::String("%dog%".to_string())],
             ),
             // Tag search - needs tag joins
             (
                 "tag=math",
-                // "SELECT DISTINCT n.id FROM note n LEFT JOIN note_tag nt ON n.id = nt.note_id LEFT JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\""),
-                // "SELECT DISTINCT n.id FROM note n WHERE n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\")",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = \"math\" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\")",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?)",
+                vec![
+                    SqlValue::String("math".to_string()),
+                    SqlValue::String("math".to_string()),
+                ],
             ),
             // Exclude tag
             (
                 "-tag=math",
-                // "SELECT DISTINCT n.id FROM note n WHERE NOT (n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\"))",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE NOT (c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = \"math\" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\"))",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE NOT (c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?))",
+                vec![
+                    SqlValue::String("math".to_string()),
+                    SqlValue::String("math".to_string()),
+                ],
             ),
             // Exclude linked to
             (
                 "-linked_to=12",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN note_link nl ON n.id = nl.parent_note_id WHERE NOT (EXISTS (SELECT 1 FROM note_link nl WHERE nl.parent_note_id = n.id AND nl.linked_note_id = 12))",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN note_link nl ON n.id = nl.parent_note_id WHERE NOT (EXISTS (SELECT 1 FROM note_link nl WHERE nl.parent_note_id = n.id AND nl.linked_note_id = ?))",
+                vec![SqlValue::Integer(12)],
             ),
             // Convert types
             (
                 "tag=2 or tag=true",
-                // "SELECT DISTINCT n.id FROM note n LEFT JOIN note_tag nt ON n.id = nt.note_id LEFT JOIN tag t ON nt.tag_id = t.id WHERE (t.name = \"2\" OR t.name = \"true\")"),
-                // "SELECT DISTINCT n.id FROM note n WHERE (n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"2\") OR n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"true\"))",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE (c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = \"2\" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"2\") OR c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = \"true\" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"true\"))",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE (c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?) OR c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?))",
+                vec![
+                    SqlValue::String("2".to_string()),
+                    SqlValue::String("2".to_string()),
+                    SqlValue::String("true".to_string()),
+                    SqlValue::String("true".to_string()),
+                ],
             ),
             // Card search - needs card join
             (
                 "-card.scheduler_buried=true",
                 "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE NOT (c.special_state = 3)",
+                vec![],
             ),
             // Complex query - needs multiple joins
             (
                 "dog and tag=math and -card.suspended=true",
-                // "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id LEFT JOIN note_tag nt ON n.id = nt.note_id LEFT JOIN tag t ON nt.tag_id = t.id WHERE ((n.data LIKE '%dog%' AND t.name = \"math\") AND NOT (c.special_state = 1))"),
-                // "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE ((n.data LIKE '%dog%' AND n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\")) AND NOT (c.special_state = 1))",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE ((n.data LIKE '%dog%' AND c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = \"math\" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\")) AND NOT (c.special_state = 1))",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE ((n.data LIKE ? AND c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?)) AND NOT (c.special_state = 1))",
+                vec![
+                    SqlValue::String("%dog%".to_string()),
+                    SqlValue::String("math".to_string()),
+                    SqlValue::String("math".to_string()),
+                ],
             ),
             (
                 "dog and tag=math and card.suspended=false",
-                // "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id LEFT JOIN note_tag nt ON n.id = nt.note_id LEFT JOIN tag t ON nt.tag_id = t.id WHERE ((n.data LIKE '%dog%' AND t.name = \"math\") AND NOT (c.special_state = 1))"),
-                // "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE ((n.data LIKE '%dog%' AND n.id IN (SELECT note_id FROM note_tag nt JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\")) AND (c.special_state IS NULL OR c.special_state != 1))",
-                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE ((n.data LIKE '%dog%' AND c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = \"math\" UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = \"math\")) AND (c.special_state IS NULL OR c.special_state != 1))",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE ((n.data LIKE ? AND c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?)) AND (c.special_state IS NULL OR c.special_state != 1))",
+                vec![
+                    SqlValue::String("%dog%".to_string()),
+                    SqlValue::String("math".to_string()),
+                    SqlValue::String("math".to_string()),
+                ],
             ),
             // Custom data - no joins needed
             (
                 "custom_data:\"$.x.y[1]\">=123",
-                "SELECT DISTINCT n.id FROM note n WHERE json_extract(n.custom_data, '$.x.y[1]') >= 123",
+                "SELECT DISTINCT n.id FROM note n WHERE json_extract(n.custom_data, ?) >= ?",
+                vec![
+                    SqlValue::String("$.x.y[1]".to_string()),
+                    SqlValue::Integer(123),
+                ],
+            ),
+            // Values cannot break out of their literal
+            (
+                "\"dog' OR 1=1 --\"",
+                "SELECT DISTINCT n.id FROM note n WHERE n.data LIKE ?",
+                vec![SqlValue::String("%dog' OR 1=1 --%".to_string())],
             ),
         ];
 
-        for (input, expected_sql) in inputs {
+        for (input, expected_sql, expected_values) in inputs {
             dbg!(&input);
             let evaluator = Evaluator::new(input);
-            let query_str = evaluator
+            let query = evaluator
                 .evaluate(EvaluatorReturnItemType::NoteIds)
                 .unwrap();
-            assert_eq!(query_str, expected_sql);
+            assert_eq!(query.sql(), expected_sql);
+            assert_eq!(query.values(), expected_values.iter().collect::<Vec<_>>());
         }
     }
 
+    #[test]
+    fn test_relative_dates() {
+        let at = DateTime::parse_from_rfc3339("2025-03-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let (start_of_today, _) = get_start_end_local_date(&at);
+        let inputs = vec![
+            (
+                "created_at>-7d",
+                "SELECT DISTINCT n.id FROM note n WHERE n.created_at > ?",
+                at - Duration::days(7),
+            ),
+            (
+                "c.due<=+3w",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE c.due <= ?",
+                at + Duration::weeks(3),
+            ),
+            (
+                "updated_at>=today",
+                "SELECT DISTINCT n.id FROM note n WHERE n.updated_at >= ?",
+                start_of_today,
+            ),
+            (
+                "c.rated_at>=-1mo",
+                "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE EXISTS (SELECT 1 FROM review_log rl WHERE rl.card_id = c.id AND rl.reviewed_at >= ?)",
+                DateTime::parse_from_rfc3339("2025-02-15T12:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+        ];
+        for (input, expected_sql, expected_date) in inputs {
+            dbg!(&input);
+            let evaluator = Evaluator::new(input).with_time(at);
+            let query = evaluator
+                .evaluate(EvaluatorReturnItemType::NoteIds)
+                .unwrap();
+            assert_eq!(query.sql(), expected_sql);
+            assert_eq!(
+                query.values(),
+                vec![&SqlValue::Integer(expected_date.timestamp())]
+            );
+        }
+
+        // Keywords are only dates when compared to a date field
+        let query = Evaluator::new("data=today")
+            .with_time(at)
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        assert_eq!(query.values(), vec![&SqlValue::String("today".to_string())]);
+    }
+
+    #[test]
+    fn test_regex_and_fuzzy() {
+        let query = Evaluator::new(r"data~/\bfoo(bar)?\b/ -tag~/^a\/b$/")
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT n.id FROM note n LEFT JOIN card c ON n.id = c.note_id WHERE (n.data REGEXP ? AND NOT (c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name REGEXP ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name REGEXP ?)))"
+        );
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::String(r"\bfoo(bar)?\b".to_string()),
+                &SqlValue::String("^a/b$".to_string()),
+                &SqlValue::String("^a/b$".to_string()),
+            ]
+        );
+
+        let query = Evaluator::new("keyword%eigenvalu")
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT n.id FROM note n WHERE fuzzy_score(n.keywords, ?) >= ?"
+        );
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::String("eigenvalu".to_string()),
+                &SqlValue::Float(FUZZY_MATCH_THRESHOLD),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_full_text_search() {
+        let query = Evaluator::new("fts=\"big dog*\" or -fts=cat")
+            .evaluate(EvaluatorReturnItemType::Notes)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT n.*, p.name, (SELECT snippet(note_fts, -1, '**', '**', '...', 16) FROM note_fts WHERE note_fts MATCH ? AND rowid = n.id) AS snippet, COALESCE((SELECT rank FROM note_fts WHERE note_fts MATCH ? AND rowid = n.id), 0) AS rank FROM note n WHERE (n.id IN (SELECT rowid FROM note_fts WHERE note_fts MATCH ?) OR NOT (n.id IN (SELECT rowid FROM note_fts WHERE note_fts MATCH ?))) ORDER BY rank, n.id"
+        );
+        // Negated searches are not used for ranking
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::String("(\"big\" \"dog\"*)".to_string()),
+                &SqlValue::String("(\"big\" \"dog\"*)".to_string()),
+                &SqlValue::String("\"big\" \"dog\"*".to_string()),
+                &SqlValue::String("\"cat\"".to_string()),
+            ]
+        );
+
+        // FTS5 syntax is escaped
+        assert_eq!(
+            to_fts_query("a\"b NEAR(c) *"),
+            Some("\"a\"\"b\" \"NEAR(c)\" \"*\"".to_string())
+        );
+        assert_eq!(to_fts_query(" "), None);
+    }
+
+    #[test]
+    fn test_tag_hierarchy() {
+        let query = Evaluator::new("tag_under=math")
+            .evaluate(EvaluatorReturnItemType::CardIds)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT c.id FROM card c LEFT JOIN note n ON n.id = c.note_id WHERE c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.id IN (WITH RECURSIVE descendant(id) AS (SELECT ancestor.id FROM tag ancestor WHERE ancestor.name = ? UNION SELECT child.id FROM tag child JOIN descendant d ON child.parent_id = d.id) SELECT id FROM descendant) UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.id IN (WITH RECURSIVE descendant(id) AS (SELECT ancestor.id FROM tag ancestor WHERE ancestor.name = ? UNION SELECT child.id FROM tag child JOIN descendant d ON child.parent_id = d.id) SELECT id FROM descendant))"
+        );
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::String("math".to_string()),
+                &SqlValue::String("math".to_string()),
+            ]
+        );
+
+        let query = Evaluator::new("tag_parent~alg tag_depth>=2")
+            .evaluate(EvaluatorReturnItemType::CardIds)
+            .unwrap();
+        assert!(query.sql().contains(
+            "t.parent_id IN (SELECT parent.id FROM tag parent WHERE parent.name LIKE ?)"
+        ));
+        assert!(
+            query
+                .sql()
+                .contains("SELECT id FROM tag_depth WHERE depth >= ?)")
+        );
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::String("%alg%".to_string()),
+                &SqlValue::String("%alg%".to_string()),
+                &SqlValue::Integer(2),
+                &SqlValue::Integer(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_saved_searches() {
+        let saved_searches = [
+            ("math", "tag=math sort:id"),
+            ("hard", "saved:math and c.difficulty>=8"),
+        ]
+        .into_iter()
+        .map(|(name, query)| (name.to_string(), query.to_string()))
+        .collect::<HashMap<_, _>>();
+        let query = Evaluator::new("saved:hard or dog")
+            .with_saved_searches(saved_searches.clone())
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        let expected_query = Evaluator::new("((tag=math) and c.difficulty>=8) or dog")
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        assert_eq!(query, expected_query);
+
+        // Saved searches must be given to be referenced
+        assert!(
+            Evaluator::new("saved:math")
+                .evaluate(EvaluatorReturnItemType::NoteIds)
+                .is_err()
+        );
+        assert!(
+            Evaluator::new("saved:other")
+                .with_saved_searches(saved_searches)
+                .evaluate(EvaluatorReturnItemType::NoteIds)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_review_fields() {
+        let at = DateTime::parse_from_rfc3339("2025-03-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let query = Evaluator::new("r.count(rating=1, reviewed_at>=-7d)>=2")
+            .with_time(at)
+            .evaluate(EvaluatorReturnItemType::CardIds)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT c.id FROM card c LEFT JOIN note n ON n.id = c.note_id WHERE (SELECT COUNT(*) FROM review_log rl WHERE rl.card_id = c.id AND rl.rating = ? AND rl.reviewed_at >= ?) >= ?"
+        );
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::Integer(1),
+                &SqlValue::Integer(resolve_relative_date("-7d", at).unwrap().timestamp()),
+                &SqlValue::Integer(2),
+            ]
+        );
+
+        let query = Evaluator::new("r.last.rating=1 r.scheduler~fs")
+            .evaluate(EvaluatorReturnItemType::CardIds)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT c.id FROM card c LEFT JOIN note n ON n.id = c.note_id WHERE ((SELECT rl.rating FROM review_log rl WHERE rl.card_id = c.id ORDER BY rl.reviewed_at DESC, rl.id DESC LIMIT 1) = ? AND EXISTS (SELECT 1 FROM review_log rl WHERE rl.card_id = c.id AND rl.scheduler_name LIKE ?))"
+        );
+
+        let inputs = [
+            "r.count(rating=abc)>1",
+            "r.count(rating)>1",
+            "r.count(stability=1)>1",
+            "r.count(rating=1,)>1",
+            "r.avg_duration=abc",
+            "r.last.rating(rating=1)=1",
+            "r.last.stability=1",
+            "r.unknown=1",
+            "sort:r.scheduler",
+        ];
+        for input in inputs {
+            assert!(
+                Evaluator::new(input)
+                    .evaluate(EvaluatorReturnItemType::CardIds)
+                    .is_err(),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_warnings() {
+        let input = "dog 5 tag=2024 c.stability~2 c.suspended>=true data=\"5\" c.due<-7d";
+        let warnings = get_warnings(input);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| &input[warning.span.clone()])
+                .collect::<Vec<_>>(),
+            vec!["tag=2024", "c.stability~2", "c.suspended>=true"]
+        );
+        assert_eq!(
+            warnings[0].message,
+            "`tag` is a text field, so `2024` is compared as text."
+        );
+        assert!(get_warnings("(unclosed").is_empty());
+    }
+
+    #[test]
+    fn test_sort_limit_offset() {
+        let query = Evaluator::new("tag=math sort:c.difficulty desc limit:20 offset:5")
+            .evaluate(EvaluatorReturnItemType::CardIds)
+            .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT DISTINCT c.id FROM card c LEFT JOIN note n ON n.id = c.note_id WHERE c.id IN (SELECT ct.card_id FROM card_tag ct JOIN tag t ON ct.tag_id = t.id WHERE t.name = ? UNION SELECT c.id FROM card c JOIN note n ON c.note_id = n.id JOIN note_tag nt ON n.id = nt.note_id JOIN tag t ON nt.tag_id = t.id WHERE t.name = ?) ORDER BY c.difficulty DESC, c.id LIMIT ? OFFSET ?"
+        );
+        assert_eq!(
+            query.values(),
+            vec![
+                &SqlValue::String("math".to_string()),
+                &SqlValue::String("math".to_string()),
+                &SqlValue::Integer(20),
+                &SqlValue::Integer(5),
+            ]
+        );
+
+        // Sorting notes by a card field needs the card table
+        let query = Evaluator::new("sort:c.retrievability offset:10")
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        assert!(query.sql().contains("LEFT JOIN card c ON n.id = c.note_id"));
+        assert!(query.sql().ends_with(" DESC, n.id LIMIT ? OFFSET ?"));
+        assert_eq!(
+            query.values()[query.values().len() - 2..],
+            [&SqlValue::Integer(-1), &SqlValue::Integer(10)]
+        );
+    }
+
+    #[test]
+    fn test_retrievability_time() {
+        let at = DateTime::parse_from_rfc3339("2025-03-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let query = Evaluator::new("c.retrievability<0.9")
+            .with_time(at)
+            .evaluate(EvaluatorReturnItemType::NoteIds)
+            .unwrap();
+        assert!(!query.sql().contains("'now'"));
+        assert_eq!(query.values()[0], &SqlValue::Integer(at.timestamp()));
+    }
+
+    #[sqlx::test]
+    async fn test_hostile_inputs(pool: sqlx::SqlitePool) -> () {
+        create_note_helper(&pool).await;
+        let payloads = ["x') OR 1=1 --", "x' OR '1'='1", "'; DROP TABLE note; --"];
+        for payload in payloads {
+            let inputs = [
+                format!("data=\"{payload}\""),
+                format!("tag=\"{payload}\""),
+                format!("custom_data:\"$.{payload}\">=0"),
+                format!("c.custom_data:\"$.{payload}\">=0"),
+            ];
+            for input in inputs {
+                let query = Evaluator::new(&input)
+                    .evaluate(EvaluatorReturnItemType::NoteIds)
+                    .unwrap();
+
+                // The payload is only in the bound values, which each have a placeholder
+                let sql = query.sql();
+                assert!(!sql.contains(payload), "{input}: {sql}");
+                assert!(!sql.contains('\''), "{input}: {sql}");
+                assert_eq!(sql.matches('?').count(), query.values().len());
+                assert!(
+                    query
+                        .values()
+                        .iter()
+                        .any(|value| value.to_string().contains(payload))
+                );
+
+                // Nothing matches when the query is run
+                let mut query_builder = QueryBuilder::new("");
+                query.push_to(&mut query_builder);
+                let note_ids: Vec<(NoteId,)> = query_builder
+                    .build_query_as()
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+                assert!(note_ids.is_empty(), "{input}");
+            }
+        }
+        let (note_count,): (u32,) = sqlx::query_as(r"SELECT COUNT(*) FROM note")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(note_count > 0);
+    }
+
     #[test]
     fn test_error() {
         let inputs = [
             // Missing value
             "tag=",
+            // Unsupported full text search operator
+            "fts>dog",
+            // Invalid regular expression
+            "data~/(/",
+            // Regular expressions only work with `~` on text fields
+            "data=/abc/",
+            "c.stability~/1/",
+            "fts~/abc/",
+            // Fuzzy matching is only supported by keywords and tags
+            "data%dog",
+            // Invalid relative date
+            "created_at>=-99999999999y",
+            // Fields with multiple values cannot be sorted by
+            "sort:tag",
+            "sort:fts",
+            // Invalid limit
+            "limit:-1",
+            // Tag depths are numbers
+            "tag_depth=math",
             // Invalid value
             "tag:personal",
             "-tag:personal",
@@ -811,9 +2331,9 @@ mod tests {
         for input in inputs {
             dbg!(&input);
             let evaluator = Evaluator::new(input);
-            let query_str_res = evaluator.evaluate(EvaluatorReturnItemType::NoteIds);
-            dbg!(&query_str_res);
-            assert!(query_str_res.is_err());
+            let query_res = evaluator.evaluate(EvaluatorReturnItemType::NoteIds);
+            dbg!(&query_res);
+            assert!(query_res.is_err());
         }
     }
 }
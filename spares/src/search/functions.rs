@@ -0,0 +1,219 @@
+//! Database functions used by searches.
+//!
+//! The database evaluates `x REGEXP y` by calling a user defined `regexp(y, x)` function, so regular expressions in searches only work on connections where [`register_functions`] has been called. See <https://www.sqlite.org/lang_expr.html#regexp> and <https://www.sqlite.org/c3ref/create_function.html>.
+
+use crate::{Error, LibraryError};
+use fancy_regex::Regex;
+use libsqlite3_sys as ffi;
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, pool::PoolConnection};
+use std::{ffi::CStr, sync::Arc};
+
+/// Values that are at least this similar to the searched value match a fuzzy search. Similarity is between 0 (nothing in common) and 1 (identical).
+pub(crate) const FUZZY_MATCH_THRESHOLD: f64 = 0.7;
+
+const REGEXP_FUNCTION_NAME: &CStr = c"regexp";
+const FUZZY_SCORE_FUNCTION_NAME: &CStr = c"fuzzy_score";
+
+/// Acquires a connection that can evaluate any search.
+pub(crate) async fn acquire_search_connection(
+    db: &SqlitePool,
+) -> Result<PoolConnection<Sqlite>, Error> {
+    let mut connection = db.acquire().await.map_err(|e| Error::Sqlx { source: e })?;
+    register_functions(&mut connection).await?;
+    Ok(connection)
+}
+
+/// Registers the functions used by searches on the connection. Registering them again replaces the existing functions.
+pub(crate) async fn register_functions(connection: &mut SqliteConnection) -> Result<(), Error> {
+    let mut handle = connection
+        .lock_handle()
+        .await
+        .map_err(|e| Error::Sqlx { source: e })?;
+    let raw_handle = handle.as_raw_handle().as_ptr();
+    let functions: [(&CStr, FunctionPointer); 2] = [
+        (REGEXP_FUNCTION_NAME, regexp),
+        (FUZZY_SCORE_FUNCTION_NAME, fuzzy_score),
+    ];
+    for (name, function) in functions {
+        // SAFETY: The handle is locked, so the connection is not being used by another thread. The name is nul terminated and the function does not use any user data.
+        let result_code = unsafe {
+            ffi::sqlite3_create_function_v2(
+                raw_handle,
+                name.as_ptr(),
+                2,
+                ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
+                std::ptr::null_mut(),
+                Some(function),
+                None,
+                None,
+                None,
+            )
+        };
+        if result_code != ffi::SQLITE_OK {
+            return Err(Error::Library(LibraryError::Search(format!(
+                "Failed to register the `{}` SQLite function (error code {}).",
+                name.to_string_lossy(),
+                result_code
+            ))));
+        }
+    }
+    Ok(())
+}
+
+type FunctionPointer =
+    unsafe extern "C" fn(*mut ffi::sqlite3_context, i32, *mut *mut ffi::sqlite3_value);
+
+/// `regexp(pattern, value)`: Whether `value` matches the regular expression `pattern`.
+unsafe extern "C" fn regexp(
+    ctx: *mut ffi::sqlite3_context,
+    arg_count: i32,
+    args: *mut *mut ffi::sqlite3_value,
+) {
+    // SAFETY: SQLite passes `arg_count` valid arguments, and the function was registered with 2 arguments.
+    let (pattern_arg, value_arg) = unsafe {
+        if arg_count != 2 {
+            set_error(ctx, "regexp() expects 2 arguments");
+            return;
+        }
+        (*args, *args.add(1))
+    };
+    // SAFETY: The arguments are valid for the duration of this call.
+    let Some(value) = (unsafe { get_text(value_arg) }) else {
+        // SAFETY: The context is valid for the duration of this call.
+        unsafe { ffi::sqlite3_result_null(ctx) };
+        return;
+    };
+    // SAFETY: The context and arguments are valid for the duration of this call.
+    let regex = match unsafe { get_regex(ctx, pattern_arg) } {
+        Ok(regex) => regex,
+        Err(message) => {
+            // SAFETY: The context is valid for the duration of this call.
+            unsafe { set_error(ctx, &message) };
+            return;
+        }
+    };
+    match regex.is_match(value) {
+        // SAFETY: The context is valid for the duration of this call.
+        Ok(is_match) => unsafe { ffi::sqlite3_result_int(ctx, i32::from(is_match)) },
+        // SAFETY: The context is valid for the duration of this call.
+        Err(e) => unsafe { set_error(ctx, &e.to_string()) },
+    }
+}
+
+/// Returns the compiled regular expression for the pattern argument. The compiled regular expression is cached by the database for the rest of the statement, since the pattern is the same for every row.
+///
+/// # Safety
+///
+/// `ctx` and `pattern_arg` must be the context and first argument of the current function call.
+unsafe fn get_regex(
+    ctx: *mut ffi::sqlite3_context,
+    pattern_arg: *mut ffi::sqlite3_value,
+) -> Result<Arc<Regex>, String> {
+    // SAFETY: Any cached data was set below, so it is a pointer from `Arc::into_raw`. SQLite keeps it alive for the rest of this call, and the strong count is incremented before `Arc::from_raw` takes ownership of one reference.
+    unsafe {
+        let cached = ffi::sqlite3_get_auxdata(ctx, 0)
+            .cast::<Regex>()
+            .cast_const();
+        if !cached.is_null() {
+            Arc::increment_strong_count(cached);
+            return Ok(Arc::from_raw(cached));
+        }
+    }
+    // SAFETY: The argument is valid for the duration of this call.
+    let pattern = unsafe { get_text(pattern_arg) }.ok_or("The regular expression must be text")?;
+    let regex = Arc::new(Regex::new(pattern).map_err(|e| e.to_string())?);
+    // SAFETY: SQLite takes ownership of one reference and releases it with `drop_cached_regex`, which may happen before this returns.
+    unsafe {
+        ffi::sqlite3_set_auxdata(
+            ctx,
+            0,
+            Arc::into_raw(Arc::clone(&regex)).cast_mut().cast(),
+            Some(drop_cached_regex),
+        );
+    }
+    Ok(regex)
+}
+
+unsafe extern "C" fn drop_cached_regex(ptr: *mut std::ffi::c_void) {
+    // SAFETY: The pointer was created by `Arc::into_raw` in `get_regex`.
+    unsafe { drop(Arc::from_raw(ptr.cast::<Regex>().cast_const())) };
+}
+
+/// `fuzzy_score(values, searched)`: How similar `searched` is to the most similar of the comma separated `values`, ignoring case. See [`get_fuzzy_score`].
+unsafe extern "C" fn fuzzy_score(
+    ctx: *mut ffi::sqlite3_context,
+    arg_count: i32,
+    args: *mut *mut ffi::sqlite3_value,
+) {
+    // SAFETY: SQLite passes `arg_count` valid arguments, and the function was registered with 2 arguments.
+    let (values_arg, searched_arg) = unsafe {
+        if arg_count != 2 {
+            set_error(ctx, "fuzzy_score() expects 2 arguments");
+            return;
+        }
+        (*args, *args.add(1))
+    };
+    // SAFETY: The context and arguments are valid for the duration of this call.
+    unsafe {
+        match (get_text(values_arg), get_text(searched_arg)) {
+            (Some(values), Some(searched)) => {
+                ffi::sqlite3_result_double(ctx, get_fuzzy_score(values, searched));
+            }
+            _ => ffi::sqlite3_result_null(ctx),
+        }
+    }
+}
+
+/// Scores how similar `searched` is to the most similar of the comma separated `values`, ignoring case. Keywords are stored as a comma separated list, so each keyword is scored separately.
+pub(crate) fn get_fuzzy_score(values: &str, searched: &str) -> f64 {
+    let searched = searched.to_lowercase();
+    values
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| strsim::normalized_damerau_levenshtein(&value.to_lowercase(), &searched))
+        .fold(0., f64::max)
+}
+
+/// Returns the argument as text, or `None` if it is `NULL`.
+///
+/// # Safety
+///
+/// `arg` must be an argument of the current function call. The returned string must not outlive the call.
+unsafe fn get_text<'a>(arg: *mut ffi::sqlite3_value) -> Option<&'a str> {
+    // SAFETY: `sqlite3_value_text` converts the value to UTF-8 text, and `sqlite3_value_bytes` must be called after it to get the length of that text.
+    unsafe {
+        if ffi::sqlite3_value_type(arg) == ffi::SQLITE_NULL {
+            return None;
+        }
+        let ptr = ffi::sqlite3_value_text(arg);
+        if ptr.is_null() {
+            return Some("");
+        }
+        let len = usize::try_from(ffi::sqlite3_value_bytes(arg)).ok()?;
+        std::str::from_utf8(std::slice::from_raw_parts(ptr, len)).ok()
+    }
+}
+
+/// # Safety
+///
+/// `ctx` must be the context of the current function call.
+unsafe fn set_error(ctx: *mut ffi::sqlite3_context, message: &str) {
+    let len = i32::try_from(message.len()).unwrap_or(i32::MAX);
+    // SAFETY: SQLite copies the message, and `len` is at most the length of the message.
+    unsafe { ffi::sqlite3_result_error(ctx, message.as_ptr().cast(), len) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!((get_fuzzy_score("Eigenvalue", "eigenvalue") - 1.).abs() < f64::EPSILON);
+        assert!(get_fuzzy_score("eigenvalue", "eigenvlaue") >= FUZZY_MATCH_THRESHOLD);
+        assert!(get_fuzzy_score("matrix, eigenvalue", "eigenvalu") >= FUZZY_MATCH_THRESHOLD);
+        assert!(get_fuzzy_score("matrix", "eigenvalue") < FUZZY_MATCH_THRESHOLD);
+        assert!(get_fuzzy_score("", "eigenvalue") < FUZZY_MATCH_THRESHOLD);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::search::{Token, TokenKind};
+use crate::search::{
+    RELATIVE_DATE_UNITS, REVIEW_FIELD_PREFIX, SAVED_SEARCH_FIELD, Token, TokenKind,
+};
 use miette::{Diagnostic, Error, LabeledSpan, SourceSpan};
+use std::ops::Range;
 use thiserror::Error;
 use unscanny::Scanner;
 
@@ -50,10 +53,33 @@ pub struct StringTerminationError {
     err_span: SourceSpan,
 }
 
+#[derive(Diagnostic, Debug, Error)]
+#[error("Unterminated arguments")]
+#[diagnostic(help("Arguments end with `)`, such as `r.count(rating=1)`."))]
+pub struct ArgumentsTerminationError {
+    #[source_code]
+    src: String,
+
+    #[label = "these arguments"]
+    err_span: SourceSpan,
+}
+
+#[derive(Diagnostic, Debug, Error)]
+#[error("Unterminated regular expression")]
+#[diagnostic(help("Regular expressions end with `/`. Use `\\/` to match a `/`."))]
+pub struct RegexTerminationError {
+    #[source_code]
+    src: String,
+
+    #[label = "this regular expression"]
+    err_span: SourceSpan,
+}
+
 impl Iterator for Lexer<'_> {
     type Item = Result<Token, Error>;
 
     /// Once the iterator returns `Err`, it will only return `None`.
+    #[allow(clippy::too_many_lines)]
     fn next(&mut self) -> Option<Self::Item> {
         // First return any buffered tokens
         if let Some(token) = self.buffered.pop() {
@@ -84,12 +110,27 @@ impl Iterator for Lexer<'_> {
             Some('>') => just(TokenKind::GreaterThan, self.s.cursor()),
             Some(':') => just(TokenKind::Colon, self.s.cursor()),
             Some('~') => just(TokenKind::Tilde, self.s.cursor()),
+            Some('%') => just(TokenKind::Percent, self.s.cursor()),
             Some('a') if self.s.eat_if("nd") => just(TokenKind::And, self.s.cursor()),
             Some('A') if self.s.eat_if("ND") => just(TokenKind::And, self.s.cursor()),
             Some('o') if self.s.eat_if('r') => just(TokenKind::Or, self.s.cursor()),
             Some('O') if self.s.eat_if('R') => just(TokenKind::Or, self.s.cursor()),
             Some('t') if self.s.eat_if("rue") => just(TokenKind::True, self.s.cursor()),
             Some('f') if self.s.eat_if("alse") => just(TokenKind::False, self.s.cursor()),
+            Some('s') if self.s.eat_if("ort:") => {
+                if let Err(e) = self.eat_sort_arguments() {
+                    return Some(Err(e));
+                }
+                just(TokenKind::Sort, self.s.cursor())
+            }
+            Some('l') if self.s.eat_if("imit:") => {
+                self.s.eat_while(char::is_ascii_digit);
+                just(TokenKind::Limit, self.s.cursor())
+            }
+            Some('o') if self.s.eat_if("ffset:") => {
+                self.s.eat_while(char::is_ascii_digit);
+                just(TokenKind::Offset, self.s.cursor())
+            }
             Some('"') => match self.parse_string(false) {
                 Ok(kind) => Ok(Token {
                     kind,
@@ -104,18 +145,34 @@ impl Iterator for Lexer<'_> {
                 }),
                 Err(e) => return Some(Err(e)),
             },
-            Some(c) if char::is_ascii_digit(&c) || c == '-' => match self.parse_date_or_number(c) {
+            Some('/') => match self.parse_regex(cursor_start) {
                 Ok(kind) => Ok(Token {
                     kind,
-                    span: cursor_start..self.s.cursor(),
+                    span: cursor_start + 1..self.s.cursor() - 1,
                 }),
                 Err(e) => return Some(Err(e)),
             },
+            Some(c)
+                if char::is_ascii_digit(&c)
+                    || c == '-'
+                    || (c == '+' && self.s.peek().is_some_and(|nc| char::is_ascii_digit(&nc))) =>
+            {
+                match self.parse_date_or_number(c) {
+                    Ok(kind) => Ok(Token {
+                        kind,
+                        span: cursor_start..self.s.cursor(),
+                    }),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
             Some(ch) if char::is_alphanumeric(ch) || ch == '.' || ch == '_' => {
                 self.s
                     .eat_while(|c| char::is_alphanumeric(c) || c == '.' || c == '_' || c == '-');
+                if let Err(e) = self.eat_review_field_arguments(cursor_start) {
+                    return Some(Err(e));
+                }
                 let token_kind = match self.s.peek() {
-                    Some('=' | '>' | '<' | '~' | ':') => TokenKind::Field,
+                    Some('=' | '>' | '<' | '~' | ':' | '%') => TokenKind::Field,
                     _ => TokenKind::String,
                 };
                 Ok(Token {
@@ -149,12 +206,13 @@ impl Iterator for Lexer<'_> {
 impl Lexer<'_> {
     fn normalize(&mut self, current_token: &mut Token) {
         if self.normalize {
-            if matches!(current_token.kind, TokenKind::String) {
+            if matches!(current_token.kind, TokenKind::String | TokenKind::Regex) {
                 let add_implied_field = self.prev.as_ref().is_none_or(|prev| {
                     !matches!(
                         prev.kind,
                         TokenKind::Equal
                             | TokenKind::Tilde
+                            | TokenKind::Percent
                             | TokenKind::GreaterThan
                             | TokenKind::GreaterThanEqual
                             | TokenKind::LessThan
@@ -179,7 +237,7 @@ impl Lexer<'_> {
                 current_token.kind,
                 TokenKind::Field | TokenKind::LeftParen | TokenKind::Minus
             ) && self.prev.is_some()
-                && !matches!(self.prev, Some(ref token) if matches!(token.kind, TokenKind::And | TokenKind::Or | TokenKind::Minus | TokenKind::LeftParen));
+                && !matches!(self.prev, Some(ref token) if matches!(token.kind, TokenKind::And | TokenKind::Or | TokenKind::Minus | TokenKind::LeftParen | TokenKind::Sort | TokenKind::Limit | TokenKind::Offset));
             if add_implied_and {
                 self.buffered.push(Ok(current_token.clone()));
                 *current_token = Token {
@@ -217,11 +275,29 @@ impl Lexer<'_> {
         Err(err.into())
     }
 
+    /// Consumes a regular expression up to its closing `/`. A `/` can be escaped with a backslash.
+    fn parse_regex(&mut self, start: usize) -> Result<TokenKind, Error> {
+        while let Some(c) = self.s.eat() {
+            match c {
+                '/' => return Ok(TokenKind::Regex),
+                '\\' => {
+                    self.s.eat();
+                }
+                _ => {}
+            }
+        }
+        let err = RegexTerminationError {
+            src: self.s.string().to_string(),
+            err_span: SourceSpan::from(start..self.s.string().len()),
+        };
+        Err(err.into())
+    }
+
     #[allow(clippy::unnecessary_wraps, reason = "For consistency")]
     fn parse_date_or_number(&mut self, c: char) -> Result<TokenKind, Error> {
         let start = self.s.cursor() - 1;
 
-        // If it's a minus, check the next character
+        // If it's a minus, check the next character. A plus is always followed by a digit.
         if c == '-' {
             let next_char = self.s.peek();
             if next_char.is_some_and(|nc| char::is_ascii_digit(&nc)) {
@@ -234,6 +310,11 @@ impl Lexer<'_> {
         // At this point, it's a number (possibly negative)
         self.s.eat_while(char::is_ascii_digit);
 
+        // Check for a relative date, such as `-7d`
+        if self.eat_relative_date_unit() {
+            return Ok(TokenKind::RelativeDate);
+        }
+
         // Check for potential date format
         if self.s.eat_if('-') {
             self.s.eat_while(char::is_ascii_digit);
@@ -268,6 +349,64 @@ impl Lexer<'_> {
 }
 
 impl Lexer<'_> {
+    /// Consumes the field and optional direction of a sort clause, such as the `c.due desc` in `sort:c.due desc`.
+    fn eat_sort_arguments(&mut self) -> Result<(), Error> {
+        let field_start = self.s.cursor();
+        self.s
+            .eat_while(|c| char::is_alphanumeric(c) || c == '.' || c == '_');
+        self.eat_review_field_arguments(field_start)?;
+        let before_direction = self.s.cursor();
+        self.s.eat_whitespace();
+        for direction in ["asc", "desc"] {
+            let is_direction = self.s.after().strip_prefix(direction).is_some_and(|rest| {
+                !rest.starts_with(|c: char| char::is_alphanumeric(c) || c == '.' || c == '_')
+            });
+            if is_direction {
+                self.s.eat_if(direction);
+                return Ok(());
+            }
+        }
+        self.s.jump(before_direction);
+        Ok(())
+    }
+
+    /// Consumes the arguments of a review field that starts at `field_start`, such as the `(rating=1, reviewed_at>-30d)` in `r.count(rating=1, reviewed_at>-30d)`. Brackets inside strings are ignored.
+    fn eat_review_field_arguments(&mut self, field_start: usize) -> Result<(), Error> {
+        if !self.s.from(field_start).starts_with(REVIEW_FIELD_PREFIX) || !self.s.eat_if('(') {
+            return Ok(());
+        }
+        let mut in_string = false;
+        while let Some(c) = self.s.eat() {
+            match c {
+                '\\' if in_string => {
+                    self.s.eat();
+                }
+                '"' => in_string = !in_string,
+                ')' if !in_string => return Ok(()),
+                _ => {}
+            }
+        }
+        let err = ArgumentsTerminationError {
+            src: self.s.string().to_string(),
+            err_span: SourceSpan::from(field_start..self.s.string().len()),
+        };
+        Err(err.into())
+    }
+
+    /// Consumes the unit of a relative date, if the unit is not part of a longer word.
+    fn eat_relative_date_unit(&mut self) -> bool {
+        for unit in RELATIVE_DATE_UNITS {
+            let is_unit = self.s.after().strip_prefix(unit).is_some_and(|rest| {
+                !rest.starts_with(|c: char| char::is_alphanumeric(c) || c == '.' || c == '_')
+            });
+            if is_unit {
+                self.s.eat_if(unit);
+                return true;
+            }
+        }
+        false
+    }
+
     pub fn expect(
         &mut self,
         expected: TokenKind,
@@ -328,6 +467,44 @@ impl Lexer<'_> {
         }
         Ok(tag_values)
     }
+
+    pub fn extract_saved_search_dependencies(&mut self) -> Result<Vec<String>, miette::Error> {
+        Ok(self
+            .extract_saved_search_references()?
+            .into_iter()
+            .map(|(_span, name)| name)
+            .collect())
+    }
+
+    /// Returns the name of each saved search referenced with `saved:name`, along with the span of the whole reference.
+    pub fn extract_saved_search_references(
+        &mut self,
+    ) -> Result<Vec<(Range<usize>, String)>, miette::Error> {
+        let tokens = self.into_iter().collect::<Result<Vec<_>, _>>()?;
+        let whole = self.s.string();
+        let tokens = tokens
+            .into_iter()
+            .map(|t| (t.kind, &whole[t.span.clone()], t.span))
+            .collect::<Vec<_>>();
+        let mut references = Vec::new();
+        for window in tokens.windows(3) {
+            if let [
+                (TokenKind::Field, SAVED_SEARCH_FIELD, field_span),
+                (TokenKind::Colon, _, _),
+                (TokenKind::String, name, name_span),
+            ] = window
+            {
+                // The span of a quoted string does not include its quotes
+                let before_name = &whole[..name_span.start];
+                let quote_len = before_name.len() - before_name.trim_end_matches(['"', '#']).len();
+                references.push((
+                    field_span.start..name_span.end + quote_len,
+                    Token::unescape(name).into_owned(),
+                ));
+            }
+        }
+        Ok(references)
+    }
 }
 
 #[cfg(test)]
@@ -512,6 +689,152 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_relative_date() {
+        let input = "created_at>-7d and c.due<=+3d and updated_at>=today and c.rated_at>=-1mo";
+        let lexer = Lexer::new(input);
+        let tokens = lexer
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Field, "created_at"),
+                (TokenKind::GreaterThan, ">"),
+                (TokenKind::RelativeDate, "-7d"),
+                (TokenKind::And, "and"),
+                (TokenKind::Field, "c.due"),
+                (TokenKind::LessThanEqual, "<="),
+                (TokenKind::RelativeDate, "+3d"),
+                (TokenKind::And, "and"),
+                (TokenKind::Field, "updated_at"),
+                (TokenKind::GreaterThanEqual, ">="),
+                (TokenKind::String, "today"),
+                (TokenKind::And, "and"),
+                (TokenKind::Field, "c.rated_at"),
+                (TokenKind::GreaterThanEqual, ">="),
+                (TokenKind::RelativeDate, "-1mo"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_regex_and_fuzzy() {
+        let input = r"data~/\bfoo(bar)?\b/ keyword%eigenvalu /a\/b c/";
+        let lexer = Lexer::new(input);
+        let tokens = lexer
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Field, "data"),
+                (TokenKind::Tilde, "~"),
+                (TokenKind::Regex, r"\bfoo(bar)?\b"),
+                (TokenKind::And, ""),
+                (TokenKind::Field, "keyword"),
+                (TokenKind::Percent, "%"),
+                (TokenKind::String, "eigenvalu"),
+                (TokenKind::And, ""),
+                (TokenKind::Field, ""),
+                (TokenKind::Tilde, ""),
+                (TokenKind::Regex, r"a\/b c"),
+            ]
+        );
+
+        let input = r"data~/abc\/";
+        let lexer = Lexer::new(input);
+        let tokens = lexer.into_iter().collect::<Result<Vec<_>, _>>();
+        assert!(tokens.is_err());
+    }
+
+    #[test]
+    fn test_sort_limit_offset() {
+        let input = "tag=math sort:c.difficulty desc sort:id limit:20 offset:5";
+        let lexer = Lexer::new(input);
+        let tokens = lexer
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Field, "tag"),
+                (TokenKind::Equal, "="),
+                (TokenKind::String, "math"),
+                (TokenKind::Sort, "sort:c.difficulty desc"),
+                (TokenKind::Sort, "sort:id"),
+                (TokenKind::Limit, "limit:20"),
+                (TokenKind::Offset, "offset:5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_review_fields() {
+        let input = r#"r.count(rating=1, scheduler="a)b")>=2 r.last.rating=1 sort:r.count(rating=1, duration>5) desc"#;
+        let lexer = Lexer::new(input);
+        let tokens = lexer
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Field, r#"r.count(rating=1, scheduler="a)b")"#),
+                (TokenKind::GreaterThanEqual, ">="),
+                (TokenKind::Integer(2), "2"),
+                (TokenKind::And, ""),
+                (TokenKind::Field, "r.last.rating"),
+                (TokenKind::Equal, "="),
+                (TokenKind::Integer(1), "1"),
+                (TokenKind::Sort, "sort:r.count(rating=1, duration>5) desc"),
+            ]
+        );
+        assert!(
+            Lexer::new("r.count(rating=1>=2")
+                .collect::<Result<Vec<_>, _>>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_saved_search_references() {
+        let input = r##"saved:math and -tag=saved (saved:"hard math" or saved:#"a"b"#)"##;
+        let mut lexer = Lexer::new(input);
+        let references = lexer.extract_saved_search_references().unwrap();
+        assert_eq!(
+            references
+                .iter()
+                .map(|(span, name)| (&input[span.clone()], name.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("saved:math", "math"),
+                (r#"saved:"hard math""#, "hard math"),
+                (r##"saved:#"a"b"#"##, r#"a"b"#),
+            ]
+        );
+        let mut lexer = Lexer::new(input);
+        assert_eq!(
+            lexer.extract_saved_search_dependencies().unwrap(),
+            vec!["math", "hard math", r#"a"b"#]
+        );
+    }
+
     #[test]
     fn test_invalid_time() {
         let input = r##"created_at>=2020-01-01T12: and c.stability>=2.0"##;
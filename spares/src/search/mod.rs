@@ -2,9 +2,18 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, fmt, ops::Range};
 
+/// Units of a relative date, such as the `d` in `-7d`. `mo` is checked before the other units since it is the only one with multiple characters.
+const RELATIVE_DATE_UNITS: [&str; 5] = ["mo", "h", "d", "w", "y"];
+/// The field used to reference a saved search, such as `saved:name`.
+const SAVED_SEARCH_FIELD: &str = "saved";
+/// The prefix of fields that search a card's review history, such as `r.count(rating=1)`.
+const REVIEW_FIELD_PREFIX: &str = "r.";
+
 pub mod evaluator;
+pub(crate) mod functions;
 pub mod lexer;
 mod parser;
+pub(crate) mod saved_search;
 
 /// Design note: There is no need to store the token's value here. Value parsing
 /// is done in the parser, and the value is stored in the Abstract Syntax Tree.
@@ -38,6 +47,8 @@ pub enum TokenKind {
     True,
     False,
     Date,
+    RelativeDate,
+    Regex,
     // Operators
     And,
     Or,
@@ -49,9 +60,14 @@ pub enum TokenKind {
     LessThanEqual,
     Colon,
     Tilde,
+    Percent,
     // Grouping
     LeftParen,
     RightParen,
+    // Clauses, such as `sort:c.due desc`. The span includes the clause's arguments.
+    Sort,
+    Limit,
+    Offset,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
@@ -72,6 +88,7 @@ pub enum Op {
     LessThanEqual,
     Colon,
     Tilde,
+    Percent,
     Group, // parens
 }
 
@@ -91,6 +108,7 @@ impl fmt::Display for Op {
                 Op::LessThanEqual => "<=",
                 Op::Colon => ":",
                 Op::Tilde => "~",
+                Op::Percent => "%",
                 Op::Group => "group",
             }
         )
@@ -107,14 +125,17 @@ pub enum Atom<'de> {
     Float(f64),
     Boolean(bool),
     DateTime(DateTime<Utc>),
+    RelativeDate(Cow<'de, str>),
+    Regex(Cow<'de, str>),
     Nil,
 }
 
 impl fmt::Display for Atom<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Atom::Field(s) => write!(f, "{s}"),
+            Atom::Field(s) | Atom::RelativeDate(s) => write!(f, "{s}"),
             Atom::String(s) => write!(f, "\"{s}\""),
+            Atom::Regex(s) => write!(f, "/{s}/"),
             Atom::Integer(n) => write!(f, "{n}"),
             Atom::Float(n) => write!(f, "{n}"),
             Atom::Boolean(b) => write!(f, "{b:?}"),
@@ -124,6 +145,29 @@ impl fmt::Display for Atom<'_> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortClause<'de> {
+    pub field: Cow<'de, str>,
+    pub direction: SortDirection,
+}
+
+/// A parsed search: the expression that results must match, along with how the results are ordered and paginated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query<'de> {
+    pub expression: TokenTree<'de>,
+    /// Results are sorted by the first clause, then ties are broken by the next clause, etc.
+    pub sort: Vec<SortClause<'de>>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenTree<'de> {
     Atom(Atom<'de>),
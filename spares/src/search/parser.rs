@@ -1,7 +1,9 @@
-use crate::search::{Atom, Op, Token, TokenKind, TokenTree, lexer::Lexer};
+use crate::search::{
+    Atom, Op, Query, SortClause, SortDirection, Token, TokenKind, TokenTree, lexer::Lexer,
+};
 use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use miette::{Error, LabeledSpan, WrapErr, miette};
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::Range};
 
 fn parse_date(date_str: &str) -> Result<DateTime<Utc>, Error> {
     // Try parsing as NaiveDate (YYYY-MM-DD)
@@ -20,6 +22,15 @@ fn parse_date(date_str: &str) -> Result<DateTime<Utc>, Error> {
     Err(miette!("Invalid date format: {}", date_str))
 }
 
+/// Removes the backslash from escaped slashes. Other escapes are part of the regular expression.
+fn unescape_regex(s: &str) -> Cow<'_, str> {
+    if s.contains("\\/") {
+        Cow::Owned(s.replace("\\/", "/"))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
 pub struct Parser<'de> {
     whole: &'de str,
     lexer: Lexer<'de>,
@@ -33,10 +44,96 @@ impl<'de> Parser<'de> {
         }
     }
 
+    #[cfg(test)]
     pub fn parse_expression(mut self) -> Result<TokenTree<'de>, Error> {
         self.parse_expression_within(0)
     }
 
+    /// Parses a search along with its `sort:`, `limit:`, and `offset:` clauses. Clauses can appear anywhere outside of brackets, and the expressions around them are joined with `and`.
+    pub fn parse_query(mut self) -> Result<Query<'de>, Error> {
+        let mut expressions = Vec::new();
+        let mut query = Query {
+            expression: TokenTree::Atom(Atom::Nil),
+            sort: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+        loop {
+            let token = match self.lexer.peek() {
+                None => break,
+                Some(Err(_)) => {
+                    return Err(self
+                        .lexer
+                        .next()
+                        .expect("checked Some above")
+                        .expect_err("checked Err above"));
+                }
+                Some(Ok(token)) => token.clone(),
+            };
+            match token.kind {
+                TokenKind::Sort => {
+                    self.lexer.next();
+                    query.sort.push(self.parse_sort_clause(token.span)?);
+                }
+                TokenKind::Limit => {
+                    self.lexer.next();
+                    let limit = self.parse_count_clause(token.span.clone(), "limit:")?;
+                    if query.limit.replace(limit).is_some() {
+                        return Err(self.clause_error(token.span, "Found more than one limit"));
+                    }
+                }
+                TokenKind::Offset => {
+                    self.lexer.next();
+                    let offset = self.parse_count_clause(token.span.clone(), "offset:")?;
+                    if query.offset.replace(offset).is_some() {
+                        return Err(self.clause_error(token.span, "Found more than one offset"));
+                    }
+                }
+                _ => expressions.push(self.parse_expression_within(0)?),
+            }
+        }
+        query.expression = match expressions.len() {
+            0 => TokenTree::Atom(Atom::Nil),
+            1 => expressions.remove(0),
+            _ => TokenTree::Cons(Op::And, expressions),
+        };
+        Ok(query)
+    }
+
+    fn parse_sort_clause(&self, span: Range<usize>) -> Result<SortClause<'de>, Error> {
+        let arguments = self.whole[span.clone()].trim_start_matches("sort:");
+        // The field can contain whitespace, such as `r.count(rating=1, duration>20)`
+        let (field, direction) = match arguments.rsplit_once(char::is_whitespace) {
+            Some((field, "desc")) => (field.trim_end(), SortDirection::Descending),
+            Some((field, "asc")) => (field.trim_end(), SortDirection::Ascending),
+            _ => (arguments, SortDirection::Ascending),
+        };
+        if field.is_empty() {
+            return Err(self.clause_error(span, "Expected a field to sort by"));
+        }
+        Ok(SortClause {
+            field: Cow::Borrowed(field),
+            direction,
+        })
+    }
+
+    fn parse_count_clause(&self, span: Range<usize>, prefix: &str) -> Result<u32, Error> {
+        self.whole[span.clone()]
+            .trim_start_matches(prefix)
+            .parse::<u32>()
+            .map_err(|_| self.clause_error(span, "Expected a non-negative integer"))
+    }
+
+    fn clause_error(&self, span: Range<usize>, message: &str) -> Error {
+        miette::miette! {
+            labels = vec![
+                LabeledSpan::at(span, "this clause"),
+            ],
+            "{message}",
+        }
+        .with_source_code(self.whole.to_string())
+    }
+
     #[allow(clippy::too_many_lines, reason = "main parser method")]
     pub fn parse_expression_within(&mut self, min_bp: u8) -> Result<TokenTree<'de>, Error> {
         let lhs = match self.lexer.next() {
@@ -78,6 +175,29 @@ impl<'de> Parser<'de> {
                 span,
                 ..
             } => TokenTree::Atom(Atom::DateTime(parse_date(&self.whole[span])?)),
+            Token {
+                kind: TokenKind::RelativeDate,
+                span,
+                ..
+            } => TokenTree::Atom(Atom::RelativeDate(Cow::Borrowed(&self.whole[span]))),
+            Token {
+                kind: TokenKind::Regex,
+                span,
+                ..
+            } => {
+                let pattern = unescape_regex(&self.whole[span.clone()]);
+                if let Err(e) = fancy_regex::Regex::new(&pattern) {
+                    return Err(miette::miette! {
+                        labels = vec![
+                            LabeledSpan::at(span, "this regular expression"),
+                        ],
+                        help = e.to_string(),
+                        "Invalid regular expression",
+                    }
+                    .with_source_code(self.whole.to_string()));
+                }
+                TokenTree::Atom(Atom::Regex(pattern))
+            }
 
             // groups
             Token {
@@ -144,7 +264,8 @@ impl<'de> Parser<'de> {
             }
             let op = match op.map(|res| res.as_ref().expect("handled Err above")) {
                 Some(Token {
-                    kind: TokenKind::RightParen,
+                    kind:
+                        TokenKind::RightParen | TokenKind::Sort | TokenKind::Limit | TokenKind::Offset,
                     ..
                 })
                 | None => break,
@@ -188,6 +309,10 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::Colon,
                     ..
                 }) => Op::Colon,
+                Some(Token {
+                    kind: TokenKind::Percent,
+                    ..
+                }) => Op::Percent,
                 Some(token) => {
                     return Err(miette::miette! {
                         labels = vec![
@@ -262,7 +387,7 @@ fn infix_binding_power(op: Op) -> Option<(u8, u8)> {
     let res = match op {
         Op::And | Op::Or => (3, 4),
         Op::LessThan | Op::LessThanEqual | Op::GreaterThan | Op::GreaterThanEqual => (6, 5),
-        Op::Equal | Op::Tilde => (11, 10),
+        Op::Equal | Op::Tilde | Op::Percent => (11, 10),
         Op::Colon => (9, 8),
         // Op::Field => (16, 15),
         _ => return None,
@@ -400,4 +525,68 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_parser_query_clauses() {
+        let input = "tag=math sort:c.difficulty desc limit:20 sort:id offset:5";
+        let parser = Parser::new(input);
+        let query_res = parser.parse_query();
+        dbg!(&query_res);
+        let query = query_res.unwrap();
+        assert_eq!(
+            query.expression,
+            TokenTree::Cons(
+                Op::Equal,
+                vec![
+                    TokenTree::Atom(Atom::Field(Cow::Borrowed("tag"))),
+                    TokenTree::Atom(Atom::String(Cow::Borrowed("math"))),
+                ]
+            )
+        );
+        assert_eq!(
+            query.sort,
+            vec![
+                SortClause {
+                    field: Cow::Borrowed("c.difficulty"),
+                    direction: SortDirection::Descending,
+                },
+                SortClause {
+                    field: Cow::Borrowed("id"),
+                    direction: SortDirection::Ascending,
+                },
+            ]
+        );
+        assert_eq!(query.limit, Some(20));
+        assert_eq!(query.offset, Some(5));
+
+        // Fields with arguments can contain whitespace
+        let query = Parser::new("sort:r.count(rating=1, duration>5) desc")
+            .parse_query()
+            .unwrap();
+        assert_eq!(
+            query.sort,
+            vec![SortClause {
+                field: Cow::Borrowed("r.count(rating=1, duration>5)"),
+                direction: SortDirection::Descending,
+            }]
+        );
+
+        // Clauses can be used without an expression
+        let query = Parser::new("limit:10").parse_query().unwrap();
+        assert_eq!(query.expression, TokenTree::Atom(Atom::Nil));
+        assert_eq!(query.limit, Some(10));
+
+        let inputs = [
+            "limit:abc",
+            "limit:1 limit:2",
+            "offset:",
+            "sort:",
+            "tag=math and limit:1",
+        ];
+        for input in inputs {
+            let query_res = Parser::new(input).parse_query();
+            dbg!(&query_res);
+            assert!(query_res.is_err());
+        }
+    }
 }
@@ -0,0 +1,139 @@
+use crate::search::{TokenKind, lexer::Lexer};
+use miette::{Error, miette};
+use std::{borrow::Cow, collections::HashMap};
+
+/// Replaces each `saved:name` reference with the saved search's query, in brackets. Saved searches can reference other saved searches, as long as no saved search ends up referencing itself.
+///
+/// Only the filter of a referenced saved search is used, so its `sort:`, `limit:`, and `offset:` clauses are ignored.
+pub(crate) fn expand_saved_searches<'a>(
+    input: &'a str,
+    saved_searches: &HashMap<String, String>,
+) -> Result<Cow<'a, str>, Error> {
+    expand(input, saved_searches, &mut Vec::new())
+}
+
+/// `expanding` contains the saved searches that are currently being expanded, starting from the outermost one.
+fn expand<'a>(
+    input: &'a str,
+    saved_searches: &HashMap<String, String>,
+    expanding: &mut Vec<String>,
+) -> Result<Cow<'a, str>, Error> {
+    let references = Lexer::new(input).extract_saved_search_references()?;
+    if references.is_empty() {
+        return Ok(Cow::Borrowed(input));
+    }
+    let mut result = String::with_capacity(input.len());
+    let mut last_end = 0;
+    for (span, name) in references {
+        if expanding.contains(&name) {
+            let cycle = expanding
+                .iter()
+                .skip_while(|expanding_name| **expanding_name != name)
+                .chain(std::iter::once(&name))
+                .map(|name| format!("`{name}`"))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(miette!(
+                "Saved search `{}` references itself: {}",
+                name,
+                cycle
+            ));
+        }
+        let query = saved_searches
+            .get(&name)
+            .ok_or_else(|| miette!("Unknown saved search `{}`", name))?;
+        let filter = remove_clauses(query)?;
+        if filter.trim().is_empty() {
+            return Err(miette!("Saved search `{}` does not have a filter", name));
+        }
+        expanding.push(name);
+        let expanded_filter = expand(&filter, saved_searches, expanding)?;
+        expanding.pop();
+        result.push_str(&input[last_end..span.start]);
+        result.push('(');
+        result.push_str(&expanded_filter);
+        result.push(')');
+        last_end = span.end;
+    }
+    result.push_str(&input[last_end..]);
+    Ok(Cow::Owned(result))
+}
+
+/// Removes the `sort:`, `limit:`, and `offset:` clauses from a query.
+fn remove_clauses(query: &str) -> Result<Cow<'_, str>, Error> {
+    let mut clause_spans = Vec::new();
+    for token in Lexer::new(query) {
+        let token = token?;
+        if matches!(
+            token.kind,
+            TokenKind::Sort | TokenKind::Limit | TokenKind::Offset
+        ) {
+            clause_spans.push(token.span);
+        }
+    }
+    if clause_spans.is_empty() {
+        return Ok(Cow::Borrowed(query));
+    }
+    let mut result = String::with_capacity(query.len());
+    let mut last_end = 0;
+    for span in clause_spans {
+        result.push_str(&query[last_end..span.start]);
+        last_end = span.end;
+    }
+    result.push_str(&query[last_end..]);
+    Ok(Cow::Owned(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_searches(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(name, query)| ((*name).to_string(), (*query).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_saved_searches() {
+        let saved_searches = saved_searches(&[
+            ("math", "tag=math or tag=physics"),
+            (
+                "hard math",
+                "saved:math c.difficulty>=8 sort:c.difficulty desc limit:5",
+            ),
+        ]);
+        assert_eq!(
+            expand_saved_searches("dog", &saved_searches).unwrap(),
+            "dog"
+        );
+        assert_eq!(
+            expand_saved_searches("saved:math and dog", &saved_searches).unwrap(),
+            "(tag=math or tag=physics) and dog"
+        );
+        assert_eq!(
+            expand_saved_searches(r#"-c.suspended saved:"hard math" limit:1"#, &saved_searches)
+                .unwrap()
+                .trim(),
+            "-c.suspended ((tag=math or tag=physics) c.difficulty>=8  ) limit:1"
+        );
+    }
+
+    #[test]
+    fn test_expand_saved_searches_error() {
+        let saved_searches = saved_searches(&[
+            ("a", "dog saved:b"),
+            ("b", "saved:c"),
+            ("c", "cat or saved:a"),
+            ("empty", "limit:10"),
+        ]);
+        let error = expand_saved_searches("saved:a", &saved_searches).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Saved search `a` references itself: `a` -> `b` -> `c` -> `a`"
+        );
+        assert!(expand_saved_searches("saved:d", &saved_searches).is_err());
+        assert!(expand_saved_searches("saved:empty", &saved_searches).is_err());
+    }
+}
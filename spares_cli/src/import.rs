@@ -6,7 +6,8 @@ use spares::adapters::SrsAdapter;
 use spares::parsers::{NoteSettings, Parseable, get_all_parsers, get_notes};
 use spares::{Error, LibraryError, ParserErrorKind};
 use std::fs::read_to_string;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 #[derive(Args, Debug)]
 pub struct ImportArgs {
@@ -25,7 +26,7 @@ pub struct ImportArgs {
     #[arg(short, long, default_value_t = false)]
     pub run: bool,
 
-    /// Input file(s)
+    /// Input file(s). Directories are searched recursively, skipping hidden files and files without notes.
     #[arg(required = true, value_delimiter = ' ', num_args = 1..)]
     pub files: Vec<PathBuf>,
 }
@@ -107,17 +108,55 @@ pub async fn import_from_files(
     run: bool,
     quiet: bool,
 ) -> Result<(), Error> {
+    let files = files
+        .into_iter()
+        .flat_map(|path| {
+            if path.is_dir() {
+                get_directory_files(&path)
+                    .into_iter()
+                    .map(|file| (file, true))
+                    .collect::<Vec<_>>()
+            } else {
+                vec![(path, false)]
+            }
+        })
+        .collect::<Vec<_>>();
     let count = files.len();
-    for file in files
+    for (file, from_directory) in files
         .into_iter()
         .progress_count(u64::try_from(count).unwrap())
     {
-        import_from_file(adapter, parser, to_parser_opt, &file, run, quiet).await?;
+        import_from_file(
+            adapter,
+            parser,
+            to_parser_opt,
+            &file,
+            run,
+            quiet,
+            from_directory,
+        )
+        .await?;
     }
     Ok(())
 }
 
+fn get_directory_files(directory: &Path) -> Vec<PathBuf> {
+    let is_hidden = |entry: &walkdir::DirEntry| {
+        entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.')
+    };
+    WalkDir::new(directory)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !is_hidden(entry))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.path().to_path_buf())
+        .collect()
+}
+
 /// If `parser.is_none()`, then this function will attempt to automatically determine the parser.
+///
+/// If `skip_without_notes` is true, files that can not be read as text or do not contain any notes are skipped.
 pub async fn import_from_file(
     adapter: &mut dyn SrsAdapter,
     parser_opt: Option<&dyn Parseable>,
@@ -125,15 +164,20 @@ pub async fn import_from_file(
     file_path: &PathBuf,
     run: bool,
     quiet: bool,
+    skip_without_notes: bool,
 ) -> Result<(), Error> {
-    if !run {
-        println!("{}\n", "DRY RUN".black().on_bright_yellow());
-    }
-
-    let file_contents = read_to_string(file_path).map_err(|e| Error::Io {
-        description: format!("Failed to read {}", &file_path.display()),
-        source: e,
-    })?;
+    let file_contents = match read_to_string(file_path) {
+        Ok(file_contents) => file_contents,
+        Err(e) if skip_without_notes && e.kind() == std::io::ErrorKind::InvalidData => {
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(Error::Io {
+                description: format!("Failed to read {}", &file_path.display()),
+                source: e,
+            });
+        }
+    };
 
     let all_parsers = get_all_parsers()
         .into_iter()
@@ -143,8 +187,22 @@ pub async fn import_from_file(
     let parsers_to_try = if let Some(parser) = parser_opt {
         vec![parser]
     } else {
-        all_parsers.iter().map(|x| x.as_ref()).collect::<Vec<_>>()
+        all_parsers
+            .iter()
+            .filter(|x| x.matches_file(file_path))
+            .map(|x| x.as_ref())
+            .collect::<Vec<_>>()
     };
+    // Parsers can adapt to the file, such as by using its comment syntax.
+    let file_parsers = parsers_to_try
+        .iter()
+        .map(|parser| parser.for_file(file_path))
+        .collect::<Vec<_>>();
+    let parsers_to_try = parsers_to_try
+        .into_iter()
+        .zip(&file_parsers)
+        .map(|(parser, file_parser)| file_parser.as_deref().unwrap_or(parser))
+        .collect::<Vec<_>>();
 
     let mut max_parser: Option<&dyn Parseable> = None;
     let mut max_parser_all_notes = Vec::new();
@@ -173,6 +231,12 @@ pub async fn import_from_file(
             max_parser_all_notes = all_notes;
         }
     }
+    if skip_without_notes && max_notes_count == 0 {
+        return Ok(());
+    }
+    if !run {
+        println!("{}\n", "DRY RUN".black().on_bright_yellow());
+    }
     if parsers_to_try.len() > 1 && max_notes_count == 0 {
         return Err(Error::Library(LibraryError::Parser(
             ParserErrorKind::FailedToGuess("All parsers parsed 0 notes.".to_string()),
@@ -25,14 +25,21 @@ use spares::{
         get_all_parsers, get_note_info_from_filepath, get_output_raw_dir,
     },
     schema::{
-        card::{CardResponse, CardsSelector, SpecialStateUpdate, UpdateCardRequest},
+        card::{
+            CardResponse, CardsSelector, LeechEventResponse, SpecialStateUpdate, UpdateCardRequest,
+        },
         note::{
-            CreateNoteRequest, CreateNotesRequest, GenerateFilesNoteIds, NoteResponse,
-            NotesResponse, NotesSelector, RenderNotesRequest, SearchKeywordRequest,
+            CreateNoteRequest, CreateNotesRequest, ExplainSearchResponse, GenerateFilesNoteIds,
+            NoteResponse, NotesResponse, NotesSelector, RenderNotesRequest, SearchKeywordRequest,
             SearchNotesRequest, SearchNotesResponse, UpdateNotesRequest,
         },
         parser::{CreateParserRequest, ParserResponse, UpdateParserRequest},
-        review::{StatisticsRequest, StatisticsResponse},
+        review::{
+            ForecastRequest, ForecastResponse, SimulateRequest, SimulateResponse,
+            StatisticsRequest, StatisticsResponse, StudyAction, SubmitStudyActionRequest,
+            SubmitStudyActionResponse,
+        },
+        saved_search::{CreateSavedSearchRequest, SavedSearchResponse, UpdateSavedSearchRequest},
         tag::{CreateTagRequest, TagResponse, UpdateTagRequest},
     },
     search::QueryReturnItemType,
@@ -72,6 +79,10 @@ enum Commands {
     /// Studying statistics
     #[command(alias = "stats")]
     Statistics(StatisticsArgs),
+    /// Fit the scheduler's parameters to your review history
+    Optimize(OptimizeArgs),
+    /// Compare the workload and knowledge retained for different desired retentions
+    Simulate(SimulateArgs),
     /// Search for notes or cards
     Search(SearchArgs),
     /// Import notes data from file
@@ -141,6 +152,12 @@ enum AddCommands {
         #[arg(short, long, default_value_t = DEFAULT_TAG_AUTO_DELETE)]
         auto_delete: bool,
     },
+    SavedSearch {
+        #[arg(short, long)]
+        name: String,
+        #[arg(short, long)]
+        query: String,
+    },
     Note {
         #[arg(short, long)]
         data: String,
@@ -179,6 +196,13 @@ enum EditCommands {
         #[arg(short, long, default_value_t = false)]
         rebuild: bool,
     },
+    SavedSearch {
+        id: i64,
+        #[arg(short, long)]
+        name: Option<String>,
+        #[arg(short, long)]
+        query: Option<String>,
+    },
     Note {
         #[command(flatten)]
         selector: NotesSelectorLocal,
@@ -245,6 +269,7 @@ struct CardsSelectorLocal {
 enum DeleteCommands {
     Parser { id: i64 },
     Tag { id: i64 },
+    SavedSearch { id: i64 },
     Note { id: i64 },
 }
 
@@ -259,6 +284,12 @@ enum GetCommands {
         #[arg(short, long, conflicts_with = "id")]
         name: Option<String>,
     },
+    SavedSearch {
+        #[arg(short, long)]
+        id: Option<i64>,
+        #[arg(short, long, conflicts_with = "id")]
+        name: Option<String>,
+    },
     Note {
         id: i64,
         // /// Open in editor
@@ -268,10 +299,13 @@ enum GetCommands {
     Card {
         #[arg(short, long)]
         id: Option<i64>,
-        #[arg(short, long, conflicts_with_all = ["id", "leeches"])]
+        #[arg(short, long, conflicts_with_all = ["id", "leeches", "recent_leeches"])]
         note_id: Option<i64>,
-        #[arg(short, long, conflicts_with_all = ["id", "note_id"])]
+        #[arg(short, long, conflicts_with_all = ["id", "note_id", "recent_leeches"])]
         leeches: bool,
+        /// Show the cards that most recently became leeches
+        #[arg(short, long, conflicts_with_all = ["id", "note_id", "leeches"])]
+        recent_leeches: bool,
     },
 }
 
@@ -299,6 +333,12 @@ enum ListCommands {
         #[arg(short, long, default_value = "full")]
         output: ListTagOutput,
     },
+    SavedSearch {
+        #[arg(short, long)]
+        page: Option<usize>,
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
     Note {
         #[arg(short, long)]
         page: Option<usize>,
@@ -327,13 +367,55 @@ struct GenerateArgs {
 }
 
 #[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct StatisticsArgs {
+    #[command(subcommand)]
+    command: Option<StatisticsCommands>,
     #[arg(short, long, default_value = "fsrs")]
     scheduler_name: String,
     #[arg(short, long, default_value_t = get_current_utc_datetime())]
     date: DateTime<Utc>,
 }
 
+#[derive(Debug, Subcommand)]
+enum StatisticsCommands {
+    /// Forecast the number of reviews and the study time of the upcoming days
+    Forecast {
+        #[arg(short, long, default_value = "fsrs")]
+        scheduler_name: String,
+        /// Number of days to forecast
+        #[arg(short, long, default_value_t = 30)]
+        days: u32,
+        /// Number of new cards studied each day. Defaults to the daily limit in the config.
+        #[arg(short, long)]
+        new_cards_per_day: Option<u32>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct OptimizeArgs {
+    #[arg(short, long, default_value = "fsrs")]
+    scheduler_name: String,
+    /// Reschedule all cards with the new parameters
+    #[arg(short, long, default_value_t = false)]
+    reschedule: bool,
+}
+
+#[derive(Args, Debug)]
+struct SimulateArgs {
+    #[arg(short, long, default_value = "fsrs")]
+    scheduler_name: String,
+    /// Number of days to simulate
+    #[arg(short, long, default_value_t = 365)]
+    days: u32,
+    /// Number of new cards studied each day. Defaults to the daily limit in the config.
+    #[arg(short, long)]
+    new_cards_per_day: Option<u32>,
+    /// Comma separated desired retentions to compare
+    #[arg(short = 'r', long, value_delimiter = ',')]
+    desired_retentions: Option<Vec<f64>>,
+}
+
 fn get_current_utc_datetime() -> DateTime<Utc> {
     let local_time = Local::now();
     local_time.with_timezone(&Utc)
@@ -368,10 +450,40 @@ struct SearchArgs {
     // This option does not work if `matches!(search_mode, SearchMode::Keyword)`. There is no easy way to get around this since clap does not support default subcommands.
     #[arg(long, default_value = "raw-filepath")]
     output_format: OutputFormat,
+    /// Explain how the query is run instead of returning its results
+    #[arg(long, default_value_t = false)]
+    explain: bool,
     // Positional argument
     query: String,
 }
 
+fn print_search_explanation(query: &str, explanation: &ExplainSearchResponse) {
+    println!("Tree: {}", explanation.tree);
+    println!("SQL: {}", explanation.sql);
+    println!("Binds: {}", Value::from(explanation.binds.clone()));
+    println!("Count: {}", explanation.count);
+    if !explanation.clauses.is_empty() {
+        println!("Clauses:");
+        for clause in &explanation.clauses {
+            println!("  {:>6}  {}", clause.count, clause.clause);
+        }
+    }
+    if !explanation.warnings.is_empty() {
+        println!("Warnings:");
+        for warning in &explanation.warnings {
+            let prefix_width = query[..warning.span.start].chars().count();
+            let span_width = query[warning.span.clone()].chars().count().max(1);
+            println!("  {}", query);
+            println!(
+                "  {}{} {}",
+                " ".repeat(prefix_width),
+                "^".repeat(span_width),
+                warning.message
+            );
+        }
+    }
+}
+
 async fn list_parsers(
     page: Option<usize>,
     limit: Option<usize>,
@@ -487,6 +599,26 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                 let response: TagResponse = response.json().await.map_err(|e| miette!("{}", e))?;
                 println!("{:#?}", &response);
             }
+            AddCommands::SavedSearch { name, query } => {
+                let request = CreateSavedSearchRequest { name, query };
+                let url = format!("{}/api/saved_searches", base_url);
+                let response = client
+                    .post(url)
+                    .json(&request)
+                    .send()
+                    .await
+                    .map_err(|e| miette!("{}", e))?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    let response_json: Value =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    let message = response_json.get("message");
+                    return Err(miette!(message.unwrap().to_string()));
+                }
+                let response: SavedSearchResponse =
+                    response.json().await.map_err(|e| miette!("{}", e))?;
+                println!("{:#?}", &response);
+            }
             AddCommands::Note {
                 data,
                 parser_id,
@@ -597,6 +729,26 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     println!("Done");
                 }
             }
+            EditCommands::SavedSearch { id, name, query } => {
+                let request = UpdateSavedSearchRequest { name, query };
+                let url = format!("{}/api/saved_searches/{}", base_url, id);
+                let response = client
+                    .patch(url)
+                    .json(&request)
+                    .send()
+                    .await
+                    .map_err(|e| miette!("{}", e))?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    let response_json: Value =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    let message = response_json.get("message");
+                    return Err(miette!(message.unwrap().to_string()));
+                }
+                let response: SavedSearchResponse =
+                    response.json().await.map_err(|e| miette!("{}", e))?;
+                println!("{:#?}", &response);
+            }
             EditCommands::Note {
                 selector,
                 data,
@@ -732,6 +884,22 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                 }
                 println!("Done");
             }
+            DeleteCommands::SavedSearch { id } => {
+                let url = format!("{}/api/saved_searches/{}", base_url, id);
+                let response = client
+                    .delete(url)
+                    .send()
+                    .await
+                    .map_err(|e| miette!("{}", e))?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    let response_json: Value =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    let message = response_json.get("message");
+                    return Err(miette!(message.unwrap().to_string()));
+                }
+                println!("Done");
+            }
             DeleteCommands::Note { id } => {
                 let url = format!("{}/api/notes/{}", base_url, id);
                 let response = client
@@ -792,6 +960,30 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     response.json().await.map_err(|e| miette!("{}", e))?;
                 println!("{:#?}", &tag_response);
             }
+            GetCommands::SavedSearch { id, name } => {
+                let url = if let Some(id) = id {
+                    format!("{}/api/saved_searches/{}", base_url, id)
+                } else if let Some(name) = name {
+                    format!("{}/api/saved_searches/name/{}", base_url, name)
+                } else {
+                    unreachable!("by clap conflicts_with");
+                };
+                let response = client
+                    .get(&url)
+                    .send()
+                    .await
+                    .map_err(|e| miette!("{}", e))?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    let response_json: Value =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    let message = response_json.get("message");
+                    return Err(miette!(message.unwrap().to_string()));
+                }
+                let saved_search_response: SavedSearchResponse =
+                    response.json().await.map_err(|e| miette!("{}", e))?;
+                println!("{:#?}", &saved_search_response);
+            }
             GetCommands::Note { id } => {
                 let url = format!("{}/api/notes/{}", base_url, id);
                 let response = client
@@ -814,6 +1006,7 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                 id,
                 note_id,
                 leeches,
+                recent_leeches,
             } => {
                 let url = if let Some(id) = id {
                     format!("{}/api/cards/{}", base_url, id)
@@ -821,6 +1014,8 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     format!("{}/api/cards/note_id/{}", base_url, note_id)
                 } else if leeches {
                     format!("{}/api/cards/leeches", base_url)
+                } else if recent_leeches {
+                    format!("{}/api/cards/leeches/recent", base_url)
                 } else {
                     unreachable!()
                 };
@@ -844,6 +1039,10 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     let card_responses: Vec<CardResponse> =
                         response.json().await.map_err(|e| miette!("{}", e))?;
                     println!("{:#?}", &card_responses);
+                } else if recent_leeches {
+                    let leech_event_responses: Vec<LeechEventResponse> =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    println!("{:#?}", &leech_event_responses);
                 } else {
                     unreachable!()
                 }
@@ -928,6 +1127,38 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     }
                 }
             }
+            ListCommands::SavedSearch { page, limit } => {
+                let url = format!("{}/api/saved_searches", base_url);
+                let mut queries: Vec<(&str, String)> = Vec::new();
+                if let Some(page) = page {
+                    queries.push(("page", page.to_string()));
+                }
+                if let Some(limit) = limit {
+                    queries.push(("limit", limit.to_string()));
+                }
+                let req_url = client
+                    .get(url)
+                    .query(&queries)
+                    .build()
+                    .unwrap()
+                    .url()
+                    .to_string();
+                let response = client
+                    .get(&req_url)
+                    .send()
+                    .await
+                    .map_err(|e| miette!("{}", e))?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    let response_json: Value =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    let message = response_json.get("message");
+                    return Err(miette!(message.unwrap().to_string()));
+                }
+                let saved_search_responses: Vec<SavedSearchResponse> =
+                    response.json().await.map_err(|e| miette!("{}", e))?;
+                println!("{:#?}", &saved_search_responses);
+            }
             ListCommands::Note { page, limit, graph } => {
                 let url = format!("{}/api/notes", base_url);
                 let mut queries: Vec<(&str, String)> = Vec::new();
@@ -1005,6 +1236,64 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                 .map_err(|e| miette!("{}", e))?;
         }
         Commands::Statistics(StatisticsArgs {
+            command:
+                Some(StatisticsCommands::Forecast {
+                    scheduler_name,
+                    days,
+                    new_cards_per_day,
+                }),
+            ..
+        }) => {
+            let request = ForecastRequest {
+                scheduler_name,
+                days,
+                new_cards_per_day,
+            };
+            let url = format!("{}/api/review/forecast", base_url);
+            let response = client
+                .post(url)
+                .json(&request)
+                .send()
+                .await
+                .map_err(|e| miette!("{}", e))?;
+            let status = response.status();
+            if status != StatusCode::OK {
+                let response_json: Value = response.json().await.map_err(|e| miette!("{}", e))?;
+                let message = response_json.get("message");
+                return Err(miette!(message.unwrap().to_string()));
+            }
+            let response: ForecastResponse = response.json().await.map_err(|e| miette!("{}", e))?;
+            println!(
+                "{:>10} | {:>7} | {:>17}",
+                "Date", "Reviews", "Study time (mins)"
+            );
+            for day in &response.days {
+                println!(
+                    "{:>10} | {:>7.1} | {:>17}",
+                    day.date,
+                    day.review_count,
+                    day.study_time.num_minutes()
+                );
+            }
+            let total_reviews = response
+                .days
+                .iter()
+                .map(|day| day.review_count)
+                .sum::<f64>();
+            let total_study_time = response
+                .days
+                .iter()
+                .fold(chrono::Duration::zero(), |total, day| {
+                    total + day.study_time
+                });
+            println!(
+                "Total: {:.0} reviews, {} mins",
+                total_reviews,
+                total_study_time.num_minutes()
+            );
+        }
+        Commands::Statistics(StatisticsArgs {
+            command: None,
             scheduler_name,
             date,
         }) => {
@@ -1029,11 +1318,99 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                 response.json().await.map_err(|e| miette!("{}", e))?;
             println!("{:#?}", &response);
         }
+        Commands::Optimize(OptimizeArgs {
+            scheduler_name,
+            reschedule,
+        }) => {
+            let mut actions = vec![StudyAction::OptimizeParameters];
+            if reschedule {
+                actions.push(StudyAction::Reschedule);
+            }
+            let url = format!("{}/api/review/submit", base_url);
+            for action in actions {
+                let request = SubmitStudyActionRequest {
+                    scheduler_name: scheduler_name.clone(),
+                    action,
+                };
+                let response = client
+                    .post(&url)
+                    .json(&request)
+                    .send()
+                    .await
+                    .map_err(|e| miette!("{}", e))?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    let response_json: Value =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    let message = response_json.get("message");
+                    return Err(miette!(message.unwrap().to_string()));
+                }
+                let response: SubmitStudyActionResponse =
+                    response.json().await.map_err(|e| miette!("{}", e))?;
+                if let Some(message) = response.message {
+                    println!("{}", message);
+                }
+            }
+            println!("Done");
+        }
+        Commands::Simulate(SimulateArgs {
+            scheduler_name,
+            days,
+            new_cards_per_day,
+            desired_retentions,
+        }) => {
+            let request = SimulateRequest {
+                days,
+                new_cards_per_day,
+                desired_retentions,
+            };
+            let url = format!("{}/api/scheduler/{}/simulate", base_url, scheduler_name);
+            let response = client
+                .post(url)
+                .json(&request)
+                .send()
+                .await
+                .map_err(|e| miette!("{}", e))?;
+            let status = response.status();
+            if status != StatusCode::OK {
+                let response_json: Value = response.json().await.map_err(|e| miette!("{}", e))?;
+                let message = response_json.get("message");
+                return Err(miette!(message.unwrap().to_string()));
+            }
+            let response: SimulateResponse = response.json().await.map_err(|e| miette!("{}", e))?;
+            println!(
+                "{:>17} | {:>11} | {:>22} | {:>18}",
+                "Desired retention", "Reviews/day", "Study time/day (mins)", "Knowledge retained"
+            );
+            let days = days.max(1);
+            for result in &response.results {
+                let reviews_per_day = result.review_counts.iter().sum::<f64>() / f64::from(days);
+                let study_time_per_day = result
+                    .study_times
+                    .iter()
+                    .fold(chrono::Duration::zero(), |total, study_time| {
+                        total + *study_time
+                    })
+                    / i32::try_from(days).unwrap_or(i32::MAX);
+                println!(
+                    "{:>17.2} | {:>11.1} | {:>22} | {:>18.0}",
+                    result.desired_retention,
+                    reviews_per_day,
+                    study_time_per_day.num_minutes(),
+                    result.knowledge_retained
+                );
+            }
+            println!(
+                "Recommended desired retention: {:.2}",
+                response.recommended_retention
+            );
+        }
         Commands::Search(SearchArgs {
             search_mode,
             query,
             output_type,
             output_format,
+            explain,
         }) => match search_mode {
             SearchMode::Query => {
                 let return_item_type = match output_type {
@@ -1041,9 +1418,29 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     OutputItemType::Notes => QueryReturnItemType::Notes,
                 };
                 let request = SearchNotesRequest {
-                    query,
+                    query: query.clone(),
                     output_type: return_item_type,
                 };
+                if explain {
+                    let url = format!("{}/api/notes/search/explain", base_url);
+                    let response = client
+                        .post(url)
+                        .json(&request)
+                        .send()
+                        .await
+                        .map_err(|e| miette!("{}", e))?;
+                    let status = response.status();
+                    if status != StatusCode::OK {
+                        let response_json: Value =
+                            response.json().await.map_err(|e| miette!("{}", e))?;
+                        let message = response_json.get("message");
+                        return Err(miette!(message.unwrap().to_string()));
+                    }
+                    let response: ExplainSearchResponse =
+                        response.json().await.map_err(|e| miette!("{}", e))?;
+                    print_search_explanation(&query, &response);
+                    return Ok(());
+                }
                 let url = format!("{}/api/notes/search", base_url);
                 let response = client
                     .post(url)
@@ -1062,7 +1459,7 @@ async fn process_args(args: Cli) -> Result<(), Error> {
                     response.json().await.map_err(|e| miette!("{}", e))?;
                 match response {
                     SearchNotesResponse::Notes(note_responses) => {
-                        for (note_response, parser_name) in note_responses {
+                        for (note_response, parser_name, _snippet) in note_responses {
                             let parser = find_parser(parser_name.as_str(), &get_all_parsers())?;
                             match output_format {
                                 OutputFormat::RawFilepath => {
@@ -15,6 +15,7 @@ use strum_macros::{Display, EnumString};
 use utils::{
     bury_card, close_rendered_file, get_scheduler_ratings, open_rendered_file,
     print_recall_duration, print_summary, submit_rating, suspend_cards, suspend_note, tag_note,
+    undo,
 };
 
 mod utils;
@@ -59,17 +60,18 @@ enum ReviewAction {
     SuspendNote,
     #[strum(serialize = "Tag to modify later")]
     TagNote,
-    // Undo,
+    Undo,
     Bury,
     Exit,
 }
 
-// NoteId is for each action
-// enum UndoReviewAction {
-//     Rate,
-//     Suspend,
-//     TagNote { tag_name: String },
-// }
+/// Actions that can be undone, in the order they were done during this session.
+enum UndoReviewAction {
+    Rate,
+    /// Bury or suspend
+    Skip,
+    TagNote,
+}
 
 async fn get_review_card(
     filter_args: &FilterArgs,
@@ -177,7 +179,7 @@ pub async fn review_cards(
     let (mut review_card_response, mut card_front_rendered_child) = review_card_opt.unwrap();
     let config = read_external_config().map_err(|e| format!("{}", e))?;
     let flagged_tag_name = config.flagged_tag_name;
-    // let mut action_history = Vec::new();
+    let mut action_history = Vec::new();
     loop {
         if advance_review_card {
             println!();
@@ -227,6 +229,7 @@ pub async fn review_cards(
                 close_rendered_file(&mut card_back_rendered_child.take().unwrap())?;
 
                 reviewed_cards_count += 1;
+                action_history.push(UndoReviewAction::Rate);
                 submit_rating(
                     recall_duration.unwrap(),
                     scheduler_name,
@@ -300,13 +303,26 @@ pub async fn review_cards(
                         .await?;
                     }
                     ReviewAction::SuspendCard => {
-                        suspend_cards(&[review_card_response.card_id], base_url, client).await?;
+                        suspend_cards(
+                            scheduler_name,
+                            &[review_card_response.card_id],
+                            base_url,
+                            client,
+                        )
+                        .await?;
                     }
                     ReviewAction::SuspendNote => {
-                        suspend_note(review_card_response.note_id, base_url, client).await?;
+                        suspend_note(
+                            scheduler_name,
+                            review_card_response.note_id,
+                            base_url,
+                            client,
+                        )
+                        .await?;
                     }
                     _ => unreachable!(),
                 }
+                action_history.push(UndoReviewAction::Skip);
                 if card_flipped {
                     // Close card back
                     close_rendered_file(&mut card_back_rendered_child.take().unwrap())?;
@@ -320,29 +336,43 @@ pub async fn review_cards(
                 advance_review_card = true;
             }
             ReviewAction::TagNote => {
-                // action_history.push()
                 tag_note(
+                    scheduler_name,
                     review_card_response.note_id,
                     &flagged_tag_name,
                     base_url,
                     client,
                 )
                 .await?;
+                action_history.push(UndoReviewAction::TagNote);
+            }
+            ReviewAction::Undo => {
+                let Some(undo_action) = action_history.pop() else {
+                    println!("Nothing to undo.");
+                    continue;
+                };
+                undo(base_url, client).await?;
+                match undo_action {
+                    UndoReviewAction::Rate | UndoReviewAction::Skip => {
+                        if matches!(undo_action, UndoReviewAction::Rate) {
+                            reviewed_cards_count -= 1;
+                        }
+                        if card_flipped {
+                            // Close card back
+                            close_rendered_file(&mut card_back_rendered_child.take().unwrap())?;
+                        } else {
+                            // Close card front
+                            close_rendered_file(&mut card_front_rendered_child)?;
+                        }
+                        card_flipped = false;
+
+                        // Get review card, which should be the card that was restored
+                        advance_review_card = true;
+                    }
+                    // The current card is still shown
+                    UndoReviewAction::TagNote => {}
+                }
             }
-            // ReviewAction::Undo => {
-            //     if card_flipped {
-            //         // Close card back
-            //         close_rendered_file(&mut card_back_rendered_child.take().unwrap())?;
-            //     } else {
-            //         // Close card front
-            //         close_rendered_file(&mut card_front_rendered_child)?;
-            //     }
-            //     // Remove previously submitted rating
-            //     // TODO
-            //
-            //     // Get review card (which should be the same as the previous card)
-            //     // TODO
-            // }
             ReviewAction::Exit => {
                 close_rendered_file(&mut card_front_rendered_child)?;
                 if let Some(mut child) = card_back_rendered_child {
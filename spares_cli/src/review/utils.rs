@@ -2,8 +2,7 @@ use super::ReviewAction;
 use reqwest::{Client, StatusCode};
 use serde_json::Value;
 use spares::model::{CardId, NoteId, RatingId, TagId};
-use spares::schema::card::{CardResponse, CardsSelector, SpecialStateUpdate, UpdateCardRequest};
-use spares::schema::note::{NotesSelector, UpdateNotesRequest};
+use spares::schema::card::CardResponse;
 use spares::schema::review::{Rating, RatingSubmission, StudyAction, SubmitStudyActionRequest};
 use std::path::Path;
 use std::process::{Child, Command, Stdio};
@@ -58,23 +57,22 @@ pub async fn get_scheduler_ratings(
 }
 
 pub async fn tag_note(
+    scheduler_name: &str,
     note_id: NoteId,
     tag_name: &str,
     base_url: &str,
     client: &Client,
 ) -> Result<(), String> {
-    let request = UpdateNotesRequest {
-        selector: NotesSelector::Ids(vec![note_id]),
-        data: None,
-        parser_id: None,
-        keywords: None,
-        tags_to_remove: None,
-        tags_to_add: Some(vec![tag_name.to_string()]),
-        custom_data: None,
+    let request = SubmitStudyActionRequest {
+        scheduler_name: scheduler_name.to_string(),
+        action: StudyAction::TagNote {
+            note_id,
+            tag_name: tag_name.to_string(),
+        },
     };
-    let url = format!("{}/api/notes", base_url);
+    let url = format!("{}/api/review/submit", base_url);
     let response = client
-        .patch(&url)
+        .post(url)
         .json(&request)
         .send()
         .await
@@ -116,7 +114,12 @@ pub async fn bury_card(
     Ok(())
 }
 
-pub async fn suspend_note(note_id: NoteId, base_url: &str, client: &Client) -> Result<(), String> {
+pub async fn suspend_note(
+    scheduler_name: &str,
+    note_id: NoteId,
+    base_url: &str,
+    client: &Client,
+) -> Result<(), String> {
     let url = format!("{}/api/cards/note_id/{}", base_url, note_id);
     let response = client.get(url).send().await.map_err(|e| format!("{}", e))?;
     let status = response.status();
@@ -128,23 +131,26 @@ pub async fn suspend_note(note_id: NoteId, base_url: &str, client: &Client) -> R
     }
     let cards: Vec<CardResponse> = response.json().await.map_err(|e| format!("{}", e))?;
     let card_ids = cards.into_iter().map(|card| card.id).collect::<Vec<_>>();
-    suspend_cards(&card_ids, base_url, client).await
+    suspend_cards(scheduler_name, &card_ids, base_url, client).await
 }
 
 pub async fn suspend_cards(
+    scheduler_name: &str,
     card_ids: &[CardId],
     base_url: &str,
     client: &Client,
 ) -> Result<(), String> {
-    let body = UpdateCardRequest {
-        selector: CardsSelector::Ids(card_ids.to_vec()),
-        desired_retention: None,
-        special_state: Some(Some(SpecialStateUpdate::Suspended)),
+    // Suspending is submitted as a study action so it can be undone
+    let request = SubmitStudyActionRequest {
+        scheduler_name: scheduler_name.to_string(),
+        action: StudyAction::Suspend {
+            card_ids: card_ids.to_vec(),
+        },
     };
-    let url = format!("{}/api/cards", base_url);
+    let url = format!("{}/api/review/submit", base_url);
     let response = client
-        .patch(url)
-        .json(&body)
+        .post(url)
+        .json(&request)
         .send()
         .await
         .map_err(|e| format!("{}", e))?;
@@ -158,6 +164,22 @@ pub async fn suspend_cards(
     Ok(())
 }
 
+pub async fn undo(base_url: &str, client: &Client) -> Result<(), String> {
+    let url = format!("{}/api/review/undo", base_url);
+    let response = client
+        .post(url)
+        .send()
+        .await
+        .map_err(|e| format!("{}", e))?;
+    let status = response.status();
+    if status != StatusCode::OK {
+        let response_json: Value = response.json().await.map_err(|e| format!("{}", e))?;
+        let message = response_json.get("message");
+        return Err(message.unwrap().to_string());
+    }
+    Ok(())
+}
+
 pub async fn submit_rating(
     recall_duration: Duration,
     scheduler_name: &str,
@@ -1,9 +1,17 @@
 use crate::{AppState, handlers::error_to_response};
-use axum::{Json, extract::Path, http::StatusCode, response::IntoResponse};
+use axum::{
+    Json,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
 use chrono::Utc;
 use spares::{
-    api::card::{get_card, get_cards, get_leeches, update_card},
-    schema::card::{GetLeechesRequest, UpdateCardRequest},
+    api::card::{get_card, get_cards, get_leech_events, get_leeches, update_card},
+    schema::{
+        FilterOptions,
+        card::{GetLeechesRequest, UpdateCardRequest},
+    },
 };
 use std::sync::Arc;
 
@@ -46,3 +54,14 @@ pub async fn get_leeches_handler(
         .map_err(error_to_response)?;
     Ok(Json(cards_res))
 }
+
+pub async fn get_leech_events_handler(
+    opts: Option<Query<FilterOptions>>,
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(opts) = opts.unwrap_or_default();
+    let leech_events_res = get_leech_events(&data.db, opts)
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(leech_events_res))
+}
@@ -6,6 +6,7 @@ pub mod card;
 pub mod note;
 pub mod parser;
 pub mod review;
+pub mod saved_search;
 pub mod scheduler;
 pub mod tag;
 
@@ -22,6 +23,19 @@ fn error_to_response(e: Error) -> (StatusCode, Json<Value>) {
     )
 }
 
+#[allow(
+    clippy::needless_pass_by_value,
+    reason = "can easily call `.map_err()`"
+)]
+fn join_error_to_response(e: tokio::task::JoinError) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({
+            "message": format!("{:?}", e)
+        })),
+    )
+}
+
 pub async fn health_check_handler() -> impl IntoResponse {
     const MESSAGE: &str = "API Services";
 
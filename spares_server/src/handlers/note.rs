@@ -8,8 +8,8 @@ use axum::{
 use chrono::Utc;
 use spares::{
     api::note::{
-        create_notes, delete_note, get_note, list_notes, render_notes, search_keyword,
-        search_notes, update_notes,
+        create_notes, delete_note, explain_search, get_note, list_notes, render_notes,
+        search_keyword, search_notes, update_notes,
     },
     parsers::get_all_parsers,
     schema::{
@@ -81,6 +81,16 @@ pub async fn search_notes_handler(
     Ok(Json(search_notes_res))
 }
 
+pub async fn explain_search_handler(
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+    Json(body): Json<SearchNotesRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let explain_search_res = explain_search(&data.db, body)
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(explain_search_res))
+}
+
 pub async fn search_keyword_handler(
     axum::extract::State(data): axum::extract::State<Arc<AppState>>,
     Json(body): Json<SearchKeywordRequest>,
@@ -1,10 +1,15 @@
-use crate::{AppState, handlers::error_to_response};
+use crate::{
+    AppState,
+    handlers::{error_to_response, join_error_to_response},
+};
 use axum::{Json, http::StatusCode, response::IntoResponse};
 use chrono::Utc;
-use spares::api::review::{get_review_card, submit_study_action};
-use spares::api::statistics::get_statistics;
+use spares::api::review::{get_review_card, submit_study_action, undo_study_action};
+use spares::api::statistics::{get_forecast, get_forecast_input, get_statistics};
 use spares::parsers::get_all_parsers;
-use spares::schema::review::{GetReviewCardRequest, StatisticsRequest, SubmitStudyActionRequest};
+use spares::schema::review::{
+    ForecastRequest, GetReviewCardRequest, StatisticsRequest, SubmitStudyActionRequest,
+};
 use std::sync::Arc;
 
 pub async fn get_review_card_handler(
@@ -21,7 +26,16 @@ pub async fn submit_study_action_handler(
     axum::extract::State(data): axum::extract::State<Arc<AppState>>,
     Json(body): Json<SubmitStudyActionRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    submit_study_action(&data.db, body, Utc::now())
+    let submit_study_action_response = submit_study_action(&data.db, body, Utc::now())
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(submit_study_action_response))
+}
+
+pub async fn undo_study_action_handler(
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    undo_study_action(&data.db)
         .await
         .map_err(error_to_response)?;
     Ok(StatusCode::OK)
@@ -36,3 +50,18 @@ pub async fn get_statistics_handler(
         .map_err(error_to_response)?;
     Ok(Json(stats_response))
 }
+
+pub async fn get_forecast_handler(
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+    Json(body): Json<ForecastRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let forecast_input = get_forecast_input(&data.db, body, Utc::now())
+        .await
+        .map_err(error_to_response)?;
+    // The forecast is CPU bound, so it shouldn't block the async runtime.
+    let forecast_response = tokio::task::spawn_blocking(move || get_forecast(forecast_input))
+        .await
+        .map_err(join_error_to_response)?
+        .map_err(error_to_response)?;
+    Ok(Json(forecast_response))
+}
@@ -0,0 +1,80 @@
+use crate::{AppState, handlers::error_to_response};
+use axum::{
+    Json,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use spares::{
+    api::saved_search::{
+        create_saved_search, delete_saved_search, get_saved_search, get_saved_search_by_name,
+        list_saved_searches, update_saved_search,
+    },
+    schema::{
+        FilterOptions,
+        saved_search::{CreateSavedSearchRequest, UpdateSavedSearchRequest},
+    },
+};
+use std::sync::Arc;
+
+pub async fn create_saved_search_handler(
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+    Json(body): Json<CreateSavedSearchRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let result = create_saved_search(&data.db, body)
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(result))
+}
+
+pub async fn get_saved_search_handler(
+    Path(id): Path<i64>,
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let saved_search_res = get_saved_search(&data.db, id)
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(saved_search_res))
+}
+
+pub async fn get_saved_search_by_name_handler(
+    Path(name): Path<String>,
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let saved_search_res = get_saved_search_by_name(&data.db, name.as_str())
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(saved_search_res))
+}
+
+pub async fn update_saved_search_handler(
+    Path(id): Path<i64>,
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+    Json(body): Json<UpdateSavedSearchRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let update_saved_search_res = update_saved_search(&data.db, body, id)
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(update_saved_search_res))
+}
+
+pub async fn delete_saved_search_handler(
+    Path(id): Path<i64>,
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    delete_saved_search(&data.db, id)
+        .await
+        .map_err(error_to_response)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn list_saved_searches_handler(
+    opts: Option<Query<FilterOptions>>,
+    axum::extract::State(data): axum::extract::State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(opts) = opts.unwrap_or_default();
+    let list_saved_searches_res = list_saved_searches(&data.db, opts)
+        .await
+        .map_err(error_to_response)?;
+    Ok(Json(list_saved_searches_res))
+}
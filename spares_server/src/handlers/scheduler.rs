@@ -1,6 +1,11 @@
-use crate::{AppState, handlers::error_to_response};
+use crate::{
+    AppState,
+    handlers::{error_to_response, join_error_to_response},
+};
 use axum::{Json, extract::Path, http::StatusCode, response::IntoResponse};
-use spares::api::scheduler::get_scheduler_ratings;
+use chrono::Utc;
+use spares::api::scheduler::{get_scheduler_ratings, simulate_desired_retention};
+use spares::schema::review::SimulateRequest;
 use std::sync::Arc;
 
 pub async fn get_scheduler_ratings_handler(
@@ -10,3 +15,18 @@ pub async fn get_scheduler_ratings_handler(
     let res = get_scheduler_ratings(name.as_str()).map_err(error_to_response)?;
     Ok(Json(res))
 }
+
+pub async fn simulate_desired_retention_handler(
+    Path(name): Path<String>,
+    axum::extract::State(_data): axum::extract::State<Arc<AppState>>,
+    Json(body): Json<SimulateRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    // The simulation is CPU bound, so it shouldn't block the async runtime.
+    let res = tokio::task::spawn_blocking(move || {
+        simulate_desired_retention(name.as_str(), body, Utc::now())
+    })
+    .await
+    .map_err(join_error_to_response)?
+    .map_err(error_to_response)?;
+    Ok(Json(res))
+}
@@ -1,19 +1,30 @@
 use crate::{
     AppState,
     handlers::{
-        card::{get_card_handler, get_cards_handler, get_leeches_handler, update_card_handler},
+        card::{
+            get_card_handler, get_cards_handler, get_leech_events_handler, get_leeches_handler,
+            update_card_handler,
+        },
         health_check_handler,
         note::{
-            create_notes_handler, delete_note_handler, generate_note_files_handler,
-            get_note_handler, list_notes_handler, search_keyword_handler, search_notes_handler,
-            update_notes_handler,
+            create_notes_handler, delete_note_handler, explain_search_handler,
+            generate_note_files_handler, get_note_handler, list_notes_handler,
+            search_keyword_handler, search_notes_handler, update_notes_handler,
         },
         parser::{
             create_parser_handler, delete_parser_handler, get_parser_handler, list_parsers_handler,
             update_parser_handler,
         },
-        review::{get_review_card_handler, get_statistics_handler, submit_study_action_handler},
-        scheduler::get_scheduler_ratings_handler,
+        review::{
+            get_forecast_handler, get_review_card_handler, get_statistics_handler,
+            submit_study_action_handler, undo_study_action_handler,
+        },
+        saved_search::{
+            create_saved_search_handler, delete_saved_search_handler,
+            get_saved_search_by_name_handler, get_saved_search_handler,
+            list_saved_searches_handler, update_saved_search_handler,
+        },
+        scheduler::{get_scheduler_ratings_handler, simulate_desired_retention_handler},
         tag::{
             create_tag_handler, delete_tag_handler, get_tag_by_name_handler, get_tag_handler,
             list_tags_handler, rebuild_tag_handler, update_tag_handler,
@@ -43,6 +54,22 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
         .route("/api/tags/:id", delete(delete_tag_handler))
         .route("/api/tags", get(list_tags_handler))
         .route("/api/tags/:id/rebuild", get(rebuild_tag_handler))
+        // Saved search
+        .route("/api/saved_searches", post(create_saved_search_handler))
+        .route("/api/saved_searches/:id", get(get_saved_search_handler))
+        .route(
+            "/api/saved_searches/name/:id",
+            get(get_saved_search_by_name_handler),
+        )
+        .route(
+            "/api/saved_searches/:id",
+            patch(update_saved_search_handler),
+        )
+        .route(
+            "/api/saved_searches/:id",
+            delete(delete_saved_search_handler),
+        )
+        .route("/api/saved_searches", get(list_saved_searches_handler))
         // Note
         .route("/api/notes", post(create_notes_handler))
         .route("/api/notes/:id", get(get_note_handler))
@@ -54,20 +81,28 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
             post(generate_note_files_handler),
         )
         .route("/api/notes/search", post(search_notes_handler))
+        .route("/api/notes/search/explain", post(explain_search_handler))
         .route("/api/notes/search/keyword", post(search_keyword_handler))
         // Card
         .route("/api/cards/:id", get(get_card_handler))
         .route("/api/cards/note_id/:id", get(get_cards_handler))
         .route("/api/cards/leeches", post(get_leeches_handler))
+        .route("/api/cards/leeches/recent", get(get_leech_events_handler))
         .route("/api/cards", patch(update_card_handler))
         // Review
         .route("/api/review", post(get_review_card_handler))
         .route("/api/review/submit", post(submit_study_action_handler))
+        .route("/api/review/undo", post(undo_study_action_handler))
         .route("/api/review/statistics", post(get_statistics_handler))
+        .route("/api/review/forecast", post(get_forecast_handler))
         // Scheduler
         .route(
             "/api/scheduler/:name/ratings",
             get(get_scheduler_ratings_handler),
         )
+        .route(
+            "/api/scheduler/:name/simulate",
+            post(simulate_desired_retention_handler),
+        )
         .with_state(app_state)
 }